@@ -0,0 +1,40 @@
+//! Links SPDK when built with `--features spdk`.
+//!
+//! SPDK's libraries come with pkg-config files (`spdk/build/lib/pkgconfig`, add it to
+//! PKG_CONFIG_PATH). The NVMe driver and the DPDK environment register themselves through
+//! constructors, so they are linked as whole archives.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    if std::env::var_os("CARGO_FEATURE_SPDK").is_none() {
+        return;
+    }
+    println!("cargo:rustc-link-arg=-Wl,--whole-archive");
+    for arg in pkg_config_libs(&["spdk_nvme", "spdk_env_dpdk"]) {
+        println!("cargo:rustc-link-arg={}", arg);
+    }
+    println!("cargo:rustc-link-arg=-Wl,--no-whole-archive");
+    for arg in pkg_config_libs(&["spdk_syslibs"]) {
+        println!("cargo:rustc-link-arg={}", arg);
+    }
+}
+
+fn pkg_config_libs(packages: &[&str]) -> Vec<String> {
+    let output = Command::new("pkg-config")
+        .arg("--libs")
+        .args(packages)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8(output.stdout)
+            .unwrap()
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        _ => panic!(
+            "pkg-config does not find {}; add spdk/build/lib/pkgconfig to PKG_CONFIG_PATH",
+            packages.join(" ")
+        ),
+    }
+}
@@ -0,0 +1,271 @@
+//! `ssd-benchy aggregate`: mean and median of repeated runs of the same configuration.
+//!
+//! A single run of a utilization point is one draw of a noisy measurement; comparisons should be
+//! made between several runs of each configuration. Every summary row carries a `config_hash` of
+//! the parameters that define the measurement, without what differs between repetitions (uuid,
+//! start time, host, serial number of the drive, random seed). The rows of all inputs are grouped
+//! by it, in the order the configurations first appear, and every group gets the number of runs
+//! and the mean and the median of the `--columns` across its runs. The provisional rows of
+//! `--summary-flush-seconds` (`partial`) are left out, as they cover only part of their point.
+
+use crate::report::{Format, Table};
+use std::fmt::Write;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AggregateArgs {
+    /// Summary files of the runs, e.g., of several machines
+    #[clap(required = true, num_args = 1..)]
+    inputs: Vec<String>,
+
+    /// Numeric summary columns to aggregate
+    #[clap(long, env = "SSD_BENCHY_COLUMNS", num_args = 1.., value_delimiter = ' ', default_values_t = ["achieved_iops", "p50th", "p99th", "p999th", "max"].map(String::from))]
+    columns: Vec<String>,
+
+    #[clap(long, env = "SSD_BENCHY_FORMAT", value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Write the table to this file instead of stdout
+    #[clap(long, env = "SSD_BENCHY_OUTPUT", short)]
+    output: Option<String>,
+}
+
+/// Columns that tell a reader which configuration a hash stands for, where the summary has them
+const DESCRIPTION: [&str; 5] = [
+    "instance_type",
+    "engine",
+    "workload",
+    "writer_threads",
+    "utilization_iop",
+];
+
+/// The runs of one configuration
+struct Group {
+    hash: String,
+    description: Vec<String>,
+    values: Vec<Vec<f64>>, // per column, one value per run
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The middle value, or the mean of the two middle ones of an even number of values
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// The groups of the rows of all inputs and the numbers of rows without a hash and of partial rows
+fn read_groups(args: &AggregateArgs) -> Result<(Vec<Group>, u64, u64), String> {
+    let mut groups: Vec<Group> = vec![];
+    let mut without_hash = 0;
+    let mut partial_rows = 0;
+    for path in &args.inputs {
+        let mut rdr =
+            csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let header: Vec<String> = rdr
+            .headers()
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?
+            .iter()
+            .map(String::from)
+            .collect();
+        let column = |name: &str| header.iter().position(|c| c == name);
+        let hash = column("config_hash").ok_or_else(|| {
+            format!(
+                "Failed to aggregate: {} has no config_hash column, it was written by an older version",
+                path
+            )
+        })?;
+        let partial = column("partial");
+        let columns = args
+            .columns
+            .iter()
+            .map(|name| {
+                column(name)
+                    .ok_or_else(|| format!("Failed to aggregate: {} has no column {}", path, name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (line, record) in rdr.records().enumerate() {
+            let record = record.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            if partial.and_then(|i| record.get(i)) == Some("true") {
+                partial_rows += 1;
+                continue;
+            }
+            let key = record.get(hash).unwrap_or("");
+            if key.is_empty() {
+                without_hash += 1;
+                continue;
+            }
+            let values = columns
+                .iter()
+                .map(|&i| {
+                    record
+                        .get(i)
+                        .and_then(|s| s.parse::<f64>().ok())
+                        .ok_or_else(|| {
+                            format!(
+                                "Failed to parse {} of row {} of {}",
+                                header[i],
+                                line + 2,
+                                path
+                            )
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let index = match groups.iter().position(|g| g.hash == key) {
+                Some(index) => index,
+                None => {
+                    groups.push(Group {
+                        hash: key.to_string(),
+                        description: DESCRIPTION
+                            .iter()
+                            .map(|name| {
+                                column(name)
+                                    .and_then(|i| record.get(i))
+                                    .unwrap_or("")
+                                    .to_string()
+                            })
+                            .collect(),
+                        values: vec![vec![]; columns.len()],
+                    });
+                    groups.len() - 1
+                }
+            };
+            for (runs, value) in groups[index].values.iter_mut().zip(values) {
+                runs.push(value);
+            }
+        }
+    }
+    Ok((groups, without_hash, partial_rows))
+}
+
+fn aggregate(args: &AggregateArgs) -> Result<String, String> {
+    let (groups, without_hash, partial_rows) = read_groups(args)?;
+    if groups.is_empty() {
+        return Err(String::from("Failed to aggregate: the inputs have no rows"));
+    }
+
+    let mut header: Vec<String> = ["config_hash"]
+        .iter()
+        .chain(DESCRIPTION.iter())
+        .chain(["runs"].iter())
+        .map(|name| name.to_string())
+        .collect();
+    for name in &args.columns {
+        header.push(format!("{} mean", name));
+        header.push(format!("{} median", name));
+    }
+    let rows = groups
+        .iter()
+        .map(|group| {
+            let mut row = vec![group.hash.clone()];
+            row.extend(group.description.iter().cloned());
+            row.push(group.values[0].len().to_string());
+            for runs in &group.values {
+                row.push(format!("{:.1}", mean(runs)));
+                row.push(format!("{:.1}", median(runs)));
+            }
+            row
+        })
+        .collect();
+
+    let mut out = String::new();
+    Table { header, rows }.write(&mut out, args.format);
+    if without_hash > 0 {
+        let _ = writeln!(
+            out,
+            "\n{} rows without a config_hash (written by an older version) were left out",
+            without_hash
+        );
+    }
+    if partial_rows > 0 {
+        let _ = writeln!(
+            out,
+            "\n{} provisional rows of points that did not end were left out",
+            partial_rows
+        );
+    }
+    Ok(out)
+}
+
+pub fn run(args: &AggregateArgs) {
+    let out = aggregate(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match &args.output {
+        Some(path) => std::fs::write(path, out).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => print!("{}", out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_and_median_of_runs() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+        assert_eq!(median(&[7.0]), 7.0);
+        assert_eq!(mean(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+
+    #[test]
+    fn complete_rows_are_grouped_by_their_hash() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-aggregate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let args = |input: &str| AggregateArgs {
+            inputs: vec![path(input)],
+            columns: vec![String::from("p99th")],
+            format: Format::Text,
+            output: None,
+        };
+        std::fs::write(
+            path("summary.csv"),
+            "config_hash,engine,p99th,partial\n\
+             a,null,100,false\n\
+             b,psync,50,false\n\
+             a,null,300,false\n\
+             a,null,9999,true\n\
+             ,null,9999,false\n",
+        )
+        .unwrap();
+        let out = aggregate(&args("summary.csv")).unwrap();
+        let rows: Vec<Vec<&str>> = out
+            .lines()
+            .skip(1)
+            .take(2)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        // the partial row and the one without a hash do not count
+        assert_eq!(rows[0], ["a", "null", "2", "200.0", "200.0"]);
+        assert_eq!(rows[1], ["b", "psync", "1", "50.0", "50.0"]);
+        assert!(out.contains("1 rows without a config_hash"), "{}", out);
+        assert!(out.contains("1 provisional rows"), "{}", out);
+
+        std::fs::write(path("old.csv"), "engine,p99th\nnull,100\n").unwrap();
+        std::fs::write(
+            path("partial.csv"),
+            "config_hash,p99th,partial\na,100,true\n",
+        )
+        .unwrap();
+        std::fs::write(path("nan.csv"), "config_hash,p99th\na,x\n").unwrap();
+        let error = |input| aggregate(&args(input)).unwrap_err();
+        assert!(error("old.csv").contains("no config_hash column"));
+        assert!(error("partial.csv").contains("the inputs have no rows"));
+        assert!(error("nan.csv").contains("p99th of row 2"));
+        assert!(error("missing.csv").starts_with("Failed to open"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+//! `ssd-benchy anonymize`: copies of result files that can be shared outside the organization.
+//!
+//! Results sent to a vendor should show the drive and how it behaved, not the infrastructure it
+//! ran in. Every value of the `--columns` (by default the host name, the serial number and name
+//! of the device, the instance type, the profile, the names of thread groups and scenario phases,
+//! and the concurrent namespaces) is replaced by a pseudonym made of the column and a number,
+//! e.g., `hostname-3`, in copies of the inputs in `--output-dir`; the other columns, including
+//! the drive model and firmware, are kept. The same value gets the same pseudonym in all files,
+//! so rows of one host or drive still belong together. The lists of the summary keep their
+//! structure: `concurrent_namespaces` gets the pseudonyms of `ssd_device`, and `thread_groups`
+//! those of `group` for the names of its groups. The pseudonyms
+//! are kept in `--mapping-file`, which stays local: it is read before and extended after every
+//! invocation, so that results anonymized later use the same pseudonyms, and it maps a question
+//! of the vendor about `device_serial-2` back to the drive.
+
+use std::path::Path;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnonymizeArgs {
+    /// Result files to anonymize, e.g., a summary and a samples file
+    #[clap(required = true, num_args = 1..)]
+    inputs: Vec<String>,
+
+    /// Directory the anonymized copies are written to, under the names of the inputs
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_DIR")]
+    output_dir: String,
+
+    /// Columns whose values are replaced by pseudonyms, where a file has them
+    #[clap(long, env = "SSD_BENCHY_COLUMNS", num_args = 1.., value_delimiter = ' ', default_values_t = ["hostname", "device_serial", "ssd_device", "instance_type", "profile", "group", "thread_groups", "name", "concurrent_namespaces"].map(String::from))]
+    columns: Vec<String>,
+
+    /// Pseudonyms of earlier invocations, extended by this one; keep it private
+    #[clap(long, env = "SSD_BENCHY_MAPPING_FILE", default_value_t = String::from("anonymize_mapping.csv"))]
+    mapping_file: String,
+}
+
+/// Pseudonyms by column and original value
+#[derive(Debug, Default)]
+pub struct Mapping {
+    entries: Vec<(String, String, String)>, // column, value, pseudonym
+    new: usize,                             // entries added since it was read
+}
+
+impl Mapping {
+    fn load(path: &str) -> Result<Mapping, String> {
+        if !Path::new(path).exists() {
+            return Ok(Mapping::default());
+        }
+        let mut rdr =
+            csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let entries = rdr
+            .deserialize()
+            .collect::<Result<Vec<(String, String, String)>, _>>()
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(Mapping { entries, new: 0 })
+    }
+
+    /// The pseudonym of `value` in `column`, a new one if it has none yet; empty stays empty
+    pub fn pseudonym(&mut self, column: &str, value: &str) -> String {
+        if value.is_empty() {
+            return String::new();
+        }
+        if let Some((_, _, pseudonym)) = self
+            .entries
+            .iter()
+            .find(|(c, v, _)| c == column && v == value)
+        {
+            return pseudonym.clone();
+        }
+        let number = self.entries.iter().filter(|(c, _, _)| c == column).count() + 1;
+        let pseudonym = format!("{}-{}", column, number);
+        self.entries
+            .push((column.to_string(), value.to_string(), pseudonym.clone()));
+        self.new += 1;
+        pseudonym
+    }
+
+    /// The anonymized `value` of `column`; the lists of the summary are anonymized element by
+    /// element, with the pseudonyms of the column of their elements
+    pub fn anonymize(&mut self, column: &str, value: &str) -> String {
+        let elements = value.split(' ');
+        let anonymized: Vec<String> = match column {
+            "concurrent_namespaces" => elements
+                .map(|device| self.pseudonym("ssd_device", device))
+                .collect(),
+            // name:threads:write_bytes:share
+            "thread_groups" => elements
+                .map(|group| match group.split_once(':') {
+                    Some((name, rest)) => format!("{}:{}", self.pseudonym("group", name), rest),
+                    None => self.pseudonym("group", group),
+                })
+                .collect(),
+            _ => return self.pseudonym(column, value),
+        };
+        anonymized.join(" ")
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        let mut wtr = csv::Writer::from_path(path)
+            .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        wtr.write_record(["column", "value", "pseudonym"])
+            .map_err(|e| e.to_string())?;
+        for entry in &self.entries {
+            wtr.serialize(entry).map_err(|e| e.to_string())?;
+        }
+        wtr.flush().map_err(|e| e.to_string())
+    }
+}
+
+/// Writes the anonymized copy of `input` to `output`; returns the number of rows
+fn anonymize_file(
+    input: &str,
+    output: &Path,
+    columns: &[String],
+    mapping: &mut Mapping,
+) -> Result<u64, String> {
+    let mut rdr =
+        csv::Reader::from_path(input).map_err(|e| format!("Failed to open {}: {}", input, e))?;
+    let header = rdr
+        .headers()
+        .map_err(|e| format!("Failed to read {}: {}", input, e))?
+        .clone();
+    let replaced: Vec<(usize, &str)> = header
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| columns.iter().any(|c| c == name))
+        .collect();
+    let mut wtr = csv::Writer::from_path(output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    wtr.write_record(&header).map_err(|e| e.to_string())?;
+    let mut rows = 0;
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", input, e))?;
+        let mut row: Vec<String> = record.iter().map(String::from).collect();
+        for &(i, column) in &replaced {
+            if let Some(value) = row.get_mut(i) {
+                *value = mapping.anonymize(column, value);
+            }
+        }
+        wtr.write_record(&row).map_err(|e| e.to_string())?;
+        rows += 1;
+    }
+    wtr.flush().map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+fn anonymize(args: &AnonymizeArgs) -> Result<(), String> {
+    let output_dir = Path::new(&args.output_dir);
+    let outputs = args
+        .inputs
+        .iter()
+        .map(|input| {
+            let name = Path::new(input)
+                .file_name()
+                .ok_or_else(|| format!("Failed to anonymize: {} is not a file", input))?;
+            let namesakes = args
+                .inputs
+                .iter()
+                .filter(|other| Path::new(other).file_name() == Some(name))
+                .count();
+            if namesakes > 1 {
+                return Err(format!(
+                    "Failed to anonymize: several inputs are named {}, anonymize them one by one",
+                    name.to_string_lossy()
+                ));
+            }
+            let output = output_dir.join(name);
+            if output.exists() {
+                return Err(format!(
+                    "Failed to anonymize: {} already exists, pass a new --output-dir",
+                    output.display()
+                ));
+            }
+            Ok(output)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    std::fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    let mut mapping = Mapping::load(&args.mapping_file)?;
+    for (input, output) in args.inputs.iter().zip(&outputs) {
+        let rows = anonymize_file(input, output, &args.columns, &mut mapping)?;
+        println!(
+            "anonymized {} rows of {} into {}",
+            rows,
+            input,
+            output.display()
+        );
+    }
+    mapping.save(&args.mapping_file)?;
+    println!(
+        "{} new pseudonyms, {} in total, kept in {}; do not share it",
+        mapping.new,
+        mapping.entries.len(),
+        args.mapping_file
+    );
+    Ok(())
+}
+
+pub fn run(args: &AnonymizeArgs) {
+    anonymize(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudonyms_are_stable_per_column() {
+        let mut mapping = Mapping::default();
+        assert_eq!(mapping.pseudonym("hostname", "db-17"), "hostname-1");
+        assert_eq!(mapping.pseudonym("hostname", "db-18"), "hostname-2");
+        assert_eq!(mapping.pseudonym("device_serial", "S4X"), "device_serial-1");
+        assert_eq!(mapping.pseudonym("hostname", "db-17"), "hostname-1");
+        assert_eq!(mapping.pseudonym("profile", ""), "");
+    }
+
+    #[test]
+    fn lists_share_the_pseudonyms_of_their_elements() {
+        let dir =
+            std::env::temp_dir().join(format!("ssd-benchy-anon-lists-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("summary.csv"),
+            "ssd_device,concurrent_namespaces,thread_groups\n\
+             nvme0n1,nvme0n2 nvme0n3,wal:1:4096:0.7 compactor:2:262144:0.3\n\
+             nvme0n2,,\n",
+        )
+        .unwrap();
+        std::fs::write(path("groups.csv"), "group,p99th\ncompactor,100\nwal,50\n").unwrap();
+        std::fs::write(path("scenario.csv"), "phase,name\n0,nightly-compaction\n").unwrap();
+        // the default --columns
+        let cli = crate::parse_cli([
+            "ssd-benchy",
+            "anonymize",
+            &path("summary.csv"),
+            &path("groups.csv"),
+            &path("scenario.csv"),
+            "--output-dir",
+            &path("out"),
+            "--mapping-file",
+            &path("mapping.csv"),
+        ])
+        .unwrap();
+        let Some(crate::Command::Anonymize(args)) = cli.command else {
+            panic!("expected the anonymize subcommand");
+        };
+        anonymize(&args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("out/summary.csv")).unwrap(),
+            "ssd_device,concurrent_namespaces,thread_groups\n\
+             ssd_device-1,ssd_device-2 ssd_device-3,group-1:1:4096:0.7 group-2:2:262144:0.3\n\
+             ssd_device-2,,\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("out/groups.csv")).unwrap(),
+            "group,p99th\ngroup-2,100\ngroup-1,50\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("out/scenario.csv")).unwrap(),
+            "phase,name\n0,name-1\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_anonymized_with_the_pseudonyms_of_earlier_runs() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-anon-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(path("summary.csv"), "hostname,p99th\ndb-17,100\n,200\n").unwrap();
+        std::fs::write(path("a/summary.csv"), "hostname\ndb-18\n").unwrap();
+        let args = |inputs: &[String], output_dir: &str| AnonymizeArgs {
+            inputs: inputs.to_vec(),
+            output_dir: path(output_dir),
+            columns: vec![String::from("hostname")],
+            mapping_file: path("mapping.csv"),
+        };
+        anonymize(&args(&[path("summary.csv")], "out1")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("out1/summary.csv")).unwrap(),
+            "hostname,p99th\nhostname-1,100\n,200\n"
+        );
+        // the mapping of the first invocation carries over
+        anonymize(&args(&[path("a/summary.csv")], "out2")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path("out2/summary.csv")).unwrap(),
+            "hostname\nhostname-2\n"
+        );
+
+        let error =
+            |inputs: &[String], output_dir| anonymize(&args(inputs, output_dir)).unwrap_err();
+        assert!(error(&[path("summary.csv")], "out1").contains("already exists"));
+        assert!(error(&[path("summary.csv"), path("a/summary.csv")], "out3")
+            .contains("several inputs are named summary.csv"));
+        assert!(error(&[path("missing.csv")], "out4").starts_with("Failed to open"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+//! Writes across atomic write boundaries (`--write-boundary`).
+//!
+//! Whether a database can drop its double-write buffer depends on whether a page write that
+//! crosses the device's atomic write unit costs more, and whether it can tear. With
+//! `--write-boundary` every writer thread starts its region in the middle of a boundary unit, so
+//! that every write of at least one unit (`--iovcnt` blocks) crosses a boundary, e.g., 16K writes
+//! across 4K, or across the unit the device reports with `--write-boundary atomic`. A run without
+//! the flag writes at aligned offsets; the summary records the boundary, so the two compare
+//! directly. `--verify` reads the misaligned writes back, and `--crash-records` with
+//! `verify-after-crash` shows whether a power cut tore them.
+
+use serde::Serialize;
+use std::ops::Range;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WriteBoundary {
+    Bytes(u64),
+    /// The atomic write unit the device reports
+    Atomic,
+}
+
+/// Accepts a number of bytes or `atomic`
+pub fn parse(value: &str) -> Result<WriteBoundary, String> {
+    if value == "atomic" {
+        return Ok(WriteBoundary::Atomic);
+    }
+    value
+        .parse::<u64>()
+        .map(WriteBoundary::Bytes)
+        .map_err(|_| format!("Failed to parse a boundary from {}: bytes or atomic", value))
+}
+
+/// The atomic write unit of `ssd_device`: what the kernel supports for atomic writes, or what
+/// the NVMe controller guarantees on power failure
+fn atomic_write_unit(ssd_device: &str) -> Result<u64, String> {
+    #[cfg(unix)]
+    if let Ok(dir) = crate::device::sysfs_dir(ssd_device) {
+        let kernel = std::fs::read_to_string(dir.join("queue/atomic_write_unit_max_bytes"))
+            .ok()
+            .and_then(|bytes| bytes.trim().parse::<u64>().ok());
+        if let Some(bytes @ 1..) = kernel {
+            return Ok(bytes);
+        }
+    }
+    crate::nvme::atomic_write_unit(ssd_device)
+}
+
+/// The boundary in bytes; 0 for aligned writes
+pub fn bytes(boundary: Option<WriteBoundary>, ssd_device: &str) -> Result<u64, String> {
+    match boundary {
+        None => Ok(0),
+        Some(WriteBoundary::Bytes(bytes)) => Ok(bytes),
+        Some(WriteBoundary::Atomic) => atomic_write_unit(ssd_device),
+    }
+}
+
+/// The part of the region `range` a thread writes: from the middle of its first boundary unit
+/// (`boundary_blocks` long) on, so that writes of at least one unit never start on a boundary
+pub fn range(range: &Range<u64>, boundary_blocks: u64) -> Range<u64> {
+    if boundary_blocks == 0 {
+        return range.clone();
+    }
+    let start = range.start.div_ceil(boundary_blocks) * boundary_blocks + boundary_blocks / 2;
+    start.min(range.end)..range.end
+}
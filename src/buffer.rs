@@ -0,0 +1,49 @@
+//! Heap buffers for O_DIRECT IO whose size is only known at runtime.
+
+use std::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    ops::{Deref, DerefMut},
+};
+
+/// Alignment that satisfies O_DIRECT for every logical block size we care about
+pub const ALIGNMENT: usize = 4096;
+
+pub struct AlignedBuffer {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// the buffer exclusively owns its allocation
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(len: usize, fill: u8) -> Self {
+        assert!(len > 0, "buffer must not be empty");
+        let layout = Layout::from_size_align(len, ALIGNMENT).expect("invalid buffer size");
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        unsafe { std::ptr::write_bytes(ptr, fill, len) };
+        AlignedBuffer { ptr, layout }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.layout.size()) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
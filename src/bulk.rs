@@ -0,0 +1,156 @@
+//! Bulk traffic class of dual-class runs.
+//!
+//! With `--bulk-threads` the writer threads become the latency-critical class (small writes at
+//! the utilization point's rate) and the bulk threads write `--bulk-write-bytes` at a time, as
+//! fast as the device allows or paced to `--bulk-mb-per-second`, e.g., compaction spills next to
+//! commit traffic. Each bulk thread owns a region of its own and writes it sequentially. The
+//! summary reports the bulk latencies in columns of their own, so a row shows how well the
+//! device isolated the two classes.
+
+use crate::{engine::Engine, histogram::Histogram, RateLimiter, RatePattern, RateSchedule};
+use serde::Serialize;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// How a bulk thread writes
+#[derive(Debug, Clone, Copy)]
+pub struct BulkLoad {
+    pub threads: u64,
+    pub write_bytes: u64,
+    pub mb_per_second: f64, // of all bulk threads together; 0 is unthrottled
+    pub runtime: Duration,
+}
+
+/// What a bulk thread hands back after a utilization point
+pub struct BulkResult {
+    begin: Instant,
+    end: Instant,
+    paused: Duration,
+    operations: u64,
+    bytes: u64,
+    io_errors: u64,
+    latency: Histogram, // nanoseconds, every write
+}
+
+/// Writes `range` (in blocks) sequentially with writes of `load.write_bytes` from `start` until
+/// the runtime is over
+pub fn run(
+    engine: &dyn Engine,
+    range: Range<u64>,
+    load: BulkLoad,
+    thread_id: u64,
+    start: Instant,
+) -> BulkResult {
+    let block_size = crate::BLOCK_SIZE as u64;
+    let blocks_per_write = load.write_bytes / block_size;
+    let buffer = crate::buffer::AlignedBuffer::new(load.write_bytes as usize, 11);
+    let iops = load.mb_per_second * 1e6 / load.write_bytes as f64;
+    let mut ratelimiter = (load.mb_per_second > 0.0).then(|| {
+        let schedule = RateSchedule {
+            pattern: RatePattern::Constant,
+            min_rate: iops,
+            max_rate: iops,
+            runtime: load.runtime,
+            period: load.runtime,
+        };
+        RateLimiter::new(start, schedule, load.threads, thread_id, 1, 1.0, 0.0)
+    });
+    let mut block = range.start;
+    let mut operations = 0;
+    let mut bytes = 0;
+    let mut io_errors = 0;
+    let mut latency = Histogram::new();
+
+    let begin = start;
+    let mut end_time = begin + load.runtime;
+    let mut paused = Duration::ZERO;
+    while Instant::now() < end_time {
+        let pause = crate::pause::wait_while_paused(false);
+        if !pause.is_zero() {
+            end_time += pause;
+            if let Some(ratelimiter) = ratelimiter.as_mut() {
+                ratelimiter.skip(pause);
+            }
+            paused += pause;
+        }
+        if block + blocks_per_write > range.end {
+            block = range.start;
+        }
+        let mut write = || match engine.write_at(&buffer, block * block_size) {
+            Ok(n) => {
+                bytes += n as u64;
+                true
+            }
+            Err(_) => {
+                io_errors += 1;
+                false
+            }
+        };
+        match ratelimiter.as_mut() {
+            Some(ratelimiter) => {
+                ratelimiter.run(write, |nanos, _| latency.record(nanos));
+            }
+            None => {
+                let start = Instant::now();
+                if write() {
+                    latency.record(crate::stats::nanos(start.elapsed()));
+                }
+            }
+        }
+        operations += 1;
+        block += blocks_per_write;
+    }
+    BulkResult {
+        begin,
+        end: Instant::now(),
+        paused,
+        operations,
+        bytes,
+        io_errors,
+        latency,
+    }
+}
+
+/// Summary columns of the bulk class; all zero without bulk threads
+#[derive(Serialize, Debug, Default)]
+pub struct BulkStatistics {
+    bulk_operations: u64,
+    bulk_achieved_mb_per_second: f64,
+    bulk_io_errors: u64,
+    bulk_p50th: u64,
+    bulk_p99th: u64,
+    bulk_p999th: u64,
+    bulk_max: u64,
+}
+
+impl BulkStatistics {
+    pub fn create_from_results(results: &[BulkResult]) -> BulkStatistics {
+        let begin = results.iter().map(|r| r.begin).min();
+        let end = results.iter().map(|r| r.end).max();
+        let paused = results.iter().map(|r| r.paused).max().unwrap_or_default();
+        let elapsed_seconds = match (begin, end) {
+            (Some(begin), Some(end)) => (end - begin).saturating_sub(paused).as_secs_f64(),
+            _ => 0.0,
+        };
+        let bytes: u64 = results.iter().map(|r| r.bytes).sum();
+        let mut latency = Histogram::new();
+        for result in results {
+            latency.merge(&result.latency);
+        }
+        BulkStatistics {
+            bulk_operations: results.iter().map(|r| r.operations).sum(),
+            bulk_achieved_mb_per_second: if elapsed_seconds > 0.0 {
+                bytes as f64 / 1e6 / elapsed_seconds
+            } else {
+                0.0
+            },
+            bulk_io_errors: results.iter().map(|r| r.io_errors).sum(),
+            bulk_p50th: latency.percentile(50.0),
+            bulk_p99th: latency.percentile(99.0),
+            bulk_p999th: latency.percentile(99.9),
+            bulk_max: latency.max(),
+        }
+    }
+}
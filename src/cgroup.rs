@@ -0,0 +1,145 @@
+//! Kernel IO throttling of the writer threads (`--cgroup-io-max`, `--cgroup-io-latency-us`).
+//!
+//! Containerized tenants do not see the SSD, they see the SSD behind the io controller of their
+//! cgroup. With `--cgroup-io-max` or `--cgroup-io-latency-us` the benchmark creates a cgroup v2
+//! `ssd-benchy-<pid>` under `--cgroup-parent`, enables the io controller for it, and sets io.max
+//! (e.g., `wiops=20000 wbps=max`) and the io.latency target for the device. The process moves
+//! into the cgroup while the writer threads of a utilization point run and back into its own
+//! cgroup between points, so preinitialization and the other setup IO are not throttled. The
+//! cgroup is removed when the run ends, however it ends. Needs root and a cgroup v2 hierarchy;
+//! the parent must not have processes of its own, which is why the default is the root.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Where cgroup v2 is mounted; /proc/self/cgroup paths are relative to it
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+
+pub struct Cgroup {
+    path: PathBuf,
+    original: PathBuf, // the cgroup the process came from
+}
+
+static CGROUP: OnceLock<Cgroup> = OnceLock::new();
+
+fn write(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| {
+        format!(
+            "Failed to write {} to {}: {}",
+            contents.trim(),
+            path.display(),
+            e
+        )
+    })
+}
+
+/// The cgroup of the process, from the cgroup v2 line (`0::/user.slice/...`) of /proc/self/cgroup
+fn current() -> Result<PathBuf, String> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup")
+        .map_err(|e| format!("Failed to read /proc/self/cgroup: {}", e))?;
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| Path::new(CGROUP_MOUNT).join(path.trim_start_matches('/')))
+        .ok_or_else(|| String::from("Failed to find the cgroup v2 of the process"))
+}
+
+/// `major:minor` of the disk of `ssd_device`; io.max and io.latency do not take partitions
+fn disk_number(ssd_device: &str) -> Result<String, String> {
+    #[cfg(unix)]
+    {
+        let dir = crate::preflight::queue_dir(&crate::device::sysfs_dir(ssd_device)?);
+        fs::read_to_string(dir.join("dev"))
+            .map(|dev| dev.trim().to_string())
+            .map_err(|e| format!("Failed to read the device number of {}: {}", ssd_device, e))
+    }
+    #[cfg(not(unix))]
+    Err(format!(
+        "Failed to read the device number of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+/// Creates the cgroup with the limits for `ssd_devices`; it is removed when the run ends
+pub fn create(
+    parent: &str,
+    io_max: Option<&str>,
+    io_latency_us: Option<u64>,
+    ssd_devices: &[String],
+) -> Result<&'static Cgroup, String> {
+    let parent = Path::new(parent);
+    let controllers = fs::read_to_string(parent.join("cgroup.controllers")).map_err(|e| {
+        format!(
+            "Failed to read the controllers of {}, not a cgroup v2: {}",
+            parent.display(),
+            e
+        )
+    })?;
+    if !controllers.split_whitespace().any(|c| c == "io") {
+        return Err(format!(
+            "the io controller is not available in {}",
+            parent.display()
+        ));
+    }
+    let original = current()?;
+    write(&parent.join("cgroup.subtree_control"), "+io")?;
+    let path = parent.join(format!("ssd-benchy-{}", std::process::id()));
+    fs::create_dir(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    if CGROUP.set(Cgroup { path, original }).is_err() {
+        return Err(String::from("the cgroup of the run exists already"));
+    }
+    crate::outcome::on_exit(remove);
+    let cgroup = CGROUP.get().unwrap();
+    for ssd_device in ssd_devices {
+        let disk = disk_number(ssd_device)?;
+        if let Some(io_max) = io_max {
+            write(
+                &cgroup.path.join("io.max"),
+                &format!("{} {}\n", disk, io_max),
+            )?;
+        }
+        if let Some(target) = io_latency_us {
+            write(
+                &cgroup.path.join("io.latency"),
+                &format!("{} target={}\n", disk, target),
+            )?;
+        }
+    }
+    Ok(cgroup)
+}
+
+impl Cgroup {
+    /// Moves the whole process, all its threads, into the cgroup
+    pub fn enter(&self) -> Result<(), String> {
+        write(
+            &self.path.join("cgroup.procs"),
+            &std::process::id().to_string(),
+        )
+    }
+
+    /// Moves the process back into the cgroup it came from
+    pub fn leave(&self) -> Result<(), String> {
+        write(
+            &self.original.join("cgroup.procs"),
+            &std::process::id().to_string(),
+        )
+    }
+}
+
+/// Leaves and removes the cgroup of the run, if there is one
+fn remove() {
+    let Some(cgroup) = CGROUP.get() else {
+        return;
+    };
+    if current().is_ok_and(|current| current == cgroup.path) {
+        if let Err(e) = cgroup.leave() {
+            println!("warning: {}", e);
+        }
+    }
+    if let Err(e) = fs::remove_dir(&cgroup.path) {
+        println!("warning: Failed to remove {}: {}", cgroup.path.display(), e);
+    }
+}
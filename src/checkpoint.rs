@@ -0,0 +1,464 @@
+//! Periodic checkpoints of a long run (`--checkpoint-file`) and `ssd-benchy analyze`.
+//!
+//! The result files are written after every utilization point, so a crash of the tool or the
+//! host in the middle of a multi-hour point loses all of it. With `--checkpoint-file` every
+//! writer thread hands its latency histogram (all writes, not only the sampled ones) and counters
+//! to a background thread at every `--checkpoint-interval-seconds`, which writes them as JSON
+//! next to the histograms of the completed points. The file is written to a temporary file, synced,
+//! and renamed, so it is always the last complete checkpoint, also after a power loss of the host.
+//! `ssd-benchy analyze --checkpoint-file` turns it into the statistics of every point, the
+//! interrupted one up to its last checkpoint. `ssd-benchy analyze --samples-file` does the same
+//! for a time window of a seekable samples file, see the seekable module.
+
+use crate::{
+    histogram::Histogram,
+    report::{Format, Table},
+    seekable::SeekableReader,
+    stats,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+const VERSION: u64 = 1;
+
+/// What a writer thread has done so far in the running point
+#[derive(Clone, Default)]
+struct Progress {
+    latency: Histogram,
+    operations: u64,
+    io_errors: u64,
+    elapsed: Duration, // without pauses
+}
+
+struct Running {
+    info: Value,
+    threads: Vec<Progress>,
+}
+
+#[derive(Default)]
+struct State {
+    completed: Vec<Value>,
+    running: Option<Running>,
+}
+
+pub struct Checkpointer {
+    path: String,
+    hostname: String,
+    instance_type: String,
+    planned_points: usize,
+    started: SystemTime,
+    state: Mutex<State>,
+    stop: Arc<AtomicBool>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn point_value(info: &Value, threads: &[Progress], complete: bool) -> Value {
+    let mut latency = Histogram::new();
+    for progress in threads {
+        latency.merge(&progress.latency);
+    }
+    let buckets: Vec<Value> = latency
+        .buckets()
+        .map(|(value, count)| Value::Array(vec![value.into(), count.into()]))
+        .collect();
+    let mut point = info.clone();
+    point["complete"] = json!(complete);
+    point["elapsed_seconds"] = json!(threads
+        .iter()
+        .map(|p| p.elapsed)
+        .max()
+        .unwrap_or_default()
+        .as_secs_f64());
+    point["operations"] = json!(threads.iter().map(|p| p.operations).sum::<u64>());
+    point["io_errors"] = json!(threads.iter().map(|p| p.io_errors).sum::<u64>());
+    point["latency"] = json!({
+        "count": latency.count(),
+        "mean": latency.mean(),
+        "min": latency.min(),
+        "max": latency.max(),
+        "buckets": buckets
+    });
+    point
+}
+
+impl Checkpointer {
+    /// Writes the file every `interval` until `stop`
+    pub fn spawn(
+        path: &str,
+        hostname: &str,
+        instance_type: &str,
+        planned_points: usize,
+        interval: Duration,
+    ) -> Result<&'static Checkpointer, String> {
+        let checkpointer: &'static Checkpointer = Box::leak(Box::new(Checkpointer {
+            path: path.to_string(),
+            hostname: hostname.to_string(),
+            instance_type: instance_type.to_string(),
+            planned_points,
+            started: SystemTime::now(),
+            state: Mutex::new(State::default()),
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: Mutex::new(None),
+        }));
+        // fail before the benchmark rather than warn every interval
+        checkpointer.write()?;
+        let stop = checkpointer.stop.clone();
+        let handle = std::thread::spawn(move || {
+            let mut failed = false;
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::park_timeout(interval);
+                if let Err(e) = checkpointer.write() {
+                    if !failed {
+                        println!("warning: {}; later failures are not reported", e);
+                        failed = true;
+                    }
+                }
+            }
+        });
+        *checkpointer.handle.lock().unwrap() = Some(handle);
+        Ok(checkpointer)
+    }
+
+    /// `info` describes the point, e.g., its uuid and utilization
+    pub fn start_point(&self, info: Value, threads: u64) {
+        self.state.lock().unwrap().running = Some(Running {
+            info,
+            threads: vec![Progress::default(); threads as usize],
+        });
+    }
+
+    /// Hands over the progress of writer thread `thread`
+    pub fn publish(
+        &self,
+        thread: u64,
+        latency: &Histogram,
+        operations: u64,
+        io_errors: u64,
+        elapsed: Duration,
+    ) {
+        if let Some(running) = self.state.lock().unwrap().running.as_mut() {
+            running.threads[thread as usize] = Progress {
+                latency: latency.clone(),
+                operations,
+                io_errors,
+                elapsed,
+            };
+        }
+    }
+
+    /// Moves the running point to the completed ones and writes the file
+    pub fn finish_point(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(running) = state.running.take() {
+                let point = point_value(&running.info, &running.threads, true);
+                state.completed.push(point);
+            }
+        }
+        if let Err(e) = self.write() {
+            println!("warning: {}", e);
+        }
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let document = {
+            let state = self.state.lock().unwrap();
+            let mut points = state.completed.clone();
+            if let Some(running) = &state.running {
+                points.push(point_value(&running.info, &running.threads, false));
+            }
+            json!({
+                "version": VERSION,
+                "hostname": self.hostname.as_str(),
+                "instance_type": self.instance_type.as_str(),
+                "start_time": unix_seconds(self.started),
+                "checkpoint_time": unix_seconds(SystemTime::now()),
+                "points_planned": self.planned_points,
+                "points": points
+            })
+        };
+        crate::outcome::replace_file(&self.path, &format!("{}\n", document))
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))
+    }
+
+    /// Stops the background thread after a last checkpoint
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.thread().unpark();
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnalyzeArgs {
+    /// Checkpoint file of a run with --checkpoint-file
+    #[clap(
+        long,
+        env = "SSD_BENCHY_CHECKPOINT_FILE",
+        required_unless_present = "samples_file",
+        conflicts_with = "samples_file"
+    )]
+    checkpoint_file: Option<String>,
+
+    /// Samples file of a point of a run with --samples-format zstd-seekable; prints the latency
+    /// of every operation within the window of --from-seconds and --to-seconds
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_FILE")]
+    samples_file: Option<String>,
+
+    /// Start of the window, in seconds after the first sample
+    #[clap(long, env = "SSD_BENCHY_FROM_SECONDS", default_value_t = 0.0)]
+    from_seconds: f64,
+
+    /// End of the window, in seconds after the first sample; the end of the file by default
+    #[clap(long, env = "SSD_BENCHY_TO_SECONDS")]
+    to_seconds: Option<f64>,
+
+    #[clap(long, env = "SSD_BENCHY_FORMAT", value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+fn us(ns: f64) -> String {
+    format!("{:.1}", ns / 1e3)
+}
+
+fn analyze(args: &AnalyzeArgs) -> Result<String, String> {
+    let Some(path) = &args.checkpoint_file else {
+        return analyze_samples(args);
+    };
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let document: Value = serde_json::from_str(&text).map_err(|e| format!("{} of {}", e, path))?;
+    let invalid = |what: &str| format!("Failed to analyze {}: no {}", path, what);
+    if document.get("version").and_then(Value::as_u64) != Some(VERSION) {
+        return Err(invalid(&format!("checkpoint of version {}", VERSION)));
+    }
+    let points = document
+        .get("points")
+        .and_then(Value::as_array)
+        .ok_or_else(|| invalid("points"))?;
+
+    let mut rows = vec![];
+    for point in points {
+        let number = |key: &str| point.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+        let text = |key: &str| {
+            point
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string()
+        };
+        let latency = point.get("latency").ok_or_else(|| invalid("latency"))?;
+        let mut histogram = Histogram::new();
+        for bucket in latency
+            .get("buckets")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("latency buckets"))?
+        {
+            let pair = bucket.as_array().map_or(&[][..], Vec::as_slice);
+            match (
+                pair.first().and_then(Value::as_u64),
+                pair.get(1).and_then(Value::as_u64),
+            ) {
+                (Some(value), Some(count)) => histogram.record_n(value, count),
+                _ => return Err(invalid("valid latency bucket")),
+            }
+        }
+        let latency_number = |key: &str| latency.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+        let elapsed = number("elapsed_seconds");
+        let complete = point.get("complete") == Some(&Value::Bool(true));
+        rows.push(vec![
+            text("ssd_device"),
+            text("engine"),
+            format!("{:.2}", number("utilization_iop")),
+            String::from(if complete { "complete" } else { "partial" }),
+            format!("{:.0}", elapsed),
+            format!("{:.0}", number("operations")),
+            format!(
+                "{:.0}",
+                if elapsed > 0.0 {
+                    number("operations") / elapsed
+                } else {
+                    0.0
+                }
+            ),
+            us(latency_number("mean")),
+            us(histogram.percentile(50.0) as f64),
+            us(histogram.percentile(99.0) as f64),
+            us(histogram.percentile(99.9) as f64),
+            us(latency_number("max")),
+            format!("{:.0}", number("io_errors")),
+        ]);
+    }
+
+    let mut out = String::new();
+    let complete = points
+        .iter()
+        .filter(|p| p.get("complete") == Some(&Value::Bool(true)))
+        .count();
+    out.push_str(&format!(
+        "{}Checkpoint of {} ({}): {} of {} points complete\n\n",
+        if args.format == Format::Markdown {
+            "# "
+        } else {
+            ""
+        },
+        document
+            .get("hostname")
+            .and_then(Value::as_str)
+            .unwrap_or(""),
+        document
+            .get("instance_type")
+            .and_then(Value::as_str)
+            .unwrap_or(""),
+        complete,
+        document
+            .get("points_planned")
+            .and_then(Value::as_u64)
+            .unwrap_or(0)
+    ));
+    Table {
+        header: [
+            "ssd_device",
+            "engine",
+            "utilization",
+            "state",
+            "seconds",
+            "writes",
+            "achieved IOPS",
+            "mean [us]",
+            "p50 [us]",
+            "p99 [us]",
+            "p99.9 [us]",
+            "max [us]",
+            "errors",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect(),
+        rows,
+    }
+    .write(&mut out, args.format);
+    Ok(out)
+}
+
+/// The latency of every operation in the samples of a seekable file within the window of `args`;
+/// only the frames that overlap the window are decompressed
+fn analyze_samples(args: &AnalyzeArgs) -> Result<String, String> {
+    let path = args.samples_file.as_deref().unwrap_or_default();
+    let mut reader = SeekableReader::open(path)?;
+    let first_ns = reader
+        .frames
+        .iter()
+        .filter(|f| f.rows > 0)
+        .map(|f| f.first_ns)
+        .min()
+        .ok_or_else(|| format!("Failed to analyze {}: no samples", path))?;
+    let seconds = |s: f64| first_ns.saturating_add(stats::nanos(Duration::from_secs_f64(s)));
+    let from_ns = seconds(args.from_seconds.max(0.0));
+    let to_ns = args.to_seconds.map_or(u64::MAX, |s| seconds(s.max(0.0)));
+
+    let header = reader.read_frame(0)?;
+    let mut latencies: BTreeMap<String, Histogram> = BTreeMap::new();
+    let mut frames_read = 0;
+    for index in 1..reader.frames.len() {
+        let frame = reader.frames[index];
+        if frame.rows == 0 || frame.last_ns < from_ns || frame.first_ns >= to_ns {
+            continue;
+        }
+        frames_read += 1;
+        let mut rows = header.clone();
+        rows.extend(reader.read_frame(index)?);
+        let mut rdr = csv::Reader::from_reader(rows.as_slice());
+        for row in rdr.deserialize::<SampleTime>() {
+            let row = row.map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+            if row.realtime_ns >= from_ns && row.realtime_ns < to_ns {
+                // a sample of --sampling-method tail counts as the writes it stands for
+                let weight = row.weight.map_or(1, |w| w.round().max(1.0) as u64);
+                latencies
+                    .entry(row.op.unwrap_or_else(|| String::from("write")))
+                    .or_default()
+                    .record_n(row.latency, weight);
+            }
+        }
+    }
+
+    let mut out = format!(
+        "{}Samples of {} from {:.1}s to {}: {} of {} frames read\n\n",
+        if args.format == Format::Markdown {
+            "# "
+        } else {
+            ""
+        },
+        path,
+        args.from_seconds,
+        args.to_seconds
+            .map_or(String::from("the end"), |s| format!("{:.1}s", s)),
+        frames_read,
+        reader.frames.len() - 1
+    );
+    Table {
+        header: [
+            "op",
+            "samples",
+            "mean [us]",
+            "p50 [us]",
+            "p99 [us]",
+            "p99.9 [us]",
+            "max [us]",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect(),
+        rows: latencies
+            .iter()
+            .map(|(op, h)| {
+                vec![
+                    op.clone(),
+                    h.count().to_string(),
+                    us(h.mean()),
+                    us(h.percentile(50.0) as f64),
+                    us(h.percentile(99.0) as f64),
+                    us(h.percentile(99.9) as f64),
+                    us(h.max() as f64),
+                ]
+            })
+            .collect(),
+    }
+    .write(&mut out, args.format);
+    Ok(out)
+}
+
+/// The columns of a samples file the window analysis needs
+#[derive(Deserialize)]
+struct SampleTime {
+    latency: u64,
+    op: Option<String>,
+    realtime_ns: u64,
+    weight: Option<f64>, // not in files of older versions
+}
+
+pub fn run(args: &AnalyzeArgs) {
+    match analyze(args) {
+        Ok(out) => print!("{}", out),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
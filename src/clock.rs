@@ -0,0 +1,65 @@
+//! Timestamps that correlate samples across hosts.
+//!
+//! Every sample carries the monotonic clock of its completion, which orders the samples of one
+//! host but means nothing on another, and the wall clock (CLOCK_REALTIME), which puts the samples
+//! of all hosts of a distributed run on one timeline. How far that timeline can be trusted depends
+//! on the time synchronization of each host; the kernel's NTP state (as `adjtimex` reports it)
+//! goes into the summary and the manifest, so samples of hosts whose clock was not synchronized
+//! can be told apart.
+
+use serde::Serialize;
+use std::time::SystemTime;
+
+/// Nanoseconds since the Unix epoch
+pub fn realtime_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, crate::stats::nanos)
+}
+
+/// Nanoseconds of CLOCK_MONOTONIC, the clock `Instant` reads
+#[cfg(unix)]
+pub fn monotonic_ns() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec to write to
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
+
+/// Nanoseconds since the first call; Windows has no CLOCK_MONOTONIC to report
+#[cfg(not(unix))]
+pub fn monotonic_ns() -> u64 {
+    static BASE: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    crate::stats::nanos(BASE.get_or_init(std::time::Instant::now).elapsed())
+}
+
+/// The time synchronization of the host; empty where it cannot be read
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct ClockStatus {
+    pub clock_synchronized: Option<bool>,
+    pub clock_max_error_us: Option<i64>, // upper bound of the offset to the reference
+    pub clock_estimated_error_us: Option<i64>,
+}
+
+#[cfg(target_os = "linux")]
+pub fn status() -> ClockStatus {
+    // SAFETY: an all-zero timex with modes 0 only reads the state
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut timex) };
+    if state == -1 {
+        return ClockStatus::default();
+    }
+    ClockStatus {
+        clock_synchronized: Some(state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0),
+        clock_max_error_us: Some(timex.maxerror),
+        clock_estimated_error_us: Some(timex.esterror),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn status() -> ClockStatus {
+    ClockStatus::default()
+}
@@ -0,0 +1,376 @@
+//! `ssd-benchy compare`: are two latency distributions actually different?
+//!
+//! Reads the latencies of two samples files (optionally of a single run each, selected by uuid)
+//! and runs a two-sample Kolmogorov–Smirnov test on them: the statistic D is the largest distance
+//! between the two empirical CDFs, and the p-value is the probability of a distance at least that
+//! large if both sample sets came from the same distribution. Latencies of consecutive writes are
+//! correlated, so the p-value is rather optimistic; a tiny D with a tiny p-value is a real but
+//! irrelevant difference, which is why the percentiles are reported next to it.
+//!
+//! The samples of `--sampling-method tail` stand for different numbers of writes (their
+//! `weight`). Such files are compared by their weighted ECDFs, with Kish's effective sample size
+//! in the p-value, and their percentiles are the nearest ranks of the weighted ECDF.
+
+use crate::{
+    schema,
+    stats::{self, PercentileMethod},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompareArgs {
+    /// Samples file of the baseline, e.g., the current firmware
+    baseline: String,
+
+    /// Samples file of the candidate
+    candidate: String,
+
+    /// Only use the baseline samples of this run
+    #[clap(long, env = "SSD_BENCHY_BASELINE_UUID")]
+    baseline_uuid: Option<u128>,
+
+    /// Only use the candidate samples of this run
+    #[clap(long, env = "SSD_BENCHY_CANDIDATE_UUID")]
+    candidate_uuid: Option<u128>,
+
+    /// How the reported percentiles are computed, as for the summary of a run
+    #[clap(long, env = "SSD_BENCHY_PERCENTILE_METHOD", value_enum, default_value_t = PercentileMethod::Nearest)]
+    percentile_method: PercentileMethod,
+
+    /// Significance level the p-value is compared against
+    #[clap(long, env = "SSD_BENCHY_ALPHA", default_value_t = 0.05)]
+    alpha: f64,
+
+    /// Result file, one row per comparison
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("compare.csv"))]
+    output_file: String,
+}
+
+/// The columns of a samples file the comparison needs
+#[derive(Deserialize)]
+struct SampleLatency {
+    latency: u64,
+    op: Option<String>, // missing in files of versions without it, which only have writes
+    uuid: u128,
+    weight: Option<f64>, // missing in files of versions without it, whose samples weigh the same
+}
+
+/// The write latencies of a samples file in ascending order, with the weight of every sample
+#[derive(Debug)]
+struct Latencies {
+    sorted: Vec<u64>,
+    weights: Vec<f64>,
+}
+
+impl Latencies {
+    /// Whether the samples stand for different numbers of writes, as tail sampling makes them
+    fn weighted(&self) -> bool {
+        self.weights.windows(2).any(|pair| pair[0] != pair[1])
+    }
+
+    /// Kish's effective sample size, the number of equally weighted samples as informative as these
+    fn effective_len(&self) -> f64 {
+        let sum: f64 = self.weights.iter().sum();
+        let sum_of_squares: f64 = self.weights.iter().map(|w| w * w).sum();
+        sum * sum / sum_of_squares
+    }
+
+    fn percentile(&self, p: f64, method: PercentileMethod) -> u64 {
+        match self.weighted() {
+            true => stats::weighted_percentile(&self.sorted, &self.weights, p),
+            false => stats::percentile(&self.sorted, p, method),
+        }
+    }
+}
+
+/// One row of the compare result file
+#[derive(Serialize, Debug, Default)]
+struct Comparison {
+    baseline: String,
+    candidate: String,
+    baseline_uuid: Option<u128>,
+    candidate_uuid: Option<u128>,
+    baseline_samples: usize,
+    candidate_samples: usize,
+    baseline_p50th: u64,
+    candidate_p50th: u64,
+    baseline_p99th: u64,
+    candidate_p99th: u64,
+    baseline_p999th: u64,
+    candidate_p999th: u64,
+    percentile_method: PercentileMethod,
+    weighted: bool, // samples of --sampling-method tail, compared by their weights
+    ks_statistic: f64,
+    ks_p_value: f64,
+    alpha: f64,
+    different: bool, // p-value below alpha
+}
+
+/// The write latencies of `path`, all or only those of run `uuid`
+fn read_latencies(path: &str, uuid: Option<u128>) -> Result<Latencies, String> {
+    let mut rdr =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut samples = vec![];
+    for row in rdr.deserialize::<SampleLatency>() {
+        let row = row.map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        if uuid.is_none_or(|uuid| uuid == row.uuid) && row.op.is_none_or(|op| op == "write") {
+            samples.push((row.latency, row.weight.unwrap_or(1.0)));
+        }
+    }
+    if let Some((_, weight)) = samples.iter().find(|(_, w)| !(*w > 0.0 && w.is_finite())) {
+        return Err(format!(
+            "Failed to parse {}: weight {} is not positive",
+            path, weight
+        ));
+    }
+    samples.sort_unstable_by_key(|&(latency, _)| latency);
+    Ok(Latencies {
+        sorted: samples.iter().map(|&(latency, _)| latency).collect(),
+        weights: samples.iter().map(|&(_, weight)| weight).collect(),
+    })
+}
+
+/// Largest distance between the weighted empirical CDFs of two non-empty sample sets
+fn ks_statistic(a: &Latencies, b: &Latencies) -> f64 {
+    let (a_total, b_total): (f64, f64) = (a.weights.iter().sum(), b.weights.iter().sum());
+    let (mut i, mut j) = (0, 0);
+    let (mut a_cumulative, mut b_cumulative) = (0.0, 0.0);
+    let mut d: f64 = 0.0;
+    while i < a.sorted.len() && j < b.sorted.len() {
+        // step over all samples of the next value in both sets, so that ties do not count
+        let value = a.sorted[i].min(b.sorted[j]);
+        while i < a.sorted.len() && a.sorted[i] == value {
+            a_cumulative += a.weights[i];
+            i += 1;
+        }
+        while j < b.sorted.len() && b.sorted[j] == value {
+            b_cumulative += b.weights[j];
+            j += 1;
+        }
+        d = d.max((a_cumulative / a_total - b_cumulative / b_total).abs());
+    }
+    d
+}
+
+/// Asymptotic p-value of the two-sample KS statistic `d` for (effective) sample sizes `n` and
+/// `m` (Stephens' approximation of the Kolmogorov distribution)
+fn ks_p_value(d: f64, n: f64, m: f64) -> f64 {
+    let effective = (n * m / (n + m)).sqrt();
+    let lambda = (effective + 0.12 + 0.11 / effective) * d;
+    if lambda < 0.2 {
+        return 1.0; // the series converges too slowly, and p is 1 to many digits anyway
+    }
+    let mut sum = 0.0;
+    let mut sign = 1.0;
+    for j in 1..=100 {
+        let term = sign * (-2.0 * (j * j) as f64 * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < 1e-12 {
+            break;
+        }
+        sign = -sign;
+    }
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+/// The comparison rows, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "compare",
+        option: "--output-file",
+        columns: schema::columns_of(&Comparison::default()),
+    }]
+}
+
+pub fn run(args: &CompareArgs) {
+    let output = Path::new(&args.output_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&Comparison::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let read = |path: &str, uuid: Option<u128>| {
+        let latencies = read_latencies(path, uuid).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if latencies.sorted.is_empty() {
+            eprintln!("{} has no samples to compare", path);
+            std::process::exit(1);
+        }
+        latencies
+    };
+    let baseline = read(&args.baseline, args.baseline_uuid);
+    let candidate = read(&args.candidate, args.candidate_uuid);
+    let weighted = baseline.weighted() || candidate.weighted();
+    if weighted && args.percentile_method != PercentileMethod::Nearest {
+        eprintln!(
+            "the samples are weighted (--sampling-method tail), \
+             which only --percentile-method nearest supports"
+        );
+        std::process::exit(1);
+    }
+
+    let percentile = |latencies: &Latencies, p| latencies.percentile(p, args.percentile_method);
+    let ks_statistic = ks_statistic(&baseline, &candidate);
+    let ks_p_value = ks_p_value(
+        ks_statistic,
+        baseline.effective_len(),
+        candidate.effective_len(),
+    );
+    let comparison = Comparison {
+        baseline: args.baseline.clone(),
+        candidate: args.candidate.clone(),
+        baseline_uuid: args.baseline_uuid,
+        candidate_uuid: args.candidate_uuid,
+        baseline_samples: baseline.sorted.len(),
+        candidate_samples: candidate.sorted.len(),
+        baseline_p50th: percentile(&baseline, 50.0),
+        candidate_p50th: percentile(&candidate, 50.0),
+        baseline_p99th: percentile(&baseline, 99.0),
+        candidate_p99th: percentile(&candidate, 99.0),
+        baseline_p999th: percentile(&baseline, 99.9),
+        candidate_p999th: percentile(&candidate, 99.9),
+        percentile_method: args.percentile_method,
+        weighted,
+        ks_statistic,
+        ks_p_value,
+        alpha: args.alpha,
+        different: ks_p_value < args.alpha,
+    };
+
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>10}",
+        "", "samples", "p50[us]", "p99[us]", "p999[us]"
+    );
+    for (name, latencies) in [("baseline", &baseline), ("candidate", &candidate)] {
+        println!(
+            "{:>10} {:>10} {:>10.1} {:>10.1} {:>10.1}",
+            name,
+            latencies.sorted.len(),
+            percentile(latencies, 50.0) as f64 / 1e3,
+            percentile(latencies, 99.0) as f64 / 1e3,
+            percentile(latencies, 99.9) as f64 / 1e3
+        );
+    }
+    println!(
+        "KS D = {:.4}, p = {:.3e}: the distributions {} at alpha = {}",
+        ks_statistic,
+        ks_p_value,
+        if comparison.different {
+            "differ"
+        } else {
+            "do not differ significantly"
+        },
+        args.alpha
+    );
+
+    let mut wtr = schema::csv_appender(output).unwrap();
+    wtr.serialize(&comparison).unwrap();
+    wtr.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples that weigh the same
+    fn unweighted(sorted: &[u64]) -> Latencies {
+        Latencies {
+            sorted: sorted.to_vec(),
+            weights: vec![1.0; sorted.len()],
+        }
+    }
+
+    #[test]
+    fn identical_sets_do_not_differ() {
+        let a = unweighted(&[1, 2, 2, 3, 10, 50]);
+        assert_eq!(ks_statistic(&a, &a), 0.0);
+        assert_eq!(ks_p_value(0.0, 6.0, 6.0), 1.0);
+    }
+
+    #[test]
+    fn disjoint_sets_have_the_largest_distance() {
+        let (low, high) = (unweighted(&[1, 2, 3]), unweighted(&[4, 5]));
+        assert_eq!(ks_statistic(&low, &high), 1.0);
+        assert_eq!(ks_statistic(&high, &low), 1.0);
+        assert!(ks_p_value(1.0, 1000.0, 1000.0) < 1e-12);
+    }
+
+    #[test]
+    fn ties_step_both_sets_at_once() {
+        // one sample after the other would see 1/3 and 2/3 of a against none of b
+        assert_eq!(
+            ks_statistic(&unweighted(&[5, 5, 5]), &unweighted(&[5])),
+            0.0
+        );
+        assert_eq!(
+            ks_statistic(&unweighted(&[1, 1, 2]), &unweighted(&[1, 2, 2])),
+            1.0 / 3.0
+        );
+    }
+
+    #[test]
+    fn weights_count_as_repeated_samples() {
+        // a tail sample of weight 1 next to body samples that stand for 3 writes each
+        let tail = Latencies {
+            sorted: vec![1, 2, 100],
+            weights: vec![3.0, 3.0, 1.0],
+        };
+        let repeated = unweighted(&[1, 1, 1, 2, 2, 2, 100]);
+        assert!(tail.weighted() && !repeated.weighted());
+        assert_eq!(ks_statistic(&tail, &repeated), 0.0);
+        assert_eq!(
+            ks_statistic(&tail, &unweighted(&[1, 2, 100])),
+            6.0 / 7.0 - 2.0 / 3.0
+        );
+        for p in [50.0, 85.0, 86.0, 99.0] {
+            assert_eq!(
+                tail.percentile(p, PercentileMethod::Nearest),
+                repeated.percentile(p, PercentileMethod::Nearest)
+            );
+        }
+        assert_eq!(tail.percentile(99.0, PercentileMethod::Nearest), 100);
+        // (7)^2 / (9 + 9 + 1)
+        assert!((tail.effective_len() - 49.0 / 19.0).abs() < 1e-12);
+        assert_eq!(repeated.effective_len(), 7.0);
+    }
+
+    #[test]
+    fn weights_are_read_from_tail_samples() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-compare-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        std::fs::write(
+            path("tail.csv"),
+            "uuid,op,latency,weight\n1,write,100,1\n1,write,2,3\n1,flush,7,3\n2,write,5,3\n",
+        )
+        .unwrap();
+        std::fs::write(path("old.csv"), "uuid,latency\n1,2\n1,1\n").unwrap();
+        std::fs::write(path("zero.csv"), "uuid,latency,weight\n1,2,0\n").unwrap();
+        let tail = read_latencies(&path("tail.csv"), Some(1)).unwrap();
+        assert_eq!((tail.sorted, tail.weights), (vec![2, 100], vec![3.0, 1.0]));
+        let old = read_latencies(&path("old.csv"), None).unwrap();
+        assert_eq!((old.sorted, old.weights), (vec![1, 2], vec![1.0, 1.0]));
+        assert!(read_latencies(&path("zero.csv"), None)
+            .unwrap_err()
+            .contains("not positive"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn p_value_matches_the_kolmogorov_distribution() {
+        // 1.358 is the 5% critical value of the Kolmogorov distribution; with 20000 samples each
+        // the effective size is 100
+        let d = 1.3581 / (100.0 + 0.12 + 0.11 / 100.0);
+        assert!((ks_p_value(d, 20_000.0, 20_000.0) - 0.05).abs() < 1e-4);
+        // and 1.628 the 1% one
+        let d = 1.6276 / (100.0 + 0.12 + 0.11 / 100.0);
+        assert!((ks_p_value(d, 20_000.0, 20_000.0) - 0.01).abs() < 1e-4);
+    }
+}
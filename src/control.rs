@@ -0,0 +1,227 @@
+//! Live reconfiguration of a running benchmark through a Unix socket (`--control-socket`).
+//!
+//! Finding the interesting rates of a new drive takes many short runs; with a control socket the
+//! target rate and the sampling rate of the running utilization point can be changed and a stats
+//! snapshot read instead, e.g., with `socat - UNIX-CONNECT:/tmp/ssd-benchy.sock`. Every line is a
+//! command and gets one line back:
+//!
+//! - `stats`: the counters of the metrics module as JSON, cumulative since the start
+//! - `rate <factor>`: multiply the target rate of the writer threads, 1 is the configured rate and
+//!   0.001 the lowest; the next utilization point starts at the configured rate again
+//! - `sample-rate <rate>`: the sampling rate, within (0, 1]
+//! - `pause`, `resume`: like SIGUSR1 (pause module)
+//!
+//! The summary records the configured rates, so a point whose rates were changed is only good for
+//! exploring; the run warns about such points.
+
+use crate::{
+    metrics::{percentile_us, Metrics},
+    pause,
+};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Lowest factor of `rate`; below it, the rate limiter would wait for days between writes
+pub const MIN_RATE_SCALE: f64 = 0.001;
+
+static RATE_SCALE: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
+static SAMPLE_RATE: AtomicU64 = AtomicU64::new(1.0f64.to_bits());
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Factor of the writer threads' target rate
+pub fn rate_scale() -> f64 {
+    f64::from_bits(RATE_SCALE.load(Ordering::Relaxed))
+}
+
+pub fn sample_rate() -> f64 {
+    f64::from_bits(SAMPLE_RATE.load(Ordering::Relaxed))
+}
+
+/// Resets the rates to the configuration before a utilization point
+pub fn start_point(sample_rate: f64) {
+    RATE_SCALE.store(1.0f64.to_bits(), Ordering::Relaxed);
+    SAMPLE_RATE.store(sample_rate.to_bits(), Ordering::Relaxed);
+    CHANGED.store(false, Ordering::Relaxed);
+}
+
+/// Whether a rate was changed since `start_point`
+pub fn changed_during_point() -> bool {
+    CHANGED.load(Ordering::Relaxed)
+}
+
+fn stats(metrics: &Metrics) -> Value {
+    let snapshot = metrics.snapshot();
+    let mut stats = json!({
+        "point": snapshot.point.as_ref().map(|p| json!({
+            "ssd_device": p.ssd_device.as_str(),
+            "engine": p.engine.as_str(),
+            "uuid": p.uuid.to_string(),
+            "utilization": p.utilization
+        })),
+        "writes": snapshot.writes,
+        "write_errors": snapshot.write_errors,
+        "target_iops": snapshot.target_iops,
+        "rate_scale": rate_scale(),
+        "sample_rate": sample_rate(),
+        "paused": pause::paused()
+    });
+    if snapshot.writes > 0 {
+        stats["latency_mean_us"] =
+            json!(snapshot.latency_sum_ns as f64 / snapshot.writes as f64 / 1e3);
+        stats["p50_us"] = json!(percentile_us(&snapshot.buckets, 50.0));
+        stats["p99_us"] = json!(percentile_us(&snapshot.buckets, 99.0));
+        stats["p999_us"] = json!(percentile_us(&snapshot.buckets, 99.9));
+    }
+    stats
+}
+
+/// The answer to one command line
+fn execute(metrics: &Metrics, line: &str) -> String {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let argument = words.next().map(|w| w.parse::<f64>());
+    match (command, argument) {
+        ("stats", None) => stats(metrics).to_string(),
+        ("rate", Some(Ok(factor))) if factor >= MIN_RATE_SCALE && factor.is_finite() => {
+            RATE_SCALE.store(factor.to_bits(), Ordering::Relaxed);
+            CHANGED.store(true, Ordering::Relaxed);
+            format!("ok: target rate x{}", factor)
+        }
+        ("sample-rate", Some(Ok(rate))) if rate > 0.0 && rate <= 1.0 => {
+            SAMPLE_RATE.store(rate.to_bits(), Ordering::Relaxed);
+            CHANGED.store(true, Ordering::Relaxed);
+            format!("ok: sample rate {}", rate)
+        }
+        ("pause", None) => {
+            pause::set(true);
+            String::from("ok: paused")
+        }
+        ("resume", None) => {
+            pause::set(false);
+            String::from("ok: resumed")
+        }
+        ("rate", _) => format!(
+            "error: rate takes a factor of at least {}, e.g., rate 0.5",
+            MIN_RATE_SCALE
+        ),
+        ("sample-rate", _) => String::from("error: sample-rate takes a rate within (0, 1]"),
+        _ => String::from(
+            "error: unknown command; stats, rate <factor>, sample-rate <rate>, pause, resume",
+        ),
+    }
+}
+
+/// Starts accepting connections on the socket at `path`, replacing a stale one
+#[cfg(unix)]
+pub fn serve(metrics: &'static Metrics, path: &str) -> Result<(), String> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::{
+            fs::FileTypeExt,
+            net::{UnixListener, UnixStream},
+        },
+    };
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        // a typo must not delete, e.g., a result file
+        if !metadata.file_type().is_socket() {
+            return Err(format!(
+                "Failed to listen on {}: the path exists and is not a socket",
+                path
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!(
+                "Failed to listen on {}: another benchmark is listening",
+                path
+            ));
+        }
+        std::fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path, e))?;
+    }
+    let listener =
+        UnixListener::bind(path).map_err(|e| format!("Failed to listen on {}: {}", path, e))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // one connection at a time is plenty for an operator
+            let mut writer = &stream;
+            for line in BufReader::new(&stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if writeln!(writer, "{}", execute(metrics, &line)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    println!("control socket at {}", path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_metrics: &'static Metrics, path: &str) -> Result<(), String> {
+    Err(format!(
+        "Failed to listen on {}: the control socket needs a Unix domain socket",
+        path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_outside_their_bounds_are_refused() {
+        let metrics = Metrics::new("host", "instance");
+        for command in [
+            "rate 0",
+            "rate 1e-300",
+            "rate -1",
+            "rate inf",
+            "rate NaN",
+            "rate",
+        ] {
+            assert!(
+                execute(metrics, command).starts_with("error:"),
+                "{}",
+                command
+            );
+        }
+        for command in ["sample-rate 0", "sample-rate 1.5", "speed 2"] {
+            assert!(
+                execute(metrics, command).starts_with("error:"),
+                "{}",
+                command
+            );
+        }
+        assert!(!changed_during_point());
+        assert_eq!(execute(metrics, "rate 0.001"), "ok: target rate x0.001");
+        assert_eq!(rate_scale(), MIN_RATE_SCALE);
+        assert!(changed_during_point());
+        start_point(0.5);
+        assert_eq!((rate_scale(), sample_rate()), (1.0, 0.5));
+        assert!(!changed_during_point());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_stale_sockets_are_replaced() {
+        let metrics = Metrics::new("host", "instance");
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("results.csv");
+        std::fs::write(&file, "keep").unwrap();
+        let error = serve(metrics, file.to_str().unwrap()).unwrap_err();
+        assert!(error.contains("not a socket"), "{}", error);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+
+        let socket = dir.join("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        serve(metrics, socket.to_str().unwrap()).unwrap();
+        let error = serve(metrics, socket.to_str().unwrap()).unwrap_err();
+        assert!(error.contains("another benchmark"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+//! Crash-consistency harness.
+//!
+//! With `--crash-records` every write carries a self-describing, checksummed record: the run, the
+//! writer thread and its region, the block, and the thread's write sequence number. The payload
+//! is derived from the sequence number, so a write that only partially reached the media (a torn
+//! write) fails its checksum even if the header sector survived.
+//!
+//! After a power cut, `ssd-benchy verify-after-crash` scans the device and checks, per thread,
+//! that the surviving records form the suffix of the write sequence the wrap-around pattern
+//! implies: a missing sequence number below the newest surviving one means an earlier write was
+//! lost or reordered behind a later one. Optionally, `--crash-ack-file` (which must live on a
+//! different device) logs how many writes each thread had acknowledged, which turns "the newest
+//! surviving record is older than the last acknowledged one" into a detectable data loss.
+
+use crate::{engine::Engine, schema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+const MAGIC: &[u8; 8] = b"SSDCRASH";
+const HEADER_LEN: usize = 72;
+const CHECKSUM_AT: usize = HEADER_LEN;
+const PAYLOAD_AT: usize = HEADER_LEN + 8;
+/// Read granularity of the scan; must be a multiple of the block size
+const READ_SIZE: usize = 2097152;
+/// How often the acknowledged write counts are persisted
+const ACK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub uuid: u128,
+    pub start_time_ns: u64, // unix time of the run; the newest run is verified by default
+    pub thread_id: u64,
+    pub block: u64,
+    pub seq: u64, // per-thread write sequence number
+    pub region_start: u64,
+    pub region_len: u64,
+}
+
+pub enum Parsed {
+    /// No record, e.g., never written or written without `--crash-records`
+    Foreign,
+    Valid(Record),
+    /// The header is intact but the checksum does not match the block
+    Torn(Record),
+}
+
+fn checksum(block: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let words = block[..CHECKSUM_AT]
+        .chunks_exact(8)
+        .chain(block[PAYLOAD_AT..].chunks_exact(8));
+    for word in words {
+        hash ^= u64::from_le_bytes(word.try_into().unwrap());
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl Record {
+    /// Fills `block` with the record, a sequence-dependent payload, and the checksum.
+    pub fn write_to(&self, block: &mut [u8]) {
+        block[0..8].copy_from_slice(MAGIC);
+        block[8..24].copy_from_slice(&self.uuid.to_le_bytes());
+        let fields = [
+            self.start_time_ns,
+            self.thread_id,
+            self.block,
+            self.seq,
+            self.region_start,
+            self.region_len,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            block[24 + i * 8..32 + i * 8].copy_from_slice(&field.to_le_bytes());
+        }
+        // xorshift stream, different for every write of the same block
+        let mut state =
+            ((self.uuid as u64) ^ self.seq.rotate_left(17) ^ self.thread_id.rotate_left(41)) | 1;
+        for word in block[PAYLOAD_AT..].chunks_exact_mut(8) {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word.copy_from_slice(&state.to_le_bytes());
+        }
+        let sum = checksum(block);
+        block[CHECKSUM_AT..PAYLOAD_AT].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    pub fn parse(block: &[u8]) -> Parsed {
+        if &block[0..8] != MAGIC {
+            return Parsed::Foreign;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(block[at..at + 8].try_into().unwrap());
+        let record = Record {
+            uuid: u128::from_le_bytes(block[8..24].try_into().unwrap()),
+            start_time_ns: u64_at(24),
+            thread_id: u64_at(32),
+            block: u64_at(40),
+            seq: u64_at(48),
+            region_start: u64_at(56),
+            region_len: u64_at(64),
+        };
+        if u64_at(CHECKSUM_AT) == checksum(block) {
+            Parsed::Valid(record)
+        } else {
+            Parsed::Torn(record)
+        }
+    }
+}
+
+/// One row of the ack file
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Ack {
+    uuid: u128,
+    thread_id: u64,
+    acknowledged_writes: u64,
+}
+
+/// Persists the number of acknowledged writes per thread to the ack file until stopped
+pub struct AckLogger {
+    pub acknowledged: Arc<Vec<AtomicU64>>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl AckLogger {
+    pub fn spawn(ack_file: &str, uuid: u128, threads: u64) -> AckLogger {
+        let acknowledged: Arc<Vec<AtomicU64>> =
+            Arc::new((0..threads).map(|_| AtomicU64::new(0)).collect());
+        let stop = Arc::new(AtomicBool::new(false));
+        let path = ack_file.to_string();
+        let handle = {
+            let acknowledged = acknowledged.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut wtr = schema::csv_appender(Path::new(&path)).unwrap();
+                let mut last = vec![0; acknowledged.len()];
+                loop {
+                    let stopping = stop.load(Ordering::Relaxed);
+                    for (thread_id, count) in acknowledged.iter().enumerate() {
+                        let count = count.load(Ordering::Acquire);
+                        if count != last[thread_id] {
+                            wtr.serialize(Ack {
+                                uuid,
+                                thread_id: thread_id as u64,
+                                acknowledged_writes: count,
+                            })
+                            .unwrap();
+                            last[thread_id] = count;
+                        }
+                    }
+                    wtr.flush().unwrap();
+                    wtr.get_ref().sync_data().unwrap();
+                    if stopping {
+                        break;
+                    }
+                    std::thread::sleep(ACK_INTERVAL);
+                }
+            })
+        };
+        AckLogger {
+            acknowledged,
+            stop,
+            handle,
+        }
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().unwrap();
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyAfterCrashArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Fraction of the SSD that is scanned; must cover the capacity fraction of the crashed run
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 1.0)]
+    capacity_fraction: f64,
+
+    /// Run to verify; defaults to the newest run found on the device
+    #[clap(long, env = "SSD_BENCHY_UUID")]
+    uuid: Option<u128>,
+
+    /// Ack file written by the crashed run with --crash-ack-file
+    #[clap(long, env = "SSD_BENCHY_ACK_FILE")]
+    ack_file: Option<String>,
+}
+
+#[derive(Default, Debug)]
+struct ThreadReport {
+    region_start: u64,
+    region_len: u64,
+    seqs: Vec<u64>,
+    torn: u64,
+}
+
+/// Sequence numbers missing among the last `region_len` up to the newest of the sorted `seqs`;
+/// all of them must have survived, as the newest write overwrote none of them
+fn holes(seqs: &[u64], region_len: u64) -> u64 {
+    match seqs.last() {
+        Some(&newest) => {
+            let window_start = (newest + 1).saturating_sub(region_len);
+            let present = seqs.iter().filter(|s| **s >= window_start).count() as u64;
+            (newest + 1 - window_start) - present
+        }
+        None => 0,
+    }
+}
+
+/// Returns whether the run survived without torn, reordered, or lost writes.
+pub fn verify_after_crash(args: &VerifyAfterCrashArgs) -> bool {
+    let block_size = crate::BLOCK_SIZE;
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let blocks = (capacity as f64 * args.capacity_fraction) as u64 / block_size as u64;
+    let file = crate::open_ssd(&args.ssd_device);
+    let mut buffer = crate::buffer::AlignedBuffer::new(READ_SIZE, 0);
+
+    // uuid -> (start time, thread -> report)
+    let mut runs: BTreeMap<u128, (u64, BTreeMap<u64, ThreadReport>)> = BTreeMap::new();
+    let blocks_per_read = (READ_SIZE / block_size) as u64;
+    let mut block = 0;
+    while block < blocks {
+        let count = blocks_per_read.min(blocks - block);
+        let bytes = count as usize * block_size;
+        file.read_exact_at(&mut buffer[..bytes], block * block_size as u64)
+            .expect("could not read device");
+        for chunk in buffer[..bytes].chunks_exact(block_size) {
+            let (record, torn) = match Record::parse(chunk) {
+                Parsed::Foreign => continue,
+                Parsed::Valid(record) => (record, false),
+                Parsed::Torn(record) => (record, true),
+            };
+            let run = runs.entry(record.uuid).or_default();
+            run.0 = run.0.max(record.start_time_ns);
+            let thread = run.1.entry(record.thread_id).or_default();
+            if torn {
+                thread.torn += 1;
+            } else {
+                thread.region_start = record.region_start;
+                thread.region_len = record.region_len;
+                thread.seqs.push(record.seq);
+            }
+        }
+        block += count;
+    }
+
+    let uuid = match args.uuid.or_else(|| {
+        runs.iter()
+            .max_by_key(|(_, run)| run.0)
+            .map(|(uuid, _)| *uuid)
+    }) {
+        Some(uuid) => uuid,
+        None => {
+            println!("no crash records found in the first {} blocks", blocks);
+            return false;
+        }
+    };
+    for (other, (_, threads)) in runs.iter().filter(|(u, _)| **u != uuid) {
+        let records: usize = threads.values().map(|t| t.seqs.len()).sum();
+        println!("ignoring {} records of older run {}", records, other);
+    }
+    let threads = match runs.remove(&uuid) {
+        Some((_, threads)) => threads,
+        None => {
+            println!("no records of run {} found", uuid);
+            return false;
+        }
+    };
+
+    let mut acknowledged: BTreeMap<u64, u64> = BTreeMap::new();
+    if let Some(ack_file) = &args.ack_file {
+        let mut rdr = csv::Reader::from_path(ack_file).expect("could not open ack file");
+        for ack in rdr.deserialize::<Ack>() {
+            let ack = ack.expect("could not parse ack file");
+            if ack.uuid == uuid {
+                let entry = acknowledged.entry(ack.thread_id).or_default();
+                *entry = (*entry).max(ack.acknowledged_writes);
+            }
+        }
+    }
+
+    println!("verifying run {}", uuid);
+    println!(
+        "{:>6} {:>10} {:>12} {:>8} {:>8} {:>12} {:>10}",
+        "thread", "records", "newest_seq", "torn", "holes", "acknowledged", "lost"
+    );
+    let mut ok = true;
+    for (thread_id, mut report) in threads {
+        report.seqs.sort_unstable();
+        report.seqs.dedup();
+        let newest = report.seqs.last().copied();
+        let holes = holes(&report.seqs, report.region_len);
+        let acked = acknowledged.get(&thread_id).copied();
+        // acknowledged writes are numbered 0..acked; the newest of them must have survived
+        let lost = match acked {
+            Some(acked) => acked.saturating_sub(newest.map_or(0, |n| n + 1)),
+            None => 0,
+        };
+        println!(
+            "{:>6} {:>10} {:>12} {:>8} {:>8} {:>12} {:>10}",
+            thread_id,
+            report.seqs.len(),
+            newest.map_or("-".to_string(), |n| n.to_string()),
+            report.torn,
+            holes,
+            acked.map_or("-".to_string(), |a| a.to_string()),
+            lost
+        );
+        ok &= report.torn == 0 && holes == 0 && lost == 0;
+    }
+    println!("{}", if ok { "[OK]" } else { "[FAILED]" });
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(seq: u64) -> Record {
+        Record {
+            uuid: 0x1234_5678_9abc_def0,
+            start_time_ns: 1_700_000_000_000_000_000,
+            thread_id: 3,
+            block: 100 + seq % 8,
+            seq,
+            region_start: 100,
+            region_len: 8,
+        }
+    }
+
+    #[test]
+    fn records_verify_their_checksum() {
+        let mut block = vec![0u8; crate::BLOCK_SIZE];
+        assert!(matches!(Record::parse(&block), Parsed::Foreign));
+        record(5).write_to(&mut block);
+        assert!(matches!(Record::parse(&block), Parsed::Valid(r) if r == record(5)));
+
+        // the header sector of a newer write survived, the rest of the block did not
+        let mut newer = vec![0u8; crate::BLOCK_SIZE];
+        record(13).write_to(&mut newer);
+        let mut torn = block.clone();
+        torn[..512].copy_from_slice(&newer[..512]);
+        assert!(matches!(Record::parse(&torn), Parsed::Torn(r) if r == record(13)));
+        // the payloads of two writes of the same block differ
+        assert_ne!(block[PAYLOAD_AT..], newer[PAYLOAD_AT..]);
+        let mut flipped = block.clone();
+        flipped[crate::BLOCK_SIZE - 1] ^= 1;
+        assert!(matches!(Record::parse(&flipped), Parsed::Torn(_)));
+    }
+
+    #[test]
+    fn only_the_newest_writes_of_a_region_must_survive() {
+        assert_eq!(holes(&[], 8), 0);
+        assert_eq!(holes(&[0, 1, 2], 8), 0);
+        // the newest 8 writes of a region of 8 blocks, after wrapping around
+        assert_eq!(holes(&[13, 14, 15, 16, 17, 18, 19, 20], 8), 0);
+        // 17 was lost or reordered behind 20
+        assert_eq!(holes(&[13, 14, 15, 16, 18, 19, 20], 8), 1);
+        // writes older than a pass over the region do not count
+        assert_eq!(holes(&[2, 19, 20], 2), 0);
+    }
+}
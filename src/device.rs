@@ -0,0 +1,187 @@
+//! Resolution of `--ssd-device` to its block device in sysfs, also inside containers.
+//!
+//! Looking up `/sys/class/block/<name>` only works if the name in /dev is the kernel's name of the
+//! device. In a container the node is often passed through under another name
+//! (`--device /dev/nvme1n1:/dev/ssd`), not passed through at all, or the path exists only as a
+//! regular file of the overlay file system. Instead, the device number of the node in /dev is
+//! looked up in `/sys/dev/block/<major>:<minor>`, which links to the real device wherever the node
+//! came from, and every failure explains what the container is missing.
+
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+/// Whether we run in a Docker, Podman, containerd, LXC, or Kubernetes container
+pub fn in_container() -> bool {
+    Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+        || std::env::var_os("container").is_some()
+        || std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+        || fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| {
+            ["docker", "kubepods", "containerd", "libpod", "lxc"]
+                .iter()
+                .any(|runtime| cgroup.contains(runtime))
+        })
+}
+
+/// What to tell the user about the container, if any
+fn container_hint(ssd_device: &str) -> String {
+    if in_container() {
+        format!(
+            "; we run in a container, pass the device through, e.g., `docker run --device /dev/{0}` or a Kubernetes volumeDevice with devicePath /dev/{0}",
+            ssd_device
+        )
+    } else {
+        String::new()
+    }
+}
+
+/// Major and minor number of a `st_rdev` (glibc's encoding of dev_t)
+fn major_minor(rdev: u64) -> (u64, u64) {
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    (major, minor)
+}
+
+/// The kernel's name of the block device `/dev/<ssd_device>`, which differs for an alias
+pub fn kernel_name(ssd_device: &str) -> Result<String, String> {
+    let dir = sysfs_dir(ssd_device)?;
+    Ok(dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default())
+}
+
+/// The sysfs directory of the block device `/dev/<ssd_device>`, e.g.,
+/// /sys/devices/pci0000:00/0000:00:04.0/nvme/nvme1/nvme1n1
+pub fn sysfs_dir(ssd_device: &str) -> Result<PathBuf, String> {
+    let node = format!("/dev/{}", ssd_device);
+    let metadata = fs::metadata(&node).map_err(|e| {
+        format!(
+            "Failed to find {}: {}{}",
+            node,
+            e,
+            container_hint(ssd_device)
+        )
+    })?;
+    if !metadata.file_type().is_block_device() {
+        return Err(format!(
+            "Failed to use {}: not a block device but a {}{}",
+            node,
+            if metadata.is_file() {
+                "regular file, e.g., of the container's file system"
+            } else {
+                "directory or character device"
+            },
+            container_hint(ssd_device)
+        ));
+    }
+    let (major, minor) = major_minor(metadata.rdev());
+    let link = format!("/sys/dev/block/{}:{}", major, minor);
+    let dir = fs::canonicalize(&link).map_err(|e| {
+        format!(
+            "Failed to resolve {} ({}:{}) in sysfs via {}: {}{}",
+            node,
+            major,
+            minor,
+            link,
+            e,
+            if in_container() {
+                "; the container must see the host's /sys (Docker mounts it read-only by default, which is enough)"
+            } else {
+                ""
+            }
+        )
+    })?;
+    Ok(dir)
+}
+
+/// A trimmed sysfs attribute, None if it is missing or empty
+fn attribute(path: PathBuf) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Model, serial number, firmware revision, and namespace ID of `/dev/<ssd_device>` from sysfs
+/// (NVMe, SCSI, and virtio disks), which needs no privileges; what the driver does not export
+/// stays None
+pub fn identity(ssd_device: &str) -> crate::engine::DeviceIdentity {
+    let Ok(dir) = sysfs_dir(ssd_device) else {
+        return crate::engine::DeviceIdentity::default();
+    };
+    let disk = whole_disk(&dir);
+    let controller = disk.join("device");
+    crate::engine::DeviceIdentity {
+        model: attribute(controller.join("model")),
+        serial: attribute(controller.join("serial")).or_else(|| attribute(disk.join("serial"))),
+        firmware_rev: attribute(controller.join("firmware_rev"))
+            .or_else(|| attribute(controller.join("rev"))),
+        nsid: attribute(disk.join("nsid")).and_then(|nsid| nsid.parse().ok()),
+    }
+}
+
+/// The logical block size of `/dev/<ssd_device>`, the smallest size of a direct IO
+pub fn logical_block_size(ssd_device: &str) -> Result<u64, String> {
+    let path = sysfs_dir(ssd_device)?.join("queue/logical_block_size");
+    let path = if path.exists() {
+        path
+    } else {
+        // a partition has the queue of its disk
+        whole_disk(&sysfs_dir(ssd_device)?).join("queue/logical_block_size")
+    };
+    attribute(path.clone())
+        .and_then(|size| size.parse().ok())
+        .ok_or_else(|| format!("Failed to read {}", path.display()))
+}
+
+/// The whole disk of a block device's sysfs directory, i.e., the parent of a partition
+fn whole_disk(dir: &Path) -> PathBuf {
+    match dir.parent() {
+        Some(parent) if dir.join("partition").exists() => parent.to_path_buf(),
+        _ => dir.to_path_buf(),
+    }
+}
+
+/// The whole disks (sysfs directories) the file system of `path` lives on, also through
+/// device-mapper and md stacks; empty for file systems without a block device, e.g., tmpfs
+fn disks_of_path(path: &Path) -> Vec<PathBuf> {
+    // the file does not have to exist yet, the closest existing ancestor is on the same device
+    let Some(metadata) = path.ancestors().find_map(|p| fs::metadata(p).ok()) else {
+        return vec![];
+    };
+    let (major, minor) = major_minor(metadata.dev());
+    let Ok(dir) = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)) else {
+        return vec![];
+    };
+    let mut disks = vec![];
+    let mut pending = vec![whole_disk(&dir)];
+    while let Some(dir) = pending.pop() {
+        let slaves: Vec<PathBuf> = fs::read_dir(dir.join("slaves"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|slave| fs::canonicalize(slave.path()).ok())
+            .map(|slave| whole_disk(&slave))
+            .collect();
+        if slaves.is_empty() {
+            disks.push(dir);
+        } else {
+            pending.extend(slaves);
+        }
+    }
+    disks
+}
+
+/// Whether the file at `path` is written to the disk of `/dev/<ssd_device>`, which perturbs the
+/// measurement; false if either cannot be resolved
+pub fn shares_disk(path: &Path, ssd_device: &str) -> bool {
+    let Ok(dir) = sysfs_dir(ssd_device) else {
+        return false;
+    };
+    let disk = whole_disk(&dir);
+    disks_of_path(path).contains(&disk)
+}
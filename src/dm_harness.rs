@@ -0,0 +1,258 @@
+//! `ssd-benchy dm-harness`: the benchmark against a device-mapper target with known behavior.
+//!
+//! Whether the statistics and the error paths of the tool are right can only be told on a device
+//! whose latency and failures are known in advance. The harness creates a sparse backing file in
+//! `--backing-dir`, attaches it to a loop device, and stacks a device-mapper target on it: dm-delay
+//! delays every IO by `--delay-ms`, and dm-flakey works for `--up-seconds` and then fails all IO
+//! (or only the writes, `--error-writes`) for `--down-seconds`, in turns. It runs the benchmark with
+//! the arguments after `--` on the mapped device and checks the new rows of `--summary-file`: the
+//! median latency must be at least the delay, and flakey runs must report IO errors. The target,
+//! the loop device, and the file are removed afterwards, also when the run fails. Needs root, the
+//! dm-delay and dm-flakey modules, and dmsetup and losetup in the PATH.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// dm-delay: every IO is delayed by --delay-ms
+    Delay,
+    /// dm-flakey: all IO fails for --down-seconds after every --up-seconds
+    Flakey,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DmHarnessArgs {
+    /// Device-mapper target stacked on the loop device
+    #[clap(long, env = "SSD_BENCHY_TARGET", value_enum, default_value_t = Target::Delay)]
+    target: Target,
+
+    /// Size of the sparse backing file of the loop device
+    #[clap(long, env = "SSD_BENCHY_BACKING_BYTES", default_value_t = 268435456)]
+    backing_bytes: u64,
+
+    /// Directory of the backing file
+    #[clap(long, env = "SSD_BENCHY_BACKING_DIR", default_value_t = String::from("/tmp"))]
+    backing_dir: String,
+
+    /// Delay of every IO of the delay target in milliseconds
+    #[clap(long, env = "SSD_BENCHY_DELAY_MS", default_value_t = 5)]
+    delay_ms: u64,
+
+    /// Seconds the flakey target works before it fails
+    #[clap(long, env = "SSD_BENCHY_UP_SECONDS", default_value_t = 1)]
+    up_seconds: u64,
+
+    /// Seconds the flakey target fails before it works again
+    #[clap(long, env = "SSD_BENCHY_DOWN_SECONDS", default_value_t = 1)]
+    down_seconds: u64,
+
+    /// Let the flakey target fail only writes; reads keep working
+    #[clap(long, env = "SSD_BENCHY_ERROR_WRITES", default_value_t = false)]
+    error_writes: bool,
+
+    /// Summary file the benchmark appends to; the harness checks the rows of its run
+    #[clap(long, env = "SSD_BENCHY_SUMMARY_FILE", default_value_t = String::from("dm_harness_summary.csv"))]
+    summary_file: String,
+
+    /// Arguments of the benchmark, without --ssd-device and --summary-file, e.g.,
+    /// -- --instance-type dm --max-iops 1000 --utilization-iops 0.5 --runtime-seconds 10
+    #[clap(last = true)]
+    benchmark_args: Vec<String>,
+}
+
+/// What the harness set up, to be torn down in reverse
+#[derive(Default)]
+struct Harness {
+    backing_file: Option<PathBuf>,
+    loop_device: Option<String>,
+    dm_name: Option<String>,
+}
+
+/// Runs `program` and returns its stdout
+fn run_tool(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to run {} {}: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl Harness {
+    /// Creates the backing file, the loop device, and the target; returns the name of the
+    /// mapped device in /dev, e.g., dm-3
+    fn set_up(&mut self, args: &DmHarnessArgs) -> Result<String, String> {
+        let path =
+            Path::new(&args.backing_dir).join(format!("ssd-benchy-dm-{}.img", std::process::id()));
+        let file = fs::File::create(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.backing_file = Some(path.clone());
+        file.set_len(args.backing_bytes)
+            .map_err(|e| format!("Failed to size {}: {}", path.display(), e))?;
+        let loop_device = run_tool("losetup", &["--find", "--show", &path.to_string_lossy()])?;
+        self.loop_device = Some(loop_device.clone());
+
+        let sectors = args.backing_bytes / 512;
+        let table = match args.target {
+            Target::Delay => format!("0 {} delay {} 0 {}", sectors, loop_device, args.delay_ms),
+            Target::Flakey => format!(
+                "0 {} flakey {} 0 {} {}{}",
+                sectors,
+                loop_device,
+                args.up_seconds,
+                args.down_seconds,
+                if args.error_writes {
+                    " 1 error_writes"
+                } else {
+                    ""
+                }
+            ),
+        };
+        let dm_name = format!("ssd-benchy-{}", std::process::id());
+        run_tool("dmsetup", &["create", &dm_name, "--table", &table])?;
+        self.dm_name = Some(dm_name.clone());
+        let mapped = Path::new("/dev/mapper").join(&dm_name);
+        let device = fs::canonicalize(&mapped)
+            .map_err(|e| format!("Failed to resolve {}: {}", mapped.display(), e))?;
+        println!("{} on {}: {}", dm_name, device.display(), table);
+        Ok(device
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default())
+    }
+
+    /// Removes whatever `set_up` created; failures are only reported
+    fn tear_down(&mut self) {
+        if let Some(dm_name) = self.dm_name.take() {
+            if let Err(e) = run_tool("dmsetup", &["remove", "--retry", &dm_name]) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some(loop_device) = self.loop_device.take() {
+            if let Err(e) = run_tool("losetup", &["--detach", &loop_device]) {
+                eprintln!("{}", e);
+            }
+        }
+        if let Some(path) = self.backing_file.take() {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The header and the rows of the summary file; none if it does not exist yet
+fn summary_rows(path: &str) -> Result<Vec<csv::StringRecord>, String> {
+    if !Path::new(path).exists() {
+        return Ok(vec![]);
+    }
+    let mut reader =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut rows = vec![reader.headers().map_err(|e| e.to_string())?.clone()];
+    for row in reader.records() {
+        rows.push(row.map_err(|e| format!("Failed to read {}: {}", path, e))?);
+    }
+    Ok(rows)
+}
+
+/// Checks the rows the run appended against what the target injects
+fn check(args: &DmHarnessArgs, rows_before: usize) -> Result<Vec<String>, String> {
+    let rows = summary_rows(&args.summary_file)?;
+    let Some((header, rows)) = rows.split_first() else {
+        return Err(format!("{} has no rows", args.summary_file));
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("{} has no {} column", args.summary_file, name))
+    };
+    let (p50th, io_errors) = (column("p50th")?, column("io_errors")?);
+    let new_rows = &rows[rows_before.saturating_sub(1).min(rows.len())..];
+    if new_rows.is_empty() {
+        return Err(String::from("the benchmark did not write a summary row"));
+    }
+    let value = |row: &csv::StringRecord, index: usize| {
+        row.get(index)
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    };
+    Ok(new_rows
+        .iter()
+        .filter_map(|row| match args.target {
+            Target::Delay => {
+                let p50th_ms = value(row, p50th) / 1e6;
+                (p50th_ms < args.delay_ms as f64).then(|| {
+                    format!(
+                        "p50 of {:.3}ms is below the injected delay of {}ms",
+                        p50th_ms, args.delay_ms
+                    )
+                })
+            }
+            Target::Flakey => (value(row, io_errors) == 0.0)
+                .then(|| String::from("no IO errors reported although the device failed IO")),
+        })
+        .collect())
+}
+
+pub fn run(args: &DmHarnessArgs) {
+    let rows_before = summary_rows(&args.summary_file)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+        .len();
+    let mut harness = Harness::default();
+    let device = match harness.set_up(args) {
+        Ok(device) => device,
+        Err(e) => {
+            harness.tear_down();
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let status = std::env::current_exe()
+        .map_err(|e| format!("Failed to find the ssd-benchy binary: {}", e))
+        .and_then(|exe| {
+            Command::new(exe)
+                .args(&args.benchmark_args)
+                .args([
+                    "--ssd-device",
+                    &device,
+                    "--summary-file",
+                    &args.summary_file,
+                ])
+                .status()
+                .map_err(|e| format!("Failed to run the benchmark: {}", e))
+        });
+    harness.tear_down();
+    let status = status.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    println!("benchmark exited with {}", status);
+    match check(args, rows_before) {
+        Ok(failures) if failures.is_empty() => println!("pass"),
+        Ok(failures) => {
+            for failure in failures {
+                println!("fail: {}", failure);
+            }
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("fail: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
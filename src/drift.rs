@@ -0,0 +1,377 @@
+//! Latency drift over long runs (`--drift-threshold`).
+//!
+//! Wear and heat degrade a drive slowly: the p99 of a multi-day run creeps up by a few percent an
+//! hour, which no single summary row shows and nobody watches for. With `--drift-threshold 0.5`
+//! a monitor thread takes the p50 and p99 of every complete window of `--stability-window-seconds`
+//! as the run goes on and compares them with those of the first stable window, the first one
+//! whose percentiles are within the threshold of the window before it (the ones before are the
+//! drive settling in). A percentile that rises more than the threshold above its baseline, here
+//! by half, raises a drift event: a warning on stdout, a row in `--drift-file`, and, with
+//! `--drift-webhook http://host:port/path`, a JSON POST, e.g., to a chat or alerting system. The
+//! event is raised once when the percentile starts to drift and again only after it came back
+//! below the threshold. The baseline of a utilization point is kept across the segments of
+//! `--soak-hours`, so a soak run compares its last hour with its first. The summary records the
+//! baseline p99, the largest rise of a window p99 over it, and the number of events.
+
+use crate::{histogram::Histogram, influx, stability};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// The percentiles of a window that are compared, in nanoseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Percentiles {
+    pub p50th: u64,
+    pub p99th: u64,
+}
+
+impl Percentiles {
+    fn of(histogram: &Histogram) -> Percentiles {
+        Percentiles {
+            p50th: histogram.percentile(50.0),
+            p99th: histogram.percentile(99.0),
+        }
+    }
+
+    fn named(&self) -> [(&'static str, u64); 2] {
+        [("p50th", self.p50th), ("p99th", self.p99th)]
+    }
+}
+
+/// A percentile that started to drift
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub percentile: &'static str,
+    pub baseline: u64,
+    pub value: u64,
+    pub change: f64, // relative to the baseline, 0.5 for half above it
+}
+
+/// Relative rise of `value` over `baseline`
+fn change(baseline: u64, value: u64) -> f64 {
+    value as f64 / baseline.max(1) as f64 - 1.0
+}
+
+/// The baseline of a utilization point and which of its percentiles drift
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    previous: Option<Percentiles>,
+    pub baseline: Option<Percentiles>,
+    drifting: [bool; 2],
+}
+
+impl Tracker {
+    /// Compares the next window with the baseline, or makes it the baseline if it is the first
+    /// stable one; returns the percentiles that start to drift with it
+    pub fn evaluate(&mut self, window: Percentiles, threshold: f64) -> Vec<Drift> {
+        let Some(baseline) = self.baseline else {
+            let stable = self.previous.is_some_and(|previous| {
+                previous
+                    .named()
+                    .iter()
+                    .zip(window.named())
+                    .all(|(&(_, before), (_, now))| change(before, now).abs() <= threshold)
+            });
+            if stable {
+                self.baseline = Some(window);
+            }
+            self.previous = Some(window);
+            return vec![];
+        };
+        let mut drifts = vec![];
+        for ((drifting, (percentile, base)), (_, value)) in self
+            .drifting
+            .iter_mut()
+            .zip(baseline.named())
+            .zip(window.named())
+        {
+            let change = change(base, value);
+            if change > threshold && !*drifting {
+                drifts.push(Drift {
+                    percentile,
+                    baseline: base,
+                    value,
+                    change,
+                });
+            }
+            *drifting = change > threshold;
+        }
+        drifts
+    }
+}
+
+/// A row of --drift-file
+#[derive(Serialize, Debug, Default)]
+pub struct DriftEvent {
+    uuid: u128,
+    utilization_iop: f64,
+    window: u64,
+    elapsed_seconds: u64, // at the end of the window
+    percentile: String,
+    baseline_ns: u64,
+    window_ns: u64,
+    change: f64,
+}
+
+/// Summary columns of --drift-threshold; all zero without it
+#[derive(Serialize, Debug, Default)]
+pub struct DriftStatistics {
+    drift_threshold: f64,
+    drift_baseline_p99th: u64, // 0 if no window was stable
+    drift_max_change: f64,     // of a window p99 over the baseline
+    drift_events: u64,
+}
+
+/// Where the events are posted to, parsed from `http://host:port/path`
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    address: String,
+    path: String,
+}
+
+impl Webhook {
+    pub fn parse(url: &str) -> Result<Webhook, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("--drift-webhook {} must be an http:// URL", url))?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if host.is_empty() {
+            return Err(format!("--drift-webhook {} has no host", url));
+        }
+        Ok(Webhook {
+            address: if host.contains(':') {
+                host.to_string()
+            } else {
+                format!("{}:80", host)
+            },
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+        })
+    }
+
+    fn post(&self, body: &serde_json::Value) -> Result<(), String> {
+        influx::request(
+            &self.address,
+            &format!("POST {}", self.path),
+            "application/json",
+            &body.to_string(),
+        )
+    }
+}
+
+pub struct Options {
+    pub threshold: f64,
+    pub window: Duration,
+    pub runtime: Duration,
+    pub webhook: Option<Webhook>,
+    pub uuid: u128,
+    pub utilization_iop: f64,
+    /// Fields of the webhook body that tell the run, e.g., the host and the device
+    pub context: serde_json::Value,
+}
+
+/// The drift events of a point with its summary columns and the tracker for the next segment
+pub struct Report {
+    pub tracker: Tracker,
+    pub events: Vec<DriftEvent>,
+    pub statistics: DriftStatistics,
+}
+
+struct State {
+    options: Options,
+    tracker: Tracker,
+    events: Vec<DriftEvent>,
+    max_change: f64,
+    evaluated: usize, // windows so far
+}
+
+impl State {
+    /// Evaluates the windows before `end` that were not evaluated yet
+    fn evaluate(&mut self, windows: &stability::Windows, end: usize) {
+        let percentiles: Vec<Option<Percentiles>> = {
+            let windows = windows.lock().unwrap();
+            (self.evaluated..end.min(windows.len()))
+                .map(|w| (windows[w].count() > 0).then(|| Percentiles::of(&windows[w])))
+                .collect()
+        };
+        for (w, percentiles) in (self.evaluated..).zip(percentiles) {
+            self.evaluated = w + 1;
+            let Some(percentiles) = percentiles else {
+                continue;
+            };
+            let drifts = self.tracker.evaluate(percentiles, self.options.threshold);
+            if let Some(baseline) = self.tracker.baseline {
+                self.max_change = self
+                    .max_change
+                    .max(change(baseline.p99th, percentiles.p99th));
+            }
+            for drift in drifts {
+                self.raise(w as u64, drift);
+            }
+        }
+    }
+
+    fn raise(&mut self, window: u64, drift: Drift) {
+        let options = &self.options;
+        let elapsed_seconds = (window + 1) * options.window.as_secs();
+        println!(
+            "warning: latency drift: the {} of the window ending at {}s is {:.1}us, {:.0}% above the baseline of {:.1}us",
+            drift.percentile,
+            elapsed_seconds,
+            drift.value as f64 / 1e3,
+            drift.change * 100.0,
+            drift.baseline as f64 / 1e3
+        );
+        if let Some(webhook) = &options.webhook {
+            let mut body = options.context.clone();
+            body["event"] = json!("latency_drift");
+            body["window"] = json!(window);
+            body["elapsed_seconds"] = json!(elapsed_seconds);
+            body["percentile"] = json!(drift.percentile);
+            body["baseline_ns"] = json!(drift.baseline);
+            body["window_ns"] = json!(drift.value);
+            body["change"] = json!(drift.change);
+            // an unreachable receiver should not end the run
+            if let Err(e) = webhook.post(&body) {
+                eprintln!("Failed to post the drift event: {}", e);
+            }
+        }
+        self.events.push(DriftEvent {
+            uuid: options.uuid,
+            utilization_iop: options.utilization_iop,
+            window,
+            elapsed_seconds,
+            percentile: drift.percentile.to_string(),
+            baseline_ns: drift.baseline,
+            window_ns: drift.value,
+            change: drift.change,
+        });
+    }
+}
+
+pub struct Monitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<State>,
+    windows: stability::Windows,
+}
+
+impl Monitor {
+    /// Starts watching the `windows` of a point, continuing the `tracker` of an earlier segment
+    /// of it; `start` tells the instant the threads of the point started at, once they did
+    pub fn spawn(
+        windows: stability::Windows,
+        start: impl Fn() -> Option<Instant> + Send + 'static,
+        tracker: Tracker,
+        options: Options,
+    ) -> Monitor {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            let windows = windows.clone();
+            std::thread::spawn(move || {
+                let window = options.window;
+                let mut state = State {
+                    options,
+                    tracker,
+                    events: vec![],
+                    max_change: 0.0,
+                    evaluated: 0,
+                };
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::park_timeout(window);
+                    let Some(start) = start() else {
+                        continue;
+                    };
+                    // a thread adds its part of a window when it records into the next one, so
+                    // a window is complete once the one after it is over as well
+                    let over = (start.elapsed().as_nanos() / window.as_nanos()) as usize;
+                    state.evaluate(&windows, over.saturating_sub(1));
+                }
+                state
+            })
+        };
+        Monitor {
+            stop,
+            handle,
+            windows,
+        }
+    }
+
+    /// Evaluates the remaining complete windows once the threads are done
+    pub fn stop(self) -> Report {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        let mut state = self.handle.join().unwrap();
+        let complete =
+            (state.options.runtime.as_nanos() / state.options.window.as_nanos()) as usize;
+        state.evaluate(&self.windows, complete);
+        Report {
+            statistics: DriftStatistics {
+                drift_threshold: state.options.threshold,
+                drift_baseline_p99th: state.tracker.baseline.map_or(0, |b| b.p99th),
+                drift_max_change: state.max_change,
+                drift_events: state.events.len() as u64,
+            },
+            tracker: state.tracker,
+            events: state.events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift_is_raised_against_the_first_stable_window() {
+        let window = |p50th, p99th| Percentiles { p50th, p99th };
+        let mut tracker = Tracker::default();
+        // the drive settles in: the second window is far from the first, the third is stable
+        assert!(tracker.evaluate(window(100, 1000), 0.5).is_empty());
+        assert!(tracker.evaluate(window(100, 3000), 0.5).is_empty());
+        assert!(tracker.evaluate(window(100, 2000), 0.5).is_empty());
+        assert_eq!(tracker.baseline, Some(window(100, 2000)));
+        assert!(tracker.evaluate(window(120, 2900), 0.5).is_empty());
+        let drifts = tracker.evaluate(window(120, 3100), 0.5);
+        assert_eq!(drifts.len(), 1);
+        assert_eq!(drifts[0].percentile, "p99th");
+        assert!((drifts[0].change - 0.55).abs() < 1e-9);
+        // raised again only after the p99 came back below the threshold
+        assert!(tracker.evaluate(window(120, 4000), 0.5).is_empty());
+        assert!(tracker.evaluate(window(120, 2000), 0.5).is_empty());
+        assert_eq!(tracker.evaluate(window(200, 3500), 0.5).len(), 2);
+        assert!(Webhook::parse("https://example.com").is_err());
+        assert!(Webhook::parse("http://alerts:8080/hooks/ssd").is_ok());
+    }
+
+    #[test]
+    fn unstable_windows_never_become_the_baseline() {
+        let mut tracker = Tracker::default();
+        for p99th in [1000, 2000, 4000, 8000] {
+            let window = Percentiles { p50th: 0, p99th };
+            assert!(tracker.evaluate(window, 0.5).is_empty());
+        }
+        assert_eq!(tracker.baseline, None);
+    }
+
+    #[test]
+    fn webhooks_need_an_http_url_with_a_host() {
+        let webhook = Webhook::parse("http://alerts").unwrap();
+        assert_eq!(
+            (webhook.address.as_str(), webhook.path.as_str()),
+            ("alerts:80", "/")
+        );
+        assert!(Webhook::parse("http:///hooks")
+            .unwrap_err()
+            .contains("has no host"));
+        assert!(Webhook::parse("alerts:8080/hooks")
+            .unwrap_err()
+            .contains("must be an http:// URL"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Energy per utilization point (`--measure-energy`).
+//!
+//! The host's energy comes from the RAPL counters of the powercap interface
+//! (/sys/class/powercap/intel-rapl:*, also used for AMD CPUs), which count microjoules per CPU
+//! package and, where supported, for its DRAM. They are read before and after every point; the
+//! summary reports the energy, average power, and energy per write. NVMe drives do not report the
+//! power they draw, only the power state they are in, so the drive's part is the power state at
+//! the end of the point and the maximum power the controller specifies for it.
+
+use crate::nvme;
+use serde::Serialize;
+use std::{fs, path::PathBuf, time::Instant};
+
+const POWERCAP: &str = "/sys/class/powercap";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Domain {
+    Package,
+    Dram,
+}
+
+/// One RAPL counter
+#[derive(Debug, Clone)]
+struct Zone {
+    domain: Domain,
+    energy_uj: PathBuf,
+    max_energy_range_uj: u64, // the counter wraps around here
+}
+
+impl Zone {
+    fn read(&self) -> Result<u64, String> {
+        fs::read_to_string(&self.energy_uj)
+            .map_err(|e| format!("Failed to read {}: {}", self.energy_uj.display(), e))?
+            .trim()
+            .parse()
+            .map_err(|_| format!("Failed to parse {}", self.energy_uj.display()))
+    }
+}
+
+/// The package and DRAM counters of all CPU packages
+#[derive(Debug, Clone)]
+pub struct Rapl {
+    zones: Vec<Zone>,
+}
+
+impl Rapl {
+    /// Finds the counters and makes sure they can be read, which usually needs root
+    pub fn open() -> Result<Rapl, String> {
+        let mut zones = vec![];
+        let entries =
+            fs::read_dir(POWERCAP).map_err(|e| format!("Failed to read {}: {}", POWERCAP, e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // intel-rapl:0 is a package, intel-rapl:0:1 one of its subzones, e.g., dram
+            let Some(index) = file_name.strip_prefix("intel-rapl:") else {
+                continue;
+            };
+            let name = fs::read_to_string(path.join("name")).unwrap_or_default();
+            let domain = match (index.contains(':'), name.trim()) {
+                (false, _) => Domain::Package,
+                (true, "dram") => Domain::Dram,
+                _ => continue,
+            };
+            let max_energy_range_uj = fs::read_to_string(path.join("max_energy_range_uj"))
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(u64::MAX);
+            zones.push(Zone {
+                domain,
+                energy_uj: path.join("energy_uj"),
+                max_energy_range_uj,
+            });
+        }
+        if zones.is_empty() {
+            return Err(format!("Failed to find RAPL counters in {}", POWERCAP));
+        }
+        for zone in &zones {
+            zone.read()?;
+        }
+        Ok(Rapl { zones })
+    }
+
+    fn read(&self) -> Vec<u64> {
+        self.zones.iter().map(|z| z.read().unwrap_or(0)).collect()
+    }
+}
+
+/// Summary columns of --measure-energy; empty without it
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct EnergyStatistics {
+    package_joules: Option<f64>,
+    dram_joules: Option<f64>,
+    average_package_watts: Option<f64>,
+    joules_per_write: Option<f64>, // package and DRAM per write of the writer threads
+    nvme_power_state: Option<u8>,
+    nvme_max_watts: Option<f64>, // specified maximum of that power state
+}
+
+/// Counts the energy of one utilization point
+pub struct EnergyMeter {
+    rapl: Rapl,
+    start: Vec<u64>,
+    begin: Instant,
+}
+
+impl EnergyMeter {
+    pub fn start(rapl: &Rapl) -> EnergyMeter {
+        EnergyMeter {
+            rapl: rapl.clone(),
+            start: rapl.read(),
+            begin: Instant::now(),
+        }
+    }
+
+    pub fn finish(self, writes: u64, ssd_device: &str) -> EnergyStatistics {
+        let end = self.rapl.read();
+        let seconds = self.begin.elapsed().as_secs_f64();
+        let mut joules = [0.0; 2];
+        for ((zone, start), end) in self.rapl.zones.iter().zip(&self.start).zip(&end) {
+            let microjoules = if end >= start {
+                end - start
+            } else {
+                end + (zone.max_energy_range_uj - start)
+            };
+            joules[(zone.domain == Domain::Dram) as usize] += microjoules as f64 / 1e6;
+        }
+        let [package_joules, dram_joules] = joules;
+        let power_state = nvme::power_state(ssd_device).ok();
+        EnergyStatistics {
+            package_joules: Some(package_joules),
+            dram_joules: Some(dram_joules),
+            average_package_watts: (seconds > 0.0).then(|| package_joules / seconds),
+            joules_per_write: (writes > 0).then(|| (package_joules + dram_joules) / writes as f64),
+            nvme_power_state: power_state.map(|p| p.state),
+            nvme_max_watts: power_state.map(|p| p.max_watts),
+        }
+    }
+}
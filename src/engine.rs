@@ -0,0 +1,725 @@
+//! IO engines and the devices they operate on.
+//!
+//! The benchmark issues IO through the `Engine` trait. A plain `File` is the synchronous pread/
+//! pwrite engine, used both for block devices (O_DIRECT) and for the memory device, a memfd of
+//! `--simulated-device-bytes` that needs no hardware. On Windows, block devices are driven by
+//! `windows_io::Drive` instead; the memory, io_uring, and pvsync2 engines are Linux only. The
+//! spdk engine (`--features spdk`) bypasses the kernel altogether. The null engine does no IO at
+//! all and completes every operation immediately (or after `--simulated-latency-us`); what it
+//! measures is the overhead of the tool itself, e.g., rate limiter precision and sampling cost.
+//! The nvme-pi engine (pi module) issues NVMe IO passthrough commands to control the protection
+//! information of namespaces formatted with it.
+//! `FaultInjector` wraps any engine and fails operations on purpose (EIO, short writes, delayed
+//! completions), so the error handling, stats, and serialization paths can be exercised in CI.
+//! An SSD with `--capacity-bytes` or `--lba-window` opens its engines behind an offset and
+//! reports the size of the window as its capacity (lba_window module).
+
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    fs::File,
+    io,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+pub trait Engine: Send {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+    fn sync(&self) -> io::Result<()>;
+
+    /// Writes `bufs` back to back starting at `offset`; engines without vectored IO issue one
+    /// write per buffer
+    fn write_vectored_at(&self, bufs: &[&[u8]], mut offset: u64) -> io::Result<usize> {
+        let mut written = 0;
+        for buf in bufs {
+            let n = self.write_at(buf, offset)?;
+            written += n;
+            offset += n as u64;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Writes `bufs` back to back starting at `offset` and makes them durable; engines that can
+    /// chain the write and the fsync into one submission do (io-uring-linked), the others sync
+    /// after the write
+    fn write_durable_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        let written = self.write_vectored_at(bufs, offset)?;
+        self.sync()?;
+        Ok(written)
+    }
+
+    /// Tells the device that `len` bytes at `offset` are no longer needed (TRIM)
+    fn discard(&self, _offset: u64, _len: u64) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Tags every following IO with the IO priority `ioprio`; false if the engine cannot, and
+    /// the priority of the calling thread applies
+    fn set_io_priority(&mut self, _ioprio: u16) -> bool {
+        false
+    }
+
+    /// Reads until `buf` is full, continuing after short reads
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<E: Engine + ?Sized> Engine for Box<E> {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        (**self).write_at(buf, offset)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        (**self).sync()
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        (**self).write_vectored_at(bufs, offset)
+    }
+
+    fn write_durable_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        (**self).write_durable_at(bufs, offset)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        (**self).discard(offset, len)
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) -> bool {
+        (**self).set_io_priority(ioprio)
+    }
+}
+
+/// BLKDISCARD on block devices, a punched hole in regular files, e.g., the memfd of the memory
+/// engine
+#[cfg(target_os = "linux")]
+pub(crate) fn discard_file(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::unix::{fs::FileTypeExt, io::AsRawFd};
+    const BLKDISCARD: libc::c_ulong = 0x1277;
+    let ret = if file.metadata()?.file_type().is_block_device() {
+        let range = [offset, len];
+        unsafe { libc::ioctl(file.as_raw_fd(), BLKDISCARD, range.as_ptr()) }
+    } else {
+        unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset as libc::off_t,
+                len as libc::off_t,
+            )
+        }
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn iovecs(bufs: &[&[u8]]) -> Vec<libc::iovec> {
+    bufs.iter()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+impl Engine for File {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_data()
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        use std::os::unix::io::AsRawFd;
+        let iov = iovecs(bufs);
+        let ret = unsafe {
+            libc::pwritev(
+                self.as_raw_fd(),
+                iov.as_ptr(),
+                iov.len() as libc::c_int,
+                offset as libc::off_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        discard_file(self, offset, len)
+    }
+}
+
+#[cfg(windows)]
+impl Engine for File {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum EngineKind {
+    /// pread/pwrite on the SSD opened with O_DIRECT
+    #[default]
+    Psync,
+    /// io_uring with one IO in flight per thread on the SSD opened with O_DIRECT
+    IoUring,
+    /// io-uring whose fdatasync of --use-fsync is linked to its write and submitted with it
+    IoUringLinked,
+    /// preadv2/pwritev2 with RWF_HIPRI (polled completions) on the SSD opened with O_DIRECT
+    Pvsync2,
+    /// NVMe IO passthrough with end-to-end protection information on the SSD, see --pi-mode
+    NvmePi,
+    /// SPDK user-space NVMe driver; --ssd-device is the PCI address (requires --features spdk)
+    Spdk,
+    /// pread/pwrite on an in-memory device of --simulated-device-bytes
+    Memory,
+    /// No IO; every operation completes after --simulated-latency-us
+    Null,
+}
+
+impl std::fmt::Display for EngineKind {
+    /// The name used on the command line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Busy-waits instead of sleeping, which would add scheduler noise
+fn spin_for(duration: Duration) {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        std::hint::spin_loop();
+    }
+}
+
+pub struct NullEngine {
+    latency: Duration,
+}
+
+impl Engine for NullEngine {
+    fn write_at(&self, buf: &[u8], _offset: u64) -> io::Result<usize> {
+        spin_for(self.latency);
+        Ok(buf.len())
+    }
+
+    fn read_at(&self, buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+        spin_for(self.latency);
+        Ok(buf.len())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], _offset: u64) -> io::Result<usize> {
+        spin_for(self.latency);
+        Ok(bufs.iter().map(|buf| buf.len()).sum())
+    }
+
+    fn discard(&self, _offset: u64, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Synchronous polled IO: the calling thread polls the NVMe poll queue for its completion
+/// instead of sleeping until the interrupt. Without poll queues the kernel silently falls back to
+/// interrupts, so this is then the same as psync.
+#[cfg(target_os = "linux")]
+pub struct Pvsync2 {
+    file: File,
+}
+
+#[cfg(target_os = "linux")]
+impl Engine for Pvsync2 {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.write_vectored_at(&[buf], offset)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        use std::os::unix::io::AsRawFd;
+        let iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let ret = unsafe {
+            libc::preadv2(
+                self.file.as_raw_fd(),
+                &iov,
+                1,
+                offset as libc::off_t,
+                libc::RWF_HIPRI,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        use std::os::unix::io::AsRawFd;
+        let iov = iovecs(bufs);
+        let ret = unsafe {
+            libc::pwritev2(
+                self.file.as_raw_fd(),
+                iov.as_ptr(),
+                iov.len() as libc::c_int,
+                offset as libc::off_t,
+                libc::RWF_HIPRI,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        discard_file(&self.file, offset, len)
+    }
+}
+
+/// Anonymous in-memory file of `bytes`
+#[cfg(target_os = "linux")]
+fn memory_file(bytes: u64) -> File {
+    use std::os::unix::io::FromRawFd;
+    let failed = |e: io::Error| -> ! {
+        crate::outcome::exit(
+            crate::outcome::Outcome::DeviceError,
+            &format!("could not create memory device: {}", e),
+        )
+    };
+    let fd = unsafe { libc::memfd_create(c"ssd-benchy".as_ptr(), 0) };
+    if fd < 0 {
+        failed(io::Error::last_os_error());
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(bytes).unwrap_or_else(|e| failed(e));
+    file
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_file(_bytes: u64) -> File {
+    crate::outcome::exit(
+        crate::outcome::Outcome::ConfigError,
+        "the memory engine is only available on Linux",
+    );
+}
+
+#[cfg(feature = "spdk")]
+fn spdk_device(ssd_device: &str) -> Device {
+    Device::Spdk(crate::spdk::Controller::attach(ssd_device))
+}
+
+#[cfg(not(feature = "spdk"))]
+fn spdk_device(_ssd_device: &str) -> Device {
+    crate::outcome::exit(
+        crate::outcome::Outcome::ConfigError,
+        "the spdk engine requires a build with --features spdk",
+    );
+}
+
+/// Setup of the io-uring engine's rings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoUringOptions {
+    pub sqpoll: bool,
+    pub sqpoll_cpu: Option<u32>,
+    pub hipri: bool, // IORING_SETUP_IOPOLL
+}
+
+/// Provenance of the device that was written, recorded in every summary row
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware_rev: Option<String>,
+    pub nsid: Option<u32>, // NVMe namespaces only
+}
+
+/// The size of the SSD `name`; the block device of a namespace with extended LBAs has none
+fn ssd_bytes(name: &str) -> u64 {
+    match crate::device_capacity(name) {
+        0 => crate::nvme::namespace_format(name).map_or(0, |format| format.capacity()),
+        bytes => bytes,
+    }
+}
+
+/// What the engines of all writer threads operate on
+pub enum Device {
+    Ssd {
+        name: String,
+        io_uring: IoUringOptions,
+        sqpoll_anchor: OnceLock<File>, // shared SQ poll thread of all io-uring engines
+        pi_mode: crate::pi::PiMode,
+        window: crate::lba_window::Window,
+    },
+    Memory(File),
+    #[cfg(feature = "spdk")]
+    Spdk(crate::spdk::Controller),
+    Null {
+        capacity: u64,
+        latency: Duration,
+    },
+}
+
+impl Device {
+    pub fn new(
+        kind: EngineKind,
+        ssd_device: Option<&str>,
+        simulated_bytes: u64,
+        simulated_latency: Duration,
+        io_uring: IoUringOptions,
+        pi_mode: crate::pi::PiMode,
+        window: crate::lba_window::Window,
+    ) -> Device {
+        match kind {
+            EngineKind::Psync
+            | EngineKind::IoUring
+            | EngineKind::IoUringLinked
+            | EngineKind::Pvsync2
+            | EngineKind::NvmePi => match ssd_device {
+                Some(name) => {
+                    #[cfg(not(target_os = "linux"))]
+                    if kind != EngineKind::Psync {
+                        crate::outcome::exit(
+                            crate::outcome::Outcome::ConfigError,
+                            &format!("the {} engine is only available on Linux", kind),
+                        );
+                    }
+                    #[cfg(unix)]
+                    match crate::device::kernel_name(name) {
+                        Ok(kernel_name) if kernel_name != name => println!(
+                            "warning: /dev/{} is the kernel's {}; the results record {}",
+                            name, kernel_name, name
+                        ),
+                        Ok(_) => {}
+                        Err(e) => {
+                            crate::outcome::exit(crate::outcome::Outcome::DeviceError, &e);
+                        }
+                    }
+                    if !window.is_whole() {
+                        if let Err(e) = window.check(name, ssd_bytes(name)) {
+                            crate::outcome::exit(crate::outcome::Outcome::ConfigError, &e);
+                        }
+                    }
+                    Device::Ssd {
+                        name: name.to_string(),
+                        io_uring,
+                        sqpoll_anchor: OnceLock::new(),
+                        pi_mode,
+                        window,
+                    }
+                }
+                None => {
+                    crate::outcome::exit(
+                        crate::outcome::Outcome::ConfigError,
+                        &format!("the {} engine requires --ssd-device", kind),
+                    );
+                }
+            },
+            EngineKind::Spdk => match ssd_device {
+                Some(name) => spdk_device(name),
+                None => {
+                    crate::outcome::exit(
+                        crate::outcome::Outcome::ConfigError,
+                        "the spdk engine requires --ssd-device",
+                    );
+                }
+            },
+            EngineKind::Memory => Device::Memory(memory_file(simulated_bytes)),
+            EngineKind::Null => Device::Null {
+                capacity: simulated_bytes,
+                latency: simulated_latency,
+            },
+        }
+    }
+
+    /// Recorded as `ssd_device` in the results
+    pub fn name(&self) -> String {
+        match self {
+            Device::Ssd { name, .. } => name.clone(),
+            Device::Memory(_) => String::from("memory"),
+            #[cfg(feature = "spdk")]
+            Device::Spdk(controller) => controller.name().to_string(),
+            Device::Null { .. } => String::from("null"),
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        match self {
+            Device::Ssd { name, window, .. } => window.capacity(ssd_bytes(name)),
+            Device::Memory(file) => file.metadata().unwrap().len(),
+            #[cfg(feature = "spdk")]
+            Device::Spdk(controller) => controller.capacity(),
+            Device::Null { capacity, .. } => *capacity,
+        }
+    }
+
+    /// Who made the device and which one it is; the default (all None) for everything but SSDs
+    pub fn identity(&self) -> DeviceIdentity {
+        match self {
+            #[cfg(unix)]
+            Device::Ssd { name, .. } => crate::device::identity(name),
+            _ => DeviceIdentity::default(),
+        }
+    }
+
+    /// LBA format of the namespace; the default (no metadata) for everything but NVMe SSDs
+    pub fn format(&self) -> crate::nvme::NamespaceFormat {
+        match self {
+            Device::Ssd { name, .. } => crate::nvme::namespace_format(name).unwrap_or_default(),
+            _ => crate::nvme::NamespaceFormat::default(),
+        }
+    }
+
+    /// Where the capacity of the device starts on the SSD, in bytes; 0 without --lba-window
+    pub fn offset(&self) -> u64 {
+        match self {
+            Device::Ssd { window, .. } => window.start,
+            _ => 0,
+        }
+    }
+
+    /// Whether written data can be read back, which --verify relies on
+    pub fn stores_data(&self) -> bool {
+        !matches!(self, Device::Null { .. })
+    }
+
+    /// A new engine of `kind` for one thread; psync for everything that is not io_uring or pvsync2
+    pub fn open(&self, kind: EngineKind) -> Box<dyn Engine> {
+        let engine = self.open_whole(kind);
+        match self.offset() {
+            0 => engine,
+            start => Box::new(crate::lba_window::Offset::new(engine, start)),
+        }
+    }
+
+    /// An engine of `kind` on the whole device
+    fn open_whole(&self, kind: EngineKind) -> Box<dyn Engine> {
+        let file = match self {
+            #[cfg(unix)]
+            Device::Ssd { name, .. } => crate::open_ssd(name),
+            #[cfg(windows)]
+            Device::Ssd { name, .. } => return Box::new(crate::open_ssd(name)),
+            Device::Memory(file) => file.try_clone().unwrap(),
+            #[cfg(feature = "spdk")]
+            Device::Spdk(controller) => return Box::new(controller.engine()),
+            Device::Null { latency, .. } => return Box::new(NullEngine { latency: *latency }),
+        };
+        match kind {
+            #[cfg(target_os = "linux")]
+            EngineKind::IoUring | EngineKind::IoUringLinked => {
+                let options = match self {
+                    Device::Ssd { io_uring, .. } => *io_uring,
+                    _ => IoUringOptions::default(),
+                };
+                let anchor = match self {
+                    Device::Ssd { sqpoll_anchor, .. } if options.sqpoll => {
+                        Some(sqpoll_anchor.get_or_init(|| {
+                            crate::io_uring::sqpoll_anchor(&options).unwrap_or_else(|e| {
+                                crate::outcome::exit(
+                                    crate::outcome::Outcome::DeviceError,
+                                    &format!("could not set up the io_uring poll thread: {}", e),
+                                )
+                            })
+                        }))
+                    }
+                    _ => None,
+                };
+                Box::new(
+                    crate::io_uring::IoUring::new(
+                        file,
+                        &options,
+                        anchor,
+                        kind == EngineKind::IoUringLinked,
+                    )
+                    .unwrap_or_else(|e| {
+                        crate::outcome::exit(
+                            crate::outcome::Outcome::DeviceError,
+                            &format!("could not set up io_uring: {}", e),
+                        )
+                    }),
+                )
+            }
+            #[cfg(target_os = "linux")]
+            EngineKind::Pvsync2 => Box::new(Pvsync2 { file }),
+            #[cfg(target_os = "linux")]
+            EngineKind::NvmePi => {
+                let Device::Ssd { name, pi_mode, .. } = self else {
+                    unreachable!("the nvme-pi engine is only created on an SSD")
+                };
+                let format = crate::nvme::namespace_format(name).unwrap_or_else(|e| {
+                    crate::outcome::exit(crate::outcome::Outcome::DeviceError, &e)
+                });
+                Box::new(crate::pi::PiEngine::new(file, format, *pi_mode))
+            }
+            _ => Box::new(file),
+        }
+    }
+}
+
+/// Probabilities of the faults `FaultInjector` injects per operation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    pub eio_probability: f64,
+    pub short_write_probability: f64,
+    pub delay_probability: f64,
+    pub delay: Duration,
+}
+
+impl Faults {
+    pub fn any(&self) -> bool {
+        self.eio_probability > 0.0
+            || self.short_write_probability > 0.0
+            || self.delay_probability > 0.0
+    }
+}
+
+#[cfg(unix)]
+fn injected_io_error() -> io::Error {
+    io::Error::from_raw_os_error(libc::EIO)
+}
+
+#[cfg(windows)]
+fn injected_io_error() -> io::Error {
+    const ERROR_IO_DEVICE: i32 = 1117;
+    io::Error::from_raw_os_error(ERROR_IO_DEVICE)
+}
+
+/// Wraps an engine and injects the faults described by `Faults`
+pub struct FaultInjector<E> {
+    inner: E,
+    faults: Faults,
+    rng: RefCell<fastrand::Rng>, // engines are used by a single thread
+}
+
+impl<E: Engine> FaultInjector<E> {
+    pub fn new(inner: E, faults: Faults) -> Self {
+        FaultInjector {
+            inner,
+            faults,
+            rng: RefCell::new(fastrand::Rng::new()),
+        }
+    }
+
+    /// Fails with EIO or delays the calling thread, depending on the dice
+    fn before_io(&self) -> io::Result<()> {
+        let mut rng = self.rng.borrow_mut();
+        if rng.f64() < self.faults.eio_probability {
+            return Err(injected_io_error());
+        }
+        if rng.f64() < self.faults.delay_probability {
+            spin_for(self.faults.delay);
+        }
+        Ok(())
+    }
+
+    /// Length of a short transfer: half the buffer, rounded down to a sector
+    fn short_len(&self, len: usize) -> Option<usize> {
+        (self.rng.borrow_mut().f64() < self.faults.short_write_probability)
+            .then_some(len / 2 / 512 * 512)
+    }
+}
+
+impl<E: Engine> Engine for FaultInjector<E> {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.before_io()?;
+        match self.short_len(buf.len()) {
+            Some(len) => self.inner.write_at(&buf[..len], offset),
+            None => self.inner.write_at(buf, offset),
+        }
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.before_io()?;
+        match self.short_len(buf.len()) {
+            Some(len) => self.inner.read_at(&mut buf[..len], offset),
+            None => self.inner.read_at(buf, offset),
+        }
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.before_io()?;
+        self.inner.sync()
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        self.before_io()?;
+        let total = bufs.iter().map(|buf| buf.len()).sum();
+        match self.short_len(total) {
+            Some(mut len) => {
+                let mut short = Vec::with_capacity(bufs.len());
+                for buf in bufs {
+                    let take = len.min(buf.len());
+                    if take == 0 {
+                        break;
+                    }
+                    short.push(&buf[..take]);
+                    len -= take;
+                }
+                self.inner.write_vectored_at(&short, offset)
+            }
+            None => self.inner.write_vectored_at(bufs, offset),
+        }
+    }
+
+    fn write_durable_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        // keeps the write and the fsync of the inner engine linked
+        self.before_io()?;
+        self.inner.write_durable_at(bufs, offset)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        self.before_io()?;
+        self.inner.discard(offset, len)
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) -> bool {
+        self.inner.set_io_priority(ioprio)
+    }
+}
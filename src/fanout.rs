@@ -0,0 +1,103 @@
+//! Tail-at-scale projection for requests that fan out to K parallel IOs.
+//!
+//! A request that waits for K independent IOs takes as long as the slowest of them. If a single IO
+//! stays below x with probability F(x), all K do with F(x)^K, so the p99 of the request is the
+//! single-IO percentile 100 * 0.99^(1/K): p99.9 for K = 10, p99.99 for K = 100. That is the
+//! projection computed from the sampled latencies of every utilization point. With
+//! `--fanout-empirical` the max of K randomly drawn samples is additionally taken many times and
+//! its p99 reported, which needs no percentile beyond the samples and shows how far the
+//! analytical projection is from the data. The samples are those of the summary percentiles, drawn
+//! uniformly with `--sample-rate`, so they weigh the same also with `--sampling-method tail`,
+//! whose additional tail samples only go to the samples file.
+
+use crate::stats::{self, PercentileMethod};
+use serde::Serialize;
+
+/// Number of simulated requests of the empirical projection
+const EMPIRICAL_REQUESTS: usize = 100000;
+
+/// One row of the fan-out file; latencies in nanoseconds
+#[derive(Serialize, Debug, Default)]
+pub struct FanoutProjection {
+    uuid: u128,
+    utilization_iop: f64,
+    fanout: u64,
+    samples: usize,
+    single_io_percentile: f64, // the single-IO percentile that is the p99 of the fan-out
+    projected_p99th: u64,
+    empirical_p99th: Option<u64>, // only with --fanout-empirical
+}
+
+impl FanoutProjection {
+    /// `sorted` are the sampled latencies of a utilization point in ascending order, `method` the
+    /// --percentile-method of the run
+    pub fn create(
+        uuid: u128,
+        utilization_iop: f64,
+        sorted: &[u64],
+        fanout: u64,
+        empirical: bool,
+        method: PercentileMethod,
+    ) -> FanoutProjection {
+        let single_io_percentile = 100.0 * 0.99f64.powf(1.0 / fanout as f64);
+        let empirical_p99th = empirical.then(|| {
+            let mut rng = fastrand::Rng::new();
+            let mut requests: Vec<u64> = (0..EMPIRICAL_REQUESTS)
+                .map(|_| {
+                    (0..fanout)
+                        .map(|_| sorted[rng.usize(0..sorted.len())])
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            requests.sort_unstable();
+            stats::percentile(&requests, 99.0, method)
+        });
+        FanoutProjection {
+            uuid,
+            utilization_iop,
+            fanout,
+            samples: sorted.len(),
+            single_io_percentile,
+            projected_p99th: stats::percentile(sorted, single_io_percentile, method),
+            empirical_p99th,
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "fan-out {:>4}: p99 {:.1}us (single-IO p{:.3}){}",
+            self.fanout,
+            self.projected_p99th as f64 / 1e3,
+            self.single_io_percentile,
+            match self.empirical_p99th {
+                Some(p99) => format!(", empirical {:.1}us", p99 as f64 / 1e3),
+                None => String::new(),
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_fanout_of_one_is_the_p99() {
+        let sorted: Vec<u64> = (1..=1000).collect();
+        for method in [PercentileMethod::Nearest, PercentileMethod::Linear] {
+            let projection = FanoutProjection::create(1, 0.5, &sorted, 1, false, method);
+            assert!((projection.single_io_percentile - 99.0).abs() < 1e-9);
+            assert_eq!(
+                projection.projected_p99th,
+                stats::percentile(&sorted, 99.0, method)
+            );
+        }
+        let projection =
+            FanoutProjection::create(1, 0.5, &sorted, 1, true, PercentileMethod::Nearest);
+        assert_eq!(projection.projected_p99th, 990);
+        // the max of a single draw is a draw, so the empirical p99 is close to it
+        let empirical = projection.empirical_p99th.unwrap();
+        assert!((980..=1000).contains(&empirical), "{}", empirical);
+    }
+}
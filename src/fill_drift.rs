@@ -0,0 +1,104 @@
+//! Growing fill level during a run (`--fill-drift-mb-per-second`).
+//!
+//! The capacity fraction fixes how much of the device holds data, and with it the spare area the
+//! FTL has left. On a real node the used capacity creeps up as data accumulates. With
+//! `--fill-drift-mb-per-second` a background thread writes never-touched blocks above the used
+//! fraction at that bandwidth, sequentially from the end of the used fraction up to
+//! `--fill-drift-limit`, so the effective overprovisioning shrinks while the writer threads run
+//! and the summary rows of consecutive points show how latency drifts with it. The fill level
+//! carries over from one utilization point to the next and starts over when a capacity sweep
+//! discards the device. Blocks only count as never touched if the device was discarded before,
+//! as capacity sweeps do.
+
+use crate::{engine::Engine, histogram::Histogram, RateLimiter, RatePattern, RateSchedule};
+use serde::Serialize;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// Size of every write of the fill thread
+pub const WRITE_BYTES: u64 = 131072;
+
+/// What the fill thread hands back after a utilization point
+pub struct FillResult {
+    pub end_block: u64, // the next block it would have written
+    io_errors: u64,
+    latency: Histogram, // nanoseconds, every write
+}
+
+/// Writes the blocks of `range` sequentially at `mb_per_second` from `start` until the runtime is
+/// over or the range is full
+pub fn run(
+    engine: &dyn Engine,
+    range: Range<u64>,
+    mb_per_second: f64,
+    start: Instant,
+    runtime: Duration,
+) -> FillResult {
+    let block_size = crate::BLOCK_SIZE as u64;
+    let blocks_per_write = WRITE_BYTES / block_size;
+    let buffer = crate::buffer::AlignedBuffer::new(WRITE_BYTES as usize, 13);
+    let iops = mb_per_second * 1e6 / WRITE_BYTES as f64;
+    let schedule = RateSchedule {
+        pattern: RatePattern::Constant,
+        min_rate: iops,
+        max_rate: iops,
+        runtime,
+        period: runtime,
+    };
+    let mut ratelimiter = RateLimiter::new(start, schedule, 1, 0, 1, 1.0, 0.0);
+    let mut block = range.start;
+    let mut io_errors = 0;
+    let mut latency = Histogram::new();
+
+    let mut end_time = start + runtime;
+    while Instant::now() < end_time && block + blocks_per_write <= range.end {
+        let pause = crate::pause::wait_while_paused(false);
+        if !pause.is_zero() {
+            end_time += pause;
+            ratelimiter.skip(pause);
+        }
+        ratelimiter.run(
+            || match engine.write_at(&buffer, block * block_size) {
+                Ok(n) => n as u64 == WRITE_BYTES,
+                Err(_) => {
+                    io_errors += 1;
+                    false
+                }
+            },
+            |nanos, _| latency.record(nanos),
+        );
+        block += blocks_per_write;
+    }
+    FillResult {
+        end_block: block,
+        io_errors,
+        latency,
+    }
+}
+
+/// Summary columns of the fill level; without fill drift it stays at the capacity fraction
+#[derive(Serialize, Debug, Default)]
+pub struct FillStatistics {
+    fill_fraction_begin: f64, // of the device, at the start of the point
+    fill_fraction_end: f64,
+    fill_drift_io_errors: u64,
+    fill_drift_p99th: u64,
+}
+
+impl FillStatistics {
+    pub fn create(
+        device_blocks: u64,
+        begin_block: u64,
+        result: Option<&FillResult>,
+    ) -> FillStatistics {
+        let end_block = result.map_or(begin_block, |r| r.end_block);
+        FillStatistics {
+            fill_fraction_begin: begin_block as f64 / device_blocks as f64,
+            fill_fraction_end: end_block as f64 / device_blocks as f64,
+            fill_drift_io_errors: result.map_or(0, |r| r.io_errors),
+            fill_drift_p99th: result.map_or(0, |r| r.latency.percentile(99.0)),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! `ssd-benchy gc-recovery`: how much idle time the FTL needs to recover from sustained writes.
+//!
+//! For every idle time T the device is first saturated with closed-loop random writes until it
+//! sits at its write cliff, then left idle for T seconds, in which garbage collection can reclaim
+//! free blocks, and then measured with the same random writes. Plotting the measured latency
+//! against T shows how quickly (and whether at all) background GC restores the fresh-out-of-box
+//! performance; the saturated numbers of every row are the floor to compare against.
+
+use crate::{
+    qd_curve::{AccessPattern, Direction, PointSpec},
+    schema,
+};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GcRecoveryArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// The idle times in seconds that are measured, e.g., 0 10 60
+    #[clap(long, env = "SSD_BENCHY_IDLE_SECONDS", value_parser, num_args = 1.., value_delimiter = ' ', default_values_t = vec![0, 1, 5, 10, 30, 60, 300])]
+    idle_seconds: Vec<u64>,
+
+    /// Seconds of random writes that drive the device to its write cliff before every idle period
+    #[clap(long, env = "SSD_BENCHY_SATURATE_SECONDS", default_value_t = 300)]
+    saturate_seconds: u64,
+
+    /// Seconds of random writes measured after every idle period
+    #[clap(long, env = "SSD_BENCHY_MEASURE_SECONDS", default_value_t = 10)]
+    measure_seconds: u64,
+
+    /// Queue depth (threads) of the random writes
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTH", default_value_t = 8)]
+    queue_depth: u64,
+
+    /// Size of every write in bytes; must be a multiple of the logical block size
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 4096)]
+    block_size: usize,
+
+    /// Fraction of the SSD the write offsets are drawn from
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 0.8)]
+    capacity_fraction: f64,
+
+    /// Result file, one row per idle time
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("gc_recovery.csv"))]
+    output_file: String,
+}
+
+/// One row of the gc-recovery result file
+#[derive(Serialize, Debug, Default)]
+struct GcRecoveryPoint {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    block_size: usize,
+    queue_depth: u64,
+    capacity_fraction: f64,
+    saturate_seconds: u64,
+    idle_seconds: u64,
+    measure_seconds: u64,
+    saturated_iops: f64, // whole saturation phase
+    saturated_p99th: u64,
+    iops: f64, // after the idle period
+    mean: f64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+/// One row per idle time, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "gc-recovery",
+        option: "--output-file",
+        columns: schema::columns_of(&GcRecoveryPoint::default()),
+    }]
+}
+
+pub fn run(args: &GcRecoveryArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let blocks = (capacity as f64 * args.capacity_fraction) as u64 / args.block_size as u64;
+    let output = Path::new(&args.output_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&GcRecoveryPoint::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let spec = |runtime_seconds| PointSpec {
+        ssd_device: &args.ssd_device,
+        queue_depth: args.queue_depth,
+        block_size: args.block_size,
+        direction: Direction::Write,
+        pattern: AccessPattern::Random,
+        blocks,
+        runtime: Duration::from_secs(runtime_seconds),
+    };
+    let uuid = Uuid::new_v4().as_u128();
+
+    println!(
+        "{:>8} {:>14} {:>14} {:>12} {:>10} {:>10} {:>10}",
+        "idle[s]", "sat. iops", "sat. p99[us]", "iops", "p50[us]", "p99[us]", "p999[us]"
+    );
+    for &idle_seconds in &args.idle_seconds {
+        let start_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("");
+        let saturated = crate::qd_curve::run_point(&spec(args.saturate_seconds));
+        std::thread::sleep(Duration::from_secs(idle_seconds));
+        let measured = crate::qd_curve::run_point(&spec(args.measure_seconds));
+        let h = &measured.histogram;
+        let point = GcRecoveryPoint {
+            uuid,
+            start_time: start_time.as_secs(),
+            hostname: gethostname().into_string().unwrap(),
+            ssd_device: args.ssd_device.clone(),
+            block_size: args.block_size,
+            queue_depth: args.queue_depth,
+            capacity_fraction: args.capacity_fraction,
+            saturate_seconds: args.saturate_seconds,
+            idle_seconds,
+            measure_seconds: args.measure_seconds,
+            saturated_iops: saturated.operations as f64 / saturated.elapsed.as_secs_f64(),
+            saturated_p99th: saturated.histogram.percentile(99.0),
+            iops: measured.operations as f64 / measured.elapsed.as_secs_f64(),
+            mean: h.mean(),
+            p50th: h.percentile(50.0),
+            p99th: h.percentile(99.0),
+            p999th: h.percentile(99.9),
+            max: h.max(),
+        };
+        println!(
+            "{:>8} {:>14.0} {:>14.1} {:>12.0} {:>10.1} {:>10.1} {:>10.1}",
+            idle_seconds,
+            point.saturated_iops,
+            point.saturated_p99th as f64 / 1e3,
+            point.iops,
+            point.p50th as f64 / 1e3,
+            point.p99th as f64 / 1e3,
+            point.p999th as f64 / 1e3
+        );
+        let mut wtr = schema::csv_appender(output).unwrap();
+        wtr.serialize(&point).unwrap();
+        wtr.flush().unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+//! `ssd-benchy grafana-dashboard`: a Grafana dashboard for the metrics of `--metrics-listen`.
+//!
+//! The dashboard has a Prometheus data source variable and an instance variable (the scrape
+//! targets that run the benchmark), and panels for the write rate against the target rate, the
+//! p50/p99/p99.9 write latency from the latency histogram, errors, and the utilization point that
+//! is running. Import it in Grafana under Dashboards > New > Import.
+
+use crate::metrics;
+use serde_json::{json, Value};
+use std::fs;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GrafanaDashboardArgs {
+    /// Title of the dashboard
+    #[clap(long, env = "SSD_BENCHY_TITLE", default_value_t = String::from("ssd-benchy"))]
+    title: String,
+
+    /// Window of the rates and latency percentiles, a Prometheus duration
+    #[clap(long, env = "SSD_BENCHY_RATE_WINDOW", default_value_t = String::from("$__rate_interval"))]
+    rate_window: String,
+
+    /// Write the dashboard to this file instead of stdout
+    #[clap(long, env = "SSD_BENCHY_OUTPUT", short)]
+    output: Option<String>,
+}
+
+fn target(expr: String, legend: &str, ref_id: &str) -> Value {
+    json!({
+        "datasource": datasource(),
+        "expr": expr,
+        "legendFormat": legend,
+        "refId": ref_id
+    })
+}
+
+fn datasource() -> Value {
+    json!({
+        "type": "prometheus",
+        "uid": "${datasource}"
+    })
+}
+
+/// A time series panel of the 24 column wide grid
+fn panel(
+    id: u64,
+    title: &str,
+    unit: &str,
+    grid: (u64, u64, u64, u64),
+    targets: Vec<Value>,
+) -> Value {
+    let (x, y, w, h) = grid;
+    json!({
+        "id": id,
+        "type": "timeseries",
+        "title": title,
+        "datasource": datasource(),
+        "gridPos": {
+            "x": x,
+            "y": y,
+            "w": w,
+            "h": h
+        },
+        "fieldConfig": {
+            "defaults": {
+                "unit": unit
+            },
+            "overrides": []
+        },
+        "targets": targets
+    })
+}
+
+fn dashboard(args: &GrafanaDashboardArgs) -> Value {
+    let selector = "{instance=~\"$instance\"}";
+    let window = &args.rate_window;
+    let quantile = |q: f64| {
+        format!(
+            "histogram_quantile({}, sum by (le, instance) (rate({}_bucket{}[{}])))",
+            q,
+            metrics::WRITE_LATENCY,
+            selector,
+            window
+        )
+    };
+    let panels = vec![
+        panel(
+            1,
+            "Write IOPS",
+            "iops",
+            (0, 0, 12, 9),
+            vec![
+                target(
+                    format!("rate({}{}[{}])", metrics::WRITES, selector, window),
+                    "achieved {{instance}}",
+                    "A",
+                ),
+                target(
+                    format!("{}{}", metrics::TARGET_IOPS, selector),
+                    "target {{instance}}",
+                    "B",
+                ),
+            ],
+        ),
+        panel(
+            2,
+            "Write latency",
+            "s",
+            (12, 0, 12, 9),
+            vec![
+                target(quantile(0.5), "p50 {{instance}}", "A"),
+                target(quantile(0.99), "p99 {{instance}}", "B"),
+                target(quantile(0.999), "p99.9 {{instance}}", "C"),
+            ],
+        ),
+        panel(
+            3,
+            "Errors per second",
+            "short",
+            (0, 9, 8, 8),
+            vec![target(
+                format!("rate({}{}[{}])", metrics::WRITE_ERRORS, selector, window),
+                "{{instance}}",
+                "A",
+            )],
+        ),
+        panel(
+            4,
+            "Utilization",
+            "percentunit",
+            (8, 9, 8, 8),
+            vec![target(
+                format!("{}{}", metrics::UTILIZATION, selector),
+                "{{instance}}",
+                "A",
+            )],
+        ),
+        panel(
+            5,
+            "Completed utilization points",
+            "short",
+            (16, 9, 8, 8),
+            vec![target(
+                format!("{}{}", metrics::POINTS, selector),
+                "{{instance}}",
+                "A",
+            )],
+        ),
+    ];
+    let variables = vec![
+        json!({
+            "name": "datasource",
+            "label": "Data source",
+            "type": "datasource",
+            "query": "prometheus"
+        }),
+        json!({
+            "name": "instance",
+            "label": "Instance",
+            "type": "query",
+            "datasource": datasource(),
+            "query": format!("label_values({}, instance)", metrics::WRITES),
+            "refresh": 2,
+            "multi": true,
+            "includeAll": true
+        }),
+    ];
+    json!({
+        "title": args.title.as_str(),
+        "tags": ["ssd-benchy"],
+        "timezone": "browser",
+        "schemaVersion": 39,
+        "refresh": "10s",
+        "time": {
+            "from": "now-1h",
+            "to": "now"
+        },
+        "templating": {
+            "list": variables
+        },
+        "panels": panels
+    })
+}
+
+pub fn run(args: &GrafanaDashboardArgs) {
+    let dashboard = format!("{}\n", dashboard(args));
+    match &args.output {
+        Some(path) => fs::write(path, dashboard).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => print!("{}", dashboard),
+    }
+}
@@ -0,0 +1,173 @@
+//! Group commit shared by several writer threads (`--group-commit-writers`).
+//!
+//! `--group-commit-us` alone lets every thread skip the fsyncs of its own writes within the
+//! interval, which is not what a write-ahead log does: there, the writers of many transactions
+//! append to one log, and one of them, the leader, issues the fsync that makes all their writes
+//! durable at once, while the others wait for it. With `--group-commit-writers K` the writer
+//! threads form groups of K that share such a commit. After its write, a thread waits until a
+//! commit covers it; the first thread that finds no commit in flight and at least
+//! `--group-commit-us` passed since the previous one becomes the leader and fsyncs for all writes
+//! of the group so far. The latency of every write therefore includes waiting for its commit, and
+//! the summary reports the commits themselves next to it: their latency (`commit_*`) and the
+//! writes each made durable (`writes_per_commit`).
+
+use crate::histogram::Histogram;
+use std::{
+    io,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A commit a thread issued as the leader
+#[derive(Debug, Clone, Copy)]
+pub struct Commit {
+    pub latency: u64, // of the fsync, in nanoseconds
+    pub writes: u64,  // made durable by it
+}
+
+struct State {
+    submitted: u64, // writes of the group so far
+    durable: u64,   // of them, covered by a completed commit
+    committing: bool,
+    last_commit: Instant,
+}
+
+/// The commit shared by the writer threads of one group
+pub struct GroupCommit {
+    state: Mutex<State>,
+    committed: Condvar,
+    interval: Duration,
+}
+
+impl GroupCommit {
+    pub fn new(interval: Duration) -> Self {
+        GroupCommit {
+            state: Mutex::new(State {
+                submitted: 0,
+                durable: 0,
+                committing: false,
+                last_commit: Instant::now(),
+            }),
+            committed: Condvar::new(),
+            interval,
+        }
+    }
+
+    /// Blocks until a commit covers a write that just completed. Returns the commit if this thread
+    /// issued it with `sync`, and the error of `sync` if that failed; the writes of a failed
+    /// commit wait for the next leader.
+    pub fn commit(&self, sync: impl FnOnce() -> io::Result<()>) -> io::Result<Option<Commit>> {
+        let mut state = self.state.lock().unwrap();
+        state.submitted += 1;
+        let ticket = state.submitted;
+        loop {
+            if state.durable >= ticket {
+                return Ok(None);
+            }
+            let since_commit = state.last_commit.elapsed();
+            if !state.committing && since_commit >= self.interval {
+                state.committing = true;
+                let target = state.submitted;
+                drop(state);
+                let begin = Instant::now();
+                let result = sync();
+                let latency = crate::stats::nanos(begin.elapsed());
+                let mut state = self.state.lock().unwrap();
+                state.committing = false;
+                state.last_commit = Instant::now();
+                let writes = target - state.durable;
+                if result.is_ok() {
+                    state.durable = target;
+                }
+                self.committed.notify_all();
+                return result.map(|()| Some(Commit { latency, writes }));
+            }
+            state = if state.committing {
+                self.committed.wait(state).unwrap()
+            } else {
+                // the leader of the next commit, unless another thread is faster
+                self.committed
+                    .wait_timeout(state, self.interval - since_commit)
+                    .unwrap()
+                    .0
+            };
+        }
+    }
+}
+
+/// The commits one thread issued as the leader
+#[derive(Debug, Clone, Default)]
+pub struct CommitLatencies {
+    pub latency: Histogram,
+    pub writes: u64,
+}
+
+impl CommitLatencies {
+    pub fn record(&mut self, commit: Commit) {
+        self.latency.record(commit.latency);
+        self.writes += commit.writes;
+    }
+
+    pub fn merge(&mut self, other: &CommitLatencies) {
+        self.latency.merge(&other.latency);
+        self.writes += other.writes;
+    }
+
+    /// Mean writes made durable per commit, 0 without commits
+    pub fn writes_per_commit(&self) -> f64 {
+        match self.latency.count() {
+            0 => 0.0,
+            commits => self.writes as f64 / commits as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_commit_covers_the_writes_of_all_threads() {
+        let shared = std::sync::Arc::new(GroupCommit::new(Duration::from_millis(2)));
+        let syncs = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                let syncs = syncs.clone();
+                std::thread::spawn(move || {
+                    let mut commits = CommitLatencies::default();
+                    for _ in 0..50 {
+                        let sync = || {
+                            syncs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            Ok(())
+                        };
+                        if let Some(commit) = shared.commit(sync).unwrap() {
+                            commits.record(commit);
+                        }
+                    }
+                    commits
+                })
+            })
+            .collect();
+        let mut commits = CommitLatencies::default();
+        for thread in threads {
+            commits.merge(&thread.join().unwrap());
+        }
+        assert_eq!(commits.writes, 200);
+        assert_eq!(
+            commits.latency.count(),
+            syncs.load(std::sync::atomic::Ordering::Relaxed)
+        );
+        assert!(commits.writes_per_commit() > 1.0, "{:?}", commits);
+    }
+
+    #[test]
+    fn writes_of_a_failed_commit_wait_for_the_next_one() {
+        let shared = GroupCommit::new(Duration::ZERO);
+        let failed = shared.commit(|| Err(io::Error::other("device gone")));
+        assert_eq!(failed.unwrap_err().to_string(), "device gone");
+        let commit = shared.commit(|| Ok(())).unwrap().unwrap();
+        assert_eq!(commit.writes, 2);
+        assert_eq!(CommitLatencies::default().writes_per_commit(), 0.0);
+    }
+}
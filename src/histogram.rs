@@ -0,0 +1,230 @@
+//! Log-linear latency histogram.
+//!
+//! Values below `2 * SUB_BUCKETS` are counted exactly; above that every power of two is split into
+//! `SUB_BUCKETS` equally sized buckets, bounding the relative error to `1 / SUB_BUCKETS` (< 1%)
+//! while covering the full `u64` range in a fixed ~60 KiB. Used where recording every operation
+//! matters more than keeping individual samples, e.g. closed-loop runs at full device speed.
+
+use crate::stats::RANK_EPSILON;
+
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize =
+    ((64 - SUB_BUCKET_BITS as usize) * SUB_BUCKETS as usize) + SUB_BUCKETS as usize;
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    fn index(value: u64) -> usize {
+        if value < 2 * SUB_BUCKETS {
+            return value as usize;
+        }
+        let exponent = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+        let mantissa = value >> exponent; // in [SUB_BUCKETS, 2 * SUB_BUCKETS)
+        (exponent as u64 * SUB_BUCKETS + mantissa) as usize
+    }
+
+    /// Largest value that maps to bucket `index`
+    fn highest_equivalent(index: usize) -> u64 {
+        let index = index as u64;
+        if index < 2 * SUB_BUCKETS {
+            return index;
+        }
+        let exponent = index / SUB_BUCKETS - 1;
+        let mantissa = index % SUB_BUCKETS + SUB_BUCKETS;
+        ((mantissa + 1) << exponent).wrapping_sub(1)
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.counts[Histogram::index(value)] += 1;
+        self.total += 1;
+        self.sum += value as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Records `value` `count` times, e.g., to rebuild a histogram from its buckets
+    pub fn record_n(&mut self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.counts[Histogram::index(value)] += count;
+        self.total += count;
+        self.sum += value as u128 * count as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Number of recorded values
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> u64 {
+        if self.total == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.total as f64
+        }
+    }
+
+    /// Non-empty buckets in ascending order as (highest value of the bucket, count)
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| (Histogram::highest_equivalent(index), *count))
+    }
+
+    /// Nearest-rank percentile, reported as the highest value of the containing bucket
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((self.total as f64 * percentile / 100.0 - RANK_EPSILON).ceil() as u64)
+            .clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Histogram::highest_equivalent(index).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_below_two_sub_buckets_are_exact() {
+        assert_eq!(Histogram::index(255), 255);
+        assert_eq!(Histogram::highest_equivalent(255), 255);
+        // 256 and 257 share the first bucket of width 2
+        assert_eq!(Histogram::index(256), 256);
+        assert_eq!(Histogram::index(257), 256);
+        assert_eq!(Histogram::index(258), 257);
+        assert_eq!(Histogram::highest_equivalent(256), 257);
+    }
+
+    #[test]
+    fn the_largest_value_has_the_last_bucket() {
+        assert_eq!(Histogram::index(u64::MAX), BUCKETS - 1);
+        assert_eq!(Histogram::highest_equivalent(BUCKETS - 1), u64::MAX);
+        let mut histogram = Histogram::new();
+        histogram.record(u64::MAX);
+        assert_eq!(histogram.percentile(100.0), u64::MAX);
+        assert_eq!(histogram.buckets().collect::<Vec<_>>(), [(u64::MAX, 1)]);
+    }
+
+    #[test]
+    fn every_value_is_at_most_the_highest_of_its_bucket() {
+        let mut value = 1u64;
+        while value < u64::MAX / 3 {
+            for v in [value - 1, value, value + 1] {
+                let index = Histogram::index(v);
+                let highest = Histogram::highest_equivalent(index);
+                assert!(v <= highest, "{} above {}", v, highest);
+                assert_eq!(Histogram::index(highest), index, "{}", v);
+                // the relative error stays below 1 / SUB_BUCKETS
+                assert!(
+                    (highest - v) as f64 <= v as f64 / SUB_BUCKETS as f64,
+                    "{}",
+                    v
+                );
+            }
+            value = value * 3 / 2 + 1;
+        }
+    }
+
+    #[test]
+    fn merge_adds_counts_and_bounds() {
+        let (mut a, mut b) = (Histogram::new(), Histogram::new());
+        for v in [5, 300, 300] {
+            a.record(v);
+        }
+        b.record_n(1_000_000, 2);
+        b.record(1);
+        a.merge(&b);
+        assert_eq!(a.count(), 6);
+        assert_eq!((a.min(), a.max()), (1, 1_000_000));
+        assert_eq!(a.mean(), (5 + 600 + 2_000_000 + 1) as f64 / 6.0);
+        a.merge(&Histogram::new());
+        assert_eq!((a.count(), a.min()), (6, 1));
+    }
+
+    #[test]
+    fn percentile_is_the_nearest_rank() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(50.0), 0);
+        for v in 1..=100 {
+            histogram.record(v);
+        }
+        assert_eq!(histogram.percentile(0.0), 1);
+        assert_eq!(histogram.percentile(50.0), 50);
+        assert_eq!(histogram.percentile(99.0), 99);
+        assert_eq!(histogram.percentile(100.0), 100);
+        // clamped to the recorded range, not the bucket's highest value
+        let mut histogram = Histogram::new();
+        histogram.record(1000);
+        assert_eq!(histogram.percentile(50.0), 1000);
+    }
+
+    #[test]
+    fn percentile_ranks_ignore_floating_point_error() {
+        // 3000 * 1.1 / 100 is an ulp above 33
+        let mut histogram = Histogram::new();
+        for v in 0..3000 {
+            histogram.record(v);
+        }
+        assert_eq!(histogram.percentile(1.1), 32);
+    }
+}
@@ -0,0 +1,118 @@
+//! Shell commands around the phases of a run (`--hook-*`).
+//!
+//! Some measurements need the host or the drive prepared or inspected in a way the tool cannot
+//! know about: a vendor tool that snapshots its own SMART pages, a script that toggles a BIOS or
+//! power setting, a trace that starts before a point and stops after it. The hooks run such
+//! commands with `sh -c` before and after the whole run (`--hook-pre-run`, `--hook-post-run`),
+//! the preinitialization of every capacity fraction (`--hook-pre-preinit`, `--hook-post-preinit`),
+//! and every utilization point (`--hook-pre-point`, `--hook-post-point`). A pre hook that fails
+//! ends the run before what it prepares, as the measurement would not be what was asked for; a
+//! post hook that fails is a warning.
+//!
+//! The standard output and error of every hook go to a log of its own in `--hook-log-dir` (by
+//! default `hooks/` next to the summary file), named after the uuid of its point or the start
+//! time of the run, with the command at the top and its exit status at the end. The hooks get the
+//! context in environment variables: `SSD_BENCHY_HOOK` (e.g., `pre-point`),
+//! `SSD_BENCHY_HOOK_DEVICE`, and `SSD_BENCHY_HOOK_CAPACITY_FRACTION`, and for the points also
+//! `SSD_BENCHY_HOOK_UUID`, `SSD_BENCHY_HOOK_ENGINE`, and `SSD_BENCHY_HOOK_UTILIZATION`. The phases
+//! of a `--scenario` get the point hooks, with the name of the phase in `SSD_BENCHY_HOOK_PHASE`.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreRun,
+    PostRun,
+    PrePreinit,
+    PostPreinit,
+    PrePoint,
+    PostPoint,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PreRun => "pre-run",
+            Hook::PostRun => "post-run",
+            Hook::PrePreinit => "pre-preinit",
+            Hook::PostPreinit => "post-preinit",
+            Hook::PrePoint => "pre-point",
+            Hook::PostPoint => "post-point",
+        }
+    }
+
+    fn is_pre(self) -> bool {
+        matches!(self, Hook::PreRun | Hook::PrePreinit | Hook::PrePoint)
+    }
+}
+
+/// The commands of the hooks and where their logs go
+#[derive(Debug, Default)]
+pub struct Hooks {
+    pub commands: Vec<(Hook, String)>,
+    pub log_dir: PathBuf,
+}
+
+impl Hooks {
+    /// Runs the command of `hook`, if there is one, with `context` in its environment; the log is
+    /// `<label>-<hook>.log`. Exits if a pre hook fails.
+    pub fn run(&self, hook: Hook, label: &str, context: &[(&str, String)]) {
+        let Some((_, command)) = self.commands.iter().find(|(h, _)| *h == hook) else {
+            return;
+        };
+        let log = self.log_dir.join(format!("{}-{}.log", label, hook.name()));
+        println!(
+            "running the {} hook, output in {}",
+            hook.name(),
+            log.display()
+        );
+        match run_command(command, hook, &log, context) {
+            Ok(()) => {}
+            Err(e) if hook.is_pre() => crate::outcome::exit(
+                crate::outcome::Outcome::ConfigError,
+                &format!("the {} hook failed: {}", hook.name(), e),
+            ),
+            Err(e) => println!("warning: the {} hook failed: {}", hook.name(), e),
+        }
+    }
+}
+
+fn run_command(
+    command: &str,
+    hook: Hook,
+    log: &Path,
+    context: &[(&str, String)],
+) -> Result<(), String> {
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let mut file =
+        fs::File::create(log).map_err(|e| format!("Failed to create {}: {}", log.display(), e))?;
+    let write_error = |e: std::io::Error| format!("Failed to write {}: {}", log.display(), e);
+    writeln!(file, "$ {}", command).map_err(write_error)?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("SSD_BENCHY_HOOK", hook.name())
+        .envs(
+            context
+                .iter()
+                .map(|(key, value)| (format!("SSD_BENCHY_HOOK_{}", key), value)),
+        )
+        .stdin(Stdio::null())
+        .stdout(file.try_clone().map_err(write_error)?)
+        .stderr(file.try_clone().map_err(write_error)?)
+        .status()
+        .map_err(|e| format!("Failed to run sh: {}", e))?;
+    writeln!(file, "# {}", status).map_err(write_error)?;
+    if !status.success() {
+        return Err(format!("{}, see {}", status, log.display()));
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+//! Time series of the benchmark host's CPU and memory utilization.
+//!
+//! With `--host-stats-interval-ms` a background thread reads /proc/stat, /proc/softirqs, and
+//! /proc/meminfo at every interval and appends one row per CPU, plus one for all CPUs together,
+//! to `--host-stats-file`. A saturated core, softirq time piling up on the core that completes the
+//! IOs, or a growing dirty page count tell that the host and not the SSD limited the run. Hosts
+//! without /proc produce no rows.
+
+use crate::schema;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// One row of the host stats file; the CPU columns are fractions of the interval
+#[derive(Serialize, Debug, Default)]
+pub struct HostSample {
+    uuid: u128,
+    utilization_iop: f64,
+    elapsed_seconds: f64,
+    cpu: String, // "all" or the number of the core
+    busy: f64,   // neither idle nor waiting for IO
+    user: f64,
+    system: f64,
+    iowait: f64,
+    irq: f64,
+    softirq: f64,
+    block_softirqs_per_second: f64,
+    mem_available_bytes: u64,
+    dirty_bytes: u64,
+    writeback_bytes: u64,
+}
+
+/// Cumulative jiffies of one line of /proc/stat
+#[derive(Debug, Default, Clone, Copy)]
+struct CpuTimes {
+    user: u64, // including nice
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl CpuTimes {
+    fn total(&self) -> u64 {
+        self.user + self.system + self.idle + self.iowait + self.irq + self.softirq + self.steal
+    }
+}
+
+/// Per CPU ("all" and the core numbers): jiffies and BLOCK softirqs so far
+type Snapshot = HashMap<String, (CpuTimes, u64)>;
+
+fn read_snapshot() -> Option<Snapshot> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let mut snapshot = Snapshot::new();
+    for line in stat.lines().filter(|line| line.starts_with("cpu")) {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?;
+        let cpu = match name.strip_prefix("cpu")? {
+            "" => String::from("all"),
+            core => core.to_string(),
+        };
+        let values: Vec<u64> = fields.map(|f| f.parse().unwrap_or(0)).collect();
+        let value = |i: usize| values.get(i).copied().unwrap_or(0);
+        let times = CpuTimes {
+            user: value(0) + value(1),
+            system: value(2),
+            idle: value(3),
+            iowait: value(4),
+            irq: value(5),
+            softirq: value(6),
+            steal: value(7),
+        };
+        snapshot.insert(cpu, (times, 0));
+    }
+    // BLOCK softirqs are raised on the core that completes the IO
+    let softirqs = std::fs::read_to_string("/proc/softirqs").unwrap_or_default();
+    if let Some(line) = softirqs
+        .lines()
+        .find(|l| l.trim_start().starts_with("BLOCK:"))
+    {
+        let counts: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .map(|c| c.parse().unwrap_or(0))
+            .collect();
+        for (core, count) in counts.iter().enumerate() {
+            if let Some(entry) = snapshot.get_mut(&core.to_string()) {
+                entry.1 = *count;
+            }
+        }
+        if let Some(entry) = snapshot.get_mut("all") {
+            entry.1 = counts.iter().sum();
+        }
+    }
+    Some(snapshot)
+}
+
+/// MemAvailable, Dirty, and Writeback in bytes
+fn read_meminfo() -> (u64, u64, u64) {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.trim_start_matches(':').split_whitespace().next())
+            .and_then(|kib| kib.parse::<u64>().ok())
+            .map_or(0, |kib| kib * 1024)
+    };
+    (field("MemAvailable"), field("Dirty"), field("Writeback"))
+}
+
+/// Samples the host in the background until it is stopped
+pub struct HostSampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl HostSampler {
+    pub fn spawn(file: &str, uuid: u128, utilization_iop: f64, interval: Duration) -> HostSampler {
+        let stop = Arc::new(AtomicBool::new(false));
+        let file = file.to_string();
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let Some(mut previous) = read_snapshot() else {
+                    println!("warning: /proc/stat is not readable; no host stats are recorded");
+                    return;
+                };
+                let begin = Instant::now();
+                let mut last = begin;
+                let mut wtr = schema::csv_appender(Path::new(&file)).unwrap();
+                loop {
+                    std::thread::park_timeout(interval.saturating_sub(last.elapsed()));
+                    if stop.load(Ordering::Relaxed) {
+                        break; // the partial last interval is not recorded
+                    }
+                    if last.elapsed() < interval {
+                        continue; // spurious wakeup
+                    }
+                    let Some(current) = read_snapshot() else {
+                        break;
+                    };
+                    let seconds = last.elapsed().as_secs_f64();
+                    last = Instant::now();
+                    let elapsed_seconds = begin.elapsed().as_secs_f64();
+                    let (mem_available_bytes, dirty_bytes, writeback_bytes) = read_meminfo();
+                    let mut cpus: Vec<_> = current.keys().cloned().collect();
+                    // "all" first, then the cores in numeric order
+                    cpus.sort_by_key(|cpu| cpu.parse::<i64>().unwrap_or(-1));
+                    for cpu in cpus {
+                        let (now, block_now) = current[&cpu];
+                        let (before, block_before) =
+                            previous.get(&cpu).copied().unwrap_or_default();
+                        let total = now.total().saturating_sub(before.total()).max(1) as f64;
+                        let fraction =
+                            |now: u64, before: u64| now.saturating_sub(before) as f64 / total;
+                        let idle = fraction(now.idle, before.idle);
+                        let iowait = fraction(now.iowait, before.iowait);
+                        wtr.serialize(HostSample {
+                            uuid,
+                            utilization_iop,
+                            elapsed_seconds,
+                            cpu,
+                            busy: 1.0 - idle - iowait,
+                            user: fraction(now.user, before.user),
+                            system: fraction(now.system, before.system),
+                            iowait,
+                            irq: fraction(now.irq, before.irq),
+                            softirq: fraction(now.softirq, before.softirq),
+                            block_softirqs_per_second: block_now.saturating_sub(block_before)
+                                as f64
+                                / seconds,
+                            mem_available_bytes,
+                            dirty_bytes,
+                            writeback_bytes,
+                        })
+                        .unwrap();
+                    }
+                    wtr.flush().unwrap();
+                    previous = current;
+                }
+            })
+        };
+        HostSampler { stop, handle }
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        self.handle.join().unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Latencies by the idle gap before the write (`--idle-gap-buckets-us`).
+//!
+//! Some drives drop into a power state or flush an internal cache as soon as they see no IO for
+//! a moment, and the first write after even a short pause pays for the wake-up. The percentiles
+//! of all writes hide that. With `--idle-gap-buckets-us 10 100 1000` every writer thread measures,
+//! for each of its writes, the time from the completion of its previous IO to the submission of
+//! the write, and records the latency of the write into the bucket of that gap: below 10us, 10us
+//! to 100us, 100us to 1ms, and 1ms and above. The first write of every thread has no gap and is
+//! not recorded. `--idle-gap-file` gets one row per utilization point and non-empty bucket.
+
+use crate::{histogram::Histogram, stats};
+use serde::Serialize;
+use std::time::Instant;
+
+/// Latency percentiles (in nanoseconds) of the writes after an idle gap within a bucket
+#[derive(Serialize, Debug, Default)]
+pub struct IdleGapBucket {
+    uuid: u128,
+    utilization_iop: f64,
+    gap_from_us: u64,
+    gap_to_us: Option<u64>, // empty for the last bucket
+    operations: u64,
+    mean: f64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+/// The latencies of the writes of a thread by their idle gap
+#[derive(Debug, Clone)]
+pub struct IdleGaps {
+    edges: Vec<u64>, // nanoseconds
+    histograms: Vec<Histogram>,
+    previous_completion: Option<Instant>,
+}
+
+impl IdleGaps {
+    /// Buckets between the increasing `edges_us`, one below the first and one above the last
+    pub fn new(edges_us: &[u64]) -> IdleGaps {
+        IdleGaps {
+            edges: edges_us.iter().map(|edge| edge * 1000).collect(),
+            histograms: vec![Histogram::new(); edges_us.len() + 1],
+            previous_completion: None,
+        }
+    }
+
+    /// Checks that `edges_us` are increasing
+    pub fn validate(edges_us: &[u64]) -> Result<(), String> {
+        if edges_us.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("--idle-gap-buckets-us must be increasing".to_string());
+        }
+        Ok(())
+    }
+
+    /// Records the latency of a write submitted at `submitted` that completed just now
+    pub fn record(&mut self, submitted: Instant, latency: u64) {
+        if let Some(previous) = self.previous_completion {
+            let gap = stats::nanos(submitted.saturating_duration_since(previous));
+            let bucket = self.edges.partition_point(|&edge| edge <= gap);
+            self.histograms[bucket].record(latency);
+        }
+        self.complete();
+    }
+
+    /// Notes the completion of an IO whose latency is not recorded, e.g., a command of --op-mix
+    pub fn complete(&mut self) {
+        self.previous_completion = Some(Instant::now());
+    }
+
+    pub fn merge(&mut self, other: &IdleGaps) {
+        for (histogram, other) in self.histograms.iter_mut().zip(&other.histograms) {
+            histogram.merge(other);
+        }
+    }
+
+    /// The rows of the non-empty buckets
+    pub fn rows(&self, uuid: u128, utilization_iop: f64) -> Vec<IdleGapBucket> {
+        self.histograms
+            .iter()
+            .enumerate()
+            .filter(|(_, histogram)| histogram.count() > 0)
+            .map(|(bucket, histogram)| IdleGapBucket {
+                uuid,
+                utilization_iop,
+                gap_from_us: bucket
+                    .checked_sub(1)
+                    .map_or(0, |below| self.edges[below] / 1000),
+                gap_to_us: self.edges.get(bucket).map(|edge| edge / 1000),
+                operations: histogram.count(),
+                mean: histogram.mean(),
+                p50th: histogram.percentile(50.0),
+                p99th: histogram.percentile(99.0),
+                p999th: histogram.percentile(99.9),
+                max: histogram.max(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_are_bucketed_by_their_idle_gap() {
+        let mut gaps = IdleGaps::new(&[100]);
+        gaps.record(Instant::now(), 1); // the first write has no gap
+        gaps.record(Instant::now() - Duration::from_secs(1), 2_000);
+        gaps.record(Instant::now() + Duration::from_millis(1), 50_000);
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+        for row in gaps.rows(1, 0.5) {
+            wtr.serialize(row).unwrap();
+        }
+        let csv = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        let rows: Vec<_> = csv.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("1,0.5,0,100,1,"), "{}", rows[0]);
+        assert!(rows[0].ends_with(",2000"), "{}", rows[0]);
+        assert!(rows[1].starts_with("1,0.5,100,,1,"), "{}", rows[1]);
+        assert!(IdleGaps::validate(&[10, 10]).is_err());
+        assert!(IdleGaps::validate(&[100, 10]).is_err());
+        assert!(IdleGaps::validate(&[]).is_ok());
+    }
+
+    #[test]
+    fn without_edges_all_writes_share_one_bucket() {
+        let mut gaps = IdleGaps::new(&[]);
+        assert!(gaps.rows(1, 0.5).is_empty());
+        // the first write has no gap; an unrecorded IO ends the gap of the next write
+        gaps.record(Instant::now(), 1_000);
+        gaps.complete();
+        gaps.record(Instant::now(), 2_000);
+        gaps.record(Instant::now(), 3_000);
+        let rows = gaps.rows(1, 0.5);
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].gap_from_us, rows[0].gap_to_us), (0, None));
+        assert_eq!((rows[0].operations, rows[0].max), (2, 3_000));
+    }
+}
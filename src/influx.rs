@@ -0,0 +1,234 @@
+//! InfluxDB line protocol of the live metrics (`--influx-output`).
+//!
+//! Labs that collect with InfluxDB or Telegraf rather than Prometheus get the counters of the
+//! metrics module pushed instead of scraped: every `--influx-interval-ms` one `ssd_benchy` line
+//! with the writes, rate, errors, and latency percentiles of the interval is either sent to the
+//! 1.x write API of `influx://host:port/database` or appended to a file, e.g., one tailed by
+//! Telegraf. The percentiles are the upper bounds of the histogram buckets of the metrics module
+//! (10us to 1s), which is plenty for a live view; the result files have the exact ones.
+
+use crate::metrics::{percentile_us, Metrics, Snapshot};
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+enum Destination {
+    Http { address: String, database: String },
+    File(String),
+}
+
+impl Destination {
+    fn parse(output: &str) -> Result<Destination, String> {
+        let Some(url) = output.strip_prefix("influx://") else {
+            return Ok(Destination::File(output.to_string()));
+        };
+        match url.split_once('/') {
+            Some((address, database)) if !address.is_empty() && !database.is_empty() => {
+                Ok(Destination::Http {
+                    address: address.to_string(),
+                    database: database.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Failed to parse {}: expected influx://host:port/database",
+                output
+            )),
+        }
+    }
+
+    /// Makes sure the file can be written or the server answers its ping
+    fn check(&self) -> Result<(), String> {
+        match self {
+            Destination::File(_) => self.send(""),
+            Destination::Http { address, .. } => request(address, "GET /ping", "text/plain", ""),
+        }
+    }
+
+    fn send(&self, lines: &str) -> Result<(), String> {
+        match self {
+            Destination::File(path) => OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(lines.as_bytes()))
+                .map_err(|e| format!("Failed to write {}: {}", path, e)),
+            Destination::Http { address, database } => request(
+                address,
+                &format!("POST /write?db={}&precision=ns", database),
+                "text/plain",
+                lines,
+            ),
+        }
+    }
+}
+
+/// Sends one HTTP/1.1 request and fails unless the status is 2xx
+pub fn request(
+    address: &str,
+    request_line: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), String> {
+    let stream = TcpStream::connect(address)
+        .map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+    write!(
+        &stream,
+        "{} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        request_line,
+        address,
+        content_type,
+        body.len(),
+        body
+    )
+    .map_err(|e| format!("Failed to send to {}: {}", address, e))?;
+    let mut status = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut status)
+        .map_err(|e| format!("Failed to read the response of {}: {}", address, e))?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!(
+            "{} answered {} with {}",
+            address,
+            request_line,
+            status.trim()
+        )),
+    }
+}
+
+/// Escapes commas, spaces, and equal signs of a tag value
+fn tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+/// The line of the interval between two snapshots, `None` if nothing ran
+fn line(
+    metrics: &Metrics,
+    previous: &Snapshot,
+    current: &Snapshot,
+    seconds: f64,
+) -> Option<String> {
+    let writes = current.writes - previous.writes;
+    if writes == 0 && current.point.is_none() {
+        return None;
+    }
+    let mut line = format!(
+        "ssd_benchy,hostname={},instance_type={}",
+        tag(&metrics.hostname),
+        tag(&metrics.instance_type)
+    );
+    if let Some(point) = &current.point {
+        let _ = write!(
+            line,
+            ",ssd_device={},engine={}",
+            tag(&point.ssd_device),
+            tag(&point.engine)
+        );
+    }
+    let _ = write!(
+        line,
+        " writes={}i,iops={},target_iops={}i,errors={}i",
+        writes,
+        writes as f64 / seconds,
+        current.target_iops,
+        current.write_errors - previous.write_errors
+    );
+    if writes > 0 {
+        let buckets: Vec<u64> = current
+            .buckets
+            .iter()
+            .zip(&previous.buckets)
+            .map(|(now, before)| now - before)
+            .collect();
+        let _ = write!(
+            line,
+            ",latency_mean_us={},p50_us={},p99_us={},p999_us={}",
+            (current.latency_sum_ns - previous.latency_sum_ns) as f64 / writes as f64 / 1e3,
+            percentile_us(&buckets, 50.0),
+            percentile_us(&buckets, 99.0),
+            percentile_us(&buckets, 99.9)
+        );
+    }
+    if let Some(point) = &current.point {
+        let _ = write!(
+            line,
+            ",utilization={},uuid=\"{}\"",
+            point.utilization, point.uuid
+        );
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("")
+        .as_nanos();
+    let _ = writeln!(line, " {}", timestamp);
+    Some(line)
+}
+
+pub struct InfluxWriter {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl InfluxWriter {
+    pub fn spawn(
+        metrics: &'static Metrics,
+        output: &str,
+        interval: Duration,
+    ) -> Result<InfluxWriter, String> {
+        let destination = Destination::parse(output)?;
+        // fail before the benchmark rather than warn every interval
+        destination.check()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut previous = metrics.snapshot();
+                let mut last = Instant::now();
+                let mut failed = false;
+                loop {
+                    std::thread::park_timeout(interval.saturating_sub(last.elapsed()));
+                    let stopping = stop.load(Ordering::Relaxed);
+                    if !stopping && last.elapsed() < interval {
+                        continue; // spurious wakeup
+                    }
+                    let current = metrics.snapshot();
+                    let seconds = last.elapsed().as_secs_f64();
+                    last = Instant::now();
+                    if let Some(line) = line(metrics, &previous, &current, seconds) {
+                        if let Err(e) = destination.send(&line) {
+                            if !failed {
+                                println!("warning: {}; later failures are not reported", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    previous = current;
+                    if stopping {
+                        break;
+                    }
+                }
+            })
+        };
+        Ok(InfluxWriter { stop, handle })
+    }
+
+    /// Sends the partial last interval and stops
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        self.handle.join().unwrap();
+    }
+}
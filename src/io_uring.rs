@@ -0,0 +1,321 @@
+//! io_uring engine on top of the io-uring crate.
+//!
+//! Every writer thread owns one ring and keeps exactly one IO in flight: it submits a single SQE
+//! and waits for its CQE within the same `io_uring_enter` call. That is the synchronous pattern of
+//! the psync engine, so the two differ only in the kernel submission and completion path.
+//!
+//! With `--sqpoll` a kernel thread picks up submissions and the writer spins on the completion
+//! queue, so an IO needs no syscall at all. All rings attach to the poll thread of one anchor ring
+//! (IORING_SETUP_ATTACH_WQ), which `--sqpoll-cpu` pins. `--hipri` sets up the rings with
+//! IORING_SETUP_IOPOLL: completions are polled from the NVMe poll queues instead of raised by
+//! interrupts, which requires poll queues (nvme.poll_queues) and O_DIRECT.
+//!
+//! The io-uring-linked engine makes a write durable with a single submission: the write SQE
+//! carries IOSQE_IO_LINK, so the fdatasync SQE behind it starts once the write completed, and
+//! the thread waits for both CQEs within one `io_uring_enter`. The io-uring engine submits the
+//! fdatasync of `--use-fsync` after the completion of the write instead, and running both
+//! engines compares the two.
+
+use crate::engine::{Engine, IoUringOptions};
+use ::io_uring::{opcode, squeue, types};
+use std::{
+    cell::RefCell,
+    fs::File,
+    io,
+    os::fd::{AsFd, AsRawFd},
+};
+
+/// Milliseconds without submissions after which the poll thread sleeps
+const SQPOLL_IDLE_MS: u32 = 1000;
+const ENTRIES: u32 = 8;
+
+/// Sets up a ring with the flags of `options`, attached to the poll thread of `anchor`
+fn setup(options: &IoUringOptions, anchor: Option<&File>) -> io::Result<::io_uring::IoUring> {
+    let mut builder = ::io_uring::IoUring::builder();
+    if options.hipri {
+        builder.setup_iopoll();
+    }
+    if options.sqpoll {
+        builder.setup_sqpoll(SQPOLL_IDLE_MS);
+        if let Some(cpu) = options.sqpoll_cpu {
+            builder.setup_sqpoll_cpu(cpu);
+        }
+        if let Some(anchor) = anchor {
+            builder.setup_attach_wq(anchor.as_raw_fd());
+        }
+    }
+    builder.build(ENTRIES)
+}
+
+/// A ring that only owns the shared SQ poll thread; the ring lives as long as the returned fd
+pub fn sqpoll_anchor(options: &IoUringOptions) -> io::Result<File> {
+    let ring = setup(options, None)?;
+    Ok(File::from(ring.as_fd().try_clone_to_owned()?))
+}
+
+/// Retries `call` until it is not interrupted by a signal
+fn uninterrupted<T>(mut call: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match call() {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+pub struct IoUring {
+    file: File,
+    ring: RefCell<::io_uring::IoUring>, // only touched by the thread that owns the engine
+    sqpoll: bool,
+    iopoll: bool,
+    ioprio: u16,  // of every read and write
+    linked: bool, // the fdatasync of a durable write is linked to it
+}
+
+impl IoUring {
+    /// Sets up a ring that issues all IO to `file`; `anchor` is the ring from `sqpoll_anchor`,
+    /// `linked` links the fdatasync of every durable write to the write
+    pub fn new(
+        file: File,
+        options: &IoUringOptions,
+        anchor: Option<&File>,
+        linked: bool,
+    ) -> io::Result<IoUring> {
+        let ring = IoUring {
+            ring: RefCell::new(setup(options, anchor)?),
+            sqpoll: options.sqpoll,
+            iopoll: options.hipri,
+            ioprio: 0,
+            linked,
+            file,
+        };
+        if ring.iopoll {
+            // devices without poll queues fail every polled IO; fail once, up front
+            let mut block = crate::buffer::AlignedBuffer::new(crate::BLOCK_SIZE, 0);
+            ring.read_at(&mut block, 0).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!(
+                        "polled IO (--hipri) is not supported by the device: {}",
+                        err
+                    ),
+                )
+            })?;
+        }
+        Ok(ring)
+    }
+
+    fn fd(&self) -> types::Fd {
+        types::Fd(self.file.as_raw_fd())
+    }
+
+    /// Submits `sqe` and waits for its completion
+    fn submit_and_wait(&self, sqe: squeue::Entry) -> io::Result<usize> {
+        let [res] = self.submit_linked([sqe])?;
+        Self::result(res)
+    }
+
+    /// Submits `sqes` at once, each linked to the next, and waits for all their completions;
+    /// returns their results in the order of `sqes`
+    fn submit_linked<const N: usize>(&self, sqes: [squeue::Entry; N]) -> io::Result<[i32; N]> {
+        let mut ring = self.ring.borrow_mut();
+        {
+            let mut queue = ring.submission();
+            for (i, sqe) in sqes.into_iter().enumerate() {
+                let mut sqe = sqe.user_data(i as u64);
+                if i + 1 < N {
+                    sqe = sqe.flags(squeue::Flags::IO_LINK);
+                }
+                // the buffers of the SQE outlive it, we wait for its completion below
+                unsafe { queue.push(&sqe) }
+                    .map_err(|_| io::Error::other("the io_uring submission queue is full"))?;
+            }
+        }
+        let mut results = [0; N];
+        let mut pending = N;
+        loop {
+            if self.sqpoll {
+                // submits nothing, but wakes the poll thread if it went idle; checked on every
+                // spin, so that a missed wakeup delays the writer instead of hanging it
+                uninterrupted(|| ring.submit())?;
+            } else {
+                uninterrupted(|| ring.submit_and_wait(pending))?;
+            }
+            for cqe in ring.completion() {
+                results[cqe.user_data() as usize] = cqe.result();
+                pending -= 1;
+            }
+            if pending == 0 {
+                return Ok(results);
+            }
+            if self.sqpoll {
+                // lets the poll thread run if it shares the CPU with the writer; with IOPOLL the
+                // poll thread also polls the device for completions
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    fn result(res: i32) -> io::Result<usize> {
+        if res < 0 {
+            Err(io::Error::from_raw_os_error(-res))
+        } else {
+            Ok(res as usize)
+        }
+    }
+
+    fn fdatasync(&self) -> squeue::Entry {
+        opcode::Fsync::new(self.fd())
+            .flags(types::FsyncFlags::DATASYNC)
+            .build()
+    }
+}
+
+impl Engine for IoUring {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.submit_and_wait(
+            opcode::Write::new(self.fd(), buf.as_ptr(), buf.len() as u32)
+                .offset(offset)
+                .ioprio(self.ioprio)
+                .build(),
+        )
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.submit_and_wait(
+            opcode::Read::new(self.fd(), buf.as_mut_ptr(), buf.len() as u32)
+                .offset(offset)
+                .ioprio(self.ioprio)
+                .build(),
+        )
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        let iov = crate::engine::iovecs(bufs);
+        self.submit_and_wait(
+            opcode::Writev::new(self.fd(), iov.as_ptr(), iov.len() as u32)
+                .offset(offset)
+                .ioprio(self.ioprio)
+                .build(),
+        )
+    }
+
+    fn write_durable_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        if !self.linked || self.iopoll {
+            // polled rings only accept reads and writes
+            let written = self.write_vectored_at(bufs, offset)?;
+            self.sync()?;
+            return Ok(written);
+        }
+        let iov = crate::engine::iovecs(bufs);
+        let [written, synced] = self.submit_linked([
+            opcode::Writev::new(self.fd(), iov.as_ptr(), iov.len() as u32)
+                .offset(offset)
+                .ioprio(self.ioprio)
+                .build(),
+            self.fdatasync(),
+        ])?;
+        let written = Self::result(written)?;
+        // a short write cancels the fdatasync linked to it
+        if written == bufs.iter().map(|buf| buf.len()).sum::<usize>() {
+            Self::result(synced)?;
+        }
+        Ok(written)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        crate::engine::discard_file(&self.file, offset, len)
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) -> bool {
+        self.ioprio = ioprio;
+        true
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        if self.iopoll {
+            // polled rings only accept reads and writes
+            return self.file.sync_data();
+        }
+        self.submit_and_wait(self.fdatasync()).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    fn linked_write_and_fsync_complete_together() {
+        let path = std::env::temp_dir().join(format!("ssd-benchy-linked-{}", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let ring = match IoUring::new(file, &IoUringOptions::default(), None, true) {
+            Ok(ring) => ring,
+            // e.g., io_uring disabled by seccomp
+            Err(_) => return std::fs::remove_file(&path).unwrap(),
+        };
+        let block = [7u8; BLOCK_SIZE];
+        assert_eq!(
+            ring.write_durable_at(&[&block, &block], 0).unwrap(),
+            2 * BLOCK_SIZE
+        );
+        let mut read = [0u8; BLOCK_SIZE];
+        ring.read_exact_at(&mut read, BLOCK_SIZE as u64).unwrap();
+        assert_eq!(read, block);
+
+        // the write fails and cancels the fdatasync linked to it
+        let read_only = std::fs::File::open(&path).unwrap();
+        let ring = IoUring::new(read_only, &IoUringOptions::default(), None, true).unwrap();
+        assert!(ring.write_durable_at(&[&block], 0).is_err());
+        ring.read_exact_at(&mut read, 0).unwrap();
+        assert_eq!(read, block);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn polled_rings_attach_to_the_anchor() {
+        let path = std::env::temp_dir().join(format!("ssd-benchy-sqpoll-{}", std::process::id()));
+        let open = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .unwrap()
+        };
+        let options = IoUringOptions {
+            sqpoll: true,
+            ..Default::default()
+        };
+        let anchor = match sqpoll_anchor(&options) {
+            Ok(anchor) => anchor,
+            // e.g., SQPOLL needs privileges before Linux 5.11
+            Err(_) => return std::fs::remove_file(&path).unwrap(),
+        };
+        let rings: Vec<_> = (0..2)
+            .map(|_| IoUring::new(open(), &options, Some(&anchor), false).unwrap())
+            .collect();
+        let block = [3u8; BLOCK_SIZE];
+        for (i, ring) in rings.iter().enumerate() {
+            assert_eq!(
+                ring.write_durable_at(&[&block], (i * BLOCK_SIZE) as u64)
+                    .unwrap(),
+                BLOCK_SIZE
+            );
+        }
+        let mut read = [0u8; BLOCK_SIZE];
+        rings[0]
+            .read_exact_at(&mut read, BLOCK_SIZE as u64)
+            .unwrap();
+        assert_eq!(read, block);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
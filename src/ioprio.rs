@@ -0,0 +1,190 @@
+//! IO priority classes of the writer threads (`--io-priorities`).
+//!
+//! Whether host-side prioritization protects latency-critical writes is the question here: with
+//! `--io-priorities rt:0,be:4,idle` the writer threads are assigned the listed classes round robin
+//! and write with them, and one row per class goes to `--priority-stats-file`. Every thread sets
+//! its class with ioprio_set(2), which the psync and pvsync2 engines inherit; the io-uring engine
+//! additionally tags every SQE, so the class also holds with `--sqpoll`, where a kernel thread
+//! submits. Only I/O schedulers act on the classes (mq-deadline and bfq), not `none`, which is
+//! what the benchmark usually runs with, so a warning is printed. Below the scheduler nothing is
+//! prioritized: NVMe has weighted round robin arbitration with urgent, high, medium, and low
+//! submission queues, but the Linux driver creates all its queues with the same priority. The
+//! arbitration weights of the controller are printed for the record.
+
+use crate::histogram::Histogram;
+use serde::Serialize;
+
+const IOPRIO_CLASS_SHIFT: u16 = 13;
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    /// No class set; the kernel derives one from the CPU nice value
+    None,
+    RealTime,
+    BestEffort,
+    Idle,
+}
+
+/// An IO class and its level (0 is the highest, 7 the lowest); the idle class has no levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    pub class: IoClass,
+    pub level: u16,
+}
+
+/// Accepts `none`, `rt:<level>`, `be:<level>`, or `idle`
+pub fn parse(value: &str) -> Result<IoPriority, String> {
+    let (class, level) = match value.split_once(':') {
+        Some((class, level)) => (
+            class,
+            level
+                .parse::<u16>()
+                .ok()
+                .filter(|level| *level < 8)
+                .ok_or_else(|| format!("Failed to parse the level of {}: 0 to 7", value))?,
+        ),
+        None => (value, 0),
+    };
+    let class = match class {
+        "none" => IoClass::None,
+        "rt" => IoClass::RealTime,
+        "be" => IoClass::BestEffort,
+        "idle" => IoClass::Idle,
+        _ => {
+            return Err(format!(
+                "Failed to parse an IO priority from {}: none, rt:<level>, be:<level>, or idle",
+                value
+            ))
+        }
+    };
+    Ok(IoPriority { class, level })
+}
+
+impl IoPriority {
+    /// The value of ioprio_set(2) and of the `ioprio` field of an SQE
+    pub fn value(&self) -> u16 {
+        let class = match self.class {
+            IoClass::None => 0,
+            IoClass::RealTime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        class << IOPRIO_CLASS_SHIFT | self.level
+    }
+}
+
+impl std::fmt::Display for IoPriority {
+    /// The form `parse` accepts
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.class {
+            IoClass::None => f.write_str("none"),
+            IoClass::RealTime => write!(f, "rt:{}", self.level),
+            IoClass::BestEffort => write!(f, "be:{}", self.level),
+            IoClass::Idle => f.write_str("idle"),
+        }
+    }
+}
+
+impl Serialize for IoPriority {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Sets the IO priority of the calling thread; the real-time class needs CAP_SYS_ADMIN
+#[cfg(target_os = "linux")]
+pub fn set_thread(priority: IoPriority) -> Result<(), String> {
+    // a `who` of 0 is the calling thread
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            priority.value() as i32,
+        )
+    };
+    if ret < 0 {
+        return Err(format!(
+            "Failed to set the IO priority {}: {}",
+            priority,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_thread(priority: IoPriority) -> Result<(), String> {
+    Err(format!(
+        "Failed to set the IO priority {}: only supported on Linux",
+        priority
+    ))
+}
+
+/// Warns about what keeps the classes from having an effect on `ssd_device`
+pub fn warn_about_device(ssd_device: &str) {
+    #[cfg(unix)]
+    if let Some(scheduler) = crate::device::sysfs_dir(ssd_device)
+        .ok()
+        .and_then(|dir| crate::preflight::active_scheduler(&dir))
+    {
+        if scheduler == "none" {
+            println!(
+                "warning: the I/O scheduler of /dev/{} is none, which ignores IO priorities; use mq-deadline or bfq",
+                ssd_device
+            );
+        }
+    }
+    if let Ok(arbitration) = crate::nvme::arbitration(ssd_device) {
+        println!(
+            "NVMe arbitration of /dev/{}: burst {}, weights high {}, medium {}, low {} (not used by the Linux driver)",
+            ssd_device,
+            arbitration.burst,
+            arbitration.high_weight,
+            arbitration.medium_weight,
+            arbitration.low_weight
+        );
+    }
+}
+
+/// One row of the priority stats file: the writer threads of one class at one utilization point
+#[derive(Serialize, Debug, Default)]
+pub struct PriorityStatistics {
+    uuid: u128, // of the utilization point
+    utilization_iop: f64,
+    io_priority: String,
+    writer_threads: u64,
+    operations: u64,
+    io_errors: u64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+impl PriorityStatistics {
+    pub fn create_from_histogram(
+        uuid: u128,
+        utilization_iop: f64,
+        priority: IoPriority,
+        writer_threads: u64,
+        operations: u64,
+        io_errors: u64,
+        latency: &Histogram,
+    ) -> PriorityStatistics {
+        PriorityStatistics {
+            uuid,
+            utilization_iop,
+            io_priority: priority.to_string(),
+            writer_threads,
+            operations,
+            io_errors,
+            p50th: latency.percentile(50.0),
+            p99th: latency.percentile(99.0),
+            p999th: latency.percentile(99.9),
+            max: latency.max(),
+        }
+    }
+}
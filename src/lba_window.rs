@@ -0,0 +1,193 @@
+//! The part of `--ssd-device` the benchmark uses (`--capacity-bytes`, `--lba-window`).
+//!
+//! The benchmark writes the whole device as sysfs reports it, which rules out disks that hold
+//! anything else, e.g., a boot partition at their start, and drives whose reported size is not
+//! what should be measured, e.g., a namespace larger than the capacity of the product it stands
+//! in for. `--capacity-bytes` replaces the reported size, and `--lba-window 2048:1953523712`
+//! confines every IO of the benchmark (writer and bulk threads, preinitialization, discards, the
+//! NVMe commands of the op mix) to the sectors from the first to before the second, in the
+//! 512-byte sectors that partition tables and `/sys/block/*/*/start` use, so the boundaries of a
+//! free partition can be copied over. The window is the device for everything else: the capacity
+//! fraction, the regions of the threads, and the fill drift are fractions of it, and the summary
+//! records its start and size. Both have to be aligned to the 4K blocks of the benchmark. The
+//! subcommands with an `--ssd-device` of their own still use the whole device.
+
+use crate::engine::Engine;
+use std::io;
+
+/// The unit of --lba-window
+pub const SECTOR_BYTES: u64 = 512;
+
+/// The part of the device the engines see, in bytes; the default is all of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Window {
+    pub start: u64,
+    pub end: Option<u64>, // the reported size without
+}
+
+/// Accepts `start:end` in sectors, end exclusive
+pub fn parse(value: &str) -> Result<(u64, u64), String> {
+    let error = || {
+        format!(
+            "Failed to parse an LBA window from {}: start:end in 512-byte sectors",
+            value
+        )
+    };
+    let (start, end) = value.split_once(':').ok_or_else(error)?;
+    let start = start.trim().parse::<u64>().map_err(|_| error())?;
+    let end = end.trim().parse::<u64>().map_err(|_| error())?;
+    if start >= end {
+        return Err(format!(
+            "--lba-window {}: the start must be below the end",
+            value
+        ));
+    }
+    Ok((start, end))
+}
+
+impl Window {
+    /// The window of --capacity-bytes and --lba-window; blocks of `block_bytes` must not straddle
+    /// its edges
+    pub fn new(
+        capacity_bytes: Option<u64>,
+        lba_window: Option<(u64, u64)>,
+        block_bytes: u64,
+    ) -> Result<Window, String> {
+        let window = match lba_window {
+            Some((start, end)) => Window {
+                start: start * SECTOR_BYTES,
+                end: Some(end * SECTOR_BYTES),
+            },
+            None => Window {
+                start: 0,
+                end: capacity_bytes,
+            },
+        };
+        if window.start % block_bytes != 0 || window.end.unwrap_or(0) % block_bytes != 0 {
+            return Err(format!(
+                "--capacity-bytes and --lba-window must be multiples of {} bytes ({} sectors)",
+                block_bytes,
+                block_bytes / SECTOR_BYTES
+            ));
+        }
+        if let (Some(capacity), Some(end)) = (capacity_bytes, window.end) {
+            if end > capacity {
+                return Err(format!(
+                    "--lba-window ends at byte {}, beyond --capacity-bytes {}",
+                    end, capacity
+                ));
+            }
+        }
+        Ok(window)
+    }
+
+    pub fn is_whole(&self) -> bool {
+        *self == Window::default()
+    }
+
+    /// The bytes of the window on a device of `device_bytes`
+    pub fn capacity(&self, device_bytes: u64) -> u64 {
+        self.end.unwrap_or(device_bytes).saturating_sub(self.start)
+    }
+
+    /// Checks that the window fits on a device of `device_bytes`
+    pub fn check(&self, name: &str, device_bytes: u64) -> Result<(), String> {
+        match self.end {
+            Some(end) if end > device_bytes => Err(format!(
+                "--capacity-bytes or --lba-window reach byte {}, beyond the {} bytes of {}",
+                end, device_bytes, name
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Moves every IO of an engine `start` bytes into the device
+pub struct Offset<E> {
+    inner: E,
+    start: u64,
+}
+
+impl<E: Engine> Offset<E> {
+    pub fn new(inner: E, start: u64) -> Self {
+        Offset { inner, start }
+    }
+}
+
+impl<E: Engine> Engine for Offset<E> {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.inner.write_at(buf, self.start + offset)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.inner.read_at(buf, self.start + offset)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.inner.sync()
+    }
+
+    fn write_vectored_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        self.inner.write_vectored_at(bufs, self.start + offset)
+    }
+
+    fn write_durable_at(&self, bufs: &[&[u8]], offset: u64) -> io::Result<usize> {
+        self.inner.write_durable_at(bufs, self.start + offset)
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        self.inner.discard(self.start + offset, len)
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) -> bool {
+        self.inner.set_io_priority(ioprio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lba_windows_move_the_io_into_the_window() {
+        assert_eq!(parse("2048:4096"), Ok((2048, 4096)));
+        assert!(parse("4096:2048").is_err());
+        assert!(parse("2048").is_err());
+        assert!(parse("2048:2048").is_err());
+        assert!(parse("-1:2048").is_err());
+        assert!(parse(":2048").is_err());
+        assert_eq!(parse(" 0 : 8 "), Ok((0, 8)));
+        let window = Window::new(Some(1 << 20), Some((8, 1024)), 4096).unwrap();
+        assert_eq!(window.start, 4096);
+        assert_eq!(window.capacity(1 << 30), 1024 * 512 - 4096);
+        assert!(window.check("nvme0n1", 1 << 20).is_ok());
+        assert!(window.check("nvme0n1", 4096).is_err());
+        assert!(Window::new(None, Some((1, 1024)), 4096).is_err());
+        assert!(Window::new(Some(1 << 19), Some((8, 2048)), 4096).is_err());
+        let capacity_only = Window::new(Some(1 << 20), None, 4096).unwrap();
+        assert_eq!(capacity_only.capacity(1 << 30), 1 << 20);
+        assert!(Window::new(Some(1000), None, 4096).is_err());
+        let whole = Window::new(None, None, 4096).unwrap();
+        assert!(whole.is_whole());
+        assert!(whole.check("nvme0n1", 0).is_ok());
+        assert_eq!(whole.capacity(1 << 30), 1 << 30);
+
+        let path = std::env::temp_dir().join(format!("ssd-benchy-window-{}", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let windowed = Offset::new(file, 4096);
+        assert_eq!(windowed.write_at(&[7; 512], 512).unwrap(), 512);
+        let mut block = [0; 512];
+        windowed.read_exact_at(&mut block, 512).unwrap();
+        assert_eq!(block, [7; 512]);
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 4096 + 1024);
+        assert!(contents[..4608].iter().all(|&b| b == 0));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
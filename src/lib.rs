@@ -0,0 +1,6 @@
+//! The parts of ssd-benchy that other tools and tests build on: how threads partition the device,
+//! how latency percentiles are computed, and the latency histogram. The benchmark itself is the
+//! binary (main.rs).
+
+pub mod histogram;
+pub mod stats;
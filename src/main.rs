@@ -6,6 +6,10 @@ It allows you to configure multiple parameters to simulate real-world workloads
 
 ## Write Pattern
 Each thread writes to its designated region sequentially until it wraps around. The size of these regions is determined based on the `preinitialized_fraction`.
+With `--iovcnt N` every write covers N consecutive blocks gathered from N separate buffers (pwritev); a region then wraps around after its last whole write.
+
+## Sampling
+The latency percentiles of the summary and the samples file are computed from a sample of the writes: by default every write is sampled independently with probability `--sample-rate` (0.2%), `--sampling-method systematic` takes every (1 / rate)-th write of a thread instead. The summary records the rate, the method, and the seed (`--sample-seed`, random otherwise), so the same seed reproduces which writes are sampled.
 
 ## Usage
 To use this tool, you can specify the parameters via command-line arguments. Here is an example:
@@ -13,195 +17,1589 @@ To use this tool, you can specify the parameters via command-line arguments. Her
 ```sh
 ssd-benchy --ssd-device nvme1n1 --max-iops 200000 --utilization-iops 0.5 0.6 0.7 --serialize-samples  --runtime-seconds=300 --instance-type i3en.3xlarge --use-fsync
 ```
+
+Every flag can also be set with an environment variable named after it, e.g., `SSD_BENCHY_MAX_IOPS=200000`. The subcommands (`ssd-benchy help` lists them) check the device, run other tests on it, or analyze result files instead of running the latency sweep.
+
+The exit code tells the outcome of a run: 0 on success, 1 for configuration errors (2 for usage errors of the command line), 3 if the device cannot be found, opened, or written before any utilization point completed, 4 if `--verify` found corrupted blocks, 5 for a partial run that failed after some points completed (their results are written), and 6 if the benchmark itself failed (a panic, i.e., a bug to report). The last line on stdout is the outcome as single-line JSON.
+
+README.md walks through the features by topic, and every module documents its own.
 */
 
+mod aggregate;
+mod anonymize;
+mod boundary;
+mod buffer;
+mod bulk;
+mod cgroup;
+mod checkpoint;
+mod clock;
+mod compare;
+mod control;
+mod crash;
+#[cfg(unix)]
+mod device;
+#[cfg(target_os = "linux")]
+mod dm_harness;
+mod drift;
+mod energy;
+mod engine;
+mod fanout;
+mod fill_drift;
+mod gc_recovery;
+mod grafana;
+mod group_commit;
+mod hooks;
+mod host_stats;
+mod idle_gap;
+mod influx;
+#[cfg(target_os = "linux")]
+mod io_uring;
+mod ioprio;
+mod lba_window;
+mod matrix;
+mod memory;
+mod merge;
+mod metrics;
+mod namespaces;
+mod nvme;
+mod nvme_ops;
+mod outcome;
+mod outliers;
+mod pause;
+mod pi;
+mod plot;
+#[cfg(unix)]
+mod preflight;
+#[cfg(target_os = "linux")]
+mod privileges;
+mod profiles;
+mod progress;
+mod pts;
+mod qd_curve;
+mod qlc_folding;
+mod quick;
+mod read_disturb;
+mod report;
+mod sample_writer;
+mod scenario;
+mod sched_stats;
+mod schema;
+mod seekable;
+mod slc_cache;
+mod soak;
+#[cfg(feature = "spdk")]
+mod spdk;
+mod stability;
+mod staging;
+mod summary_flush;
+mod tail_sampling;
+mod telemetry;
+mod thermal;
+mod thread_groups;
+mod thread_profile;
+mod transaction;
+mod trim_freshness;
+mod verify;
+#[cfg(windows)]
+mod windows_io;
+
 use gethostname::gethostname;
-use libc::{O_DIRECT, O_RDWR};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use ssd_benchy::{
+    histogram,
+    stats::{self, partition, PercentileMethod, SummaryStatistics},
+};
 use std::{
     arch::x86_64::_mm_pause,
-    fs::{self, OpenOptions},
+    fs,
     ops::Range,
-    os::unix::fs::{FileExt, OpenOptionsExt},
     path::Path,
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime},
 };
 use uuid::Uuid;
 
-use clap::Parser;
-#[derive(Parser, Debug, Clone, Serialize)]
+use clap::{CommandFactory, FromArgMatches};
+#[derive(clap::Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand the latency benchmark is run
+    #[clap(flatten)]
+    benchmark: Option<CliConfig>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+    /// Mean and median of the runs of every configuration (config_hash) of summary files
+    Aggregate(aggregate::AggregateArgs),
+    /// Copy result files with host names, serial numbers, and labels replaced by pseudonyms
+    Anonymize(anonymize::AnonymizeArgs),
+    /// Print the statistics of every utilization point of a --checkpoint-file, also of a crashed run,
+    /// or of a time window of a zstd-seekable --samples-file
+    Analyze(checkpoint::AnalyzeArgs),
+    /// Test whether the latencies of two samples files differ (Kolmogorov–Smirnov)
+    Compare(compare::CompareArgs),
+    /// Run the benchmark on a dm-delay or dm-flakey target over a loop device and check the results
+    #[cfg(target_os = "linux")]
+    DmHarness(dm_harness::DmHarnessArgs),
+    /// Draw an SVG or PNG chart of a result file: a line chart, a CDF, or a time series
+    Plot(plot::PlotArgs),
+    /// Check permissions, O_DIRECT, alignment, capacity, mounts, scheduler, and governor of a device
+    #[cfg(unix)]
+    Preflight(preflight::PreflightArgs),
+    /// Sweep the queue depth at a fixed block size and report the latency/IOPS curve
+    QdCurve(qd_curve::QdCurveArgs),
+    /// Saturate the device, idle for T seconds, and measure again, for every T: how fast GC recovers
+    GcRecovery(gc_recovery::GcRecoveryArgs),
+    /// Print a Grafana dashboard for the Prometheus metrics of --metrics-listen
+    GrafanaDashboard(grafana::GrafanaDashboardArgs),
+    /// Pivot the p99 of summary files of several devices into a device × utilization table, ranked
+    Matrix(matrix::MatrixArgs),
+    /// Merge result files with the same header into one, dropping runs that appear twice
+    Merge(merge::MergeArgs),
+    /// Alternate SLC-cache-filling write bursts with sustained mid-rate writes, as QLC drives see
+    QlcFolding(qlc_folding::QlcFoldingArgs),
+    /// 4K random-read quick test: IOPS at a high queue depth and latency at queue depth 1
+    Quick(quick::QuickArgs),
+    /// Read a narrow LBA range for a long time and sample latency and SMART media errors
+    ReadDisturb(read_disturb::ReadDisturbArgs),
+    /// Print a results table and the key findings (knee, max stable utilization) of a summary file
+    Report(report::ReportArgs),
+    /// Print the columns and types of every result file of this version
+    Schema(schema::SchemaArgs),
+    /// Write sequentially at full bandwidth and find where the SLC write cache runs out
+    SlcCache(slc_cache::SlcCacheArgs),
+    /// Compare write latency into freshly trimmed LBAs with overwrites of LBAs that were never trimmed
+    TrimFreshness(trim_freshness::TrimFreshnessArgs),
+    /// Scan the device for torn, reordered, or lost writes of a run with --crash-records
+    VerifyAfterCrash(crash::VerifyAfterCrashArgs),
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
 struct CliConfig {
     /// instance type
-    #[clap(long, required = true)]
+    #[clap(long, env = "SSD_BENCHY_INSTANCE_TYPE", required = true, default_value_t = String::new(), hide_default_value = true)]
     instance_type: String,
 
     /// The number of writer threads; must be large enough
-    #[clap(long, default_value_t = 10)]
+    #[clap(long, env = "SSD_BENCHY_WRITER_THREADS", default_value_t = 10)]
     writer_threads: u64,
 
     /// preinitialize the capacity first
-    #[clap(long, default_value_t = false)]
+    #[clap(long, env = "SSD_BENCHY_PREINITIALIZE", default_value_t = false)]
     preinitialize: bool,
 
-    /// Fraction of the SSD that is being used., 0.8 means 80% (important for benchmarks)
-    #[clap(long, default_value_t = 0.8)]
-    capacity_fraction: f64,
+    /// Fraction of the SSD that is being used., 0.8 means 80% (important for benchmarks). Several
+    /// values sweep the overprovisioning, e.g., 0.5 0.7 0.9 1.0: before each, the whole device is
+    /// discarded and the used fraction preinitialized again
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", value_parser, num_args = 1.., value_delimiter = ' ', default_values_t = vec![0.8])]
+    capacity_fraction: Vec<f64>,
+
+    /// Give every writer thread another region of the used capacity in every utilization point,
+    /// a random permutation seeded with --sample-seed, so no result depends on which physical
+    /// region a thread owned for the whole run
+    #[clap(long, env = "SSD_BENCHY_SHUFFLE_REGIONS", default_value_t = false)]
+    shuffle_regions: bool,
 
     /// The maximum specified IOPS of this device (based on the spec)
-    #[clap(long)]
+    #[clap(
+        long,
+        env = "SSD_BENCHY_MAX_IOPS",
+        required = true,
+        default_value_t = 0,
+        hide_default_value = true
+    )]
     max_iops: u64,
 
     /// The utilization levels at which the benchmark is performed, e.g., 0.6 0.7
-    #[clap(long, value_parser, num_args = 1.., value_delimiter = ' ', required = true)]
+    #[clap(long, env = "SSD_BENCHY_UTILIZATION_IOPS", value_parser, num_args = 1.., value_delimiter = ' ', required = true)]
     utilization_iops: Vec<f64>,
 
     /// Use fsync after every write
-    #[clap(long, default_value_t = false)]
+    #[clap(long, env = "SSD_BENCHY_USE_FSYNC", default_value_t = false)]
     use_fsync: bool,
 
+    /// With --use-fsync, commit in groups: fsync after the first write at least this many
+    /// microseconds after the previous fsync instead of after every write (0 = every write)
+    #[clap(
+        long,
+        env = "SSD_BENCHY_GROUP_COMMIT_US",
+        default_value_t = 0,
+        requires = "use_fsync"
+    )]
+    group_commit_us: u64,
+
+    /// With --use-fsync, let groups of this many writer threads share their commits: one thread
+    /// fsyncs for the writes of its group at most every --group-commit-us, the others wait for it
+    /// (0 = every thread commits on its own)
+    #[clap(
+        long,
+        env = "SSD_BENCHY_GROUP_COMMIT_WRITERS",
+        default_value_t = 0,
+        requires = "use_fsync"
+    )]
+    group_commit_writers: u64,
+
+    /// overwrite: every thread writes its region sequentially and wraps around; log: appends to a
+    /// log of --log-segments segments and discards (TRIMs) the oldest segment before reusing it;
+    /// transaction: every write is a transaction of --transaction-blocks data writes, a flush
+    /// barrier, and a durable commit record
+    #[clap(long, env = "SSD_BENCHY_WORKLOAD", value_enum, default_value_t = Workload::Overwrite)]
+    workload: Workload,
+
+    /// Number of segments the log of every thread consists of, for --workload log
+    #[clap(long, env = "SSD_BENCHY_LOG_SEGMENTS", default_value_t = 8, value_parser = clap::value_parser!(u64).range(2..))]
+    log_segments: u64,
+
+    /// Data writes of every transaction before its commit record, for --workload transaction
+    #[clap(long, env = "SSD_BENCHY_TRANSACTION_BLOCKS", default_value_t = 4, value_parser = clap::value_parser!(u64).range(1..))]
+    transaction_blocks: u64,
+
+    /// Threads of a second, bulk traffic class running next to the writer threads, which then
+    /// are the latency-critical class; the bulk latencies are reported in the bulk_* columns
+    #[clap(long, env = "SSD_BENCHY_BULK_THREADS", default_value_t = 0)]
+    bulk_threads: u64,
+
+    /// Size of every bulk write; a multiple of 4096
+    #[clap(long, env = "SSD_BENCHY_BULK_WRITE_BYTES", default_value_t = 1048576)]
+    bulk_write_bytes: u64,
+
+    /// Bandwidth of all bulk threads together in MB/s; 0 writes as fast as the device allows
+    #[clap(long, env = "SSD_BENCHY_BULK_MB_PER_SECOND", default_value_t = 0.0)]
+    bulk_mb_per_second: f64,
+
+    /// Grow the used capacity during the run: a background thread writes never-touched blocks
+    /// above the capacity fraction at this bandwidth in MB/s; 0 disables it
+    #[clap(
+        long,
+        env = "SSD_BENCHY_FILL_DRIFT_MB_PER_SECOND",
+        default_value_t = 0.0
+    )]
+    fill_drift_mb_per_second: f64,
+
+    /// Fraction of the device up to which --fill-drift-mb-per-second fills it
+    #[clap(long, env = "SSD_BENCHY_FILL_DRIFT_LIMIT", default_value_t = 1.0)]
+    fill_drift_limit: f64,
+
+    /// Fraction of the writer threads' operations issued as NVMe Write Zeroes commands on the
+    /// blocks of the write instead, e.g., 0.1
+    #[clap(long, env = "SSD_BENCHY_WRITE_ZEROES_FRACTION", default_value_t = 0.0)]
+    write_zeroes_fraction: f64,
+
+    /// Fraction of the writer threads' operations issued as NVMe Dataset Management (deallocate)
+    /// commands on the blocks of the write instead, e.g., 0.1
+    #[clap(long, env = "SSD_BENCHY_DEALLOCATE_FRACTION", default_value_t = 0.0)]
+    deallocate_fraction: f64,
+
+    /// Fraction of the writer threads' operations issued as NVMe Simple Copy commands into the
+    /// blocks of the write instead, every other one as the equivalent reads and write, e.g., 0.1
+    #[clap(long, env = "SSD_BENCHY_COPY_FRACTION", default_value_t = 0.0)]
+    copy_fraction: f64,
+
+    /// Source ranges of every copy; the write size is split evenly between them
+    #[clap(long, env = "SSD_BENCHY_COPY_RANGES", default_value_t = 1)]
+    copy_ranges: u64,
+
     /// serialize the full sample vector
-    #[clap(long, default_value_t = false)]
+    #[clap(long, env = "SSD_BENCHY_SERIALIZE_SAMPLES", default_value_t = false)]
     serialize_samples: bool,
 
-    /// Forces the threads to write roughly at the same time creating a micro spike but still
-    /// maintains the rate
-    #[clap(long, default_value_t = false)]
-    spiky: bool,
+    /// Write samples into one file per writer thread (next to the samples file, tied together by
+    /// a json manifest) instead of one shared samples file
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_PER_THREAD", default_value_t = false)]
+    samples_per_thread: bool,
 
-    /// Name of the SSD device, e.g., /dev/md0; must be the real name of the block device and not an alias
-    #[clap(long)]
-    ssd_device: String,
+    /// Write result files also to a file system on the disk under test, which perturbs the
+    /// measurement; refused otherwise
+    #[clap(long, env = "SSD_BENCHY_ALLOW_SAME_DEVICE", default_value_t = false)]
+    allow_same_device: bool,
+
+    /// Write the result files below this directory, e.g., in /dev/shm, during the run and copy
+    /// them to their paths when it ends, so writing results does not interfere with the measurement
+    #[clap(long, env = "SSD_BENCHY_STAGING_DIR")]
+    staging_dir: Option<String>,
+
+    /// Refuse to run unless the serial number of --ssd-device is this one, so that a run in a
+    /// chassis of several drives does not overwrite the wrong one
+    #[clap(long, env = "SSD_BENCHY_EXPECT_SERIAL")]
+    expect_serial: Option<String>,
+
+    /// A preset of flags, the TOML file <name>.toml in --profile-dir. The built-in snia-iops and
+    /// snia-latency run the SNIA PTS-E IOPS or latency test on the whole --ssd-device instead of
+    /// the latency sweep, with its purge, preconditioning, and steady state rounds
+    #[clap(long, env = "SSD_BENCHY_PROFILE")]
+    profile: Option<String>,
+
+    /// Directory of the --profile presets [default: $XDG_CONFIG_HOME/ssd-benchy/profiles]
+    #[clap(long, env = "SSD_BENCHY_PROFILE_DIR")]
+    profile_dir: Option<String>,
+
+    /// With --profile, result file with a row per combination of block size and read/write mix
+    /// of every round
+    #[clap(long, env = "SSD_BENCHY_PTS_FILE", default_value_t = String::from("pts.csv"))]
+    pts_file: String,
+
+    /// With --profile, runtime of every combination of a round; the PTS asks for 60
+    #[clap(long, env = "SSD_BENCHY_PTS_SECONDS", default_value_t = 60)]
+    pts_seconds: u64,
+
+    /// With --profile, the rounds end without steady state after this many; the PTS asks for 25
+    #[clap(long, env = "SSD_BENCHY_PTS_MAX_ROUNDS", default_value_t = 25)]
+    pts_max_rounds: usize,
+
+    /// Upper bound of the samples written per utilization point; a point that would write more
+    /// keeps a uniform random subset of its samples in memory and writes it at the end. 0 for
+    /// no bound
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_MAX_ROWS", default_value_t = 0)]
+    samples_max_rows: u64,
+
+    /// Memory the writer threads may keep samples in, in MiB; a thread that exceeds its share
+    /// flushes held-back samples and halves its sample rate instead of growing further. 0 for no
+    /// bound
+    #[clap(long, env = "SSD_BENCHY_MEMORY_BUDGET_MB", default_value_t = 0)]
+    memory_budget_mb: u64,
+
+    /// Format of the samples: csv, or zstd-seekable for a compressed file per utilization point
+    /// (<samples file>.<uuid>.zst) with a time index, see analyze --samples-file; zstd-seekable
+    /// needs a build with --features zstd
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_FORMAT", value_enum, default_value_t = sample_writer::SamplesFormat::Csv)]
+    samples_format: sample_writer::SamplesFormat,
+
+    /// Time span of every compressed frame of a zstd-seekable samples file, the granularity of
+    /// its index
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_FRAME_MS", default_value_t = 1000)]
+    samples_frame_ms: u64,
+
+    /// Fraction of the writes that are sampled into the latency percentiles and the samples file
+    #[clap(long, env = "SSD_BENCHY_SAMPLE_RATE", default_value_t = 0.002)]
+    sample_rate: f64,
+
+    /// bernoulli samples every write independently with --sample-rate; systematic samples every
+    /// (1 / rate)-th write of a thread, starting at a random offset; tail samples like bernoulli
+    /// and also writes every write above --tail-percentile to the samples file
+    #[clap(long, env = "SSD_BENCHY_SAMPLING_METHOD", value_enum, default_value_t = SamplingMethod::Bernoulli)]
+    sampling_method: SamplingMethod,
+
+    /// Percentile of the write latencies of a thread above which --sampling-method tail writes
+    /// every write to the samples file
+    #[clap(long, env = "SSD_BENCHY_TAIL_PERCENTILE", default_value_t = 99.0)]
+    tail_percentile: f64,
+
+    /// Seed of the sampling decisions, for reproducible samples; drawn at random (and recorded in
+    /// the summary) otherwise
+    #[clap(long, env = "SSD_BENCHY_SAMPLE_SEED")]
+    sample_seed: Option<u64>,
+
+    /// How the target rate evolves during each utilization point: constant at the utilization, a
+    /// linear ramp from --rate-min-utilization up to it, or a sine wave between both
+    #[clap(long, env = "SSD_BENCHY_RATE_PATTERN", value_enum, default_value_t = RatePattern::Constant)]
+    rate_pattern: RatePattern,
+
+    /// What a writer thread that fell behind its schedule does with the writes it missed: issue
+    /// them back to back with latencies counted from when they were due, skip them, or skip them
+    /// and record the latencies they would have had (coordinated omission correction)
+    #[clap(long, env = "SSD_BENCHY_CATCH_UP", value_enum, default_value_t = CatchUp::Burst)]
+    catch_up: CatchUp,
+
+    /// Lower end of the ramp and sine patterns as a fraction of max_iops; above 0 and at most the
+    /// lowest utilization
+    #[clap(long, env = "SSD_BENCHY_RATE_MIN_UTILIZATION", default_value_t = 0.05)]
+    rate_min_utilization: f64,
+
+    /// Period of the sine pattern in seconds
+    #[clap(long, env = "SSD_BENCHY_RATE_PERIOD_SECONDS", default_value_t = 60)]
+    rate_period_seconds: u64,
+
+    /// Number of equally wide utilization buckets the latencies of ramp and sine patterns are
+    /// grouped into
+    #[clap(long, env = "SSD_BENCHY_RATE_BUCKETS", default_value_t = 10)]
+    rate_buckets: usize,
+
+    /// Result file for the per-bucket percentiles of ramp and sine patterns
+    #[clap(long, env = "SSD_BENCHY_RATE_BUCKETS_FILE", default_value_t = String::from("rate_buckets_file.csv"))]
+    rate_buckets_file: String,
+
+    /// Record the latency and the inter-completion time (across all threads) of every operation in
+    /// histograms and export them, e.g., to fit queueing models
+    #[clap(long, env = "SSD_BENCHY_EXPORT_HISTOGRAMS", default_value_t = false)]
+    export_histograms: bool,
+
+    /// Result file for --export-histograms, one row per non-empty bucket
+    #[clap(long, env = "SSD_BENCHY_HISTOGRAM_FILE", default_value_t = String::from("histogram_file.csv"))]
+    histogram_file: String,
+
+    /// Split the device into this many equally sized LBA slices and report the latency
+    /// percentiles of every slice, e.g., 16; 0 disables per-slice statistics
+    #[clap(long, env = "SSD_BENCHY_LBA_SLICES", default_value_t = 0)]
+    lba_slices: u64,
+
+    /// Result file for --lba-slices, one row per slice that was written
+    #[clap(long, env = "SSD_BENCHY_LBA_SLICES_FILE", default_value_t = String::from("lba_slices_file.csv"))]
+    lba_slices_file: String,
+
+    /// Report the latency percentiles of the writes by the idle gap of their thread before them,
+    /// in buckets between these microseconds, e.g., 10 100 1000
+    #[clap(long, env = "SSD_BENCHY_IDLE_GAP_BUCKETS_US", value_parser, num_args = 1.., value_delimiter = ' ')]
+    idle_gap_buckets_us: Vec<u64>,
+
+    /// Result file for --idle-gap-buckets-us, one row per bucket that has writes
+    #[clap(long, env = "SSD_BENCHY_IDLE_GAP_FILE", default_value_t = String::from("idle_gap_file.csv"))]
+    idle_gap_file: String,
+
+    /// Project the p99 of requests that fan out to this many parallel writes from the sampled
+    /// latencies, e.g., 4 16 64, into --fanout-file
+    #[clap(long, env = "SSD_BENCHY_FANOUTS", value_parser, num_args = 1.., value_delimiter = ' ')]
+    fanouts: Vec<u64>,
+
+    /// Also project the fan-outs empirically, from the max of randomly drawn samples
+    #[clap(long, env = "SSD_BENCHY_FANOUT_EMPIRICAL", default_value_t = false)]
+    fanout_empirical: bool,
+
+    /// Result file for --fanouts, one row per fan-out and utilization point
+    #[clap(long, env = "SSD_BENCHY_FANOUT_FILE", default_value_t = String::from("fanout_file.csv"))]
+    fanout_file: String,
+
+    /// Record every write slower than this many microseconds, regardless of sampling, with the
+    /// latencies of the preceding writes and the writes in flight into --outliers-file; 0 disables it
+    #[clap(long, env = "SSD_BENCHY_OUTLIER_THRESHOLD_US", default_value_t = 0)]
+    outlier_threshold_us: u64,
+
+    /// Number of preceding writes of the same thread recorded with every outlier
+    #[clap(long, env = "SSD_BENCHY_OUTLIER_CONTEXT", default_value_t = 16)]
+    outlier_context: usize,
+
+    /// Result file for --outlier-threshold-us, one row per outlier
+    #[clap(long, env = "SSD_BENCHY_OUTLIERS_FILE", default_value_t = String::from("outliers_file.csv"))]
+    outliers_file: String,
+
+    /// Capture the NVMe SMART, error, and telemetry logs into --telemetry-dir when a write is
+    /// slower than this many microseconds; 0 disables it
+    #[clap(long, env = "SSD_BENCHY_TELEMETRY_THRESHOLD_US", default_value_t = 0)]
+    telemetry_threshold_us: u64,
+
+    /// Directory for --telemetry-threshold-us, one subdirectory per capture
+    #[clap(long, env = "SSD_BENCHY_TELEMETRY_DIR", default_value_t = String::from("telemetry"))]
+    telemetry_dir: String,
+
+    /// Minimum time between two captures of --telemetry-threshold-us
+    #[clap(
+        long,
+        env = "SSD_BENCHY_TELEMETRY_COOLDOWN_SECONDS",
+        default_value_t = 300
+    )]
+    telemetry_cooldown_seconds: u64,
+
+    /// Also write these namespaces of the --ssd-device's controller during every utilization
+    /// point, e.g., nvme0n2 nvme0n3, with --writer-threads threads each at the same rate, and
+    /// report their latencies into --namespace-stats-file
+    #[clap(long, env = "SSD_BENCHY_CONCURRENT_NAMESPACES", value_parser, num_args = 1.., value_delimiter = ' ')]
+    concurrent_namespaces: Vec<String>,
+
+    /// Result file for --concurrent-namespaces, one row per namespace and utilization point
+    #[clap(long, env = "SSD_BENCHY_NAMESPACE_STATS_FILE", default_value_t = String::from("namespace_stats_file.csv"))]
+    namespace_stats_file: String,
+
+    /// IO priority classes of the writer threads, assigned round robin, e.g., rt:0,be:4,idle; one
+    /// row per class goes to --priority-stats-file
+    #[clap(long, env = "SSD_BENCHY_IO_PRIORITIES", value_parser = ioprio::parse, num_args = 1.., value_delimiter = ',')]
+    io_priorities: Vec<ioprio::IoPriority>,
+
+    /// Result file for --io-priorities, one row per class and utilization point
+    #[clap(long, env = "SSD_BENCHY_PRIORITY_STATS_FILE", default_value_t = String::from("priority_stats_file.csv"))]
+    priority_stats_file: String,
+
+    /// Throttle the writer threads with these io.max limits of a cgroup v2 created for the run,
+    /// e.g., "wiops=20000 wbps=max"
+    #[clap(long, env = "SSD_BENCHY_CGROUP_IO_MAX")]
+    cgroup_io_max: Option<String>,
+
+    /// Latency target in microseconds of io.latency of a cgroup v2 created for the run
+    #[clap(long, env = "SSD_BENCHY_CGROUP_IO_LATENCY_US")]
+    cgroup_io_latency_us: Option<u64>,
+
+    /// Where the cgroup of --cgroup-io-max and --cgroup-io-latency-us is created; it must not
+    /// have processes of its own
+    #[clap(long, env = "SSD_BENCHY_CGROUP_PARENT", default_value_t = String::from("/sys/fs/cgroup"))]
+    cgroup_parent: String,
+
+    /// How the percentiles of the summary are computed from the samples: nearest rank, linear
+    /// interpolation between the neighbouring samples, or estimates from the latency histogram
+    #[clap(long, env = "SSD_BENCHY_PERCENTILE_METHOD", value_enum, default_value_t = PercentileMethod::Nearest)]
+    percentile_method: PercentileMethod,
+
+    /// TOML file of writer thread groups with rates, write sizes, and rate patterns of their own,
+    /// e.g., a WAL writer, a flusher, and a compactor; replaces --writer-threads
+    #[clap(long, env = "SSD_BENCHY_THREAD_GROUPS")]
+    thread_groups: Option<String>,
+
+    /// Result file for --thread-groups, one row per group and utilization point
+    #[clap(long, env = "SSD_BENCHY_THREAD_GROUP_STATS_FILE", default_value_t = String::from("thread_group_stats_file.csv"))]
+    thread_group_stats_file: String,
+
+    /// TOML file of a sequence of phases (precondition, burst, idle, mixed, read-scan) with their
+    /// own durations and rates, run in order instead of the utilization points
+    #[clap(long, env = "SSD_BENCHY_SCENARIO")]
+    scenario: Option<String>,
+
+    /// Result file for --scenario, one row per phase
+    #[clap(long, env = "SSD_BENCHY_SCENARIO_STATS_FILE", default_value_t = String::from("scenario_stats_file.csv"))]
+    scenario_stats_file: String,
+
+    /// The groups of --thread-groups, read before the run
+    #[clap(skip)]
+    #[serde(skip)]
+    groups: Vec<thread_groups::ThreadGroup>,
+
+    /// Open the device without O_DIRECT (with O_DSYNC), for loop devices on file systems without
+    /// O_DIRECT and brd ramdisks on CI runners; the latencies include the page cache
+    #[cfg(feature = "ci")]
+    #[clap(long, env = "SSD_BENCHY_BUFFERED_IO", default_value_t = false)]
+    buffered_io: bool,
+
+    /// Compute the p99 of consecutive windows of this many seconds and report its spread across
+    /// windows in the summary (stability score); 0 disables it
+    #[clap(
+        long,
+        env = "SSD_BENCHY_STABILITY_WINDOW_SECONDS",
+        default_value_t = 10
+    )]
+    stability_window_seconds: u64,
+
+    /// Raise an event when the p50 or p99 of a window of --stability-window-seconds rises more
+    /// than this fraction above the first stable window, e.g., 0.5 for half
+    #[clap(long, env = "SSD_BENCHY_DRIFT_THRESHOLD")]
+    drift_threshold: Option<f64>,
+
+    /// Post every drift event as JSON to this http:// URL
+    #[clap(long, env = "SSD_BENCHY_DRIFT_WEBHOOK", requires = "drift_threshold")]
+    drift_webhook: Option<String>,
+
+    /// Result file for --drift-threshold, one row per drift event
+    #[clap(long, env = "SSD_BENCHY_DRIFT_FILE", default_value_t = String::from("drift_file.csv"))]
+    drift_file: String,
+
+    /// Sample the host's per-core CPU utilization, softirqs, and memory at this interval into
+    /// --host-stats-file, to tell when the host and not the SSD is the bottleneck; 0 disables it
+    #[clap(long, env = "SSD_BENCHY_HOST_STATS_INTERVAL_MS", default_value_t = 0)]
+    host_stats_interval_ms: u64,
+
+    /// Result file for --host-stats-interval-ms, one row per CPU and interval
+    #[clap(long, env = "SSD_BENCHY_HOST_STATS_FILE", default_value_t = String::from("host_stats_file.csv"))]
+    host_stats_file: String,
+
+    /// Read the host's RAPL energy counters (and the NVMe power state) around every utilization
+    /// point and report energy per write and average power; usually needs root
+    #[clap(long, env = "SSD_BENCHY_MEASURE_ENERGY", default_value_t = false)]
+    measure_energy: bool,
+
+    /// Serve live Prometheus metrics of the writer threads on this address, e.g., 0.0.0.0:9464;
+    /// see the grafana-dashboard subcommand
+    #[clap(long, env = "SSD_BENCHY_METRICS_LISTEN")]
+    metrics_listen: Option<String>,
+
+    /// Send the write rate, latency percentiles, and errors of every --influx-interval-ms as
+    /// InfluxDB line protocol to influx://host:port/database (the 1.x write API), or append it to
+    /// a file
+    #[clap(long, env = "SSD_BENCHY_INFLUX_OUTPUT")]
+    influx_output: Option<String>,
+
+    /// Interval of --influx-output
+    #[clap(long, env = "SSD_BENCHY_INFLUX_INTERVAL_MS", default_value_t = 1000)]
+    influx_interval_ms: u64,
+
+    /// Checkpoint the latency histograms of the running point to this file, so that `ssd-benchy
+    /// analyze` still yields its statistics if the tool or the host crashes
+    #[clap(long, env = "SSD_BENCHY_CHECKPOINT_FILE")]
+    checkpoint_file: Option<String>,
+
+    /// Interval of --checkpoint-file
+    #[clap(
+        long,
+        env = "SSD_BENCHY_CHECKPOINT_INTERVAL_SECONDS",
+        default_value_t = 60
+    )]
+    checkpoint_interval_seconds: u64,
+
+    /// Accept commands on this Unix socket to change the target and sampling rate of the running
+    /// point, pause, or read live stats, e.g., /tmp/ssd-benchy.sock
+    #[clap(long, env = "SSD_BENCHY_CONTROL_SOCKET")]
+    control_socket: Option<String>,
+
+    /// When the run ends, write its outcome (also the exit code, see the crate documentation) and
+    /// the key numbers of every completed utilization point as JSON to this path, e.g.,
+    /// /results/done.json for a Kubernetes Job
+    #[clap(long, env = "SSD_BENCHY_RESULT_JSON")]
+    result_json: Option<String>,
+
+    /// Print progress as text only, or also as one JSON event per line for wrappers (json)
+    #[clap(long, env = "SSD_BENCHY_PROGRESS_FORMAT", value_enum, default_value_t = progress::ProgressFormat::Text)]
+    progress_format: progress::ProgressFormat,
+
+    /// Interval of the point_progress events of --progress-format json
+    #[clap(
+        long,
+        env = "SSD_BENCHY_PROGRESS_INTERVAL_SECONDS",
+        default_value_t = 10
+    )]
+    progress_interval_seconds: u64,
+
+    /// Shell command run before the first utilization point, e.g., to snapshot SMART with a vendor
+    /// tool; the run ends if it fails. Its output goes to --hook-log-dir
+    #[clap(long, env = "SSD_BENCHY_HOOK_PRE_RUN")]
+    hook_pre_run: Option<String>,
+
+    /// Shell command run after the last utilization point
+    #[clap(long, env = "SSD_BENCHY_HOOK_POST_RUN")]
+    hook_post_run: Option<String>,
+
+    /// Shell command run before the device is preinitialized for a capacity fraction; the run
+    /// ends if it fails
+    #[clap(long, env = "SSD_BENCHY_HOOK_PRE_PREINIT")]
+    hook_pre_preinit: Option<String>,
+
+    /// Shell command run after the device was preinitialized for a capacity fraction
+    #[clap(long, env = "SSD_BENCHY_HOOK_POST_PREINIT")]
+    hook_post_preinit: Option<String>,
+
+    /// Shell command run before every utilization point; the run ends if it fails
+    #[clap(long, env = "SSD_BENCHY_HOOK_PRE_POINT")]
+    hook_pre_point: Option<String>,
+
+    /// Shell command run after every utilization point, once its results are written
+    #[clap(long, env = "SSD_BENCHY_HOOK_POST_POINT")]
+    hook_post_point: Option<String>,
+
+    /// Directory of the output of the hooks, one log per hook and point [default: hooks next to
+    /// --summary-file]
+    #[clap(long, env = "SSD_BENCHY_HOOK_LOG_DIR")]
+    hook_log_dir: Option<String>,
+
+    /// Before every utilization point, write sequentially until the drive reaches this temperature
+    /// (SMART composite temperature), so that runs in different ambient conditions are comparable
+    #[clap(long, env = "SSD_BENCHY_SOAK_TEMPERATURE_CELSIUS")]
+    soak_temperature_celsius: Option<i64>,
+
+    /// Give up heating the drive to --soak-temperature-celsius after this many seconds
+    #[clap(long, env = "SSD_BENCHY_SOAK_TIMEOUT_SECONDS", default_value_t = 1800)]
+    soak_timeout_seconds: u64,
+
+    /// Before every utilization point, pause until the drive cooled down to this temperature; a
+    /// running point is not interrupted, temperature_max_celsius reports whether it got hotter
+    #[clap(long, env = "SSD_BENCHY_MAX_TEMPERATURE_CELSIUS")]
+    max_temperature_celsius: Option<i64>,
+
+    /// Number of IOs every thread submits back to back at each scheduled instant; the instants are
+    /// spaced so that the rate is maintained, creating micro bursts
+    #[clap(long, env = "SSD_BENCHY_BATCH_SIZE", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// How the instants of the threads are offset against each other: 1.0 spreads them evenly
+    /// over the batch interval, 0.0 aligns all threads so that their batches coincide
+    #[clap(long, env = "SSD_BENCHY_BATCH_PHASE", default_value_t = 1.0)]
+    batch_phase: f64,
+
+    /// Perturbs every inter-arrival interval by a uniformly random factor within ±jitter while
+    /// keeping the mean rate, e.g., 20% or 0.2
+    #[clap(long, env = "SSD_BENCHY_JITTER", default_value = "0", value_parser = parse_jitter)]
+    jitter: f64,
+
+    /// Number of consecutive blocks every write consists of, each from its own buffer (pwritev),
+    /// like a database writing a batch of pages; latencies are per write
+    #[clap(long, env = "SSD_BENCHY_IOVCNT", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    iovcnt: u64,
+
+    /// Make every write cross a boundary of this many bytes, e.g., 4096, or of the device's atomic
+    /// write unit with `atomic`; the writes of --iovcnt blocks must be at least one unit long
+    #[clap(long, env = "SSD_BENCHY_WRITE_BOUNDARY", value_parser = boundary::parse)]
+    write_boundary: Option<boundary::WriteBoundary>,
+
+    /// Name of the SSD device in /dev, e.g., md0 (PhysicalDrive1 on Windows); an alias, e.g., of a
+    /// device passed into a container under another name, is resolved to its block device.
+    /// Required by the psync, io-uring, and pvsync2 engines
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: Option<String>,
+
+    /// Size of --ssd-device in bytes instead of the one it reports, e.g., of the product a larger
+    /// namespace stands in for; verify-after-crash would check the whole device
+    #[clap(
+        long,
+        env = "SSD_BENCHY_CAPACITY_BYTES",
+        conflicts_with = "crash_records"
+    )]
+    capacity_bytes: Option<u64>,
+
+    /// Only use the 512-byte sectors start:end (end exclusive) of --ssd-device, e.g., a free
+    /// partition, as the device; verify-after-crash would check the whole device
+    #[clap(long, env = "SSD_BENCHY_LBA_WINDOW", value_parser = lba_window::parse, conflicts_with = "crash_records")]
+    lba_window: Option<(u64, u64)>,
+
+    /// Turn off the requested features the process lacks the privileges for, e.g., telemetry
+    /// captures without CAP_SYS_ADMIN, with a warning each instead of refusing to run
+    #[clap(
+        long,
+        env = "SSD_BENCHY_DEGRADE_WITHOUT_PRIVILEGES",
+        default_value_t = false
+    )]
+    degrade_without_privileges: bool,
+
+    /// The window of --capacity-bytes and --lba-window, checked before the run
+    #[clap(skip)]
+    #[serde(skip)]
+    window: lba_window::Window,
+
+    /// How IO is issued; `memory` and `null` need no SSD. With several engines, each utilization
+    /// point is run with every engine back to back, e.g., psync io-uring
+    #[clap(long, env = "SSD_BENCHY_ENGINES", alias = "engine", value_enum, num_args = 1.., value_delimiter = ' ', default_values_t = vec![engine::EngineKind::Psync])]
+    engines: Vec<engine::EngineKind>,
+
+    /// io-uring: submit through a kernel poll thread (IORING_SETUP_SQPOLL) and spin on completions
+    #[clap(long, env = "SSD_BENCHY_SQPOLL", default_value_t = false)]
+    sqpoll: bool,
+
+    /// io-uring: CPU the poll thread of --sqpoll is pinned to
+    #[clap(long, env = "SSD_BENCHY_SQPOLL_CPU", requires = "sqpoll")]
+    sqpoll_cpu: Option<u32>,
+
+    /// io-uring: poll for completions (IORING_SETUP_IOPOLL); the device needs NVMe poll queues
+    #[clap(long, env = "SSD_BENCHY_HIPRI", default_value_t = false)]
+    hipri: bool,
+
+    /// nvme-pi: who generates and checks the protection information of every block
+    #[clap(long, env = "SSD_BENCHY_PI_MODE", value_enum, default_value_t = pi::PiMode::Host)]
+    pi_mode: pi::PiMode,
+
+    /// Size of the device simulated by the memory and null engines
+    #[clap(long, env = "SSD_BENCHY_SIMULATED_DEVICE_BYTES", default_value_t = 1 << 30)]
+    simulated_device_bytes: u64,
+
+    /// Completion time of every IO of the null engine in microseconds
+    #[clap(long, env = "SSD_BENCHY_SIMULATED_LATENCY_US", default_value_t = 0.0)]
+    simulated_latency_us: f64,
+
+    /// Probability that a write (or fsync) fails with EIO; for testing the tool itself
+    #[clap(long, env = "SSD_BENCHY_FAULT_EIO_PROBABILITY", default_value_t = 0.0)]
+    fault_eio_probability: f64,
+
+    /// Probability that a write transfers only half of the block
+    #[clap(
+        long,
+        env = "SSD_BENCHY_FAULT_SHORT_WRITE_PROBABILITY",
+        default_value_t = 0.0
+    )]
+    fault_short_write_probability: f64,
+
+    /// Probability that a write completes only after --fault-delay-us
+    #[clap(
+        long,
+        env = "SSD_BENCHY_FAULT_DELAY_PROBABILITY",
+        default_value_t = 0.0
+    )]
+    fault_delay_probability: f64,
+
+    /// Added completion time of delayed writes in microseconds
+    #[clap(long, env = "SSD_BENCHY_FAULT_DELAY_US", default_value_t = 1000)]
+    fault_delay_us: u64,
 
     /// The runtime in seconds for each utilization point
-    #[clap(long, default_value_t = 10)]
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 10)]
     runtime_seconds: u64,
 
+    /// Repeat the utilization points in segments of --soak-rotate-minutes for this many hours,
+    /// with a summary row per segment and rotated samples and host statistics files
+    #[clap(long, env = "SSD_BENCHY_SOAK_HOURS", conflicts_with = "staging_dir")]
+    soak_hours: Option<f64>,
+
+    /// Length of a segment of --soak-hours in minutes; replaces --runtime-seconds
+    #[clap(long, env = "SSD_BENCHY_SOAK_ROTATE_MINUTES", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    soak_rotate_minutes: u64,
+
+    /// Segments of --soak-hours whose rotated files are kept, including the running one; older
+    /// ones are deleted. 0 keeps all
+    #[clap(long, env = "SSD_BENCHY_SOAK_KEEP_SEGMENTS", default_value_t = 48)]
+    soak_keep_segments: u64,
+
     /// Result file
-    #[clap(long, default_value_t = String::from("summary_file.csv"))]
+    #[clap(long, env = "SSD_BENCHY_SUMMARY_FILE", default_value_t = String::from("summary_file.csv"))]
     summary_file: String,
 
+    /// Write a provisional summary row of the running point every this many seconds, replaced by
+    /// the next one and the final row; 0 disables it
+    #[clap(
+        long,
+        env = "SSD_BENCHY_SUMMARY_FLUSH_SECONDS",
+        default_value_t = 0,
+        conflicts_with = "staging_dir"
+    )]
+    summary_flush_seconds: u64,
+
     /// Name of the SSD device, e.g., /dev/md0; must be the real name of the block device and not an alias
     /// Result file
-    #[clap(long, default_value_t = String::from("samples_file.csv"))]
+    #[clap(long, env = "SSD_BENCHY_SAMPLES_FILE", default_value_t = String::from("samples_file.csv"))]
     samples_file: String,
+
+    /// Stamp every written block and read all regions back after each utilization point to check
+    /// that the partitioning and wrap-around wrote exactly the expected blocks
+    #[clap(long, env = "SSD_BENCHY_VERIFY", default_value_t = false, conflicts_with_all = [
+        "fault_eio_probability", "fault_short_write_probability"
+    ])]
+    verify: bool,
+
+    /// Write sequence-numbered, checksummed records that `verify-after-crash` checks after a power cut
+    #[clap(
+        long,
+        env = "SSD_BENCHY_CRASH_RECORDS",
+        default_value_t = false,
+        conflicts_with = "verify"
+    )]
+    crash_records: bool,
+
+    /// Log of acknowledged writes per thread for `verify-after-crash`; must not be on the tested SSD
+    #[clap(long, env = "SSD_BENCHY_CRASH_ACK_FILE", requires = "crash_records")]
+    crash_ack_file: Option<String>,
+
+    /// What to do when an existing result file was written with a different set of columns
+    #[clap(long, env = "SSD_BENCHY_SCHEMA_MISMATCH", value_enum, default_value_t = schema::SchemaMismatchPolicy::Refuse)]
+    schema_mismatch: schema::SchemaMismatchPolicy,
+}
+
+impl CliConfig {
+    /// The blocks per write of a writer thread; the same in every utilization point
+    fn iovcnt_of(&self, worker_id: u64) -> u64 {
+        thread_groups::of(&self.groups, worker_id).map_or(self.iovcnt, |(g, _)| g.iovcnt)
+    }
+
+    /// The class of a writer thread; the same in every utilization point
+    fn io_priority(&self, worker_id: u64) -> Option<ioprio::IoPriority> {
+        (!self.io_priorities.is_empty())
+            .then(|| self.io_priorities[(worker_id % self.io_priorities.len() as u64) as usize])
+    }
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Workload {
+    #[default]
+    Overwrite,
+    Log,
+    Transaction,
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SamplingMethod {
+    #[default]
+    Bernoulli,
+    Systematic,
+    Tail,
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum CatchUp {
+    /// Issue the missed writes immediately; their latencies include how late they were
+    #[default]
+    Burst,
+    /// Drop the missed writes and continue with the next one that is due
+    Skip,
+    /// Drop the missed writes but record the latencies they would have had
+    CoCorrect,
+}
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum RatePattern {
+    #[default]
+    Constant,
+    Ramp,
+    Sine,
 }
 
 /// Describes the current benchmark parameter and environment
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, Default)]
 struct BenchmarkConfig {
+    schema_version: u32,
     instance_type: String,
+    profile: String, // the --profile preset the flags came from, empty without
     start_time: u64, // start time from unix epoch
     hostname: String,
     ssd_device: String,
+    device_model: Option<String>,
+    device_serial: Option<String>,
+    device_firmware_rev: Option<String>,
+    device_nsid: Option<u32>,
+    engine: engine::EngineKind,
+    simulated_latency_us: f64, // only used by the null engine
+    sqpoll: bool,              // only used by the io-uring engine
+    hipri: bool,
+    pi_mode: pi::PiMode, // only used by the nvme-pi engine
+    pi_type: u8,         // of the namespace format, 0 without protection information
+    metadata_bytes: u64, // per block, of the namespace format
     writer_threads: u64,
     runtime_seconds: u64,
+    soak_segment: Option<u64>, // empty without --soak-hours
     preinitialize: bool,
+    window_start_bytes: u64,    // of --lba-window, 0 without
+    window_capacity_bytes: u64, // of the device the benchmark used
     capacity_fraction: f64,
+    region_order: String, // the region of every writer thread, separated by spaces
+    clock_synchronized: Option<bool>, // NTP state of the host at the start of the point
+    clock_max_error_us: Option<i64>,
     max_iops: u64,
     iops: u64,
     utilization_iop: f64, // single measurement point
     use_fsync: bool,
+    group_commit_us: u64,
+    group_commit_writers: u64,
     uuid: u128,
-    spiky: bool,
+    config_hash: String, // of the parameters, equal for repeated runs of the same configuration
+    workload: Workload,
+    log_segments: u64,
+    transaction_blocks: u64, // 0 unless --workload transaction
+    bulk_threads: u64,
+    bulk_write_bytes: u64,
+    bulk_mb_per_second: f64,
+    fill_drift_mb_per_second: f64,
+    fill_drift_limit: f64,
+    write_zeroes_fraction: f64,
+    deallocate_fraction: f64,
+    copy_fraction: f64,
+    copy_ranges: u64,
+    batch_size: u64,
+    batch_phase: f64,
+    jitter: f64,
+    iovcnt: u64,
+    write_boundary_bytes: u64, // 0 for aligned writes
+    sample_rate: f64,
+    memory_budget_mb: u64,
+    sampling_method: SamplingMethod,
+    tail_percentile: f64, // 0 unless --sampling-method tail
+    sample_seed: u64,     // thread i seeds its sampling with sample_seed + i
+    rate_pattern: RatePattern,
+    catch_up: CatchUp,
+    rate_min_utilization: f64,
+    rate_period_seconds: u64,
+    fault_eio_probability: f64,
+    fault_short_write_probability: f64,
+    fault_delay_probability: f64,
+    fault_delay_us: u64,
+    soak_temperature_celsius: Option<i64>,
+    max_temperature_celsius: Option<i64>,
+    concurrent_namespaces: String, // written during the point, separated by spaces
+    io_priorities: String,         // of the writer threads, separated by commas
+    thread_groups: String,         // name:threads:write_bytes:share, separated by spaces
+    cgroup_io_max: String,         // empty without a cgroup
+    cgroup_io_latency_us: u64,     // 0 without a latency target
+    direct_io: bool,               // false with --buffered-io
+    percentile_method: PercentileMethod,
 }
 
 impl BenchmarkConfig {
     pub fn from_cli_config(
         config: &CliConfig,
+        device: &engine::Device,
+        engine: engine::EngineKind,
+        capacity_fraction: f64,
         iops_utilization: f64,
         uuid: u128,
+        sample_seed: u64,
     ) -> BenchmarkConfig {
         let start_time = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .expect("");
+        let clock_status = clock::status();
+
+        let format = device.format();
+        let identity = device.identity();
 
-        BenchmarkConfig {
+        let mut benchmark_config = BenchmarkConfig {
+            schema_version: schema::SUMMARY_SCHEMA_VERSION,
             instance_type: config.instance_type.clone(),
+            profile: config.profile.clone().unwrap_or_default(),
             start_time: start_time.as_secs(),
             hostname: gethostname().into_string().unwrap(),
-            ssd_device: config.ssd_device.clone(),
+            ssd_device: device.name(),
+            device_model: identity.model,
+            device_serial: identity.serial,
+            device_firmware_rev: identity.firmware_rev,
+            device_nsid: identity.nsid,
+            engine,
+            simulated_latency_us: config.simulated_latency_us,
+            sqpoll: config.sqpoll,
+            hipri: config.hipri,
+            pi_mode: config.pi_mode,
+            pi_type: format.pi_type,
+            metadata_bytes: format.metadata_bytes as u64,
             writer_threads: config.writer_threads,
             runtime_seconds: config.runtime_seconds,
             preinitialize: config.preinitialize,
-            capacity_fraction: config.capacity_fraction,
+            window_start_bytes: device.offset(),
+            window_capacity_bytes: device.capacity(),
+            capacity_fraction,
+            region_order: String::new(),
+            clock_synchronized: clock_status.clock_synchronized,
+            clock_max_error_us: clock_status.clock_max_error_us,
             max_iops: config.max_iops,
             utilization_iop: iops_utilization,
             iops: (iops_utilization * config.max_iops as f64) as u64,
             use_fsync: config.use_fsync,
+            group_commit_us: config.group_commit_us,
+            group_commit_writers: config.group_commit_writers,
             uuid,
-            spiky: config.spiky,
-        }
+            workload: config.workload,
+            log_segments: config.log_segments,
+            transaction_blocks: if config.workload == Workload::Transaction {
+                config.transaction_blocks
+            } else {
+                0
+            },
+            bulk_threads: config.bulk_threads,
+            bulk_write_bytes: config.bulk_write_bytes,
+            bulk_mb_per_second: config.bulk_mb_per_second,
+            fill_drift_mb_per_second: config.fill_drift_mb_per_second,
+            fill_drift_limit: config.fill_drift_limit,
+            write_zeroes_fraction: config.write_zeroes_fraction,
+            deallocate_fraction: config.deallocate_fraction,
+            copy_fraction: config.copy_fraction,
+            copy_ranges: config.copy_ranges,
+            batch_size: config.batch_size,
+            batch_phase: config.batch_phase,
+            jitter: config.jitter,
+            iovcnt: config.iovcnt,
+            write_boundary_bytes: boundary::bytes(config.write_boundary, &device.name())
+                .unwrap_or_default(),
+            sample_rate: config.sample_rate,
+            memory_budget_mb: config.memory_budget_mb,
+            sampling_method: config.sampling_method,
+            tail_percentile: if config.sampling_method == SamplingMethod::Tail {
+                config.tail_percentile
+            } else {
+                0.0
+            },
+            sample_seed,
+            rate_pattern: config.rate_pattern,
+            catch_up: config.catch_up,
+            rate_min_utilization: config.rate_min_utilization,
+            rate_period_seconds: config.rate_period_seconds,
+            fault_eio_probability: config.fault_eio_probability,
+            fault_short_write_probability: config.fault_short_write_probability,
+            fault_delay_probability: config.fault_delay_probability,
+            fault_delay_us: config.fault_delay_us,
+            soak_temperature_celsius: config.soak_temperature_celsius,
+            max_temperature_celsius: config.max_temperature_celsius,
+            concurrent_namespaces: config.concurrent_namespaces.join(" "),
+            io_priorities: config
+                .io_priorities
+                .iter()
+                .map(ioprio::IoPriority::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            thread_groups: config
+                .groups
+                .iter()
+                .map(thread_groups::ThreadGroup::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+            cgroup_io_max: config.cgroup_io_max.clone().unwrap_or_default(),
+            cgroup_io_latency_us: config.cgroup_io_latency_us.unwrap_or(0),
+            direct_io: !buffered_io(),
+            percentile_method: config.percentile_method,
+            ..BenchmarkConfig::default()
+        };
+        benchmark_config.config_hash = benchmark_config.hash();
+        benchmark_config
+    }
+
+    /// 64-bit FNV-1a of the JSON of the fields that define the measurement, in hex. The fields
+    /// that differ between repetitions of a configuration are left out; the hash is stable across
+    /// hosts and builds, but a version that adds fields starts new configurations.
+    fn hash(&self) -> String {
+        const PER_RUN: [&str; 14] = [
+            "schema_version",
+            "profile", // a name for the parameters, which are hashed themselves
+            "start_time",
+            "hostname",
+            "ssd_device",
+            "device_serial",
+            "device_nsid",
+            "region_order", // follows from the seed
+            "clock_synchronized",
+            "clock_max_error_us",
+            "uuid",
+            "config_hash",
+            "sample_seed",
+            "soak_segment",
+        ];
+        let serde_json::Value::Object(fields) = serde_json::to_value(self).unwrap() else {
+            unreachable!("a struct is a json object");
+        };
+        let parameters = serde_json::Value::Object(
+            fields
+                .into_iter()
+                .filter(|(name, _)| !PER_RUN.contains(&name.as_str()))
+                .collect(),
+        );
+        let hash = parameters
+            .to_string()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        format!("{:016x}", hash)
     }
 }
 
-#[derive(Serialize, PartialEq, PartialOrd, Ord, Eq, Debug)]
+#[derive(Serialize, PartialEq, PartialOrd, Debug, Default)]
 struct Sample {
-    latency: u128,
-    id: u64,
+    latency: u64,
+    op: SampleOp,
+    id: u64,          // per-thread operation counter
+    thread_id: u64,   // writer thread that issued the operation
+    seq: u64,         // globally unique and monotonically increasing across all threads
+    target_iops: u64, // target rate (all threads) when the operation was issued
+    uuid: u128,
+    monotonic_ns: u64, // completion, CLOCK_MONOTONIC of the host
+    realtime_ns: u64,  // completion, CLOCK_REALTIME (Unix epoch) for correlating hosts
+    weight: f64,       // the operations the sample stands for, 1 / sample rate or 1 for the tail
+}
+
+/// Operation of a sample. A flush is the fsync of a write with --use-fsync and a trim the discard
+/// of a log segment (--workload log); the latency of their write includes them. The other kinds
+/// are the commands of the op mix (--write-zeroes-fraction, --deallocate-fraction,
+/// --copy-fraction)
+#[derive(Serialize, PartialEq, PartialOrd, Ord, Eq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+enum SampleOp {
+    #[default]
+    Write,
+    Flush,
+    Trim,
+    WriteZeroes,
+    Copy,
+}
+
+impl From<nvme_ops::Op> for SampleOp {
+    fn from(op: nvme_ops::Op) -> Self {
+        match op {
+            nvme_ops::Op::Write => SampleOp::Write,
+            nvme_ops::Op::WriteZeroes => SampleOp::WriteZeroes,
+            nvme_ops::Op::Deallocate => SampleOp::Trim,
+            nvme_ops::Op::Copy | nvme_ops::Op::HostCopy => SampleOp::Copy,
+        }
+    }
+}
+
+/// Latency percentiles of the operations issued while the target rate was within one bucket
+#[derive(Serialize, Debug, Default)]
+struct RateBucket {
     uuid: u128,
+    rate_pattern: RatePattern,
+    bucket: usize,
+    utilization_low: f64,
+    utilization_high: f64,
+    samples: usize,
 }
 
-#[derive(Serialize, Debug)]
-struct SummaryStatistics {
-    min: u128,
-    max: u128,
-    p50th: u128,
-    p75th: u128,
-    p90th: u128,
-    p99th: u128,
-    p999th: u128,
+/// Latency percentiles (in nanoseconds) of the operations that wrote into one LBA slice
+#[derive(Serialize, Debug, Default)]
+struct LbaSlice {
+    uuid: u128,
+    utilization_iop: f64,
+    slice: u64,
+    start_bytes: u64,
+    end_bytes: u64,
+    operations: u64,
+    mean: f64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum HistogramMetric {
+    #[default]
+    Latency,
+    InterCompletion, // time between two consecutive completions of any threads
+}
+
+/// One bucket of an exported histogram
+#[derive(Serialize, Debug, Default)]
+struct HistogramBucket {
+    uuid: u128,
+    utilization_iop: f64,
+    metric: HistogramMetric,
+    upper_bound_ns: u64, // highest value counted in this bucket
+    count: u64,
+}
+
+/// Maps target utilizations between `low` and `high` to `buckets` equally wide buckets
+#[derive(Clone, Copy, Debug)]
+struct RateBuckets {
+    low: f64,
+    high: f64,
+    buckets: usize,
 }
 
-impl SummaryStatistics {
-    fn percentile(samples: &[Sample], percentile: f64) -> &Sample {
-        let len = samples.len();
-        let index = ((len as f64) * percentile / 100.0).ceil() as usize - 1;
-        &samples[index]
+impl RateBuckets {
+    fn bucket_of(&self, utilization: f64) -> usize {
+        if self.high <= self.low {
+            return 0;
+        }
+        let position = (utilization - self.low) / (self.high - self.low);
+        ((position * self.buckets as f64) as usize).min(self.buckets - 1)
+    }
+
+    fn bounds(&self, bucket: usize) -> (f64, f64) {
+        let width = (self.high - self.low) / self.buckets as f64;
+        (
+            self.low + width * bucket as f64,
+            self.low + width * (bucket + 1) as f64,
+        )
     }
+}
 
-    pub fn create_from_sample(samples: &mut [Sample]) -> SummaryStatistics {
-        samples.sort_by_key(|sample| sample.latency);
-        let min = samples.first().expect("no samples collected").latency;
-        let max = samples.last().expect("no samples collected").latency;
+/// What was actually achieved during a utilization point, as opposed to what was configured
+#[derive(Serialize, Debug, Default)]
+struct AchievedStatistics {
+    partial: bool, // a provisional row of --summary-flush-seconds of a point that did not end
+    elapsed_seconds: f64, // without the pauses
+    paused_seconds: f64, // SIGUSR1
+    total_operations: u64,
+    total_bytes: u64,
+    achieved_iops: f64,
+    io_errors: u64, // failed writes, fsyncs, and discards, not part of the latencies
+    short_writes: u64, // writes that transferred less than a block
+    fsyncs: u64,
+    discards: u64,      // segments discarded by --workload log
+    discard_p50th: u64, // nanoseconds
+    discard_max: u64,
+    // fsyncs of --group-commit-writers, which the latencies of the writes wait for
+    writes_per_commit: f64,
+    commit_p50th: u64, // nanoseconds
+    commit_p99th: u64,
+    commit_max: u64,
+    // parts of the transactions of --workload transaction, whose latencies are the whole ones
+    transaction_data_p99th: u64, // nanoseconds
+    transaction_barrier_p99th: u64,
+    transaction_commit_p99th: u64, // the commit record and its fsync
+    samples_written: u64,
+    samples_dropped: u64, // the serialization fell too far behind, see the sample_writer module
+    samples_fraction: f64, // of the writes, below --sample-rate if --samples-max-rows downsampled
+    // --memory-budget-mb: the sample rate of a thread was divided by its stride to stay within
+    memory_peak_mb: f64,    // sum of the peaks of the threads
+    memory_max_stride: u64, // of any thread, 1 if none degraded
+    memory_flushes: u64,
+    // actual minus intended submit time in nanoseconds; the rate limiter could not hold the
+    // schedule by this much and it is part of the reported latencies
+    scheduling_error_p50th: u64,
+    scheduling_error_p99th: u64,
+    scheduling_error_max: u64,
+}
 
-        SummaryStatistics {
-            min,
-            max,
-            p50th: SummaryStatistics::percentile(&samples, 50.0).latency,
-            p75th: SummaryStatistics::percentile(&samples, 75.0).latency,
-            p90th: SummaryStatistics::percentile(&samples, 90.0).latency,
-            p99th: SummaryStatistics::percentile(&samples, 99.0).latency,
-            p999th: SummaryStatistics::percentile(&samples, 99.9).latency,
+impl AchievedStatistics {
+    pub fn create_from_results(results: &[WorkerResult], sample_rate: f64) -> AchievedStatistics {
+        let begin = results.iter().map(|r| r.begin).min();
+        let end = results.iter().map(|r| r.end).max();
+        // the threads pause within one write of each other
+        let paused = results.iter().map(|r| r.paused).max().unwrap_or_default();
+        let elapsed_seconds = match (begin, end) {
+            (Some(begin), Some(end)) => (end - begin).saturating_sub(paused).as_secs_f64(),
+            _ => 0.0,
+        };
+        let total_operations = results.iter().map(|r| r.operations).sum();
+        let total_bytes = results.iter().map(|r| r.bytes).sum();
+        let io_errors = results.iter().map(|r| r.io_errors).sum();
+        let short_writes = results.iter().map(|r| r.short_writes).sum();
+        let fsyncs = results.iter().map(|r| r.fsyncs).sum();
+        let samples_written = results.iter().map(|r| r.sample_count).sum();
+        let samples_dropped = results.iter().map(|r| r.samples_dropped).sum();
+        let samples_offered: u64 = results.iter().map(|r| r.samples_offered).sum();
+        let memory: Vec<_> = results.iter().filter_map(|r| r.memory.as_ref()).collect();
+        let mut scheduling_error = histogram::Histogram::new();
+        let mut discard_latency = histogram::Histogram::new();
+        let mut commits = group_commit::CommitLatencies::default();
+        let mut transactions = transaction::TransactionLatencies::default();
+        for result in results {
+            scheduling_error.merge(&result.scheduling_error);
+            discard_latency.merge(&result.discard_latency);
+            commits.merge(&result.commits);
+            transactions.merge(&result.transactions);
+        }
+        AchievedStatistics {
+            partial: false,
+            elapsed_seconds,
+            paused_seconds: paused.as_secs_f64(),
+            total_operations,
+            total_bytes,
+            achieved_iops: if elapsed_seconds > 0.0 {
+                total_operations as f64 / elapsed_seconds
+            } else {
+                0.0
+            },
+            io_errors,
+            short_writes,
+            fsyncs,
+            discards: discard_latency.count(),
+            discard_p50th: discard_latency.percentile(50.0),
+            discard_max: discard_latency.max(),
+            writes_per_commit: commits.writes_per_commit(),
+            commit_p50th: commits.latency.percentile(50.0),
+            commit_p99th: commits.latency.percentile(99.0),
+            commit_max: commits.latency.max(),
+            transaction_data_p99th: transactions.data.percentile(99.0),
+            transaction_barrier_p99th: transactions.barrier.percentile(99.0),
+            transaction_commit_p99th: transactions.commit.percentile(99.0),
+            samples_written,
+            samples_dropped,
+            samples_fraction: if samples_offered > 0 {
+                sample_rate * samples_written as f64 / samples_offered as f64
+            } else {
+                0.0
+            },
+            memory_peak_mb: memory.iter().map(|m| m.peak_bytes).sum::<usize>() as f64
+                / (1024.0 * 1024.0),
+            memory_max_stride: memory.iter().map(|m| m.stride).max().unwrap_or(1),
+            memory_flushes: memory.iter().map(|m| m.flushes).sum(),
+            scheduling_error_p50th: scheduling_error.percentile(50.0),
+            scheduling_error_p99th: scheduling_error.percentile(99.0),
+            scheduling_error_max: scheduling_error.max(),
         }
     }
 }
 
+/// What a writer thread hands back after a utilization point
+struct WorkerResult {
+    begin: Instant,
+    end: Instant,
+    paused: Duration,
+    operations: u64, // issued, including failed ones
+    bytes: u64,
+    io_errors: u64,
+    short_writes: u64,
+    fsyncs: u64,
+    discard_latency: histogram::Histogram,
+    commits: group_commit::CommitLatencies, // as the leader of --group-commit-writers
+    transactions: transaction::TransactionLatencies, // only with --workload transaction
+    latencies: Vec<u64>,
+    bucket_latencies: Vec<Vec<u64>>, // only for ramp and sine patterns
+    sample_count: u64,               // written to the samples file
+    samples_offered: u64,            // before --samples-max-rows downsampled them
+    backpressure_events: u64,
+    max_pending_samples: usize,
+    samples_dropped: u64, // held back samples beyond the limit of the sample writer
+    scheduling_error: histogram::Histogram,
+    latency_histogram: histogram::Histogram, // with --export-histograms, --checkpoint-file, or --io-priorities
+    inter_completion_histogram: histogram::Histogram, // only with --export-histograms
+    slice_histograms: Vec<histogram::Histogram>, // one per LBA slice, only with --lba-slices
+    idle_gaps: Option<idle_gap::IdleGaps>,   // only with --idle-gap-buckets-us
+    memory: Option<memory::ThreadMemory>,    // only with --memory-budget-mb
+    outliers: Vec<outliers::Outlier>,        // only with --outlier-threshold-us
+    dropped_outliers: u64,
+    op_latencies: nvme_ops::OpLatencies, // of the Write Zeroes, deallocate, and copy commands
+    switches: sched_stats::ThreadSwitches,
+    profile: thread_profile::ThreadProfile,
+}
+
+/// What the writer threads of a utilization point share
+struct Point {
+    config: &'static CliConfig,
+    device: &'static engine::Device,
+    engine_kind: engine::EngineKind,
+    faults: engine::Faults,
+    uuid: Uuid,
+    utilization: &'static f64,
+    start_time_ns: u64,
+    sample_seed: u64,
+    device_blocks: u64,
+    initialized_blocks: u64,
+    participants: u64, // writer and bulk threads, each with a region of its own
+    boundary_blocks: u64,
+    regions: Vec<u64>, // the region of every writer thread
+    op_mix: nvme_ops::OpMix,
+    rate_buckets: Option<RateBuckets>,
+    reservoir_rows: Option<usize>, // per thread, only with --samples-max-rows
+    memory_share: Option<usize>,   // per thread, only with --memory-budget-mb
+    stability_window: Duration,
+    stability_windows: stability::Windows,
+    start_barrier: std::sync::Arc<StartBarrier>,
+    group_commits: Vec<group_commit::GroupCommit>, // one per --group-commit-writers
+    sample_sequence: std::sync::atomic::AtomicU64,
+    completion_base: Instant,
+    // nanoseconds since `completion_base` of the most recent completion of any thread
+    last_completion: std::sync::atomic::AtomicU64,
+    in_flight: std::sync::atomic::AtomicU64, // only counted with --outlier-threshold-us
+    acknowledged: Option<std::sync::Arc<Vec<std::sync::atomic::AtomicU64>>>,
+    telemetry_capturer: Option<std::sync::Arc<telemetry::Capturer>>,
+    checkpointer: Option<&'static checkpoint::Checkpointer>,
+    checkpoint_interval: Duration,
+    summary_flusher: Option<&'static summary_flush::Flusher>,
+    summary_flush_interval: Duration,
+    progress_reporter: Option<&'static progress::Reporter>,
+    progress_interval: Duration,
+    metrics: Option<&'static metrics::Metrics>,
+}
+
 #[repr(align(4096))]
 struct DirectIOBuffer<const SIZE: usize>([u8; SIZE]);
 
+impl<const SIZE: usize> DirectIOBuffer<SIZE> {
+    /// Allocates the buffer directly on the heap; large buffers would overflow a thread's stack
+    fn new_boxed(fill: u8) -> Box<Self> {
+        let layout = std::alloc::Layout::new::<Self>();
+        unsafe {
+            let ptr = std::alloc::alloc(layout) as *mut Self;
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            std::ptr::write_bytes(ptr as *mut u8, fill, SIZE);
+            Box::from_raw(ptr)
+        }
+    }
+}
+
+/// Target rate over the course of a utilization point
+#[derive(Clone, Copy, Debug)]
+struct RateSchedule {
+    pattern: RatePattern,
+    min_rate: f64, // low end of ramp and sine
+    max_rate: f64, // rate of the utilization point
+    runtime: Duration,
+    period: Duration, // of the sine
+}
+
+impl RateSchedule {
+    fn rate_at(&self, elapsed: Duration) -> f64 {
+        let amplitude = self.max_rate - self.min_rate;
+        match self.pattern {
+            RatePattern::Constant => self.max_rate,
+            RatePattern::Ramp => {
+                let progress = (elapsed.as_secs_f64() / self.runtime.as_secs_f64()).min(1.0);
+                self.min_rate + amplitude * progress
+            }
+            RatePattern::Sine => {
+                let phase =
+                    2.0 * std::f64::consts::PI * elapsed.as_secs_f64() / self.period.as_secs_f64();
+                self.min_rate + amplitude * (1.0 - phase.cos()) / 2.0
+            }
+        }
+    }
+}
+
+/// Releases the threads of a utilization point together; once all arrived, one of them picks the
+/// start instant all of them measure and schedule from
+struct StartBarrier {
+    barrier: std::sync::Barrier,
+    start: std::sync::OnceLock<Instant>,
+    clocks: std::sync::OnceLock<(u64, u64)>, // CLOCK_REALTIME and CLOCK_MONOTONIC of the start
+}
+
+impl StartBarrier {
+    fn new(threads: usize) -> Self {
+        StartBarrier {
+            barrier: std::sync::Barrier::new(threads),
+            start: std::sync::OnceLock::new(),
+            clocks: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Blocks until all threads arrived and returns the common start instant
+    fn wait(&self) -> Instant {
+        if self.barrier.wait().is_leader() {
+            let _ = self.start.set(Instant::now());
+            let _ = self
+                .clocks
+                .set((clock::realtime_ns(), clock::monotonic_ns()));
+        }
+        *self.start.wait()
+    }
+}
+
+/// Lowest rate of a writer thread in writes per second; a rate of 0 would schedule the next batch
+/// never, and the instant of it overflows
+const MIN_THREAD_RATE: f64 = 0.01;
+
+/// Microseconds between the batches of a thread when all `threads` together write at `rate`
+fn inter_arrival_us(rate: f64, threads: u64, batch_size: u64) -> f64 {
+    1e6 / (rate / threads as f64).max(MIN_THREAD_RATE) * batch_size as f64
+}
+
 struct RateLimiter {
-    inter_arrival_time: f64,
-    next_time: Instant,
+    schedule: RateSchedule,
+    threads: u64,
+    start: Instant,
+    next_time: Instant, // instant of the current batch
+    batch_size: u64,
+    batch_index: u64, // position of the next operation within its batch
+    rate: f64,        // target rate of the current batch
+    scale: f64,       // of the schedule's rate, see the control module
+    jitter: f64,
+    rng: fastrand::Rng,
+    scheduling_error: histogram::Histogram, // of the first operation of every batch, in nanoseconds
+    catch_up: CatchUp,
+    missed_batches: u64,   // skipped by the current batch, for CatchUp::CoCorrect
+    inter_arrival_ns: u64, // of the current batch
+    profile: thread_profile::ThreadProfile,
 }
 
 impl RateLimiter {
-    pub fn new(rate: f64, threads: u64, thread_id: u64, spiky: bool) -> Self {
-        let rate_per_thread = rate / threads as f64;
-        let inter_arrival_time = 1e6 / rate_per_thread; // microseconds
-        let mut inter_arrival_time_offset =
-            (inter_arrival_time / threads as f64) * thread_id as f64;
-        if spiky {
-            inter_arrival_time_offset = 0.0; // forces threads to start at roughly the same time
-        }
-        let next_time = Instant::now()
+    /// A limiter whose schedule begins at `start`, the instant shared by the threads of a point,
+    /// so the phase offsets of the threads do not depend on when each thread got to run
+    pub fn new(
+        start: Instant,
+        schedule: RateSchedule,
+        threads: u64,
+        thread_id: u64,
+        batch_size: u64,
+        batch_phase: f64,
+        jitter: f64,
+    ) -> Self {
+        let inter_arrival_time =
+            inter_arrival_us(schedule.rate_at(Duration::ZERO), threads, batch_size);
+        // a phase of 0 forces threads to start at roughly the same time
+        let inter_arrival_time_offset =
+            (inter_arrival_time / threads as f64) * thread_id as f64 * batch_phase;
+        let next_time = start
             + Duration::from_micros(inter_arrival_time_offset as u64 + inter_arrival_time as u64);
 
         RateLimiter {
-            inter_arrival_time,
+            schedule,
+            threads,
+            start,
             next_time,
+            batch_size,
+            batch_index: 0,
+            rate: 0.0,
+            scale: 1.0,
+            jitter,
+            rng: fastrand::Rng::new(),
+            scheduling_error: histogram::Histogram::new(),
+            catch_up: CatchUp::Burst,
+            missed_batches: 0,
+            inter_arrival_ns: 0,
+            profile: thread_profile::ThreadProfile::default(),
         }
     }
+
+    pub fn set_catch_up(&mut self, catch_up: CatchUp) {
+        self.catch_up = catch_up;
+    }
+
+    /// Multiplies the rate of the schedule from the next batch on
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale.max(control::MIN_RATE_SCALE);
+    }
+
+    /// Moves the schedule by a pause, so the writes it missed are not issued as a burst
+    pub fn skip(&mut self, paused: Duration) {
+        self.start += paused;
+        self.next_time += paused;
+    }
+
     // write reate limiter
     fn wait_until(next: Instant) {
         let mut current = Instant::now();
@@ -209,34 +1607,115 @@ impl RateLimiter {
 
         while time_span.as_secs_f64() > 0.0 {
             current = Instant::now();
-            unsafe { _mm_pause() };
+            _mm_pause();
             time_span = next.duration_since(current);
         }
     }
 
-    pub fn run<F: FnMut()>(&mut self, mut action: F, mut sampling: impl FnMut(u128)) {
-        self.next_time = self.next_time + Duration::from_micros(self.inter_arrival_time as u64);
-        let diff = (Instant::now() - self.next_time).as_nanos();
-        RateLimiter::wait_until(self.next_time);
-        let begin = Instant::now();
-        action();
-        let mut end = begin.elapsed().as_nanos();
-        if diff > 0 {
-            end += diff;
+    /// `sampling` receives the latency in nanoseconds and the target rate (all threads) the
+    /// operation was issued at; it is skipped when `action` reports a failed operation. Operations
+    /// after the first of a batch are due at the batch instant, so their latency includes waiting
+    /// for their predecessors. With `CatchUp::CoCorrect` the first operation of a batch also passes
+    /// the latencies of the operations the batch skipped.
+    pub fn run<F: FnMut() -> bool>(&mut self, mut action: F, mut sampling: impl FnMut(u64, f64)) {
+        let lateness;
+        let begin;
+        let first = self.batch_index == 0;
+        let waiting = Instant::now();
+        if first {
+            self.rate = self.schedule.rate_at(self.next_time - self.start) * self.scale;
+            let jitter = 1.0 + self.jitter * (2.0 * self.rng.f64() - 1.0); // mean 1
+            let inter_arrival_time =
+                inter_arrival_us(self.rate, self.threads, self.batch_size) * jitter;
+            self.next_time += Duration::from_micros(inter_arrival_time as u64);
+            self.inter_arrival_ns = inter_arrival_time as u64 * 1000;
+            lateness = match self.catch_up {
+                CatchUp::Burst => late_by(Instant::now(), self.next_time),
+                CatchUp::Skip | CatchUp::CoCorrect => self.skip_missed(),
+            };
+            RateLimiter::wait_until(self.next_time);
+            begin = Instant::now();
+            self.scheduling_error
+                .record(late_by(begin, self.next_time).max(0) as u64);
+        } else {
+            begin = Instant::now();
+            lateness = late_by(begin, self.next_time);
+        }
+        self.profile.spin += begin - waiting;
+        self.batch_index = (self.batch_index + 1) % self.batch_size;
+        let rate = self.rate;
+        let succeeded = action();
+        let done = Instant::now();
+        self.profile.io += done - begin;
+        if !succeeded {
+            return;
+        }
+        let latency = corrected_latency(done - begin, lateness);
+        sampling(latency, rate);
+        if first && self.catch_up == CatchUp::CoCorrect {
+            // the k-th missed batch was due k inter-arrival times before this one
+            for k in 1..=self.missed_batches {
+                let missed = latency.saturating_add(k.saturating_mul(self.inter_arrival_ns));
+                for _ in 0..self.batch_size {
+                    sampling(missed, rate);
+                }
+            }
         }
-        sampling(end);
+        self.profile.sampling += done.elapsed();
+    }
+
+    /// Moves the schedule to the latest batch that is due, skipping whole batches, and returns
+    /// how late that one is
+    fn skip_missed(&mut self) -> i64 {
+        let lateness = late_by(Instant::now(), self.next_time);
+        self.missed_batches = match self.inter_arrival_ns {
+            0 => 0,
+            interval => lateness.max(0) as u64 / interval,
+        };
+        let skipped = self.missed_batches * self.inter_arrival_ns;
+        self.next_time += Duration::from_nanos(skipped);
+        lateness - skipped as i64
+    }
+}
+
+/// Signed nanoseconds `now` is after `due`, negative if it is before; saturates at the i64 range
+/// (292 years), so neither order of the instants can panic or wrap
+fn late_by(now: Instant, due: Instant) -> i64 {
+    match now.checked_duration_since(due) {
+        Some(late) => i64::try_from(late.as_nanos()).unwrap_or(i64::MAX),
+        None => i64::try_from(due.duration_since(now).as_nanos()).map_or(i64::MIN, |early| -early),
+    }
+}
+
+/// The latency of an operation that took `service` and was issued `lateness` nanoseconds after it
+/// was due: being late is part of the latency (coordinated omission), being early is not
+fn corrected_latency(service: Duration, lateness: i64) -> u64 {
+    stats::nanos(service).saturating_add(lateness.max(0) as u64)
+}
+
+/// Accepts a percentage (`20%`) or a fraction (`0.2`)
+fn parse_jitter(value: &str) -> Result<f64, String> {
+    let jitter = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => value.trim().parse::<f64>(),
+    }
+    .map_err(|_| format!("Failed to parse jitter from {}", value))?;
+    if !(0.0..=1.0).contains(&jitter) {
+        return Err(format!("jitter must be between 0% and 100%, got {}", value));
     }
+    Ok(jitter)
 }
 
+#[cfg(unix)]
 fn get_device_capacity(device_name: &str) -> Result<u64, String> {
-    let sys_block_path = format!("/sys/class/block/{}/size", device_name);
-    let size_str = fs::read_to_string(Path::new(&sys_block_path))
-        .map_err(|_| format!("Failed to read from {}", sys_block_path))?;
+    let sys_block_path = device::sysfs_dir(device_name)?.join("size");
+    let size_str = fs::read_to_string(&sys_block_path)
+        .map_err(|_| format!("Failed to read from {}", sys_block_path.display()))?;
 
     let size_in_sectors: u64 = size_str
         .trim()
         .parse()
-        .map_err(|_| format!("Failed to parse size from {}", sys_block_path))?;
+        .map_err(|_| format!("Failed to parse size from {}", sys_block_path.display()))?;
 
     // The size is given in 512-byte sectors, convert to bytes
     let size_in_bytes = size_in_sectors * 512;
@@ -244,180 +1723,3246 @@ fn get_device_capacity(device_name: &str) -> Result<u64, String> {
     Ok(size_in_bytes)
 }
 
+#[cfg(windows)]
+fn get_device_capacity(device_name: &str) -> Result<u64, String> {
+    windows_io::Drive::open(device_name)
+        .and_then(|drive| drive.capacity())
+        .map_err(|e| format!("Failed to query the size of {}: {}", device_name, e))
+}
+
+/// The capacity of `device_name` in bytes; exits with the reason if it cannot be determined
+fn device_capacity(device_name: &str) -> u64 {
+    get_device_capacity(device_name)
+        .unwrap_or_else(|e| outcome::exit(outcome::Outcome::DeviceError, &e))
+}
+
+/// Set from --buffered-io before the first device is opened
+#[cfg(feature = "ci")]
+static BUFFERED_IO: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Whether the device is opened without O_DIRECT
+fn buffered_io() -> bool {
+    #[cfg(feature = "ci")]
+    return BUFFERED_IO.load(std::sync::atomic::Ordering::Relaxed);
+    #[cfg(not(feature = "ci"))]
+    false
+}
+
+#[cfg(unix)]
+fn open_ssd(ssd_device: &str) -> std::fs::File {
+    use libc::{O_DIRECT, O_RDWR};
+    use std::os::unix::fs::OpenOptionsExt;
+    let flags = if buffered_io() {
+        O_RDWR | libc::O_DSYNC
+    } else {
+        O_RDWR | O_DIRECT
+    };
+    let ssd_path = format!("/dev/{}", ssd_device);
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(flags)
+        .open(&ssd_path)
+        .unwrap_or_else(|e| {
+            let message = format!(
+                "Failed to open {}: {}{}",
+                ssd_path,
+                e,
+                if device::in_container() && e.kind() == std::io::ErrorKind::PermissionDenied {
+                    "; the container needs read and write access to the device (e.g., docker run --device with rw permissions)"
+                } else {
+                    ""
+                }
+            );
+            outcome::exit(outcome::Outcome::DeviceError, &message)
+        })
+}
+
+/// Opens a physical drive, e.g., `PhysicalDrive1`, unbuffered and write-through
+#[cfg(windows)]
+fn open_ssd(ssd_device: &str) -> windows_io::Drive {
+    windows_io::Drive::open(ssd_device).unwrap()
+}
+
 // returns the number of bytes that were intitizlied
-fn initialize_ssd(ssd_device: &str, utilization: f64) -> u64 {
+fn initialize_ssd(device: &engine::Device, utilization: f64) -> u64 {
     // write sequentially
     const BLOCK_SIZE: usize = 2097152;
-    let ssd_capacity_bytes = get_device_capacity(ssd_device).unwrap();
+    let ssd_capacity_bytes = device.capacity();
     let scratch_buffer = Box::new(DirectIOBuffer([5; BLOCK_SIZE]));
     let number_ios = ((ssd_capacity_bytes as f64 / BLOCK_SIZE as f64) * utilization) as u64;
-    let flags = O_RDWR | O_DIRECT;
-    let ssd_path = format!("/dev/{}", ssd_device);
-    let ssd_fd = std::fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .custom_flags(flags)
-        .open(ssd_path)
-        .unwrap();
+    let ssd_fd = device.open(engine::EngineKind::Psync);
 
+    let failed = |e: String| -> ! {
+        outcome::exit(
+            outcome::Outcome::DeviceError,
+            &format!("Failed to initialize {}: {}", device.name(), e),
+        )
+    };
     let mut initialized_bytes = 0;
     for i in 0..number_ios {
-        let res = ssd_fd
-            .write_at(&scratch_buffer.0, i * BLOCK_SIZE as u64)
-            .expect("Could not write");
-        assert_eq!(res, BLOCK_SIZE);
-        initialized_bytes += res as u64;
+        match ssd_fd.write_at(&scratch_buffer.0, i * BLOCK_SIZE as u64) {
+            Ok(res) if res == BLOCK_SIZE => initialized_bytes += res as u64,
+            Ok(res) => failed(format!("short write of {} bytes", res)),
+            Err(e) => failed(e.to_string()),
+        }
     }
-    ssd_fd.sync_data().unwrap();
+    ssd_fd.sync().unwrap_or_else(|e| failed(e.to_string()));
     initialized_bytes
 }
 
-fn partition(id: u64, participants: u64, n: u64) -> Range<u64> {
-    let block_size = n / participants;
-    let begin = id * block_size;
-    let mut end = begin + block_size;
-    if id == participants - 1 {
-        end = n;
+fn discard_ssd(device: &engine::Device) {
+    let ssd_fd = device.open(engine::EngineKind::Psync);
+    if let Err(e) = ssd_fd.discard(0, device.capacity()) {
+        println!(
+            "warning: could not discard {} ({}); the unused capacity may not act as overprovisioning",
+            device.name(),
+            e
+        );
     }
-    begin..end
 }
 
-const BLOCK_SIZE: usize = 4096;
-
-fn main() {
-    let config: &'static CliConfig = Box::leak(Box::new(CliConfig::parse()));
-
-    if config.preinitialize {
-        println!("Initializing SSDs ... ");
-        initialize_ssd(&config.ssd_device, config.capacity_fraction);
-        println!(" [Done]");
-    } else {
-        println!("No preinitialize");
+/// Writes `bytes` of the device once, e.g., so that reads hit mapped LBAs and not only the FTL
+fn fill_range(ssd_device: &str, bytes: Range<u64>) {
+    use engine::Engine;
+    const FILL_BLOCK_SIZE: u64 = 1048576;
+    let ssd_fd = open_ssd(ssd_device);
+    let buffer = buffer::AlignedBuffer::new(FILL_BLOCK_SIZE as usize, 0x3c);
+    let failed = |e: String| -> ! {
+        outcome::exit(
+            outcome::Outcome::DeviceError,
+            &format!("Failed to fill {}: {}", ssd_device, e),
+        )
+    };
+    for offset in bytes.clone().step_by(FILL_BLOCK_SIZE as usize) {
+        let len = FILL_BLOCK_SIZE.min(bytes.end - offset) as usize;
+        match ssd_fd.write_at(&buffer[..len], offset) {
+            Ok(res) if res == len => {}
+            Ok(res) => failed(format!("short write of {} bytes", res)),
+            Err(e) => failed(e.to_string()),
+        }
     }
+    ssd_fd.sync().unwrap_or_else(|e| failed(e.to_string()));
+}
 
-    let initialized_blocks = (get_device_capacity(&config.ssd_device).unwrap() as f64
-        * config.capacity_fraction) as u64
-        / BLOCK_SIZE as u64;
+/// First write of log segment `segment` when a pass over the region takes `writes` writes
+fn log_segment_start(segment: u64, writes: u64, segments: u64) -> u64 {
+    (segment * writes).div_ceil(segments)
+}
 
-    for utilization in config.utilization_iops.iter() {
-        let uuid = Uuid::new_v4();
-        // TODO: atomic counter
-        let barrier_counter = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
-        let threads: Vec<_> = (0..config.writer_threads)
-            .map(|worker_id| {
-                let barrier_counter = barrier_counter.clone();
-                std::thread::spawn(move || {
-                    let flags = O_RDWR | O_DIRECT;
-                    let ssd_path = format!("/dev/{}", config.ssd_device);
-                    let ssd_fd = std::fs::OpenOptions::new()
-                        .read(true)
-                        .write(true)
-                        .custom_flags(flags)
-                        .open(ssd_path)
-                        .unwrap();
-                    let buffer = Box::new(DirectIOBuffer([7; BLOCK_SIZE]));
-                    let mut samples = Vec::with_capacity(10000);
-                    let write_rate = config.max_iops as f64 * utilization;
-                    let range = partition(worker_id, config.writer_threads, initialized_blocks);
-                    let mut block_current = range.start;
-                    let mut operations = 0;
-
-                    barrier_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-                    while barrier_counter.load(std::sync::atomic::Ordering::SeqCst)
-                        != config.writer_threads
-                    {
-                        // spin
-                        std::hint::spin_loop();
-                    }
+/// The part of a region that is written: whole writes of `iovcnt` blocks wrap around it
+fn written_range(range: &Range<u64>, iovcnt: u64) -> Range<u64> {
+    range.start..range.start + (range.end - range.start) / iovcnt * iovcnt
+}
+
+const BLOCK_SIZE: usize = 4096;
+/// Warn when the p99 scheduling error exceeds this fraction of the per-thread inter-arrival time
+const SCHEDULING_ERROR_WARN_FRACTION: f64 = 0.1;
 
-                    let mut ratelimiter = RateLimiter::new(
-                        write_rate,
-                        config.writer_threads,
-                        worker_id,
-                        config.spiky,
+/// Parses the command line. Every flag can also be set with its SSD_BENCHY_* environment variable,
+/// e.g., SSD_BENCHY_MAX_IOPS for --max-iops; the variables of the benchmark flags are ignored when
+/// a subcommand runs, otherwise clap would take them for benchmark flags next to the subcommand.
+fn parse_cli<I, T>(args: I) -> Result<Cli, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let mut args: Vec<std::ffi::OsString> = args.into_iter().map(Into::into).collect();
+    let mut command = Cli::command();
+    if args
+        .get(1)
+        .is_some_and(|name| command.find_subcommand(name).is_some())
+    {
+        command = command.mut_args(|arg| arg.env(None));
+    } else {
+        let profile = profiles::name(&args);
+        if profile.as_deref().and_then(profiles::builtin).is_some() {
+            // the built-in profiles replace the sweep these flags define
+            for id in ["instance_type", "max_iops", "utilization_iops"] {
+                command = command.mut_arg(id, |arg| arg.required(false));
+            }
+        }
+        if let Some(name) = profile.filter(|name| profiles::builtin(name).is_none()) {
+            let flags: Vec<(String, String)> = command
+                .get_arguments()
+                .filter_map(|arg| Some((arg.get_long()?, arg.get_all_aliases())))
+                .flat_map(|(long, aliases)| {
+                    aliases
+                        .into_iter()
+                        .flatten()
+                        .chain([long])
+                        .map(move |name| (name.to_string(), long.to_string()))
+                })
+                .collect();
+            args = profiles::expand(&args, &name, &flags)
+                .map_err(|e| command.error(clap::error::ErrorKind::InvalidValue, e))?;
+        }
+        if profiles::flag_value(&args, "scenario", "SSD_BENCHY_SCENARIO").is_some() {
+            // the phases have utilizations of their own
+            command = command.mut_arg("utilization_iops", |arg| arg.required(false));
+        }
+    }
+    Cli::from_arg_matches(&command.try_get_matches_from(args)?)
+}
+
+/// Every result file the tool writes, for `ssd-benchy schema`
+fn result_files() -> Vec<schema::ResultFile> {
+    let benchmark = |option, columns| schema::ResultFile {
+        command: "ssd-benchy",
+        option,
+        columns,
+    };
+    let mut files = vec![
+        benchmark(
+            "--summary-file",
+            schema::columns_of(&(
+                BenchmarkConfig::default(),
+                SummaryStatistics::default(),
+                AchievedStatistics::default(),
+                bulk::BulkStatistics::default(),
+                nvme_ops::OpStatistics::default(),
+                thermal::TemperatureStatistics::default(),
+                energy::EnergyStatistics::default(),
+                stability::StabilityStatistics::default(),
+                fill_drift::FillStatistics::default(),
+                drift::DriftStatistics::default(),
+                sched_stats::SchedStatistics::default(),
+                thread_profile::ProfileStatistics::default(),
+            )),
+        ),
+        benchmark("--samples-file", schema::columns_of(&Sample::default())),
+        benchmark(
+            "--rate-buckets-file",
+            schema::columns_of(&(RateBucket::default(), SummaryStatistics::default())),
+        ),
+        benchmark(
+            "--histogram-file",
+            schema::columns_of(&HistogramBucket::default()),
+        ),
+        benchmark(
+            "--lba-slices-file",
+            schema::columns_of(&LbaSlice::default()),
+        ),
+        benchmark(
+            "--idle-gap-file",
+            schema::columns_of(&idle_gap::IdleGapBucket::default()),
+        ),
+        benchmark(
+            "--drift-file",
+            schema::columns_of(&drift::DriftEvent::default()),
+        ),
+        benchmark(
+            "--fanout-file",
+            schema::columns_of(&fanout::FanoutProjection::default()),
+        ),
+        benchmark(
+            "--namespace-stats-file",
+            schema::columns_of(&namespaces::NamespaceStatistics::default()),
+        ),
+        benchmark(
+            "--priority-stats-file",
+            schema::columns_of(&ioprio::PriorityStatistics::default()),
+        ),
+        benchmark(
+            "--thread-group-stats-file",
+            schema::columns_of(&thread_groups::GroupStatistics::default()),
+        ),
+        benchmark(
+            "--outliers-file",
+            schema::columns_of(&outliers::Outlier::default()),
+        ),
+        benchmark(
+            "--host-stats-file",
+            schema::columns_of(&host_stats::HostSample::default()),
+        ),
+        benchmark(
+            "--crash-ack-file",
+            schema::columns_of(&crash::Ack::default()),
+        ),
+    ];
+    files.extend(compare::result_files());
+    files.extend(gc_recovery::result_files());
+    files.extend(qd_curve::result_files());
+    files.extend(pts::result_files());
+    files.extend(qlc_folding::result_files());
+    files.extend(read_disturb::result_files());
+    files.extend(scenario::result_files());
+    files.extend(slc_cache::result_files());
+    files.extend(trim_freshness::result_files());
+    files
+}
+
+/// Exits unless the device `name` has the serial number of --expect-serial, if given
+fn ensure_serial(config: &CliConfig, name: &str, serial: Option<String>) {
+    let Some(expected) = &config.expect_serial else {
+        return;
+    };
+    if serial.as_deref() != Some(expected.as_str()) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            &format!(
+                "{} has the serial number {}, not the --expect-serial {}; refusing to write it",
+                name,
+                serial.as_deref().unwrap_or("(unknown)"),
+                expected
+            ),
+        );
+    }
+}
+
+fn main() {
+    let cli = parse_cli(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    match cli.command {
+        Some(Command::Aggregate(args)) => aggregate::run(&args),
+        Some(Command::Anonymize(args)) => anonymize::run(&args),
+        Some(Command::Analyze(args)) => checkpoint::run(&args),
+        Some(Command::Compare(args)) => compare::run(&args),
+        #[cfg(target_os = "linux")]
+        Some(Command::DmHarness(args)) => dm_harness::run(&args),
+        Some(Command::Plot(args)) => plot::run(&args),
+        #[cfg(unix)]
+        Some(Command::Preflight(args)) => preflight::run(&args),
+        Some(Command::QdCurve(args)) => qd_curve::run(&args),
+        Some(Command::GcRecovery(args)) => gc_recovery::run(&args),
+        Some(Command::GrafanaDashboard(args)) => grafana::run(&args),
+        Some(Command::Matrix(args)) => matrix::run(&args),
+        Some(Command::Merge(args)) => merge::run(&args),
+        Some(Command::QlcFolding(args)) => qlc_folding::run(&args),
+        Some(Command::Quick(args)) => quick::run(&args),
+        Some(Command::ReadDisturb(args)) => read_disturb::run(&args),
+        Some(Command::Report(args)) => report::run(&args),
+        Some(Command::Schema(args)) => schema::run(&args, &result_files()),
+        Some(Command::SlcCache(args)) => slc_cache::run(&args),
+        Some(Command::TrimFreshness(args)) => trim_freshness::run(&args),
+        Some(Command::VerifyAfterCrash(args)) => {
+            if !crash::verify_after_crash(&args) {
+                std::process::exit(1);
+            }
+        }
+        None => {
+            let mut config = cli
+                .benchmark
+                .expect("clap requires the benchmark arguments");
+            progress::set_format(config.progress_format);
+            config.window = lba_window::Window::new(
+                config.capacity_bytes,
+                config.lba_window,
+                BLOCK_SIZE as u64,
+            )
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+            #[cfg(target_os = "linux")]
+            privileges::enforce(&mut config);
+            if let Some(path) = &config.thread_groups {
+                config.groups = thread_groups::load(path, config.iovcnt, config.rate_pattern)
+                    .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+                config.writer_threads = config.groups.iter().map(|g| g.threads).sum();
+            }
+            if let Some(profile) = config.profile.as_deref().and_then(profiles::builtin) {
+                let Some(ssd_device) = &config.ssd_device else {
+                    outcome::exit(
+                        outcome::Outcome::ConfigError,
+                        "--profile requires --ssd-device",
+                    );
+                };
+                validate(&config);
+                if config.hook_pre_point.is_some() || config.hook_post_point.is_some() {
+                    outcome::exit(
+                        outcome::Outcome::ConfigError,
+                        "--profile snia-iops and snia-latency run rounds instead of utilization points; --hook-pre-point and --hook-post-point do not apply",
+                    );
+                }
+                #[cfg(unix)]
+                ensure_serial(&config, ssd_device, device::identity(ssd_device).serial);
+                refuse_results_on_written_disks(
+                    &config,
+                    &[&config.pts_file],
+                    std::slice::from_ref(ssd_device),
+                );
+                let hooks = configured_hooks(&config, &config.pts_file);
+                outcome::start(config.result_json.clone(), 0);
+                pts::run(&pts::Options {
+                    profile,
+                    ssd_device,
+                    combination: Duration::from_secs(config.pts_seconds),
+                    max_rounds: config.pts_max_rounds,
+                    output_file: &config.pts_file,
+                    hooks: &hooks,
+                });
+                outcome::exit(outcome::Outcome::Success, "");
+            }
+            if let Some(dir) = config.staging_dir.clone() {
+                let paths = vec![
+                    &mut config.summary_file,
+                    &mut config.samples_file,
+                    &mut config.rate_buckets_file,
+                    &mut config.histogram_file,
+                    &mut config.lba_slices_file,
+                    &mut config.idle_gap_file,
+                    &mut config.drift_file,
+                    &mut config.fanout_file,
+                    &mut config.outliers_file,
+                    &mut config.namespace_stats_file,
+                    &mut config.priority_stats_file,
+                    &mut config.thread_group_stats_file,
+                    &mut config.host_stats_file,
+                    &mut config.scenario_stats_file,
+                ];
+                staging::stage(&dir, paths)
+                    .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+            }
+            if let Some(path) = &config.scenario {
+                let phases = scenario::load(path)
+                    .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+                validate(&config);
+                if config.hook_pre_preinit.is_some() || config.hook_post_preinit.is_some() {
+                    outcome::exit(
+                        outcome::Outcome::ConfigError,
+                        "--scenario preconditions in its phases, which run the point hooks; --hook-pre-preinit and --hook-post-preinit do not apply",
                     );
-                    let end_time = Instant::now() + Duration::from_secs(config.runtime_seconds);
+                }
+                let hooks = configured_hooks(&config, &config.scenario_stats_file);
+                outcome::start(config.result_json.clone(), phases.len());
+                scenario::run(&config, &phases, &hooks);
+                outcome::exit(outcome::Outcome::Success, "");
+            }
+            if let Some(hours) = config.soak_hours {
+                if !(hours > 0.0 && hours.is_finite()) {
+                    outcome::exit(
+                        outcome::Outcome::ConfigError,
+                        "--soak-hours must be positive",
+                    );
+                }
+                config.runtime_seconds = config.soak_rotate_minutes * 60;
+            }
+            run_benchmark(Box::leak(Box::new(config)));
+        }
+    }
+}
 
-                    while Instant::now() < end_time {
-                        if block_current >= range.end {
-                            block_current = range.start;
-                        }
-                        ratelimiter.run(
-                            || {
-                                let res = ssd_fd
-                                    .write_at(&buffer.0, block_current * BLOCK_SIZE as u64)
-                                    .expect("could not write");
-                                if config.use_fsync {
-                                    ssd_fd.sync_data().unwrap();
-                                }
-                                assert_eq!(res, BLOCK_SIZE)
-                            },
-                            |latency| {
-                                if fastrand::u64(0..1000) <= 1 {
-                                    samples.push(Sample {
-                                        latency,
-                                        id: operations,
-                                        uuid: uuid.as_u128(),
-                                    })
-                                }
-                            },
-                        );
-                        operations += 1;
-                        block_current += 1;
+/// Refuses flag combinations no device could run, before any device is opened or written.
+fn validate(config: &CliConfig) {
+    let varying_rate = config.rate_pattern != RatePattern::Constant
+        || config
+            .groups
+            .iter()
+            .any(|g| g.rate_pattern != RatePattern::Constant);
+    let lowest_utilization = config
+        .utilization_iops
+        .iter()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    if varying_rate
+        && !(config.rate_min_utilization > 0.0 && config.rate_min_utilization <= lowest_utilization)
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            &format!(
+                "--rate-min-utilization must be within (0, {}], the lowest --utilization-iops",
+                lowest_utilization
+            ),
+        );
+    }
+    if config.engines.contains(&engine::EngineKind::IoUringLinked)
+        && (!config.use_fsync || config.group_commit_us > 0 || config.group_commit_writers > 0)
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "the io-uring-linked engine links an fsync to every write and requires --use-fsync without group commit",
+        );
+    }
+    if config.workload == Workload::Log && (config.verify || config.crash_records) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--workload log discards data and cannot be combined with --verify or --crash-records",
+        );
+    }
+    if config.workload == Workload::Transaction
+        && (config.verify
+            || config.crash_records
+            || config.use_fsync
+            || config.engines.contains(&engine::EngineKind::IoUringLinked))
+    {
+        outcome::exit(outcome::Outcome::ConfigError, "--workload transaction issues its own flushes and cannot be combined with --use-fsync, --verify, or --crash-records");
+    }
+    if let Err(e) = idle_gap::IdleGaps::validate(&config.idle_gap_buckets_us) {
+        outcome::exit(outcome::Outcome::ConfigError, &e);
+    }
+    if config.sampling_method == SamplingMethod::Tail {
+        if let Err(e) = tail_sampling::TailSampler::validate(config.tail_percentile) {
+            outcome::exit(outcome::Outcome::ConfigError, &e);
+        }
+        if !config.serialize_samples || config.samples_max_rows > 0 {
+            outcome::exit(outcome::Outcome::ConfigError, "--sampling-method tail requires --serialize-samples and keeps every write of the tail, which --samples-max-rows would drop");
+        }
+    }
+    if !(config.sample_rate > 0.0 && config.sample_rate <= 1.0) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--sample-rate must be within (0, 1]",
+        );
+    }
+    if config.samples_format == sample_writer::SamplesFormat::ZstdSeekable {
+        if !seekable::SUPPORTED {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--samples-format zstd-seekable requires a build with --features zstd",
+            );
+        }
+        if config.samples_per_thread || config.samples_frame_ms == 0 {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--samples-format zstd-seekable writes one file per point and cannot be combined with --samples-per-thread; --samples-frame-ms must be positive",
+            );
+        }
+    }
+    if config.fanouts.contains(&0) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--fanouts must be at least 1",
+        );
+    }
+    if config.bulk_threads > 0 && config.verify {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--bulk-threads cannot be combined with --verify",
+        );
+    }
+    if config.fill_drift_mb_per_second < 0.0
+        || !(config.fill_drift_limit > 0.0 && config.fill_drift_limit <= 1.0)
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--fill-drift-mb-per-second must not be negative and --fill-drift-limit must be within (0, 1]",
+        );
+    }
+    if config.bulk_write_bytes == 0 || !config.bulk_write_bytes.is_multiple_of(BLOCK_SIZE as u64) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            &format!("--bulk-write-bytes must be a multiple of {}", BLOCK_SIZE),
+        );
+    }
+    if let (Some(soak), Some(max)) = (
+        config.soak_temperature_celsius,
+        config.max_temperature_celsius,
+    ) {
+        if soak > max {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--soak-temperature-celsius must not exceed --max-temperature-celsius",
+            );
+        }
+    }
+    if let Some(threshold) = config.drift_threshold {
+        if threshold.is_nan() || threshold <= 0.0 || config.stability_window_seconds == 0 {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--drift-threshold must be positive and requires --stability-window-seconds",
+            );
+        }
+    }
+    if !config.groups.is_empty()
+        && (config.write_boundary.is_some()
+            || config.write_zeroes_fraction > 0.0
+            || config.deallocate_fraction > 0.0
+            || config.copy_fraction > 0.0)
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--thread-groups cannot be combined with --write-boundary, --write-zeroes-fraction, --deallocate-fraction, or --copy-fraction",
+        );
+    }
+    if config.write_zeroes_fraction > 0.0
+        || config.deallocate_fraction > 0.0
+        || config.copy_fraction > 0.0
+    {
+        let fractions = [
+            config.write_zeroes_fraction,
+            config.deallocate_fraction,
+            config.copy_fraction,
+        ];
+        if fractions.iter().any(|f| !(0.0..=1.0).contains(f)) || fractions.iter().sum::<f64>() > 1.0
+        {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--write-zeroes-fraction, --deallocate-fraction, and --copy-fraction must be within [0, 1] and add up to at most 1",
+            );
+        }
+        if config.verify || config.crash_records {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--write-zeroes-fraction, --deallocate-fraction, and --copy-fraction replace writes and cannot be combined with --verify or --crash-records",
+            );
+        }
+        let write_len = config.iovcnt * BLOCK_SIZE as u64;
+        if config.copy_fraction > 0.0
+            && (config.copy_ranges == 0 || !write_len.is_multiple_of(config.copy_ranges))
+        {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!(
+                    "--copy-ranges must split the write size of {} bytes evenly",
+                    write_len
+                ),
+            );
+        }
+    }
+    if !config.concurrent_namespaces.is_empty()
+        && !config.engines.iter().all(|kind| {
+            matches!(
+                kind,
+                engine::EngineKind::Psync
+                    | engine::EngineKind::IoUring
+                    | engine::EngineKind::IoUringLinked
+                    | engine::EngineKind::Pvsync2
+            )
+        })
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--concurrent-namespaces requires the psync, io-uring, or pvsync2 engine",
+        );
+    }
+    if let Some(ssd_device) = &config.ssd_device {
+        if config.concurrent_namespaces.contains(ssd_device) {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--concurrent-namespaces must not contain the --ssd-device",
+            );
+        }
+    }
+    if (config.cgroup_io_max.is_some() || config.cgroup_io_latency_us.is_some())
+        && !config.engines.iter().all(|kind| {
+            matches!(
+                kind,
+                engine::EngineKind::Psync
+                    | engine::EngineKind::IoUring
+                    | engine::EngineKind::IoUringLinked
+                    | engine::EngineKind::Pvsync2
+                    | engine::EngineKind::NvmePi
+            )
+        })
+    {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            "--cgroup-io-max and --cgroup-io-latency-us require an engine that writes the --ssd-device",
+        );
+    }
+    if config.memory_budget_mb > 0 {
+        if let Err(e) = memory::thread_share(
+            config.memory_budget_mb,
+            config.writer_threads,
+            config.serialize_samples,
+        ) {
+            outcome::exit(outcome::Outcome::ConfigError, &e);
+        }
+    }
+}
+
+/// Refuses result files the run cannot append to or that share a disk with a written device,
+/// and warns about firmware updates since their earlier rows
+fn check_result_files(
+    config: &CliConfig,
+    devices: &[(engine::EngineKind, &'static engine::Device)],
+) {
+    let (first_engine, first_device) = devices[0];
+    // refuse (or migrate) before spending hours on a run whose results cannot be appended
+    let summary_header = schema::header_of(&(
+        BenchmarkConfig::from_cli_config(
+            config,
+            first_device,
+            first_engine,
+            config.capacity_fraction[0],
+            config.utilization_iops[0],
+            0,
+            0,
+        ),
+        SummaryStatistics::default(),
+        AchievedStatistics::default(),
+        bulk::BulkStatistics::default(),
+        nvme_ops::OpStatistics::default(),
+        thermal::TemperatureStatistics::default(),
+        energy::EnergyStatistics::default(),
+        stability::StabilityStatistics::default(),
+        fill_drift::FillStatistics::default(),
+        drift::DriftStatistics::default(),
+        sched_stats::SchedStatistics::default(),
+        thread_profile::ProfileStatistics::default(),
+    ));
+    let mut schema_checks = vec![(&config.summary_file, summary_header)];
+    if config.serialize_samples && config.samples_format == sample_writer::SamplesFormat::Csv {
+        schema_checks.push((&config.samples_file, schema::header_of(&Sample::default())));
+    }
+    if config.rate_pattern != RatePattern::Constant {
+        schema_checks.push((
+            &config.rate_buckets_file,
+            schema::header_of(&(RateBucket::default(), SummaryStatistics::default())),
+        ));
+    }
+    if config.export_histograms {
+        schema_checks.push((
+            &config.histogram_file,
+            schema::header_of(&HistogramBucket::default()),
+        ));
+    }
+    if config.lba_slices > 0 {
+        schema_checks.push((
+            &config.lba_slices_file,
+            schema::header_of(&LbaSlice::default()),
+        ));
+    }
+    if config.drift_threshold.is_some() {
+        schema_checks.push((
+            &config.drift_file,
+            schema::header_of(&drift::DriftEvent::default()),
+        ));
+    }
+    if !config.idle_gap_buckets_us.is_empty() {
+        schema_checks.push((
+            &config.idle_gap_file,
+            schema::header_of(&idle_gap::IdleGapBucket::default()),
+        ));
+    }
+    if !config.fanouts.is_empty() {
+        schema_checks.push((
+            &config.fanout_file,
+            schema::header_of(&fanout::FanoutProjection::default()),
+        ));
+    }
+    if !config.concurrent_namespaces.is_empty() {
+        schema_checks.push((
+            &config.namespace_stats_file,
+            schema::header_of(&namespaces::NamespaceStatistics::default()),
+        ));
+    }
+    if !config.io_priorities.is_empty() {
+        schema_checks.push((
+            &config.priority_stats_file,
+            schema::header_of(&ioprio::PriorityStatistics::default()),
+        ));
+    }
+    if !config.groups.is_empty() {
+        schema_checks.push((
+            &config.thread_group_stats_file,
+            schema::header_of(&thread_groups::GroupStatistics::default()),
+        ));
+    }
+    if config.outlier_threshold_us > 0 {
+        schema_checks.push((
+            &config.outliers_file,
+            schema::header_of(&outliers::Outlier::default()),
+        ));
+    }
+    if config.host_stats_interval_ms > 0 {
+        schema_checks.push((
+            &config.host_stats_file,
+            schema::header_of(&host_stats::HostSample::default()),
+        ));
+    }
+    if let Some(ack_file) = &config.crash_ack_file {
+        schema_checks.push((ack_file, schema::header_of(&crash::Ack::default())));
+    }
+    // results written to the device under test are IO the measurement did not ask for
+    let mut result_files: Vec<&String> = schema_checks.iter().map(|(file, _)| *file).collect();
+    if config.serialize_samples {
+        result_files.push(&config.samples_file);
+    }
+    let written_devices: Vec<String> = devices
+        .iter()
+        .map(|(_, device)| device.name())
+        .chain(config.concurrent_namespaces.iter().cloned())
+        .collect();
+    refuse_results_on_written_disks(config, &result_files, &written_devices);
+    for (file, header) in schema_checks {
+        if let Err(e) = schema::ensure_compatible(Path::new(file), &header, config.schema_mismatch)
+        {
+            outcome::exit(outcome::Outcome::ConfigError, &e);
+        }
+    }
+    // silent firmware updates between runs make the rows of one file incomparable
+    for (_, device) in devices.iter() {
+        let identity = device.identity();
+        let (Some(serial), Some(firmware)) = (identity.serial, identity.firmware_rev) else {
+            continue;
+        };
+        let revisions = schema::firmware_revisions(Path::new(&config.summary_file), &serial)
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+        let others: Vec<&String> = revisions.iter().filter(|r| **r != firmware).collect();
+        if !others.is_empty() {
+            println!(
+                "warning: {} (serial {}) runs firmware {}, but earlier rows of {} were measured with {}; their latencies may not be comparable",
+                device.name(),
+                serial,
+                firmware,
+                config.summary_file,
+                others.iter().map(|r| r.as_str()).collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+}
+
+/// Refuses result `files` on a file system of one of the `written_devices`, or warns about them
+/// with --allow-same-device
+fn refuse_results_on_written_disks(
+    config: &CliConfig,
+    files: &[&String],
+    written_devices: &[String],
+) {
+    #[cfg(unix)]
+    for file in files {
+        if let Some(ssd_device) = written_devices
+            .iter()
+            .find(|ssd_device| device::shares_disk(Path::new(file), ssd_device))
+        {
+            let message = format!(
+                "{} is on the same disk as {}, which the benchmark writes; its writes perturb the measurement",
+                file, ssd_device
+            );
+            if !config.allow_same_device {
+                outcome::exit(
+                    outcome::Outcome::ConfigError,
+                    &format!(
+                        "{}; write the results elsewhere or pass --allow-same-device",
+                        message
+                    ),
+                );
+            }
+            println!("warning: {}", message);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (config, files, written_devices);
+}
+
+/// The --hook-* commands; without --hook-log-dir their logs go to `hooks/` next to `results`
+fn configured_hooks(config: &CliConfig, results: &str) -> hooks::Hooks {
+    hooks::Hooks {
+        commands: [
+            (hooks::Hook::PreRun, &config.hook_pre_run),
+            (hooks::Hook::PostRun, &config.hook_post_run),
+            (hooks::Hook::PrePreinit, &config.hook_pre_preinit),
+            (hooks::Hook::PostPreinit, &config.hook_post_preinit),
+            (hooks::Hook::PrePoint, &config.hook_pre_point),
+            (hooks::Hook::PostPoint, &config.hook_post_point),
+        ]
+        .into_iter()
+        .filter_map(|(hook, command)| Some((hook, command.clone()?)))
+        .collect(),
+        log_dir: match &config.hook_log_dir {
+            Some(dir) => dir.into(),
+            None => Path::new(&staging::final_path(results))
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join("hooks"),
+        },
+    }
+}
+
+fn run_benchmark(config: &'static CliConfig) {
+    #[cfg(feature = "ci")]
+    BUFFERED_IO.store(config.buffered_io, std::sync::atomic::Ordering::Relaxed);
+    pause::install();
+    // the utilization points are repeated in every segment of a soak run
+    let soak_segments = config
+        .soak_hours
+        .map_or(1, |hours| soak::segments(hours, config.soak_rotate_minutes));
+    outcome::start(
+        config.result_json.clone(),
+        config.capacity_fraction.len()
+            * config.utilization_iops.len()
+            * config.engines.len()
+            * soak_segments as usize,
+    );
+    validate(config);
+    let max_iovcnt = (0..config.writer_threads)
+        .map(|worker_id| config.iovcnt_of(worker_id))
+        .max()
+        .unwrap_or(config.iovcnt);
+    // engines on the same target share one device, e.g., psync and io-uring on the SSD
+    let mut devices: Vec<(engine::EngineKind, &'static engine::Device)> = vec![];
+    for &kind in &config.engines {
+        let device = engine::Device::new(
+            kind,
+            config.ssd_device.as_deref(),
+            config.simulated_device_bytes,
+            Duration::from_secs_f64(config.simulated_latency_us / 1e6),
+            engine::IoUringOptions {
+                sqpoll: config.sqpoll,
+                sqpoll_cpu: config.sqpoll_cpu,
+                hipri: config.hipri,
+            },
+            config.pi_mode,
+            config.window,
+        );
+        if (config.soak_temperature_celsius.is_some() || config.max_temperature_celsius.is_some())
+            && thermal::temperature(&device).is_none()
+        {
+            outcome::exit(outcome::Outcome::ConfigError, "--soak-temperature-celsius and --max-temperature-celsius require the SMART log of an NVMe device");
+        }
+        if config.verify && !device.stores_data() {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                "--verify requires an engine that stores the written data",
+            );
+        }
+        let device = match devices.iter().find(|(_, d)| d.name() == device.name()) {
+            Some((_, d)) => *d,
+            None => Box::leak(Box::new(device)),
+        };
+        devices.push((kind, device));
+    }
+    let (_, first_device) = devices[0];
+    let drift_webhook = config.drift_webhook.as_deref().map(|url| {
+        drift::Webhook::parse(url)
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e))
+    });
+    if config.telemetry_threshold_us > 0 {
+        for (_, device) in devices.iter() {
+            if let Err(e) = telemetry::check(&device.name()) {
+                outcome::exit(
+                    outcome::Outcome::ConfigError,
+                    &format!("--telemetry-threshold-us requires an NVMe device: {}", e),
+                );
+            }
+        }
+    }
+    if config.engines.contains(&engine::EngineKind::NvmePi) {
+        let format = nvme::namespace_format(config.ssd_device.as_deref().unwrap_or_default())
+            .unwrap_or_else(|e| {
+                outcome::exit(
+                    outcome::Outcome::DeviceError,
+                    &format!("the nvme-pi engine requires an NVMe namespace: {}", e),
+                )
+            });
+        if config.pi_mode != pi::PiMode::Unchecked && format.pi_type == 0 {
+            outcome::exit(outcome::Outcome::ConfigError, &format!("--pi-mode {} requires a namespace formatted with protection information, e.g., nvme format --pi 1; --pi-mode unchecked runs without", config.pi_mode));
+        }
+        if config.pi_mode == pi::PiMode::Controller && format.metadata_bytes != 8 {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!(
+                    "--pi-mode controller requires 8 bytes of metadata per block, the namespace has {}",
+                    format.metadata_bytes
+                ),
+            );
+        }
+        if format.lba_bytes > BLOCK_SIZE || !BLOCK_SIZE.is_multiple_of(format.lba_bytes) {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!(
+                    "the nvme-pi engine requires an LBA size that divides {}, the namespace has {}",
+                    BLOCK_SIZE, format.lba_bytes
+                ),
+            );
+        }
+        if format.extended
+            && (config.preinitialize
+                || config.capacity_fraction.len() > 1
+                || config.verify
+                || config
+                    .engines
+                    .iter()
+                    .any(|&kind| kind != engine::EngineKind::NvmePi))
+        {
+            outcome::exit(outcome::Outcome::ConfigError, "the namespace has extended LBAs, which only the nvme-pi engine can write; run without --preinitialize, capacity sweeps, and --verify");
+        }
+    }
+    let boundary_bytes = boundary::bytes(
+        config.write_boundary,
+        config.ssd_device.as_deref().unwrap_or_default(),
+    )
+    .unwrap_or_else(|e| {
+        outcome::exit(
+            outcome::Outcome::DeviceError,
+            &format!("--write-boundary atomic: {}", e),
+        )
+    });
+    if !boundary_bytes.is_multiple_of(BLOCK_SIZE as u64) {
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            &format!(
+                "--write-boundary must be a multiple of {}, got {}",
+                BLOCK_SIZE, boundary_bytes
+            ),
+        );
+    }
+    let boundary_blocks = boundary_bytes / BLOCK_SIZE as u64;
+    if config.write_boundary.is_some() && (config.iovcnt < 2 || config.iovcnt < boundary_blocks) {
+        outcome::exit(outcome::Outcome::ConfigError, &format!("--write-boundary of {} bytes needs writes of at least one unit and two blocks; raise --iovcnt to {}", boundary_bytes, boundary_blocks.max(2)));
+    }
+    if config.write_boundary.is_some() {
+        println!("every write crosses a boundary of {} bytes", boundary_bytes);
+    }
+    let op_mix = nvme_ops::OpMix {
+        write_zeroes: config.write_zeroes_fraction,
+        deallocate: config.deallocate_fraction,
+        copy: config.copy_fraction,
+        copy_ranges: config.copy_ranges,
+    };
+    if op_mix.any() {
+        let write_len = config.iovcnt * BLOCK_SIZE as u64;
+        for (_, device) in devices.iter() {
+            let format = nvme::namespace_format(&device.name()).unwrap_or_else(|e| {
+                outcome::exit(
+                    outcome::Outcome::DeviceError,
+                    &format!("--write-zeroes-fraction, --deallocate-fraction, and --copy-fraction require an NVMe namespace: {}", e),
+                )
+            });
+            if config.copy_fraction == 0.0 {
+                continue;
+            }
+            let limits = nvme::copy_limits(&device.name())
+                .unwrap_or_else(|e| outcome::exit(outcome::Outcome::DeviceError, &e));
+            let range_len = write_len / config.copy_ranges;
+            if !range_len.is_multiple_of(format.lba_bytes as u64)
+                || config.copy_ranges > limits.max_ranges as u64
+                || range_len / format.lba_bytes as u64 > limits.max_range_blocks as u64
+                || write_len / format.lba_bytes as u64 > limits.max_blocks as u64
+            {
+                outcome::exit(
+                    outcome::Outcome::ConfigError,
+                    &format!(
+                        "/dev/{} copies at most {} ranges of {} blocks and {} blocks in total of {} bytes, --copy-ranges {} of {} bytes do not fit",
+                        device.name(),
+                        limits.max_ranges,
+                        limits.max_range_blocks,
+                        limits.max_blocks,
+                        format.lba_bytes,
+                        config.copy_ranges,
+                        range_len
+                    ),
+                );
+            }
+        }
+    }
+    let telemetry_options = telemetry::Options {
+        dir: config.telemetry_dir.clone(),
+        threshold_ns: config.telemetry_threshold_us * 1000,
+        cooldown: Duration::from_secs(config.telemetry_cooldown_seconds),
+    };
+    if let Some(ssd_device) = &config.ssd_device {
+        if first_device.name() == *ssd_device {
+            namespaces::warn_about_siblings(ssd_device, &config.concurrent_namespaces);
+        }
+    }
+    for (_, device) in devices.iter() {
+        ensure_serial(config, &device.name(), device.identity().serial);
+    }
+    if !config.io_priorities.is_empty() {
+        for (_, device) in devices.iter() {
+            ioprio::warn_about_device(&device.name());
+        }
+    }
+    let cgroup =
+        (config.cgroup_io_max.is_some() || config.cgroup_io_latency_us.is_some()).then(|| {
+            let ssd_devices: Vec<_> = devices.iter().map(|(_, device)| device.name()).collect();
+            cgroup::create(
+                &config.cgroup_parent,
+                config.cgroup_io_max.as_deref(),
+                config.cgroup_io_latency_us,
+                &ssd_devices,
+            )
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e))
+        });
+    // the concurrent namespaces are written with the engine of the point, like the measured one
+    let namespace_devices: Vec<&'static engine::Device> = config
+        .concurrent_namespaces
+        .iter()
+        .map(|name| {
+            let device: &'static engine::Device = Box::leak(Box::new(engine::Device::new(
+                engine::EngineKind::Psync,
+                Some(name),
+                config.simulated_device_bytes,
+                Duration::ZERO,
+                engine::IoUringOptions {
+                    sqpoll: config.sqpoll,
+                    sqpoll_cpu: config.sqpoll_cpu,
+                    hipri: config.hipri,
+                },
+                config.pi_mode,
+                lba_window::Window::default(),
+            )));
+            device
+        })
+        .collect();
+    check_result_files(config, &devices);
+
+    let hooks = configured_hooks(config, &config.summary_file);
+    let run_label = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("")
+        .as_secs()
+        .to_string();
+    let hooked_devices = devices
+        .iter()
+        .map(|(_, device)| device.name())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let faults = engine::Faults {
+        eio_probability: config.fault_eio_probability,
+        short_write_probability: config.fault_short_write_probability,
+        delay_probability: config.fault_delay_probability,
+        delay: Duration::from_micros(config.fault_delay_us),
+    };
+
+    let sample_seed = config.sample_seed.unwrap_or_else(|| fastrand::u64(..));
+    let rapl = config.measure_energy.then(|| {
+        energy::Rapl::open().unwrap_or_else(|e| {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!("--measure-energy: {}", e),
+            );
+        })
+    });
+    let metrics = (config.metrics_listen.is_some()
+        || config.influx_output.is_some()
+        || config.control_socket.is_some())
+    .then(|| metrics::Metrics::new(&gethostname().to_string_lossy(), &config.instance_type));
+    if let (Some(metrics), Some(address)) = (metrics, &config.metrics_listen) {
+        metrics.serve(address).unwrap_or_else(|e| {
+            outcome::exit(outcome::Outcome::ConfigError, &e);
+        });
+    }
+    let influx_writer = metrics
+        .zip(config.influx_output.as_ref())
+        .map(|(metrics, output)| {
+            influx::InfluxWriter::spawn(
+                metrics,
+                output,
+                Duration::from_millis(config.influx_interval_ms.max(1)),
+            )
+            .unwrap_or_else(|e| {
+                outcome::exit(outcome::Outcome::ConfigError, &e);
+            })
+        });
+    let checkpoint_interval = Duration::from_secs(config.checkpoint_interval_seconds.max(1));
+    let checkpointer = config.checkpoint_file.as_ref().map(|path| {
+        checkpoint::Checkpointer::spawn(
+            path,
+            &gethostname().to_string_lossy(),
+            &config.instance_type,
+            config.capacity_fraction.len()
+                * config.utilization_iops.len()
+                * devices.len()
+                * soak_segments as usize,
+            checkpoint_interval,
+        )
+        .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e))
+    });
+    let progress_interval = Duration::from_secs(config.progress_interval_seconds.max(1));
+    let progress_reporter = (config.progress_format == progress::ProgressFormat::Json)
+        .then(|| progress::Reporter::spawn(progress_interval));
+    let summary_flush_interval = Duration::from_secs(config.summary_flush_seconds.max(1));
+    let summary_flusher = (config.summary_flush_seconds > 0)
+        .then(|| summary_flush::Flusher::spawn(&config.summary_file, summary_flush_interval));
+    if let (Some(metrics), Some(path)) = (metrics, &config.control_socket) {
+        control::serve(metrics, path).unwrap_or_else(|e| {
+            outcome::exit(outcome::Outcome::ConfigError, &e);
+        });
+    }
+
+    hooks.run(
+        hooks::Hook::PreRun,
+        &run_label,
+        &[("DEVICE", hooked_devices.clone())],
+    );
+    progress::emit(
+        "run_start",
+        json!({
+            "hostname": gethostname().to_string_lossy().into_owned(),
+            "instance_type": config.instance_type.clone(),
+            "devices": devices
+                .iter()
+                .map(|(_, device)| serde_json::Value::from(device.name()))
+                .collect::<Vec<_>>(),
+            "points_planned": config.capacity_fraction.len()
+                * config.utilization_iops.len()
+                * devices.len()
+                * soak_segments as usize
+        }),
+    );
+    let mut verify_failed = false;
+    let sweep = config.capacity_fraction.len() > 1;
+    let devices = &devices;
+    let points: Vec<_> = config
+        .capacity_fraction
+        .iter()
+        .flat_map(|&capacity_fraction| {
+            config.utilization_iops.iter().flat_map(move |utilization| {
+                devices.iter().map(move |&(engine_kind, device)| {
+                    (capacity_fraction, utilization, engine_kind, device)
+                })
+            })
+        })
+        .collect();
+    // the drift baseline of every point, kept across the segments of a soak run
+    let mut drift_trackers = vec![drift::Tracker::default(); points.len()];
+    let points = (0..soak_segments).flat_map(|segment| {
+        points.iter().enumerate().map(
+            move |(index, &(capacity_fraction, utilization, engine_kind, device))| {
+                (
+                    segment,
+                    index,
+                    capacity_fraction,
+                    utilization,
+                    engine_kind,
+                    device,
+                )
+            },
+        )
+    });
+    let mut preconditioned_fraction = None;
+    let mut region_rng = fastrand::Rng::with_seed(sample_seed.rotate_left(16));
+    // the first block above the used capacity the fill drift has not written yet, per device
+    let mut fill_levels: Vec<(*const engine::Device, u64)> = vec![];
+    let mut soak_segment = None;
+    for (segment, index, capacity_fraction, utilization, engine_kind, device) in points {
+        if config.soak_hours.is_some() && soak_segment != Some(segment) {
+            soak_segment = Some(segment);
+            println!("soak segment {} of {}", segment + 1, soak_segments);
+            if let Some(expired) = segment
+                .checked_sub(config.soak_keep_segments)
+                .filter(|_| config.soak_keep_segments > 0)
+            {
+                for path in [&config.samples_file, &config.host_stats_file] {
+                    // a file that cannot be deleted should not end a run of days
+                    if let Err(e) = soak::prune(path, expired) {
+                        eprintln!("{}", e);
                     }
-                    samples
+                }
+            }
+        }
+        // the samples and host statistics of a soak segment go to files of their own
+        let (samples_file, host_stats_file) = match soak_segment {
+            Some(segment) => (
+                soak::segment_path(&config.samples_file, segment),
+                soak::segment_path(&config.host_stats_file, segment),
+            ),
+            None => (config.samples_file.clone(), config.host_stats_file.clone()),
+        };
+        if preconditioned_fraction != Some(capacity_fraction) {
+            preconditioned_fraction = Some(capacity_fraction);
+            fill_levels.clear();
+            if sweep {
+                println!("capacity fraction {}", capacity_fraction);
+            }
+            let preinit_label = format!("{}-capacity{}", run_label, capacity_fraction);
+            let preinit_context = [
+                ("DEVICE", hooked_devices.clone()),
+                ("CAPACITY_FRACTION", capacity_fraction.to_string()),
+            ];
+            if config.preinitialize || sweep {
+                hooks.run(hooks::Hook::PrePreinit, &preinit_label, &preinit_context);
+            }
+            let unique_devices = devices
+                .iter()
+                .enumerate()
+                .filter(|(i, (_, d))| !devices[..*i].iter().any(|(_, o)| std::ptr::eq(*o, *d)))
+                .map(|(_, (_, d))| *d)
+                .chain(namespace_devices.iter().copied());
+            if sweep {
+                // the unused capacity only counts as overprovisioning if the FTL knows it is free
+                for device in unique_devices.clone() {
+                    discard_ssd(device);
+                }
+            }
+            if config.preinitialize || sweep {
+                progress::emit(
+                    "preinit_start",
+                    json!({
+                        "capacity_fraction": capacity_fraction
+                    }),
+                );
+                println!("Initializing SSDs ... ");
+                for device in unique_devices {
+                    initialize_ssd(device, capacity_fraction);
+                }
+                println!(" [Done]");
+                progress::emit(
+                    "preinit_end",
+                    json!({
+                        "capacity_fraction": capacity_fraction
+                    }),
+                );
+                hooks.run(hooks::Hook::PostPreinit, &preinit_label, &preinit_context);
+            } else {
+                println!("No preinitialize");
+            }
+        }
+        if config.engines.len() > 1 {
+            println!("engine {} at utilization {}", engine_kind, utilization);
+        }
+        let device_blocks = device.capacity() / BLOCK_SIZE as u64;
+        let initialized_blocks =
+            (device.capacity() as f64 * capacity_fraction) as u64 / BLOCK_SIZE as u64;
+        // the bulk threads get regions of their own after those of the writer threads
+        let participants = config.writer_threads + config.bulk_threads;
+        // the region of every writer thread
+        let mut regions: Vec<u64> = (0..config.writer_threads).collect();
+        if config.shuffle_regions {
+            region_rng.shuffle(&mut regions);
+        }
+        let fill_drift = config.fill_drift_mb_per_second > 0.0;
+        // the threads of the concurrent namespaces and the fill thread start together with those
+        // of the device
+        let starting = participants
+            + config.writer_threads * namespace_devices.len() as u64
+            + u64::from(fill_drift);
+        let fill_level = match fill_levels.iter().find(|(d, _)| std::ptr::eq(*d, device)) {
+            Some(&(_, level)) => level,
+            None => initialized_blocks,
+        };
+        let region_blocks = initialized_blocks / participants;
+        if region_blocks
+            < max_iovcnt.max(config.bulk_write_bytes / BLOCK_SIZE as u64) + 2 * boundary_blocks
+        {
+            outcome::exit(outcome::Outcome::ConfigError, &format!("the region of every thread ({} blocks) must hold at least --iovcnt {} blocks and one bulk write", region_blocks,
+                max_iovcnt));
+        }
+        if config.workload == Workload::Transaction
+            && region_blocks / max_iovcnt < config.transaction_blocks + 1
+        {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!(
+                    "the region of every thread must hold a transaction of {} writes",
+                    config.transaction_blocks + 1
+                ),
+            );
+        }
+        if config.workload == Workload::Log && region_blocks / max_iovcnt < config.log_segments {
+            outcome::exit(
+                outcome::Outcome::ConfigError,
+                &format!(
+                    "the region of every thread must hold at least one write per log segment ({})",
+                    config.log_segments
+                ),
+            );
+        }
+        let mut temperature = thermal::TemperatureStatistics::default();
+        if let Some(max) = config.max_temperature_celsius {
+            temperature.cooldown_seconds = thermal::cool_down(device, max).as_secs_f64();
+        }
+        if let Some(target) = config.soak_temperature_celsius {
+            temperature.soak_seconds = thermal::soak(
+                device,
+                capacity_fraction,
+                target,
+                Duration::from_secs(config.soak_timeout_seconds),
+            )
+            .as_secs_f64();
+        }
+        let temperature_monitor = thermal::Monitor::spawn(device).map(|(monitor, start)| {
+            temperature.temperature_start_celsius = Some(start);
+            monitor
+        });
+        let uuid = Uuid::new_v4();
+        let point_context = [
+            ("DEVICE", device.name()),
+            ("CAPACITY_FRACTION", capacity_fraction.to_string()),
+            ("UUID", uuid.as_u128().to_string()),
+            ("ENGINE", engine_kind.to_string()),
+            ("UTILIZATION", utilization.to_string()),
+        ];
+        hooks.run(
+            hooks::Hook::PrePoint,
+            &uuid.as_u128().to_string(),
+            &point_context,
+        );
+        control::start_point(config.sample_rate);
+        let point_info = json!({
+            "uuid": uuid.as_u128().to_string(),
+            "ssd_device": device.name(),
+            "engine": engine_kind.to_string(),
+            "capacity_fraction": capacity_fraction,
+            "utilization_iop": *utilization
+        });
+        let target_iops = config.max_iops as f64 * utilization;
+        if let Some(checkpointer) = checkpointer {
+            let mut info = point_info.clone();
+            info["target_iops"] = json!(target_iops);
+            checkpointer.start_point(info, config.writer_threads);
+        }
+        let mut start = point_info.clone();
+        start["soak_segment"] = json!(soak_segment);
+        start["target_iops"] = json!(target_iops);
+        start["runtime_seconds"] = json!(config.runtime_seconds);
+        progress::emit("point_start", start);
+        if let Some(reporter) = progress_reporter {
+            reporter.start_point(
+                point_info,
+                target_iops,
+                Duration::from_secs(config.runtime_seconds),
+                config.writer_threads,
+            );
+        }
+        if let Some(metrics) = metrics {
+            metrics.start_point(
+                &device.name(),
+                &engine_kind.to_string(),
+                uuid.as_u128(),
+                *utilization,
+            );
+        }
+        let start_time_ns = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("")
+            .as_nanos() as u64;
+        let ack_logger = config
+            .crash_ack_file
+            .as_ref()
+            .map(|file| crash::AckLogger::spawn(file, uuid.as_u128(), config.writer_threads));
+        let acknowledged = ack_logger.as_ref().map(|l| l.acknowledged.clone());
+        let start_barrier = std::sync::Arc::new(StartBarrier::new(starting as usize));
+        let rate_buckets = (config.rate_pattern != RatePattern::Constant).then_some(RateBuckets {
+            low: config.rate_min_utilization.min(*utilization),
+            high: *utilization,
+            buckets: config.rate_buckets.max(1),
+        });
+        // an equal share of --samples-max-rows per thread if the point would write more
+        let projected_samples = config.max_iops as f64
+            * utilization
+            * config.runtime_seconds as f64
+            * config.sample_rate;
+        let reservoir_rows = (config.serialize_samples
+            && config.samples_max_rows > 0
+            && projected_samples > config.samples_max_rows as f64)
+            .then(|| {
+                println!(
+                    "{:.0} samples projected, downsampling to --samples-max-rows {}",
+                    projected_samples, config.samples_max_rows
+                );
+                config.samples_max_rows.div_ceil(config.writer_threads) as usize
+            });
+        let memory_share = (config.memory_budget_mb > 0).then(|| {
+            memory::thread_share(
+                config.memory_budget_mb,
+                config.writer_threads,
+                config.serialize_samples,
+            )
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e))
+        });
+        let (sample_writer, sample_sender) = if config.serialize_samples {
+            let destination = if config.samples_format == sample_writer::SamplesFormat::ZstdSeekable
+            {
+                sample_writer::Destination::Seekable {
+                    path: sample_writer::samples_path_for_run(&samples_file, uuid.as_u128(), "zst"),
+                    frame: Duration::from_millis(config.samples_frame_ms),
+                }
+            } else if config.samples_per_thread {
+                sample_writer::Destination::PerThread {
+                    samples_file: samples_file.clone(),
+                    uuid: uuid.as_u128(),
+                    threads: config.writer_threads,
+                }
+            } else {
+                sample_writer::Destination::Shared {
+                    samples_file: samples_file.clone(),
+                }
+            };
+            let (writer, sender) = sample_writer::SampleWriter::spawn(destination);
+            (Some(writer), Some(sender))
+        } else {
+            (None, None)
+        };
+        let host_sampler = (config.host_stats_interval_ms > 0).then(|| {
+            host_stats::HostSampler::spawn(
+                &host_stats_file,
+                uuid.as_u128(),
+                *utilization,
+                Duration::from_millis(config.host_stats_interval_ms),
+            )
+        });
+        let energy_meter = rapl.as_ref().map(energy::EnergyMeter::start);
+        let benchmark_config = BenchmarkConfig {
+            region_order: regions
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+            soak_segment,
+            ..BenchmarkConfig::from_cli_config(
+                config,
+                device,
+                engine_kind,
+                capacity_fraction,
+                *utilization,
+                uuid.as_u128(),
+                sample_seed,
+            )
+        };
+        if let Some(flusher) = summary_flusher {
+            let benchmark_config = benchmark_config.clone();
+            flusher.start_point(config.writer_threads, move |progress| {
+                let elapsed_seconds = progress.elapsed.as_secs_f64();
+                (
+                    benchmark_config.clone(),
+                    progress.statistics(),
+                    AchievedStatistics {
+                        partial: true,
+                        elapsed_seconds,
+                        total_operations: progress.operations,
+                        achieved_iops: if elapsed_seconds > 0.0 {
+                            progress.operations as f64 / elapsed_seconds
+                        } else {
+                            0.0
+                        },
+                        io_errors: progress.io_errors,
+                        ..Default::default()
+                    },
+                    bulk::BulkStatistics::default(),
+                    nvme_ops::OpStatistics::default(),
+                    thermal::TemperatureStatistics::default(),
+                    energy::EnergyStatistics::default(),
+                    stability::StabilityStatistics::default(),
+                    fill_drift::FillStatistics::default(),
+                    drift::DriftStatistics::default(),
+                    sched_stats::SchedStatistics::default(),
+                    thread_profile::ProfileStatistics::default(),
+                )
+            });
+        }
+        let stability_window = Duration::from_secs(config.stability_window_seconds);
+        let stability_windows = stability::Windows::default();
+        let host_counters = sched_stats::HostCounters::read();
+        let drift_monitor = config.drift_threshold.map(|threshold| {
+            let start_barrier = start_barrier.clone();
+            drift::Monitor::spawn(
+                stability_windows.clone(),
+                move || start_barrier.start.get().copied(),
+                std::mem::take(&mut drift_trackers[index]),
+                drift::Options {
+                    threshold,
+                    window: stability_window,
+                    runtime: Duration::from_secs(config.runtime_seconds),
+                    webhook: drift_webhook.clone(),
+                    uuid: uuid.as_u128(),
+                    utilization_iop: *utilization,
+                    context: json!({
+                        "hostname": gethostname().to_string_lossy().into_owned(),
+                        "instance_type": config.instance_type.clone(),
+                        "ssd_device": device.name(),
+                        "engine": engine_kind.to_string(),
+                        "uuid": uuid.as_u128().to_string(),
+                        "utilization_iop": *utilization
+                    }),
+                },
+            )
+        });
+        let telemetry_capturer = (config.telemetry_threshold_us > 0).then(|| {
+            telemetry::Capturer::spawn(
+                &device.name(),
+                &telemetry_options,
+                json!({
+                    "uuid": uuid.as_u128().to_string(),
+                    "engine": engine_kind.to_string(),
+                    "capacity_fraction": capacity_fraction,
+                    "utilization_iop": *utilization
+                }),
+            )
+        });
+        if let Some(cgroup) = cgroup {
+            cgroup
+                .enter()
+                .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+        }
+        let point = std::sync::Arc::new(Point {
+            config,
+            device,
+            engine_kind,
+            faults,
+            uuid,
+            utilization,
+            start_time_ns,
+            sample_seed,
+            device_blocks,
+            initialized_blocks,
+            participants,
+            boundary_blocks,
+            regions,
+            op_mix,
+            rate_buckets,
+            reservoir_rows,
+            memory_share,
+            stability_window,
+            stability_windows: stability_windows.clone(),
+            start_barrier: start_barrier.clone(),
+            group_commits: (0..config
+                .writer_threads
+                .div_ceil(config.group_commit_writers.max(1)))
+                .map(|_| {
+                    group_commit::GroupCommit::new(Duration::from_micros(config.group_commit_us))
+                })
+                .collect(),
+            sample_sequence: std::sync::atomic::AtomicU64::new(0),
+            completion_base: Instant::now(),
+            last_completion: std::sync::atomic::AtomicU64::new(0),
+            in_flight: std::sync::atomic::AtomicU64::new(0),
+            acknowledged,
+            telemetry_capturer: telemetry_capturer.clone(),
+            checkpointer,
+            checkpoint_interval,
+            summary_flusher,
+            summary_flush_interval,
+            progress_reporter,
+            progress_interval,
+            metrics,
+        });
+        let threads: Vec<_> = (0..config.writer_threads)
+            .map(|worker_id| {
+                let point = point.clone();
+                let sample_sender = sample_sender.clone();
+                std::thread::spawn(move || run_writer(&point, worker_id, sample_sender))
+            })
+            .collect();
+
+        let bulk_load = bulk::BulkLoad {
+            threads: config.bulk_threads,
+            write_bytes: config.bulk_write_bytes,
+            mb_per_second: config.bulk_mb_per_second,
+            runtime: Duration::from_secs(config.runtime_seconds),
+        };
+        let bulk_threads: Vec<_> = (0..config.bulk_threads)
+            .map(|bulk_id| {
+                let start_barrier = start_barrier.clone();
+                std::thread::spawn(move || {
+                    let engine: Box<dyn engine::Engine> = if faults.any() {
+                        Box::new(engine::FaultInjector::new(device.open(engine_kind), faults))
+                    } else {
+                        device.open(engine_kind)
+                    };
+                    let range = partition(
+                        config.writer_threads + bulk_id,
+                        participants,
+                        initialized_blocks,
+                    );
+                    let start = start_barrier.wait();
+                    bulk::run(engine.as_ref(), range, bulk_load, bulk_id, start)
                 })
             })
             .collect();
 
-        let benchmark_config =
-            BenchmarkConfig::from_cli_config(config, *utilization, uuid.as_u128());
-        let mut samples: Vec<Sample> = vec![];
-        for th in threads {
-            let mut s = th.join().unwrap();
-            samples.append(&mut s);
+        let fill_limit = (device_blocks as f64 * config.fill_drift_limit) as u64;
+        let fill_thread = fill_drift.then(|| {
+            let start_barrier = start_barrier.clone();
+            std::thread::spawn(move || {
+                let engine = device.open(engine_kind);
+                let start = start_barrier.wait();
+                fill_drift::run(
+                    engine.as_ref(),
+                    fill_level..fill_limit.max(fill_level),
+                    config.fill_drift_mb_per_second,
+                    start,
+                    Duration::from_secs(config.runtime_seconds),
+                )
+            })
+        });
+
+        let namespace_threads: Vec<Vec<_>> = namespace_devices
+            .iter()
+            .map(|&namespace| spawn_namespace_writers(&point, namespace, capacity_fraction))
+            .collect();
+
+        let mut results: Vec<WorkerResult> =
+            threads.into_iter().map(|th| th.join().unwrap()).collect();
+        if let Some(cgroup) = cgroup {
+            cgroup
+                .leave()
+                .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+        }
+        let host_counters = sched_stats::HostCounters::read().since(&host_counters);
+        let profile_statistic = thread_profile::ProfileStatistics::create(
+            &results
+                .iter()
+                .map(|r| (r.profile, (r.end - r.begin).saturating_sub(r.paused)))
+                .collect::<Vec<_>>(),
+        );
+        let sched_statistic = sched_stats::SchedStatistics::create(
+            &results.iter().map(|r| r.switches).collect::<Vec<_>>(),
+            &host_counters,
+        );
+        let (mut latencies, achieved) = aggregate(&point, &mut results);
+        let op_statistic =
+            nvme_ops::OpStatistics::create_from_latencies(results.iter().map(|r| &r.op_latencies));
+        if let Some(checkpointer) = checkpointer {
+            checkpointer.finish_point();
+        }
+        if let Some(reporter) = progress_reporter {
+            reporter.finish_point();
+        }
+        if control::changed_during_point() {
+            println!(
+                "warning: the control socket changed the target or sampling rate of this point; the summary records the configured ones"
+            );
+        }
+        if let Some(metrics) = metrics {
+            metrics.finish_point();
+        }
+        let bulk_results: Vec<_> = bulk_threads
+            .into_iter()
+            .map(|th| th.join().unwrap())
+            .collect();
+        let bulk_statistic = bulk::BulkStatistics::create_from_results(&bulk_results);
+        let fill_result = fill_thread.map(|th| th.join().unwrap());
+        let fill_statistic =
+            fill_drift::FillStatistics::create(device_blocks, fill_level, fill_result.as_ref());
+        if let Some(result) = &fill_result {
+            fill_levels.retain(|(d, _)| !std::ptr::eq(*d, device));
+            fill_levels.push((device, result.end_block));
+        }
+        if !namespace_threads.is_empty() {
+            let mut wtr = schema::csv_appender(Path::new(&config.namespace_stats_file)).unwrap();
+            for (namespace, threads) in namespace_devices.iter().zip(namespace_threads) {
+                let results: Vec<_> = threads.into_iter().map(|th| th.join().unwrap()).collect();
+                wtr.serialize(namespaces::NamespaceStatistics::create_from_results(
+                    uuid.as_u128(),
+                    namespace.name(),
+                    *utilization,
+                    (config.max_iops as f64 * utilization) as u64,
+                    &results,
+                ))
+                .unwrap();
+            }
+            wtr.flush().unwrap();
+        }
+        temperature.temperature_max_celsius = temperature_monitor.map(thermal::Monitor::stop);
+        if let Some(capturer) = telemetry_capturer {
+            capturer.stop();
+        }
+        if let Some(sampler) = host_sampler {
+            sampler.stop();
+        }
+        let stability_statistic = if stability_window.is_zero() {
+            stability::StabilityStatistics::default()
+        } else {
+            stability::StabilityStatistics::create_from_windows(
+                &stability_windows,
+                stability_window,
+                Duration::from_secs(config.runtime_seconds),
+            )
+        };
+        let drift_statistic = match drift_monitor.map(drift::Monitor::stop) {
+            Some(report) => {
+                drift_trackers[index] = report.tracker;
+                let mut wtr = schema::csv_appender(Path::new(&config.drift_file)).unwrap();
+                for event in report.events {
+                    wtr.serialize(event).unwrap();
+                }
+                wtr.flush().unwrap();
+                report.statistics
+            }
+            None => drift::DriftStatistics::default(),
+        };
+        let energy_statistic = energy_meter.map_or_else(Default::default, |meter| {
+            meter.finish(achieved.total_operations, &device.name())
+        });
+        if let Some(logger) = ack_logger {
+            logger.stop();
+        }
+        drop(sample_sender);
+        if let Some(writer) = sample_writer {
+            writer.join();
+        }
+        if config.verify {
+            println!("verifying written regions ...");
+            let ranges: Vec<_> = point
+                .regions
+                .iter()
+                .map(|&region| partition(region, participants, initialized_blocks))
+                .collect();
+            let written: Vec<_> = ranges
+                .iter()
+                .zip(0..)
+                .map(|(range, id)| {
+                    written_range(
+                        &boundary::range(range, boundary_blocks),
+                        config.iovcnt_of(id),
+                    )
+                })
+                .collect();
+            let written_blocks: Vec<_> = (0..)
+                .zip(&results)
+                .map(|(id, r)| r.operations * config.iovcnt_of(id))
+                .collect();
+            let report = verify::verify_regions(
+                device,
+                uuid.as_u128(),
+                &ranges,
+                &written,
+                &written_blocks,
+                initialized_blocks,
+                BLOCK_SIZE,
+            );
+            report.print();
+            verify_failed |= report.failed();
+        }
+        let backpressure_events: u64 = results.iter().map(|r| r.backpressure_events).sum();
+        let max_pending_samples = results
+            .iter()
+            .map(|r| r.max_pending_samples)
+            .max()
+            .unwrap_or(0);
+        if backpressure_events > 0 {
+            println!(
+                "warning: sample serialization fell behind {} times (up to {} samples held back by a thread, {} dropped)",
+                backpressure_events, max_pending_samples, achieved.samples_dropped
+            );
         }
 
-        let statistic = SummaryStatistics::create_from_sample(&mut samples);
+        // spinning should hit the schedule within a fraction of the inter-arrival time
+        let inter_arrival_time_ns = 1e9 * config.writer_threads as f64 * config.batch_size as f64
+            / (config.max_iops as f64 * utilization);
+        if achieved.scheduling_error_p99th as f64
+            > inter_arrival_time_ns * SCHEDULING_ERROR_WARN_FRACTION
+        {
+            println!(
+                "warning: the rate limiter could not hold the requested rate on this host: p99 scheduling error {:.1}us (max {:.1}us) at {:.1}us between batches per thread; the latencies include this error",
+                achieved.scheduling_error_p99th as f64 / 1e3,
+                achieved.scheduling_error_max as f64 / 1e3,
+                inter_arrival_time_ns / 1e3
+            );
+        }
+
+        let statistic =
+            SummaryStatistics::create_with_method(&mut latencies, config.percentile_method);
+
+        write_point_files(&point, &achieved, &latencies, &mut results);
+
+        let completed_point = json!({
+            "uuid": uuid.as_u128().to_string(),
+            "ssd_device": device.name(),
+            "engine": engine_kind.to_string(),
+            "capacity_fraction": capacity_fraction,
+            "utilization_iop": *utilization,
+            "achieved_iops": achieved.achieved_iops,
+            "io_errors": achieved.io_errors,
+            "p50th": statistic.p50th,
+            "p99th": statistic.p99th,
+            "p999th": statistic.p999th
+        });
 
         println!("serializing summary_file");
+        if let Some(flusher) = summary_flusher {
+            flusher.finish_point();
+        }
         //--------- Summary File
         {
-            let file_exists = Path::new(&config.summary_file).exists();
-            let file = OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(&config.summary_file)
-                .unwrap();
+            let mut wtr = schema::csv_appender(Path::new(&config.summary_file)).unwrap();
+            wtr.serialize((
+                benchmark_config.clone(),
+                statistic,
+                achieved,
+                bulk_statistic,
+                op_statistic,
+                temperature,
+                energy_statistic,
+                stability_statistic,
+                fill_statistic,
+                drift_statistic,
+                sched_statistic,
+                profile_statistic,
+            ))
+            .unwrap();
+            wtr.flush().unwrap();
+        }
 
-            let mut wtr = csv::WriterBuilder::new()
-                .has_headers(!file_exists)
-                .from_writer(file);
+        //------ Sample File manifest (the samples themselves are streamed during the run)
+        if config.serialize_samples && config.samples_per_thread {
+            let files: Vec<serde_json::Value> = results
+                .iter()
+                .enumerate()
+                .map(|(thread_id, result)| {
+                    json!({
+                        "thread_id": thread_id,
+                        "path": staging::final_path(&sample_writer::thread_samples_path(
+                            &samples_file,
+                            uuid.as_u128(),
+                            thread_id as u64,
+                        )),
+                        "samples": result.sample_count,
+                        "voluntary_switches": result.switches.voluntary,
+                        "involuntary_switches": result.switches.involuntary
+                    })
+                })
+                .collect();
+            let manifest = json!({
+                "uuid": uuid.as_u128().to_string(),
+                "config": &benchmark_config,
+                "clock": clock::status(),
+                "start_realtime_ns": start_barrier.clocks.get().map_or(0, |c| c.0),
+                "start_monotonic_ns": start_barrier.clocks.get().map_or(0, |c| c.1),
+                "samples_files": files,
+                "host": host_counters.to_json(),
+                "backpressure_events": backpressure_events,
+                "max_pending_samples": max_pending_samples
+            });
+            let path =
+                sample_writer::samples_path_for_run(&samples_file, uuid.as_u128(), "manifest.json");
+            fs::write(&path, format!("{}\n", manifest)).unwrap();
+        }
 
-            wtr.serialize((benchmark_config.clone(), statistic))
-                .unwrap();
-            wtr.flush().unwrap();
+        hooks.run(
+            hooks::Hook::PostPoint,
+            &uuid.as_u128().to_string(),
+            &point_context,
+        );
+        progress::emit("point_end", completed_point.clone());
+        outcome::point_completed(completed_point);
+    }
+
+    if let Some(writer) = influx_writer {
+        writer.stop();
+    }
+    if let Some(flusher) = summary_flusher {
+        flusher.stop();
+    }
+    if let Some(reporter) = progress_reporter {
+        reporter.stop();
+    }
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.stop();
+    }
+    hooks.run(
+        hooks::Hook::PostRun,
+        &run_label,
+        &[("DEVICE", hooked_devices)],
+    );
+    if verify_failed {
+        outcome::exit(outcome::Outcome::AssertionFailed, "verification failed");
+    }
+    outcome::exit(outcome::Outcome::Success, "");
+}
+
+/// Spawns the writer threads of a concurrent namespace; they write at the rate of the point
+fn spawn_namespace_writers(
+    point: &Point,
+    namespace: &'static engine::Device,
+    capacity_fraction: f64,
+) -> Vec<std::thread::JoinHandle<namespaces::NamespaceResult>> {
+    let &Point {
+        config,
+        engine_kind,
+        utilization,
+        ref start_barrier,
+        ..
+    } = point;
+    let namespace_blocks =
+        (namespace.capacity() as f64 * capacity_fraction) as u64 / BLOCK_SIZE as u64;
+    (0..config.writer_threads)
+        .map(|worker_id| {
+            let start_barrier = start_barrier.clone();
+            std::thread::spawn(move || {
+                let engine = namespace.open(engine_kind);
+                let range = written_range(
+                    &partition(worker_id, config.writer_threads, namespace_blocks),
+                    config.iovcnt,
+                );
+                let schedule = RateSchedule {
+                    pattern: config.rate_pattern,
+                    min_rate: config.max_iops as f64 * config.rate_min_utilization,
+                    max_rate: config.max_iops as f64 * utilization,
+                    runtime: Duration::from_secs(config.runtime_seconds),
+                    period: Duration::from_secs(config.rate_period_seconds),
+                };
+                let start = start_barrier.wait();
+                let mut ratelimiter = RateLimiter::new(
+                    start,
+                    schedule,
+                    config.writer_threads,
+                    worker_id,
+                    config.batch_size,
+                    config.batch_phase,
+                    config.jitter,
+                );
+                ratelimiter.set_catch_up(config.catch_up);
+                namespaces::run(
+                    engine.as_ref(),
+                    range,
+                    config.iovcnt as usize * BLOCK_SIZE,
+                    ratelimiter,
+                    start,
+                    Duration::from_secs(config.runtime_seconds),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Merges the sampled latencies of the writer threads of a point into one unsorted vector;
+/// a point without any ends the run
+fn aggregate(point: &Point, results: &mut [WorkerResult]) -> (Vec<u64>, AchievedStatistics) {
+    let &Point {
+        config,
+        utilization,
+        ..
+    } = point;
+    let latencies: Vec<u64> = results
+        .iter_mut()
+        .flat_map(|r| std::mem::take(&mut r.latencies))
+        .collect();
+    let achieved = AchievedStatistics::create_from_results(results, config.sample_rate);
+    if latencies.is_empty() {
+        if achieved.io_errors + achieved.short_writes >= achieved.total_operations {
+            outcome::exit(
+                outcome::Outcome::DeviceError,
+                &format!(
+                    "every write of the point at utilization {} failed",
+                    utilization
+                ),
+            );
+        }
+        outcome::exit(
+            outcome::Outcome::ConfigError,
+            &format!(
+                "no write of the point at utilization {} was sampled ({} writes at --sample-rate {}); raise --sample-rate or --runtime-seconds",
+                utilization, achieved.total_operations, config.sample_rate
+            ),
+        );
+    }
+    (latencies, achieved)
+}
+
+/// Appends the rows of a point to the result files next to the summary file; `latencies` are
+/// sorted
+fn write_point_files(
+    point: &Point,
+    achieved: &AchievedStatistics,
+    latencies: &[u64],
+    results: &mut [WorkerResult],
+) {
+    let &Point {
+        config,
+        uuid,
+        utilization,
+        device_blocks,
+        rate_buckets,
+        ..
+    } = point;
+    if !config.io_priorities.is_empty() {
+        let mut wtr = schema::csv_appender(Path::new(&config.priority_stats_file)).unwrap();
+        let mut classes: Vec<ioprio::IoPriority> = vec![];
+        for priority in &config.io_priorities {
+            if !classes.contains(priority) {
+                classes.push(*priority);
+            }
+        }
+        for priority in classes {
+            let threads: Vec<_> = (0..)
+                .zip(results.iter())
+                .filter(|(worker_id, _)| config.io_priority(*worker_id) == Some(priority))
+                .map(|(_, result)| result)
+                .collect();
+            let mut latency = histogram::Histogram::new();
+            for result in &threads {
+                latency.merge(&result.latency_histogram);
+            }
+            wtr.serialize(ioprio::PriorityStatistics::create_from_histogram(
+                uuid.as_u128(),
+                *utilization,
+                priority,
+                threads.len() as u64,
+                threads.iter().map(|r| r.operations).sum(),
+                threads.iter().map(|r| r.io_errors).sum(),
+                &latency,
+            ))
+            .unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+    if !config.groups.is_empty() {
+        let mut wtr = schema::csv_appender(Path::new(&config.thread_group_stats_file)).unwrap();
+        let mut first = 0;
+        for group in &config.groups {
+            let threads = &results[first as usize..(first + group.threads) as usize];
+            first += group.threads;
+            let mut latency = histogram::Histogram::new();
+            for result in threads {
+                latency.merge(&result.latency_histogram);
+            }
+            let operations = threads.iter().map(|r| r.operations).sum::<u64>();
+            wtr.serialize(thread_groups::GroupStatistics::create_from_histogram(
+                uuid.as_u128(),
+                *utilization,
+                group,
+                if achieved.elapsed_seconds > 0.0 {
+                    operations as f64 / achieved.elapsed_seconds
+                } else {
+                    0.0
+                },
+                operations,
+                threads.iter().map(|r| r.io_errors).sum(),
+                &latency,
+            ))
+            .unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+
+    if !config.fanouts.is_empty() {
+        let mut wtr = schema::csv_appender(Path::new(&config.fanout_file)).unwrap();
+        for &fanout in &config.fanouts {
+            let projection = fanout::FanoutProjection::create(
+                uuid.as_u128(),
+                *utilization,
+                latencies,
+                fanout,
+                config.fanout_empirical,
+                config.percentile_method,
+            );
+            projection.print();
+            wtr.serialize(projection).unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+
+    if let Some(buckets) = rate_buckets {
+        let mut wtr = schema::csv_appender(Path::new(&config.rate_buckets_file)).unwrap();
+        for bucket in 0..buckets.buckets {
+            let mut bucket_latencies: Vec<u64> = results
+                .iter_mut()
+                .flat_map(|r| std::mem::take(&mut r.bucket_latencies[bucket]))
+                .collect();
+            if bucket_latencies.is_empty() {
+                continue;
+            }
+            let (utilization_low, utilization_high) = buckets.bounds(bucket);
+            let row = RateBucket {
+                uuid: uuid.as_u128(),
+                rate_pattern: config.rate_pattern,
+                bucket,
+                utilization_low,
+                utilization_high,
+                samples: bucket_latencies.len(),
+            };
+            let statistic = SummaryStatistics::create_with_method(
+                &mut bucket_latencies,
+                config.percentile_method,
+            );
+            wtr.serialize((row, statistic)).unwrap();
         }
+        wtr.flush().unwrap();
+    }
 
-        println!("serializing samples_file");
-        //------ Sample File
-        if config.serialize_samples {
-            let file_exists = Path::new(&config.samples_file).exists();
-            let file = OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(&config.samples_file)
+    if config.export_histograms {
+        let mut latency = histogram::Histogram::new();
+        let mut inter_completion = histogram::Histogram::new();
+        for result in results.iter() {
+            latency.merge(&result.latency_histogram);
+            inter_completion.merge(&result.inter_completion_histogram);
+        }
+        let mut wtr = schema::csv_appender(Path::new(&config.histogram_file)).unwrap();
+        for (metric, histogram) in [
+            (HistogramMetric::Latency, &latency),
+            (HistogramMetric::InterCompletion, &inter_completion),
+        ] {
+            for (upper_bound_ns, count) in histogram.buckets() {
+                wtr.serialize(HistogramBucket {
+                    uuid: uuid.as_u128(),
+                    utilization_iop: *utilization,
+                    metric,
+                    upper_bound_ns,
+                    count,
+                })
                 .unwrap();
-            let mut wtr = csv::WriterBuilder::new()
-                .has_headers(!file_exists)
-                .from_writer(file);
-            for s in samples {
-                wtr.serialize(&s).unwrap();
             }
-            wtr.flush().unwrap();
         }
+        wtr.flush().unwrap();
+    }
+
+    if let Some(slice_bytes) = (device_blocks * BLOCK_SIZE as u64).checked_div(config.lba_slices) {
+        let mut wtr = schema::csv_appender(Path::new(&config.lba_slices_file)).unwrap();
+        for slice in 0..config.lba_slices {
+            let mut histogram = histogram::Histogram::new();
+            for result in results.iter() {
+                histogram.merge(&result.slice_histograms[slice as usize]);
+            }
+            if histogram.count() == 0 {
+                continue;
+            }
+            wtr.serialize(LbaSlice {
+                uuid: uuid.as_u128(),
+                utilization_iop: *utilization,
+                slice,
+                start_bytes: slice * slice_bytes,
+                end_bytes: (slice + 1) * slice_bytes,
+                operations: histogram.count(),
+                mean: histogram.mean(),
+                p50th: histogram.percentile(50.0),
+                p99th: histogram.percentile(99.0),
+                p999th: histogram.percentile(99.9),
+                max: histogram.max(),
+            })
+            .unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+
+    if !config.idle_gap_buckets_us.is_empty() {
+        let mut idle_gaps = idle_gap::IdleGaps::new(&config.idle_gap_buckets_us);
+        for gaps in results.iter().filter_map(|r| r.idle_gaps.as_ref()) {
+            idle_gaps.merge(gaps);
+        }
+        let mut wtr = schema::csv_appender(Path::new(&config.idle_gap_file)).unwrap();
+        for row in idle_gaps.rows(uuid.as_u128(), *utilization) {
+            wtr.serialize(row).unwrap();
+        }
+        wtr.flush().unwrap();
+    }
+
+    if config.outlier_threshold_us > 0 {
+        let mut wtr = schema::csv_appender(Path::new(&config.outliers_file)).unwrap();
+        for outlier in results.iter().flat_map(|r| &r.outliers) {
+            wtr.serialize(outlier).unwrap();
+        }
+        wtr.flush().unwrap();
+        let dropped: u64 = results.iter().map(|r| r.dropped_outliers).sum();
+        if dropped > 0 {
+            println!(
+                "warning: {} outliers beyond {} per thread were not recorded; raise --outlier-threshold-us",
+                dropped,
+                outliers::MAX_OUTLIERS_PER_THREAD
+            );
+        }
+    }
+}
+
+/// The writes of one writer thread over a utilization point
+fn run_writer(
+    point: &Point,
+    worker_id: u64,
+    sample_sender: Option<std::sync::mpsc::SyncSender<Vec<Sample>>>,
+) -> WorkerResult {
+    let &Point {
+        config,
+        device,
+        engine_kind,
+        faults,
+        uuid,
+        utilization,
+        start_time_ns,
+        sample_seed,
+        device_blocks,
+        initialized_blocks,
+        participants,
+        boundary_blocks,
+        ref regions,
+        op_mix,
+        rate_buckets,
+        reservoir_rows,
+        memory_share,
+        stability_window,
+        ref stability_windows,
+        ref start_barrier,
+        ref group_commits,
+        ref sample_sequence,
+        completion_base,
+        ref last_completion,
+        ref in_flight,
+        ref acknowledged,
+        ref telemetry_capturer,
+        checkpointer,
+        checkpoint_interval,
+        summary_flusher,
+        summary_flush_interval,
+        progress_reporter,
+        progress_interval,
+        metrics,
+    } = point;
+    let mut ssd_fd: Box<dyn engine::Engine> = if faults.any() {
+        Box::new(engine::FaultInjector::new(device.open(engine_kind), faults))
+    } else {
+        device.open(engine_kind)
+    };
+    if let Some(priority) = config.io_priority(worker_id) {
+        ioprio::set_thread(priority)
+            .unwrap_or_else(|e| outcome::exit(outcome::Outcome::ConfigError, &e));
+        ssd_fd.set_io_priority(priority.value());
+    }
+    let group = thread_groups::of(&config.groups, worker_id);
+    let iovcnt = config.iovcnt_of(worker_id);
+    // of the target rate of the point
+    let share = group.map_or(1.0, |(g, _)| g.share);
+    let mut buffers: Vec<_> = (0..iovcnt)
+        .map(|_| DirectIOBuffer::<BLOCK_SIZE>::new_boxed(7))
+        .collect();
+    let write_len = iovcnt as usize * BLOCK_SIZE;
+    let mut latencies = Vec::with_capacity(10000);
+    let mut sample_stream = sample_sender.map(|sender| match reservoir_rows {
+        Some(rows) => sample_writer::SampleStream::downsampled(
+            sender,
+            rows,
+            sample_seed.wrapping_add(worker_id).rotate_left(48),
+        ),
+        None => sample_writer::SampleStream::new(sender),
+    });
+    let schedule = RateSchedule {
+        pattern: group.map_or(config.rate_pattern, |(g, _)| g.rate_pattern),
+        min_rate: config.max_iops as f64 * config.rate_min_utilization * share,
+        max_rate: config.max_iops as f64 * utilization * share,
+        runtime: Duration::from_secs(config.runtime_seconds),
+        period: Duration::from_secs(config.rate_period_seconds),
+    };
+    let mut bucket_latencies = vec![vec![]; rate_buckets.map_or(0, |b| b.buckets)];
+    // blocks every write covers, a transaction all of its writes
+    let stride = if config.workload == Workload::Transaction {
+        iovcnt * (config.transaction_blocks + 1)
+    } else {
+        iovcnt
+    };
+    let range = written_range(
+        &boundary::range(
+            &partition(
+                regions[worker_id as usize],
+                participants,
+                initialized_blocks,
+            ),
+            boundary_blocks,
+        ),
+        stride,
+    );
+    let mut block_current = range.start;
+    let mut operations = 0;
+    let mut bytes = 0;
+    let mut io_errors = 0;
+    let mut reported_errors = 0;
+    let mut short_writes = 0;
+    let mut fsyncs = 0;
+    let mut discard_latency = histogram::Histogram::new();
+    let group_commit = Duration::from_micros(config.group_commit_us);
+    let mut last_commit = Instant::now();
+    let linked = engine_kind == engine::EngineKind::IoUringLinked;
+    let shared_commit = (config.group_commit_writers > 0)
+        .then(|| &group_commits[(worker_id / config.group_commit_writers) as usize]);
+    let mut commits = group_commit::CommitLatencies::default();
+    let mut transactions = transaction::TransactionLatencies::default();
+    let mut latency_histogram = histogram::Histogram::new();
+    let mut inter_completion_histogram = histogram::Histogram::new();
+    let mut slice_histograms = vec![histogram::Histogram::new(); config.lba_slices as usize];
+    let mut idle_gaps = (!config.idle_gap_buckets_us.is_empty())
+        .then(|| idle_gap::IdleGaps::new(&config.idle_gap_buckets_us));
+    let submitted = std::cell::Cell::new(Instant::now());
+    let capture_outliers = config.outlier_threshold_us > 0;
+    let mut outlier_capture = capture_outliers.then(|| {
+        outliers::OutlierCapture::new(
+            uuid.as_u128(),
+            *utilization,
+            worker_id,
+            config.outlier_threshold_us * 1000,
+            config.outlier_context,
+        )
+    });
+    let submitted_in_flight = std::cell::Cell::new(0);
+    let mut sample_rng = fastrand::Rng::with_seed(sample_seed.wrapping_add(worker_id));
+    let mut sample_rate = config.sample_rate;
+    let mut memory = memory_share.map(memory::ThreadMemory::new);
+    let mut tail_sampler = (config.serialize_samples
+        && config.sampling_method == SamplingMethod::Tail)
+        .then(|| tail_sampling::TailSampler::new(config.tail_percentile));
+    let mut sample_interval = (1.0 / sample_rate).round() as u64;
+    let mut sample_phase = sample_rng.u64(0..sample_interval);
+    let mut window_recorder = (!stability_window.is_zero())
+        .then(|| stability::WindowRecorder::new(stability_window, stability_windows.clone()));
+    let mut commands = op_mix.any().then(|| {
+        nvme_ops::Commands::open(
+            &device.name(),
+            op_mix,
+            sample_seed.wrapping_add(worker_id).rotate_left(32),
+            range.start * BLOCK_SIZE as u64..range.end * BLOCK_SIZE as u64,
+            device.offset(),
+        )
+        .unwrap_or_else(|e| outcome::exit(outcome::Outcome::DeviceError, &e))
+    });
+    let current_op = std::cell::Cell::new(nvme_ops::Op::Write);
+    // latencies of the fsync and the log segment discard of the current write
+    let flush_latency = std::cell::Cell::new(None);
+    let trim_latency = std::cell::Cell::new(None);
+    let mut command_errors = 0;
+    let mut op_latencies = nvme_ops::OpLatencies::default();
+
+    let begin = start_barrier.wait();
+    let switches = sched_stats::ThreadSwitches::current();
+    let mut ratelimiter = RateLimiter::new(
+        begin,
+        schedule,
+        group.map_or(config.writer_threads, |(g, _)| g.threads),
+        group.map_or(worker_id, |(_, index)| index),
+        config.batch_size,
+        config.batch_phase,
+        config.jitter,
+    );
+    ratelimiter.set_catch_up(config.catch_up);
+    let mut end_time = begin + Duration::from_secs(config.runtime_seconds);
+    let mut paused = Duration::ZERO;
+    let mut last_checkpoint = begin;
+    let mut last_summary_flush = begin;
+    let mut last_progress = begin;
+
+    while Instant::now() < end_time {
+        let pause = pause::wait_while_paused(worker_id == 0);
+        if !pause.is_zero() {
+            end_time += pause;
+            ratelimiter.skip(pause);
+            paused += pause;
+        }
+        if let Some(checkpointer) =
+            checkpointer.filter(|_| last_checkpoint.elapsed() >= checkpoint_interval)
+        {
+            checkpointer.publish(
+                worker_id,
+                &latency_histogram,
+                operations,
+                io_errors,
+                begin.elapsed() - paused,
+            );
+            last_checkpoint = Instant::now();
+        }
+        if let Some(flusher) =
+            summary_flusher.filter(|_| last_summary_flush.elapsed() >= summary_flush_interval)
+        {
+            flusher.publish(
+                worker_id,
+                &latency_histogram,
+                operations,
+                io_errors,
+                begin.elapsed() - paused,
+            );
+            last_summary_flush = Instant::now();
+        }
+        if let Some(reporter) =
+            progress_reporter.filter(|_| last_progress.elapsed() >= progress_interval)
+        {
+            reporter.publish(worker_id, operations, io_errors, begin.elapsed() - paused);
+            last_progress = Instant::now();
+        }
+        ratelimiter.set_scale(control::rate_scale());
+        let stride = memory.as_ref().map_or(1, |m| m.stride);
+        if control::sample_rate() / stride as f64 != sample_rate {
+            sample_rate = control::sample_rate() / stride as f64;
+            sample_interval = (1.0 / sample_rate).round() as u64;
+            sample_phase = sample_rng.u64(0..sample_interval);
+        }
+        if block_current >= range.end {
+            block_current = range.start;
+        }
+        ratelimiter.run(
+            || {
+                let op = commands
+                    .as_mut()
+                    .map_or(nvme_ops::Op::Write, nvme_ops::Commands::choose);
+                current_op.set(op);
+                if !config.idle_gap_buckets_us.is_empty() {
+                    submitted.set(Instant::now());
+                }
+                flush_latency.set(None);
+                trim_latency.set(None);
+                if let Some(commands) = commands.as_mut().filter(|_| op != nvme_ops::Op::Write) {
+                    let issued =
+                        commands.issue(op, block_current * BLOCK_SIZE as u64, write_len as u64);
+                    command_errors += u64::from(issued.is_err());
+                    return issued.is_ok();
+                }
+                if config.workload == Workload::Transaction {
+                    let bufs: Vec<&[u8]> = buffers.iter().map(|b| &b.0[..]).collect();
+                    return match transaction::execute(
+                        &*ssd_fd,
+                        &bufs,
+                        block_current * BLOCK_SIZE as u64,
+                        config.transaction_blocks,
+                        &mut bytes,
+                    ) {
+                        Ok(transaction) => {
+                            flush_latency.set(Some(transaction.barrier));
+                            fsyncs += 2;
+                            transactions.record(transaction);
+                            true
+                        }
+                        Err(transaction::Failure::ShortWrite) => {
+                            short_writes += 1;
+                            false
+                        }
+                        Err(transaction::Failure::IoError) => {
+                            io_errors += 1;
+                            false
+                        }
+                    };
+                }
+                let len = range.end - range.start;
+                if config.workload == Workload::Log {
+                    // the log is full: discard the oldest segment before reusing it
+                    let writes = len / iovcnt;
+                    let write = operations % writes;
+                    let segment = write * config.log_segments / writes;
+                    let start = log_segment_start(segment, writes, config.log_segments);
+                    if operations >= writes && write == start {
+                        let end = log_segment_start(segment + 1, writes, config.log_segments);
+                        let discard_begin = Instant::now();
+                        match ssd_fd.discard(
+                            block_current * BLOCK_SIZE as u64,
+                            (end - start) * iovcnt * BLOCK_SIZE as u64,
+                        ) {
+                            Ok(()) => {
+                                let latency = stats::nanos(discard_begin.elapsed());
+                                discard_latency.record(latency);
+                                trim_latency.set(Some(latency));
+                            }
+                            Err(_) => io_errors += 1,
+                        }
+                    }
+                }
+                for (i, buffer) in buffers.iter_mut().enumerate() {
+                    let i = i as u64;
+                    // blocks this thread wrote before this one
+                    let written = operations * iovcnt + i;
+                    if config.verify {
+                        verify::Stamp {
+                            uuid: uuid.as_u128(),
+                            thread_id: worker_id,
+                            block: block_current + i,
+                            pass: written / len,
+                        }
+                        .write_to(&mut buffer.0);
+                    }
+                    if config.crash_records {
+                        crash::Record {
+                            uuid: uuid.as_u128(),
+                            start_time_ns,
+                            thread_id: worker_id,
+                            block: block_current + i,
+                            seq: written,
+                            region_start: range.start,
+                            region_len: len,
+                        }
+                        .write_to(&mut buffer.0);
+                    }
+                }
+                if capture_outliers {
+                    submitted_in_flight
+                        .set(in_flight.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1);
+                }
+                let result = if linked {
+                    let bufs: Vec<&[u8]> = buffers.iter().map(|b| &b.0[..]).collect();
+                    ssd_fd.write_durable_at(&bufs, block_current * BLOCK_SIZE as u64)
+                } else if iovcnt == 1 {
+                    ssd_fd.write_at(&buffers[0].0, block_current * BLOCK_SIZE as u64)
+                } else {
+                    let bufs: Vec<&[u8]> = buffers.iter().map(|b| &b.0[..]).collect();
+                    ssd_fd.write_vectored_at(&bufs, block_current * BLOCK_SIZE as u64)
+                };
+                if capture_outliers {
+                    in_flight.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                match result {
+                    Ok(res) if res == write_len => bytes += res as u64,
+                    Ok(res) => {
+                        bytes += res as u64;
+                        short_writes += 1;
+                        return false;
+                    }
+                    Err(_) => {
+                        io_errors += 1;
+                        return false;
+                    }
+                }
+                let mut durable = true;
+                if linked {
+                    // the fsync is part of the write and has no latency of its own
+                    fsyncs += 1;
+                } else if let Some(shared_commit) = shared_commit {
+                    match shared_commit.commit(|| ssd_fd.sync()) {
+                        Ok(Some(commit)) => {
+                            flush_latency.set(Some(commit.latency));
+                            fsyncs += 1;
+                            commits.record(commit);
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            io_errors += 1;
+                            return false;
+                        }
+                    }
+                } else if config.use_fsync {
+                    if last_commit.elapsed() >= group_commit {
+                        let sync_begin = Instant::now();
+                        if ssd_fd.sync().is_err() {
+                            io_errors += 1;
+                            return false;
+                        }
+                        flush_latency.set(Some(stats::nanos(sync_begin.elapsed())));
+                        fsyncs += 1;
+                        last_commit = Instant::now();
+                    } else {
+                        // waits for the next group commit
+                        durable = false;
+                    }
+                }
+                if let Some(acknowledged) = acknowledged.as_ref().filter(|_| durable) {
+                    acknowledged[worker_id as usize].store(
+                        (operations + 1) * iovcnt,
+                        std::sync::atomic::Ordering::Release,
+                    );
+                }
+                true
+            },
+            |latency, target_rate| {
+                let target_rate = target_rate / share; // of all threads
+                let sampled = match config.sampling_method {
+                    SamplingMethod::Bernoulli | SamplingMethod::Tail => {
+                        sample_rng.f64() < sample_rate
+                    }
+                    SamplingMethod::Systematic => operations % sample_interval == sample_phase,
+                };
+                let sample = |op, latency, weight| Sample {
+                    latency,
+                    op,
+                    id: operations,
+                    thread_id: worker_id,
+                    seq: sample_sequence.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+                    target_iops: target_rate as u64,
+                    uuid: uuid.as_u128(),
+                    monotonic_ns: clock::monotonic_ns(),
+                    realtime_ns: clock::realtime_ns(),
+                    weight,
+                };
+                if let Some(idle_gaps) = idle_gaps.as_mut() {
+                    if current_op.get() == nvme_ops::Op::Write {
+                        idle_gaps.record(submitted.get(), latency);
+                    } else {
+                        idle_gaps.complete();
+                    }
+                }
+                if current_op.get() != nvme_ops::Op::Write {
+                    op_latencies.record(current_op.get(), latency);
+                    if let Some(stream) = sample_stream.as_mut().filter(|_| sampled) {
+                        stream.push(sample(current_op.get().into(), latency, 1.0 / sample_rate));
+                    }
+                    return;
+                }
+                if config.export_histograms {
+                    let now = stats::nanos(completion_base.elapsed());
+                    let previous = last_completion.swap(now, std::sync::atomic::Ordering::Relaxed);
+                    if previous > 0 {
+                        inter_completion_histogram.record(now.saturating_sub(previous));
+                    }
+                }
+                if config.export_histograms
+                    || checkpointer.is_some()
+                    || summary_flusher.is_some()
+                    || !config.io_priorities.is_empty()
+                    || !config.groups.is_empty()
+                {
+                    latency_histogram.record(latency);
+                }
+                if let Some(metrics) = metrics {
+                    metrics.record_write(latency, target_rate);
+                }
+                if let Some(capturer) = &telemetry_capturer {
+                    capturer.record(latency);
+                }
+                if let Some(capture) = outlier_capture.as_mut() {
+                    capture.record(
+                        operations,
+                        stats::nanos(begin.elapsed().saturating_sub(paused)),
+                        latency,
+                        submitted_in_flight.get(),
+                    );
+                }
+                if let Some(recorder) = window_recorder.as_mut() {
+                    recorder.record(begin.elapsed().saturating_sub(paused), latency);
+                }
+                if config.lba_slices > 0 {
+                    let slice = block_current * config.lba_slices / device_blocks;
+                    slice_histograms[slice as usize].record(latency);
+                }
+                // once per write, also if the latency is sampled again below
+                let side_ops = [
+                    (SampleOp::Trim, trim_latency.take()),
+                    (SampleOp::Flush, flush_latency.take()),
+                ];
+                let tail = tail_sampler
+                    .as_mut()
+                    .is_some_and(|sampler| sampler.captures(latency));
+                if sampled {
+                    latencies.push(latency);
+                    if let Some(buckets) = rate_buckets {
+                        let utilization = target_rate / config.max_iops as f64;
+                        bucket_latencies[buckets.bucket_of(utilization)].push(latency);
+                    }
+                }
+                if sampled || tail {
+                    // a write of the tail is in the samples whatever the sampling
+                    let weight = if tail { 1.0 } else { 1.0 / sample_rate };
+                    if let Some(stream) = sample_stream.as_mut() {
+                        for (op, latency) in side_ops {
+                            if let Some(latency) = latency {
+                                stream.push(sample(op, latency, weight));
+                            }
+                        }
+                        stream.push(sample(SampleOp::Write, latency, weight));
+                    }
+                    if let Some(memory) = memory.as_mut() {
+                        memory.account(
+                            &mut latencies,
+                            &mut bucket_latencies,
+                            sample_stream.as_mut(),
+                        );
+                    }
+                }
+            },
+        );
+        if let Some(metrics) = metrics.filter(|_| io_errors > reported_errors) {
+            metrics.record_errors(io_errors - reported_errors);
+            reported_errors = io_errors;
+        }
+        operations += 1;
+        block_current += stride;
+    }
+    let end = Instant::now();
+    let switches = sched_stats::ThreadSwitches::current().since(switches);
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.publish(
+            worker_id,
+            &latency_histogram,
+            operations,
+            io_errors,
+            end - begin - paused,
+        );
+    }
+    if let Some(recorder) = window_recorder {
+        recorder.finish();
+    }
+    let mut backpressure_events = 0;
+    let mut max_pending_samples = 0;
+    let mut samples_offered = 0;
+    let mut samples_dropped = 0;
+    let mut sample_count = 0;
+    if let Some(stream) = sample_stream {
+        backpressure_events = stream.backpressure_events;
+        max_pending_samples = stream.max_pending;
+        samples_offered = stream.offered;
+        samples_dropped = stream.dropped;
+        sample_count = stream.finish();
+    }
+    WorkerResult {
+        begin,
+        end,
+        paused,
+        operations,
+        bytes,
+        io_errors,
+        short_writes,
+        fsyncs,
+        discard_latency,
+        commits,
+        transactions,
+        latencies,
+        bucket_latencies,
+        sample_count,
+        samples_offered,
+        backpressure_events,
+        max_pending_samples,
+        samples_dropped,
+        scheduling_error: ratelimiter.scheduling_error,
+        latency_histogram,
+        inter_completion_histogram,
+        slice_histograms,
+        idle_gaps,
+        memory,
+        dropped_outliers: outlier_capture.as_ref().map_or(0, |c| c.dropped),
+        outliers: outlier_capture.map_or(vec![], |c| c.outliers),
+        op_latencies: nvme_ops::OpLatencies {
+            errors: command_errors,
+            ..op_latencies
+        },
+        switches,
+        profile: ratelimiter.profile,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// The environment is shared by all tests of the process
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn with_env<R>(vars: &[(&str, &str)], f: impl FnOnce() -> R) -> R {
+        let _guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        let result = f();
+        for (key, _) in vars {
+            std::env::remove_var(key);
+        }
+        result
+    }
+
+    const REQUIRED: [(&str, &str); 3] = [
+        ("SSD_BENCHY_INSTANCE_TYPE", "i3en.3xlarge"),
+        ("SSD_BENCHY_MAX_IOPS", "200000"),
+        ("SSD_BENCHY_UTILIZATION_IOPS", "0.5 0.7"),
+    ];
+
+    #[test]
+    fn environment_configures_the_benchmark() {
+        let vars = [
+            REQUIRED.as_slice(),
+            &[
+                ("SSD_BENCHY_USE_FSYNC", "true"),
+                ("SSD_BENCHY_ENGINES", "null"),
+            ],
+        ]
+        .concat();
+        let config = with_env(&vars, || parse_cli(["ssd-benchy"]))
+            .unwrap()
+            .benchmark
+            .unwrap();
+        assert_eq!(config.instance_type, "i3en.3xlarge");
+        assert_eq!(config.max_iops, 200000);
+        assert_eq!(config.utilization_iops, vec![0.5, 0.7]);
+        assert!(config.use_fsync);
+        assert_eq!(config.engines, vec![engine::EngineKind::Null]);
+    }
+
+    #[test]
+    fn command_line_overrides_the_environment() {
+        let vars = [REQUIRED.as_slice(), &[("SSD_BENCHY_WRITER_THREADS", "4")]].concat();
+        let config = with_env(&vars, || {
+            parse_cli(["ssd-benchy", "--writer-threads", "2", "--max-iops", "1000"])
+        })
+        .unwrap()
+        .benchmark
+        .unwrap();
+        assert_eq!(config.writer_threads, 2);
+        assert_eq!(config.max_iops, 1000);
+    }
+
+    #[test]
+    fn invalid_environment_values_are_rejected() {
+        let vars = [
+            REQUIRED.as_slice(),
+            &[("SSD_BENCHY_WRITER_THREADS", "many")],
+        ]
+        .concat();
+        assert!(with_env(&vars, || parse_cli(["ssd-benchy"])).is_err());
+    }
+
+    #[test]
+    fn benchmark_variables_do_not_affect_subcommands() {
+        let vars = [REQUIRED.as_slice(), &[("SSD_BENCHY_KNEE_FACTOR", "3")]].concat();
+        let cli = with_env(&vars, || parse_cli(["ssd-benchy", "report"])).unwrap();
+        assert!(cli.benchmark.is_none());
+        let Some(Command::Report(args)) = cli.command else {
+            panic!("expected the report subcommand");
+        };
+        assert!(format!("{:?}", args).contains("knee_factor: 3.0"));
+    }
+
+    #[test]
+    fn every_flag_has_an_environment_variable() {
+        fn check(command: &clap::Command) {
+            for arg in command.get_arguments() {
+                let Some(long) = arg.get_long() else {
+                    continue;
+                };
+                if long == "help" || long == "version" {
+                    continue;
+                }
+                let expected = format!("SSD_BENCHY_{}", long.to_uppercase().replace('-', "_"));
+                assert_eq!(
+                    arg.get_env().and_then(|e| e.to_str()),
+                    Some(expected.as_str()),
+                    "--{} of {}",
+                    long,
+                    command.get_name()
+                );
+            }
+            for subcommand in command.get_subcommands() {
+                check(subcommand);
+            }
+        }
+        check(&Cli::command());
+    }
+
+    #[test]
+    fn lateness_is_signed_in_both_orders() {
+        let due = Instant::now();
+        let later = due + Duration::from_micros(250);
+        assert_eq!(late_by(later, due), 250_000);
+        assert_eq!(late_by(due, later), -250_000);
+        assert_eq!(late_by(due, due), 0);
+        // with an earlier `now` the unsigned subtraction used to panic or clamp to zero
+        let far = due + Duration::from_secs(1_000_000_000); // 31 years
+        assert_eq!(late_by(due, far), -1_000_000_000_000_000_000);
+    }
+
+    #[test]
+    fn a_rate_of_zero_schedules_the_slowest_rate() {
+        let slowest = 1e6 / MIN_THREAD_RATE;
+        assert_eq!(inter_arrival_us(0.0, 4, 1), slowest);
+        assert_eq!(inter_arrival_us(1e-300, 4, 2), 2.0 * slowest);
+        assert_eq!(inter_arrival_us(f64::NAN, 1, 1), slowest);
+        assert_eq!(inter_arrival_us(4000.0, 4, 1), 1000.0);
+        // a ramp from 0 used to overflow the instant of the first batch
+        let schedule = RateSchedule {
+            pattern: RatePattern::Ramp,
+            min_rate: 0.0,
+            max_rate: 1000.0,
+            runtime: Duration::from_secs(1),
+            period: Duration::from_secs(1),
+        };
+        let start = Instant::now();
+        let ratelimiter = RateLimiter::new(start, schedule, 4, 3, 1, 1.0, 0.0);
+        assert!(ratelimiter.next_time - start <= Duration::from_secs_f64(2.0 * slowest / 1e6));
+    }
+
+    /// A limiter at 1000 writes per second whose schedule is `behind` in the past
+    fn behind_schedule(catch_up: CatchUp, behind: Duration) -> RateLimiter {
+        let schedule = RateSchedule {
+            pattern: RatePattern::Constant,
+            min_rate: 0.0,
+            max_rate: 1000.0,
+            runtime: Duration::from_secs(1),
+            period: Duration::from_secs(1),
+        };
+        let mut ratelimiter = RateLimiter::new(Instant::now(), schedule, 1, 0, 1, 0.0, 0.0);
+        ratelimiter.set_catch_up(catch_up);
+        ratelimiter.next_time = Instant::now() - behind;
+        ratelimiter
+    }
+
+    #[test]
+    fn rate_limiter_profiles_spinning_io_and_sampling() {
+        let mut ratelimiter = behind_schedule(CatchUp::Burst, Duration::ZERO);
+        for _ in 0..3 {
+            ratelimiter.run(
+                || {
+                    std::thread::sleep(Duration::from_millis(2));
+                    true
+                },
+                |_, _| std::thread::sleep(Duration::from_millis(1)),
+            );
+        }
+        let profile = ratelimiter.profile;
+        assert!(profile.io >= Duration::from_millis(6), "{:?}", profile);
+        assert!(
+            profile.sampling >= Duration::from_millis(3),
+            "{:?}",
+            profile
+        );
+        // the first write waits a millisecond, the later ones are late already
+        assert!(profile.spin >= Duration::from_micros(500), "{:?}", profile);
+        assert!(profile.spin < profile.io, "{:?}", profile);
+    }
+
+    #[test]
+    fn threads_start_at_the_same_instant() {
+        let barrier = std::sync::Arc::new(StartBarrier::new(4));
+        let spawned = Instant::now();
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(5 * i));
+                    barrier.wait()
+                })
+            })
+            .collect();
+        let starts: Vec<Instant> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(starts.iter().all(|start| *start == starts[0]));
+        // not before the last thread arrived
+        assert!(starts[0] >= spawned + Duration::from_millis(15));
+        assert!(barrier.clocks.get().is_some());
+
+        // a single thread does not wait for anyone
+        let alone = StartBarrier::new(1);
+        let start = alone.wait();
+        assert!(start <= Instant::now());
+        assert!(alone.clocks.get().is_some());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn missing_privileges_are_named_per_feature() {
+        let mut config = with_env(&[], || {
+            parse_cli([
+                "ssd-benchy",
+                "--engine",
+                "null",
+                "--instance-type",
+                "test",
+                "--max-iops",
+                "1000",
+                "--utilization-iops",
+                "0.5",
+                "--telemetry-threshold-us",
+                "500",
+                "--io-priorities",
+                "rt:0,be:4",
+                "--write-zeroes-fraction",
+                "0.1",
+            ])
+        })
+        .unwrap()
+        .benchmark
+        .unwrap();
+        assert!(privileges::missing(&config, u64::MAX).is_empty());
+        let missing = privileges::missing(&config, 0);
+        let features: Vec<&str> = missing.iter().map(|r| r.feature.as_str()).collect();
+        assert_eq!(
+            features,
+            [
+                "--write-zeroes-fraction",
+                "--telemetry-threshold-us",
+                "--io-priorities rt"
+            ]
+        );
+        assert!(missing.iter().all(|r| r.can_degrade()));
+        assert!(missing[2].privilege.contains("CAP_SYS_NICE"));
+        // CAP_SYS_ADMIN alone is enough for all of them
+        assert!(privileges::missing(&config, 1 << 21).is_empty());
+        config.telemetry_threshold_us = 0;
+        config.write_zeroes_fraction = 0.0;
+        config.io_priorities.clear();
+        assert!(privileges::missing(&config, 0).is_empty());
+
+        // an engine cannot be turned off
+        config.engines = vec![engine::EngineKind::NvmePi];
+        let missing = privileges::missing(&config, 0);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].feature, "--engines nvme-pi");
+        assert!(!missing[0].can_degrade());
+    }
+
+    #[test]
+    fn catch_up_policies_handle_missed_writes() {
+        let behind = Duration::from_micros(10_500);
+        let run = |catch_up| {
+            let mut ratelimiter = behind_schedule(catch_up, behind);
+            let mut latencies = vec![];
+            ratelimiter.run(|| true, |latency, _| latencies.push(latency));
+            (ratelimiter, latencies)
+        };
+
+        let (_, latencies) = run(CatchUp::Burst);
+        assert_eq!(latencies.len(), 1);
+        assert!(latencies[0] >= 9_500_000, "{:?}", latencies);
+
+        let (ratelimiter, latencies) = run(CatchUp::Skip);
+        assert!(ratelimiter.missed_batches >= 9);
+        assert_eq!(latencies.len(), 1);
+        assert!(latencies[0] < 5_000_000, "{:?}", latencies);
+
+        let (ratelimiter, latencies) = run(CatchUp::CoCorrect);
+        assert_eq!(latencies.len() as u64, 1 + ratelimiter.missed_batches);
+        for (k, latency) in latencies.iter().enumerate() {
+            assert_eq!(*latency, latencies[0] + k as u64 * 1_000_000);
+        }
+
+        // a failed write records neither its latency nor those of the writes it skipped
+        let mut ratelimiter = behind_schedule(CatchUp::CoCorrect, behind);
+        let mut recorded = 0;
+        ratelimiter.run(|| false, |_, _| recorded += 1);
+        assert!(ratelimiter.missed_batches >= 9);
+        assert_eq!(recorded, 0);
+
+        // on schedule, nothing is skipped
+        let mut ratelimiter = behind_schedule(CatchUp::Skip, Duration::ZERO);
+        ratelimiter.run(|| true, |_, _| {});
+        assert_eq!(ratelimiter.missed_batches, 0);
+
+        let parse = |catch_up| {
+            with_env(&REQUIRED, || {
+                parse_cli(["ssd-benchy", "--engine", "null", "--catch-up", catch_up])
+            })
+        };
+        assert!(parse("co-correct").is_ok());
+        assert!(parse("never").is_err());
+    }
+
+    #[test]
+    fn only_being_late_adds_to_the_latency() {
+        let service = Duration::from_micros(80);
+        assert_eq!(corrected_latency(service, 20_000), 100_000);
+        assert_eq!(corrected_latency(service, 0), 80_000);
+        assert_eq!(corrected_latency(service, -20_000), 80_000);
+        assert_eq!(corrected_latency(service, i64::MIN), 80_000);
+        assert_eq!(corrected_latency(Duration::MAX, i64::MAX), u64::MAX);
+        assert_eq!(
+            corrected_latency(Duration::from_nanos(u64::MAX - 1), 5),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn schema_matches_the_written_header() {
+        let files = result_files();
+        let summary = &files[0];
+        assert_eq!(summary.option, "--summary-file");
+        let header = schema::header_of(&(
+            BenchmarkConfig::default(),
+            SummaryStatistics::default(),
+            AchievedStatistics::default(),
+            bulk::BulkStatistics::default(),
+            nvme_ops::OpStatistics::default(),
+            thermal::TemperatureStatistics::default(),
+            energy::EnergyStatistics::default(),
+            stability::StabilityStatistics::default(),
+            fill_drift::FillStatistics::default(),
+            drift::DriftStatistics::default(),
+            sched_stats::SchedStatistics::default(),
+            thread_profile::ProfileStatistics::default(),
+        ));
+        let names: Vec<&String> = summary.columns.iter().map(|c| &c.name).collect();
+        assert_eq!(names, header.iter().collect::<Vec<_>>());
+        let column = |name: &str| summary.columns.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(column("uuid").kind, "u128");
+        assert_eq!(column("engine").kind, "string");
+        assert!(!column("p99th").nullable);
+        // a None still has the type of its Option
+        let temperature = column("temperature_max_celsius");
+        assert_eq!(
+            (temperature.kind.as_str(), temperature.nullable),
+            ("i64", true)
+        );
+        assert!(files
+            .iter()
+            .all(|f| !f.columns.is_empty() && f.option.starts_with("--")));
+        // a reader that looks columns up by name sees only one of two namesakes
+        for file in &files {
+            let mut names: Vec<&String> = file.columns.iter().map(|c| &c.name).collect();
+            names.sort();
+            let count = names.len();
+            names.dedup();
+            assert_eq!(names.len(), count, "{} {}", file.command, file.option);
+        }
+        let mut options: Vec<(&str, &str)> = files.iter().map(|f| (f.command, f.option)).collect();
+        options.sort();
+        options.dedup();
+        assert_eq!(options.len(), files.len());
+        assert!(parse_cli(["ssd-benchy", "schema", "--format", "json"]).is_ok());
+        assert!(parse_cli(["ssd-benchy", "schema", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn samples_are_labeled_with_their_operation() {
+        assert_eq!(SampleOp::from(nvme_ops::Op::Deallocate), SampleOp::Trim);
+        assert_eq!(SampleOp::from(nvme_ops::Op::HostCopy), SampleOp::Copy);
+        assert_eq!(SampleOp::from(nvme_ops::Op::Copy), SampleOp::Copy);
+        assert_eq!(SampleOp::from(nvme_ops::Op::Write), SampleOp::default());
+        let header = schema::header_of(&Sample {
+            op: SampleOp::WriteZeroes,
+            ..Sample::default()
+        });
+        assert_eq!(&header[..2], ["latency", "op"]);
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+        for op in [
+            SampleOp::Write,
+            SampleOp::Flush,
+            SampleOp::Trim,
+            SampleOp::WriteZeroes,
+            SampleOp::Copy,
+        ] {
+            wtr.serialize(op).unwrap();
+        }
+        assert_eq!(
+            wtr.into_inner().unwrap(),
+            b"write\nflush\ntrim\nwrite-zeroes\ncopy\n"
+        );
+    }
+
+    #[test]
+    fn repeated_runs_share_their_config_hash() {
+        let config = with_env(&[], || {
+            parse_cli([
+                "ssd-benchy",
+                "--engine",
+                "null",
+                "--instance-type",
+                "test",
+                "--max-iops",
+                "1000",
+                "--utilization-iops",
+                "0.5",
+            ])
+        })
+        .unwrap()
+        .benchmark
+        .unwrap();
+        let device = engine::Device::Null {
+            capacity: 1 << 20,
+            latency: Duration::ZERO,
+        };
+        let run = |utilization, uuid, seed| {
+            BenchmarkConfig::from_cli_config(
+                &config,
+                &device,
+                engine::EngineKind::Null,
+                0.5,
+                utilization,
+                uuid,
+                seed,
+            )
+            .config_hash
+        };
+        assert_eq!(run(0.5, 1, 1), run(0.5, 2, 7));
+        assert_ne!(run(0.5, 1, 1), run(0.6, 1, 1));
+        assert_eq!(run(0.5, 1, 1).len(), 16);
+    }
+
+    #[test]
+    fn profiles_preset_flags_the_command_line_overrides() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("commit.toml"),
+            "description = \"commit path\"\n\
+             instance_type = \"i3en.3xlarge\"\n\
+             max-iops = 1000\n\
+             utilization-iops = [0.3, 0.5]\n\
+             engine = [\"null\"]\n\
+             use-fsync = true\n",
+        )
+        .unwrap();
+        let dir_arg = dir.to_string_lossy().into_owned();
+        let parse = |args: &[&str]| {
+            let args = [
+                "ssd-benchy",
+                "--profile-dir",
+                &dir_arg,
+                "--profile",
+                "commit",
+            ]
+            .iter()
+            .chain(args)
+            .copied()
+            .collect::<Vec<_>>();
+            with_env(&[], || parse_cli(args)).map(|cli| cli.benchmark.unwrap())
+        };
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("commit"));
+        assert_eq!(config.max_iops, 1000);
+        assert_eq!(config.utilization_iops, vec![0.3, 0.5]);
+        assert_eq!(config.engines, vec![engine::EngineKind::Null]);
+        assert!(config.use_fsync);
+        let config = parse(&["--utilization-iops", "0.9", "--engines", "psync"]).unwrap();
+        assert_eq!(config.utilization_iops, vec![0.9]);
+        assert_eq!(config.engines, vec![engine::EngineKind::Psync]);
+
+        std::fs::write(dir.join("typo.toml"), "max-iop = 1000\n").unwrap();
+        let args = ["ssd-benchy", "--profile-dir", &dir_arg, "--profile", "typo"];
+        assert!(with_env(&[], || parse_cli(args)).is_err());
+        let args = [
+            "ssd-benchy",
+            "--profile-dir",
+            &dir_arg,
+            "--profile",
+            "missing",
+        ];
+        assert!(with_env(&[], || parse_cli(args)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
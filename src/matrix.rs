@@ -0,0 +1,321 @@
+//! `ssd-benchy matrix`: a device × utilization table of summary files, with a ranking.
+//!
+//! Picking a drive or an instance type means putting the results of several machines side by
+//! side. The matrix reads the summary files of all of them, labels every row with its device
+//! (`--device-columns`, by default the instance type and the drive model), and pivots the
+//! `--metric` (by default the p99) into one row per device and one column per utilization point.
+//! Repeated runs of a device at a utilization are summarized by their median. The devices are
+//! ranked by the mean of the metric over the utilization points that all of them were measured
+//! at, so that a device is not favored for missing the high utilizations; lower is better unless
+//! `--higher-is-better` (e.g., for achieved_iops). `--filter engine=io-uring` keeps the rows with
+//! that value only, e.g., to not mix engines or workloads in one table.
+
+use crate::{
+    aggregate::{mean, median},
+    report::{Format, Table},
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MatrixArgs {
+    /// Summary files of the devices, e.g., one per machine
+    #[clap(required = true, num_args = 1..)]
+    inputs: Vec<String>,
+
+    /// Numeric summary column in the cells
+    #[clap(long, env = "SSD_BENCHY_METRIC", default_value_t = String::from("p99th"))]
+    metric: String,
+
+    /// Summary columns whose values name the device of a row; rows without any are named after
+    /// their file
+    #[clap(long, env = "SSD_BENCHY_DEVICE_COLUMNS", num_args = 1.., value_delimiter = ' ', default_values_t = ["instance_type", "device_model"].map(String::from))]
+    device_columns: Vec<String>,
+
+    /// Only rows whose column has this value, e.g., engine=io-uring; may be repeated
+    #[clap(long, env = "SSD_BENCHY_FILTER", num_args = 1.., value_delimiter = ' ')]
+    filter: Vec<String>,
+
+    /// Rank larger values of --metric first
+    #[clap(long, env = "SSD_BENCHY_HIGHER_IS_BETTER", default_value_t = false)]
+    higher_is_better: bool,
+
+    #[clap(long, env = "SSD_BENCHY_FORMAT", value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Write the table to this file instead of stdout
+    #[clap(long, env = "SSD_BENCHY_OUTPUT", short)]
+    output: Option<String>,
+}
+
+/// The runs of one device
+struct Device {
+    name: String,
+    cells: Vec<(f64, Vec<f64>)>, // per utilization point, the metric of every run
+}
+
+impl Device {
+    fn cell(&self, utilization: f64) -> Option<&[f64]> {
+        self.cells
+            .iter()
+            .find(|(u, _)| *u == utilization)
+            .map(|(_, runs)| runs.as_slice())
+    }
+}
+
+/// The devices of the rows of all inputs, in the order they first appear
+fn read_devices(args: &MatrixArgs) -> Result<Vec<Device>, String> {
+    let filters = args
+        .filter
+        .iter()
+        .map(|filter| {
+            filter
+                .split_once('=')
+                .ok_or_else(|| format!("--filter {} is not column=value", filter))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut devices: Vec<Device> = vec![];
+    for path in &args.inputs {
+        let mut rdr =
+            csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let header: Vec<String> = rdr
+            .headers()
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?
+            .iter()
+            .map(String::from)
+            .collect();
+        let column = |name: &str| {
+            header.iter().position(|c| c == name).ok_or_else(|| {
+                format!(
+                    "Failed to build the matrix: {} has no column {}",
+                    path, name
+                )
+            })
+        };
+        let metric = column(&args.metric)?;
+        let utilization = column("utilization_iop")?;
+        let filters = filters
+            .iter()
+            .map(|(name, value)| column(name).map(|i| (i, *value)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let names: Vec<usize> = args
+            .device_columns
+            .iter()
+            .filter_map(|name| column(name).ok())
+            .collect();
+        for (line, record) in rdr.records().enumerate() {
+            let record = record.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            if !filters
+                .iter()
+                .all(|&(i, value)| record.get(i) == Some(value))
+            {
+                continue;
+            }
+            let number = |i: usize| {
+                record
+                    .get(i)
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .ok_or_else(|| {
+                        format!(
+                            "Failed to parse {} of row {} of {}",
+                            header[i],
+                            line + 2,
+                            path
+                        )
+                    })
+            };
+            let (value, utilization) = (number(metric)?, number(utilization)?);
+            let name = names
+                .iter()
+                .filter_map(|&i| record.get(i).filter(|value| !value.is_empty()))
+                .collect::<Vec<_>>()
+                .join(" / ");
+            let name = if name.is_empty() { path.clone() } else { name };
+            let index = match devices.iter().position(|d| d.name == name) {
+                Some(index) => index,
+                None => {
+                    devices.push(Device {
+                        name,
+                        cells: vec![],
+                    });
+                    devices.len() - 1
+                }
+            };
+            let cells = &mut devices[index].cells;
+            match cells.iter_mut().find(|(u, _)| *u == utilization) {
+                Some((_, runs)) => runs.push(value),
+                None => cells.push((utilization, vec![value])),
+            }
+        }
+    }
+    Ok(devices)
+}
+
+pub fn matrix(args: &MatrixArgs) -> Result<String, String> {
+    let devices = read_devices(args)?;
+    if devices.is_empty() {
+        return Err(String::from(
+            "Failed to build the matrix: the inputs have no rows",
+        ));
+    }
+    let mut utilizations: Vec<f64> = devices
+        .iter()
+        .flat_map(|d| d.cells.iter().map(|(u, _)| *u))
+        .collect();
+    utilizations.sort_by(f64::total_cmp);
+    utilizations.dedup();
+    let common: Vec<f64> = utilizations
+        .iter()
+        .copied()
+        .filter(|&u| devices.iter().all(|d| d.cell(u).is_some()))
+        .collect();
+    if common.is_empty() {
+        return Err(String::from(
+            "Failed to build the matrix: no utilization point was measured on all devices",
+        ));
+    }
+
+    let score = |device: &Device| {
+        let medians: Vec<f64> = common
+            .iter()
+            .map(|&u| median(device.cell(u).unwrap()))
+            .collect();
+        mean(&medians)
+    };
+    let mut ranked: Vec<(f64, &Device)> = devices.iter().map(|d| (score(d), d)).collect();
+    ranked.sort_by(|(a, _), (b, _)| {
+        if args.higher_is_better {
+            b.total_cmp(a)
+        } else {
+            a.total_cmp(b)
+        }
+    });
+
+    let mut header: Vec<String> = ["rank", "device", "runs"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    header.extend(
+        utilizations
+            .iter()
+            .map(|u| format!("{} @ {}", args.metric, u)),
+    );
+    header.push(format!("mean of {} common", common.len()));
+    let rows = ranked
+        .iter()
+        .enumerate()
+        .map(|(rank, (score, device))| {
+            let mut row = vec![
+                (rank + 1).to_string(),
+                device.name.clone(),
+                device
+                    .cells
+                    .iter()
+                    .map(|(_, runs)| runs.len())
+                    .sum::<usize>()
+                    .to_string(),
+            ];
+            row.extend(utilizations.iter().map(|&u| {
+                device
+                    .cell(u)
+                    .map_or_else(|| String::from("-"), |runs| format!("{:.1}", median(runs)))
+            }));
+            row.push(format!("{:.1}", score));
+            row
+        })
+        .collect();
+
+    let mut out = String::new();
+    Table { header, rows }.write(&mut out, args.format);
+    Ok(out)
+}
+
+pub fn run(args: &MatrixArgs) {
+    let out = matrix(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match &args.output {
+        Some(path) => std::fs::write(path, out).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => print!("{}", out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn args(inputs: &[&PathBuf]) -> MatrixArgs {
+        MatrixArgs {
+            inputs: inputs
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect(),
+            metric: String::from("p99th"),
+            device_columns: vec![String::from("instance_type")],
+            filter: vec![],
+            higher_is_better: false,
+            format: Format::Text,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn matrix_ranks_devices_on_common_utilizations() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-matrix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.csv");
+        let b = dir.join("b.csv");
+        std::fs::write(
+            &a,
+            "instance_type,utilization_iop,p99th\nfast,0.5,100\nfast,0.5,300\nfast,0.9,1000\n",
+        )
+        .unwrap();
+        std::fs::write(&b, "instance_type,utilization_iop,p99th\nslow,0.5,150\n").unwrap();
+        let table = matrix(&args(&[&a, &b])).unwrap();
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        // only 0.5 counts, where fast has a median of 200
+        assert_eq!(rows[0], ["1", "slow", "1", "150.0", "-", "150.0"]);
+        assert_eq!(rows[1], ["2", "fast", "3", "200.0", "1000.0", "200.0"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn inputs_without_a_common_table_are_refused() {
+        let dir =
+            std::env::temp_dir().join(format!("ssd-benchy-matrix-err-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let header_only = dir.join("empty.csv");
+        let other_point = dir.join("other.csv");
+        let no_metric = dir.join("no_metric.csv");
+        let not_a_number = dir.join("nan.csv");
+        std::fs::write(&header_only, "instance_type,utilization_iop,p99th\n").unwrap();
+        std::fs::write(
+            &other_point,
+            "instance_type,utilization_iop,p99th\nfast,0.5,100\nslow,0.9,100\n",
+        )
+        .unwrap();
+        std::fs::write(&no_metric, "instance_type,utilization_iop\nfast,0.5\n").unwrap();
+        std::fs::write(
+            &not_a_number,
+            "instance_type,utilization_iop,p99th\nfast,0.5,x\n",
+        )
+        .unwrap();
+        let error = |inputs: &[&PathBuf]| matrix(&args(inputs)).unwrap_err();
+        assert!(error(&[&header_only]).contains("no rows"));
+        assert!(error(&[&other_point]).contains("no utilization point"));
+        assert!(error(&[&no_metric]).contains("has no column p99th"));
+        assert!(error(&[&not_a_number]).contains("p99th of row 2"));
+        assert!(error(&[&dir.join("missing.csv")]).starts_with("Failed to open"));
+        let mut filtered = args(&[&other_point]);
+        filtered.filter = vec![String::from("instance_type")];
+        assert!(matrix(&filtered).unwrap_err().contains("not column=value"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Memory ceiling of a run (`--memory-budget-mb`).
+//!
+//! Most of the memory of the benchmark is fixed: histograms, buffers, and the channel to the
+//! sample writer thread, which holds a bounded number of batches. What grows with the length of
+//! a utilization point are the sampled latencies every writer thread keeps for the percentiles
+//! (and the rate pattern buckets), and the samples a thread holds back while the sample writer is
+//! behind. At the default sample rate that is harmless, but a 24-hour point with
+//! `--sample-rate 1` would keep billions of latencies and take the host down with it.
+//!
+//! With a budget, the fixed part (the channel) is set aside and every writer thread gets an equal
+//! share of the rest, at least 1 MiB. The latencies it keeps count twice, as a growing vector may
+//! allocate up to twice its length. A thread whose held-back samples exceed half of its share
+//! hands them over and waits for the sample writer instead (`memory_flushes`); the wait delays
+//! its next write, which shows up in the scheduling error. A thread that still exceeds its share
+//! doubles its sampling stride, i.e., halves its sample rate, and drops every other latency it
+//! kept, so that what remains is an evenly thinned sample of the whole point. The summary records
+//! the largest stride of any thread (`memory_max_stride`, 1 without degradation) and the memory
+//! the threads peaked at (`memory_peak_mb`).
+
+use crate::sample_writer::{self, SampleStream};
+
+/// Bytes every writer thread needs at least, well above a batch of samples
+const MIN_THREAD_SHARE: usize = 1024 * 1024;
+
+/// Bytes a writer thread may use, out of a budget of `budget_mb` for `threads` threads; the
+/// channel of the sample writer counts if `samples` are serialized
+pub fn thread_share(budget_mb: u64, threads: u64, samples: bool) -> Result<usize, String> {
+    let budget = budget_mb as usize * 1024 * 1024;
+    let fixed = if samples {
+        sample_writer::max_queued_bytes()
+    } else {
+        0
+    };
+    if budget <= fixed {
+        return Err(format!(
+            "--memory-budget-mb {} does not leave room beyond the {} MiB of the sample writer channel",
+            budget_mb,
+            fixed.div_ceil(1024 * 1024)
+        ));
+    }
+    let share = (budget - fixed) / threads.max(1) as usize;
+    if share < MIN_THREAD_SHARE {
+        return Err(format!(
+            "--memory-budget-mb {} leaves less than 1 MiB for each of the {} writer threads",
+            budget_mb, threads
+        ));
+    }
+    Ok(share)
+}
+
+/// The accounting of one writer thread
+#[derive(Debug, Clone, Default)]
+pub struct ThreadMemory {
+    share: usize,
+    pub stride: u64, // the sample rate of the thread is divided by it
+    pub peak_bytes: usize,
+    pub flushes: u64,
+}
+
+impl ThreadMemory {
+    pub fn new(share: usize) -> ThreadMemory {
+        ThreadMemory {
+            share,
+            stride: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Accounts the memory of a thread after it kept a sample; flushes the held-back samples or
+    /// thins the latencies out if it exceeds its share
+    pub fn account(
+        &mut self,
+        latencies: &mut Vec<u64>,
+        buckets: &mut [Vec<u64>],
+        stream: Option<&mut SampleStream>,
+    ) {
+        let kept = |latencies: &Vec<u64>, buckets: &[Vec<u64>]| {
+            (latencies.len() + buckets.iter().map(Vec::len).sum::<usize>())
+                * 2
+                * std::mem::size_of::<u64>()
+        };
+        let mut pending = stream.as_ref().map_or(0, |s| s.pending_bytes());
+        self.peak_bytes = self.peak_bytes.max(kept(latencies, buckets) + pending);
+        if let Some(stream) = stream.filter(|_| pending > self.share / 2) {
+            stream.flush();
+            self.flushes += 1;
+            pending = stream.pending_bytes();
+        }
+        if kept(latencies, buckets) + pending > self.share {
+            self.stride *= 2;
+            thin_out(latencies);
+            for bucket in buckets {
+                thin_out(bucket);
+            }
+        }
+    }
+}
+
+/// Drops every other value and frees the memory
+fn thin_out(values: &mut Vec<u64>) {
+    let mut keep = false;
+    values.retain(|_| {
+        keep = !keep;
+        keep
+    });
+    values.shrink_to_fit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_budget_thins_out_the_latencies() {
+        assert!(thread_share(1, 2, false).is_err());
+        let share = thread_share(1, 1, false).unwrap();
+        let mut memory = ThreadMemory::new(share);
+        let mut latencies = vec![];
+        for latency in 0..share as u64 / 16 + 1 {
+            latencies.push(latency);
+            memory.account(&mut latencies, &mut [], None);
+        }
+        assert_eq!(memory.stride, 2);
+        // every other latency of the whole point is left
+        assert_eq!(latencies[..3], [0, 2, 4]);
+        assert_eq!(latencies.len() as u64, share as u64 / 32 + 1);
+    }
+
+    #[test]
+    fn budgets_below_the_fixed_memory_are_refused() {
+        let channel_mb = sample_writer::max_queued_bytes() as u64 / (1024 * 1024);
+        let error = thread_share(channel_mb, 1, true).unwrap_err();
+        assert!(error.contains("sample writer channel"), "{}", error);
+        assert!(thread_share(channel_mb + 1, 1, true).is_ok());
+        assert!(thread_share(channel_mb, 1, false).is_ok());
+        assert_eq!(thread_share(4, 0, false), Ok(4 << 20));
+        assert!(thread_share(4, 5, false).is_err());
+    }
+
+    #[test]
+    fn held_back_samples_are_flushed_above_half_the_share() {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(4);
+        let mut stream = SampleStream::new(sender);
+        for _ in 0..300 {
+            stream.push(crate::Sample::default());
+        }
+        let mut memory = ThreadMemory::new(stream.pending_bytes());
+        memory.account(&mut vec![], &mut [], Some(&mut stream));
+        assert_eq!((memory.flushes, memory.stride), (1, 1));
+        let batches: Vec<usize> = receiver.try_iter().map(|batch| batch.len()).collect();
+        assert_eq!(batches, [256, 44]);
+    }
+}
@@ -0,0 +1,151 @@
+//! `ssd-benchy merge`: combine result files of several machines or invocations into one.
+//!
+//! All inputs must have the same header, otherwise their columns would not line up
+//! (`--schema-mismatch migrate` of the benchmark brings an old file up to date first). Every run
+//! has its own uuid, so a run that appears in more than one input, e.g., because a file was
+//! copied before it was merged, is only taken from the first input that has it; rows of the same
+//! run within one input are all kept, which makes this work for the samples file as well. With
+//! `--sort-by-start-time` the rows are ordered by their `start_time` column, otherwise they keep
+//! the order of the inputs.
+
+use crate::schema;
+use std::{collections::HashSet, path::Path};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MergeArgs {
+    /// Result files to merge, e.g., summary files of several machines
+    #[clap(required = true, num_args = 2..)]
+    inputs: Vec<String>,
+
+    /// Merged file; must not exist yet
+    #[clap(short, long, env = "SSD_BENCHY_OUTPUT")]
+    output: String,
+
+    /// Order the rows by their start_time column (stable, so ties keep the order of the inputs)
+    #[clap(long, env = "SSD_BENCHY_SORT_BY_START_TIME")]
+    sort_by_start_time: bool,
+}
+
+struct Input {
+    path: String,
+    header: Vec<String>,
+    records: Vec<csv::StringRecord>,
+}
+
+fn read_input(path: &str) -> Result<Input, String> {
+    let header = schema::existing_header(Path::new(path))?
+        .ok_or_else(|| format!("Failed to read {}: no such file or empty", path))?;
+    let mut rdr =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let records = rdr
+        .records()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(Input {
+        path: path.to_string(),
+        header,
+        records,
+    })
+}
+
+fn merge(args: &MergeArgs) -> Result<(), String> {
+    if Path::new(&args.output).exists() {
+        return Err(format!(
+            "Failed to merge: {} already exists, pass a new file",
+            args.output
+        ));
+    }
+    let inputs = args
+        .inputs
+        .iter()
+        .map(|path| read_input(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let header = &inputs[0].header;
+    for input in &inputs[1..] {
+        if &input.header != header {
+            let missing: Vec<_> = header
+                .iter()
+                .filter(|c| !input.header.contains(c))
+                .collect();
+            let unknown: Vec<_> = input
+                .header
+                .iter()
+                .filter(|c| !header.contains(c))
+                .collect();
+            return Err(format!(
+                "Failed to merge: header of {} does not match {} (missing columns: {:?}, unknown columns: {:?}{})",
+                input.path,
+                inputs[0].path,
+                missing,
+                unknown,
+                if missing.is_empty() && unknown.is_empty() { ", different order" } else { "" }
+            ));
+        }
+    }
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let uuid = column("uuid");
+    let start_time = match args.sort_by_start_time {
+        true => Some(column("start_time").ok_or_else(|| {
+            format!(
+                "Failed to sort: {} has no start_time column",
+                inputs[0].path
+            )
+        })?),
+        false => None,
+    };
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut rows = vec![];
+    let mut duplicates = 0;
+    for input in &inputs {
+        let mut runs = HashSet::new();
+        for record in &input.records {
+            if let Some(uuid) = uuid.and_then(|i| record.get(i)) {
+                if seen.contains(uuid) {
+                    duplicates += 1;
+                    continue;
+                }
+                runs.insert(uuid.to_string());
+            }
+            rows.push(record);
+        }
+        seen.extend(runs);
+    }
+    if let Some(start_time) = start_time {
+        let key = |record: &csv::StringRecord| {
+            record
+                .get(start_time)
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        rows.sort_by_key(|record| key(record));
+    }
+
+    let mut wtr = csv::Writer::from_path(&args.output)
+        .map_err(|e| format!("Failed to create {}: {}", args.output, e))?;
+    wtr.write_record(header).map_err(|e| e.to_string())?;
+    for record in &rows {
+        wtr.write_record(*record).map_err(|e| e.to_string())?;
+    }
+    wtr.flush().map_err(|e| e.to_string())?;
+
+    if uuid.is_none() {
+        println!("warning: no uuid column, duplicates were not removed");
+    }
+    println!(
+        "merged {} rows of {} files into {} ({} duplicate rows dropped)",
+        rows.len(),
+        inputs.len(),
+        args.output,
+        duplicates
+    );
+    Ok(())
+}
+
+pub fn run(args: &MergeArgs) {
+    merge(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+}
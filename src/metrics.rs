@@ -0,0 +1,341 @@
+//! Live Prometheus metrics of a running benchmark (`--metrics-listen`).
+//!
+//! The result files are only written after every utilization point, which is too late to watch a
+//! run of several hours. With `--metrics-listen` a background thread serves the counters below in
+//! the Prometheus text format on `/metrics`; `ssd-benchy grafana-dashboard` emits a dashboard that
+//! is wired to them. The same counters feed the line protocol of `--influx-output` (influx
+//! module). The writer threads update shared atomics on every write, so this costs a little
+//! throughput at very high rates and is off by default.
+
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// How long a scrape may stall before its connection is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const WRITES: &str = "ssd_benchy_writes_total";
+pub const WRITE_ERRORS: &str = "ssd_benchy_write_errors_total";
+pub const WRITE_LATENCY: &str = "ssd_benchy_write_latency_seconds";
+pub const TARGET_IOPS: &str = "ssd_benchy_target_iops";
+pub const UTILIZATION: &str = "ssd_benchy_utilization";
+pub const POINTS: &str = "ssd_benchy_points_completed_total";
+pub const POINT_INFO: &str = "ssd_benchy_point_info";
+
+/// Upper bounds of the latency histogram buckets in nanoseconds, 10us to 1s
+pub const BUCKETS_NS: [u64; 15] = [
+    10_000,
+    25_000,
+    50_000,
+    100_000,
+    250_000,
+    500_000,
+    1_000_000,
+    2_500_000,
+    5_000_000,
+    10_000_000,
+    25_000_000,
+    50_000_000,
+    100_000_000,
+    250_000_000,
+    1_000_000_000,
+];
+
+/// Upper bound in microseconds of the bucket that holds the `percentile` of `buckets`
+pub fn percentile_us(buckets: &[u64], percentile: f64) -> f64 {
+    let total: u64 = buckets.iter().sum();
+    let rank = ((total as f64 * percentile / 100.0).ceil() as u64).max(1);
+    let mut cumulative = 0;
+    for (count, bound) in buckets.iter().zip(BUCKETS_NS) {
+        cumulative += count;
+        if cumulative >= rank {
+            return bound as f64 / 1e3;
+        }
+    }
+    BUCKETS_NS[BUCKETS_NS.len() - 1] as f64 / 1e3
+}
+
+/// Labels of the utilization point that is running
+#[derive(Debug, Clone)]
+pub struct Point {
+    pub ssd_device: String,
+    pub engine: String,
+    pub uuid: u128,
+    pub utilization: f64,
+}
+
+/// The counters at one point in time
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub writes: u64,
+    pub write_errors: u64,
+    pub buckets: [u64; BUCKETS_NS.len()], // not cumulative
+    pub latency_sum_ns: u64,
+    pub target_iops: u64,
+    pub point: Option<Point>,
+}
+
+pub struct Metrics {
+    pub hostname: String,
+    pub instance_type: String,
+    writes: AtomicU64,
+    write_errors: AtomicU64,
+    buckets: [AtomicU64; BUCKETS_NS.len()], // not cumulative, the +Inf bucket is `writes`
+    latency_sum_ns: AtomicU64,
+    target_iops: AtomicU64,
+    points: AtomicU64,
+    point: Mutex<Option<Point>>,
+}
+
+impl Metrics {
+    /// The counters live as long as the benchmark, which hands them to several threads
+    pub fn new(hostname: &str, instance_type: &str) -> &'static Metrics {
+        Box::leak(Box::new(Metrics {
+            hostname: hostname.to_string(),
+            instance_type: instance_type.to_string(),
+            writes: AtomicU64::new(0),
+            write_errors: AtomicU64::new(0),
+            buckets: Default::default(),
+            latency_sum_ns: AtomicU64::new(0),
+            target_iops: AtomicU64::new(0),
+            points: AtomicU64::new(0),
+            point: Mutex::new(None),
+        }))
+    }
+
+    /// Starts serving on `address`, e.g., 0.0.0.0:9464
+    pub fn serve(&'static self, address: &str) -> Result<(), String> {
+        let metrics = self;
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // a client that connects and sends nothing must not hold up the next scrape
+                std::thread::spawn(move || metrics.respond(stream));
+            }
+        });
+        println!("serving metrics on http://{}/metrics", address);
+        Ok(())
+    }
+
+    /// Answers the request on `stream`, giving up on clients that stall for `REQUEST_TIMEOUT`
+    fn respond(&self, stream: TcpStream) {
+        if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(REQUEST_TIMEOUT)).is_err()
+        {
+            return;
+        }
+        let mut request_line = String::new();
+        let mut reader = BufReader::new(&stream);
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        // the headers are not needed, but a client may wait until they are consumed
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+            header.clear();
+        }
+        let (status, body) = match request_line.split_whitespace().nth(1) {
+            Some("/metrics") => ("200 OK", self.render()),
+            _ => ("404 Not Found", String::from("not found\n")),
+        };
+        let _ = write!(
+            &stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+    }
+
+    pub fn start_point(&self, ssd_device: &str, engine: &str, uuid: u128, utilization: f64) {
+        *self.point.lock().unwrap() = Some(Point {
+            ssd_device: ssd_device.to_string(),
+            engine: engine.to_string(),
+            uuid,
+            utilization,
+        });
+    }
+
+    pub fn finish_point(&self) {
+        *self.point.lock().unwrap() = None;
+        self.target_iops.store(0, Ordering::Relaxed);
+        self.points.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `target_iops` is the rate of all threads the write was issued at
+    pub fn record_write(&self, latency_ns: u64, target_iops: f64) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_ns.fetch_add(latency_ns, Ordering::Relaxed);
+        if let Some(bucket) = BUCKETS_NS.iter().position(|&b| latency_ns <= b) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.target_iops
+            .store(target_iops as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_errors(&self, errors: u64) {
+        self.write_errors.fetch_add(errors, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            writes: self.writes.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            latency_sum_ns: self.latency_sum_ns.load(Ordering::Relaxed),
+            target_iops: self.target_iops.load(Ordering::Relaxed),
+            point: self.point.lock().unwrap().clone(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let labels = format!(
+            "hostname=\"{}\",instance_type=\"{}\"",
+            escape(&self.hostname),
+            escape(&self.instance_type)
+        );
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (suffix_and_labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, suffix_and_labels, value);
+            }
+        };
+        let writes = self.writes.load(Ordering::Relaxed);
+        metric(
+            WRITES,
+            "counter",
+            "Writes of the writer threads that completed",
+            &[(format!("{{{}}}", labels), writes.to_string())],
+        );
+        metric(
+            WRITE_ERRORS,
+            "counter",
+            "Failed writes, fsyncs, and discards of the writer threads",
+            &[(
+                format!("{{{}}}", labels),
+                self.write_errors.load(Ordering::Relaxed).to_string(),
+            )],
+        );
+        let mut cumulative = 0;
+        let mut histogram: Vec<(String, String)> = BUCKETS_NS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(bound, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                (
+                    format!("_bucket{{{},le=\"{}\"}}", labels, *bound as f64 / 1e9),
+                    cumulative.to_string(),
+                )
+            })
+            .collect();
+        // the counters are read one after the other, the buckets must not exceed the count
+        let count = writes.max(cumulative);
+        histogram.push((
+            format!("_bucket{{{},le=\"+Inf\"}}", labels),
+            count.to_string(),
+        ));
+        histogram.push((
+            format!("_sum{{{}}}", labels),
+            (self.latency_sum_ns.load(Ordering::Relaxed) as f64 / 1e9).to_string(),
+        ));
+        histogram.push((format!("_count{{{}}}", labels), count.to_string()));
+        metric(
+            WRITE_LATENCY,
+            "histogram",
+            "Write latency including the time the rate limiter was behind schedule",
+            &histogram,
+        );
+        metric(
+            TARGET_IOPS,
+            "gauge",
+            "Rate the writer threads are paced to, 0 between utilization points",
+            &[(
+                format!("{{{}}}", labels),
+                self.target_iops.load(Ordering::Relaxed).to_string(),
+            )],
+        );
+        metric(
+            POINTS,
+            "counter",
+            "Utilization points that completed",
+            &[(
+                format!("{{{}}}", labels),
+                self.points.load(Ordering::Relaxed).to_string(),
+            )],
+        );
+        let point = self.point.lock().unwrap();
+        metric(
+            UTILIZATION,
+            "gauge",
+            "Utilization of the running point, 0 between points",
+            &[(
+                format!("{{{}}}", labels),
+                point.as_ref().map_or(0.0, |p| p.utilization).to_string(),
+            )],
+        );
+        metric(
+            POINT_INFO,
+            "gauge",
+            "The running utilization point",
+            &point
+                .iter()
+                .map(|p| {
+                    (
+                        format!(
+                            "{{{},ssd_device=\"{}\",engine=\"{}\",uuid=\"{}\"}}",
+                            labels,
+                            escape(&p.ssd_device),
+                            escape(&p.engine),
+                            p.uuid
+                        ),
+                        String::from("1"),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn an_idle_client_does_not_block_scrapes() {
+        let metrics = Metrics::new("host", "instance");
+        let address = {
+            // a free port; the listener is dropped before serve binds it again
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        metrics.serve(&address).unwrap();
+        let _idle = TcpStream::connect(&address).unwrap();
+        let mut scrape = TcpStream::connect(&address).unwrap();
+        scrape
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(scrape, "GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.contains(WRITES));
+    }
+}
@@ -0,0 +1,210 @@
+//! Namespaces of one NVMe controller (`--concurrent-namespaces`).
+//!
+//! The namespaces of a controller share its queues, DRAM, and flash, so writes to one show up in
+//! the latency of the others. When the measured namespace has siblings the benchmark warns, and
+//! names the ones that are mounted, i.e., likely in use. `--concurrent-namespaces` turns the
+//! interference into the experiment: every listed namespace gets `--writer-threads` threads of
+//! its own that write it like the measured one, at the same rate and with the same write size,
+//! while the measured namespace runs its utilization point. Their statistics are written to
+//! `--namespace-stats-file`, one row per namespace and point, next to the summary row of the
+//! measured namespace with the same uuid.
+
+use crate::{engine::Engine, histogram::Histogram, RateLimiter};
+use serde::Serialize;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// The other namespaces of the controller of `ssd_device`, empty for devices that are not NVMe
+#[cfg(unix)]
+pub fn siblings(ssd_device: &str) -> Vec<String> {
+    let Ok(dir) = crate::device::sysfs_dir(ssd_device) else {
+        return vec![];
+    };
+    // namespaces have an nsid attribute, their controller's (or subsystem's) directory lists all
+    if !dir.join("nsid").exists() {
+        return vec![];
+    }
+    let own_name = dir.file_name().unwrap_or_default().to_os_string();
+    let Some(Ok(entries)) = dir.parent().map(std::fs::read_dir) else {
+        return vec![];
+    };
+    let mut siblings: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_name() != own_name && entry.path().join("nsid").exists())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    siblings.sort();
+    siblings
+}
+
+#[cfg(not(unix))]
+pub fn siblings(_ssd_device: &str) -> Vec<String> {
+    vec![]
+}
+
+/// Major:minor numbers of the mounted block devices
+fn mounted() -> Vec<String> {
+    std::fs::read_to_string("/proc/self/mountinfo")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2).map(String::from))
+        .collect()
+}
+
+/// Warns about siblings of `ssd_device` that are not written by the benchmark itself
+pub fn warn_about_siblings(ssd_device: &str, concurrent: &[String]) {
+    let mounted = mounted();
+    let is_mounted = |name: &str| {
+        std::fs::read_to_string(format!("/sys/class/block/{}/dev", name))
+            .is_ok_and(|dev| mounted.contains(&dev.trim().to_string()))
+    };
+    let others: Vec<String> = siblings(ssd_device)
+        .into_iter()
+        .filter(|name| !concurrent.contains(name))
+        .map(|name| {
+            if is_mounted(&name) {
+                format!("{} (mounted)", name)
+            } else {
+                name
+            }
+        })
+        .collect();
+    if !others.is_empty() {
+        println!(
+            "warning: {} shares its controller with {}; IO on them interferes with the results (--concurrent-namespaces writes them on purpose)",
+            ssd_device,
+            others.join(", ")
+        );
+    }
+    let siblings = siblings(ssd_device);
+    for name in concurrent {
+        if !siblings.contains(name) {
+            println!(
+                "warning: --concurrent-namespaces {} is not a namespace of the controller of {}",
+                name, ssd_device
+            );
+        }
+    }
+}
+
+/// What a thread of a concurrent namespace hands back after a utilization point
+pub struct NamespaceResult {
+    begin: Instant,
+    end: Instant,
+    paused: Duration,
+    operations: u64,
+    io_errors: u64,
+    latency: Histogram, // nanoseconds, every write
+}
+
+/// Writes `range` (in blocks of `block_size`) sequentially with writes of `write_len` bytes at the
+/// rate of `ratelimiter` from `start` until the runtime is over
+pub fn run(
+    engine: &dyn Engine,
+    range: Range<u64>,
+    write_len: usize,
+    mut ratelimiter: RateLimiter,
+    start: Instant,
+    runtime: Duration,
+) -> NamespaceResult {
+    let block_size = crate::BLOCK_SIZE as u64;
+    let blocks_per_write = write_len as u64 / block_size;
+    let buffer = crate::buffer::AlignedBuffer::new(write_len, 13);
+    let mut block = range.start;
+    let mut operations = 0;
+    let mut io_errors = 0;
+    let mut latency = Histogram::new();
+
+    let begin = start;
+    let mut end_time = begin + runtime;
+    let mut paused = Duration::ZERO;
+    while Instant::now() < end_time {
+        let pause = crate::pause::wait_while_paused(false);
+        if !pause.is_zero() {
+            end_time += pause;
+            ratelimiter.skip(pause);
+            paused += pause;
+        }
+        if block + blocks_per_write > range.end {
+            block = range.start;
+        }
+        ratelimiter.run(
+            || match engine.write_at(&buffer, block * block_size) {
+                Ok(n) => n == write_len,
+                Err(_) => {
+                    io_errors += 1;
+                    false
+                }
+            },
+            |nanos, _| latency.record(nanos),
+        );
+        operations += 1;
+        block += blocks_per_write;
+    }
+    NamespaceResult {
+        begin,
+        end: Instant::now(),
+        paused,
+        operations,
+        io_errors,
+        latency,
+    }
+}
+
+/// One row of the namespace stats file
+#[derive(Serialize, Debug, Default)]
+pub struct NamespaceStatistics {
+    uuid: u128, // of the utilization point, i.e., the summary row of the measured namespace
+    ssd_device: String,
+    utilization_iop: f64,
+    iops: u64,
+    operations: u64,
+    achieved_iops: f64,
+    io_errors: u64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+impl NamespaceStatistics {
+    pub fn create_from_results(
+        uuid: u128,
+        ssd_device: String,
+        utilization_iop: f64,
+        iops: u64,
+        results: &[NamespaceResult],
+    ) -> NamespaceStatistics {
+        let begin = results.iter().map(|r| r.begin).min();
+        let end = results.iter().map(|r| r.end).max();
+        let paused = results.iter().map(|r| r.paused).max().unwrap_or_default();
+        let elapsed_seconds = match (begin, end) {
+            (Some(begin), Some(end)) => (end - begin).saturating_sub(paused).as_secs_f64(),
+            _ => 0.0,
+        };
+        let operations = results.iter().map(|r| r.operations).sum();
+        let mut latency = Histogram::new();
+        for result in results {
+            latency.merge(&result.latency);
+        }
+        NamespaceStatistics {
+            uuid,
+            ssd_device,
+            utilization_iop,
+            iops,
+            operations,
+            achieved_iops: if elapsed_seconds > 0.0 {
+                operations as f64 / elapsed_seconds
+            } else {
+                0.0
+            },
+            io_errors: results.iter().map(|r| r.io_errors).sum(),
+            p50th: latency.percentile(50.0),
+            p99th: latency.percentile(99.0),
+            p999th: latency.percentile(99.9),
+            max: latency.max(),
+        }
+    }
+}
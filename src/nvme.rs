@@ -0,0 +1,601 @@
+//! NVMe SMART / health information log (log page 0x02), power state, raw log pages, the LBA
+//! format of a namespace, and IO passthrough (reads and writes, Write Zeroes, deallocate, Simple
+//! Copy).
+//!
+//! Read with admin passthrough commands (NVME_IOCTL_ADMIN_CMD) on the namespace block device,
+//! which needs CAP_SYS_ADMIN. Devices that are not NVMe (or platforms other than Linux) return an
+//! error, and callers carry on without the counters. The nvme-pi engine (pi module) issues its
+//! reads and writes as IO passthrough commands (NVME_IOCTL_IO_CMD) to control the protection
+//! information of every block.
+
+/// The fields of the SMART log the benchmarks look at
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmartLog {
+    pub critical_warning: u8,
+    pub temperature_celsius: i64, // composite temperature
+    pub media_errors: u64,        // unrecovered data integrity errors
+    pub error_log_entries: u64,
+}
+
+const SMART_LOG_BYTES: usize = 512;
+
+impl SmartLog {
+    fn parse(page: &[u8; SMART_LOG_BYTES]) -> SmartLog {
+        // the counters are 128-bit little endian; the upper half is zero for any real device
+        let counter =
+            |offset: usize| u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap());
+        SmartLog {
+            critical_warning: page[0],
+            temperature_celsius: u16::from_le_bytes([page[1], page[2]]) as i64 - 273,
+            media_errors: counter(160),
+            error_log_entries: counter(176),
+        }
+    }
+}
+
+/// struct nvme_admin_cmd of <linux/nvme_ioctl.h>
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct AdminCommand {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+/// _IOWR('N', 0x41, struct nvme_admin_cmd)
+#[cfg(target_os = "linux")]
+const NVME_IOCTL_ADMIN_CMD: u64 = 0xc048_4e41;
+/// _IOWR('N', 0x43, struct nvme_passthru_cmd), the same layout as nvme_admin_cmd
+#[cfg(target_os = "linux")]
+const NVME_IOCTL_IO_CMD: u64 = 0xc048_4e43;
+/// _IO('N', 0x40), the namespace id of the block device
+#[cfg(target_os = "linux")]
+const NVME_IOCTL_ID: u64 = 0x4e40;
+#[cfg(target_os = "linux")]
+const NVME_ADMIN_GET_LOG_PAGE: u8 = 0x02;
+#[cfg(target_os = "linux")]
+const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+#[cfg(target_os = "linux")]
+const NVME_ADMIN_GET_FEATURES: u8 = 0x0a;
+#[cfg(target_os = "linux")]
+const NVME_LOG_SMART: u32 = 0x02;
+#[cfg(target_os = "linux")]
+const NVME_LOG_ERROR: u32 = 0x01;
+#[cfg(target_os = "linux")]
+const NVME_LOG_TELEMETRY_HOST: u32 = 0x07;
+#[cfg(target_os = "linux")]
+const NVME_FEATURE_ARBITRATION: u32 = 0x01;
+#[cfg(target_os = "linux")]
+const NVME_FEATURE_POWER_MANAGEMENT: u32 = 0x02;
+#[cfg(target_os = "linux")]
+const NVME_IDENTIFY_CONTROLLER: u32 = 0x01;
+#[cfg(target_os = "linux")]
+const NVME_IDENTIFY_NAMESPACE: u32 = 0x00;
+#[cfg(target_os = "linux")]
+const NVME_NSID_ALL: u32 = 0xffff_ffff;
+#[cfg(target_os = "linux")]
+const NVME_CMD_WRITE_ZEROES: u8 = 0x08;
+#[cfg(target_os = "linux")]
+const NVME_CMD_DATASET_MANAGEMENT: u8 = 0x09;
+#[cfg(target_os = "linux")]
+const NVME_DSM_DEALLOCATE: u32 = 1 << 2;
+#[cfg(target_os = "linux")]
+const NVME_CMD_COPY: u8 = 0x19;
+
+/// Issues `command` on `/dev/<ssd_device>` and returns the completion's result dword; `what`
+/// names the command in errors
+#[cfg(target_os = "linux")]
+fn admin(ssd_device: &str, command: &mut AdminCommand, what: &str) -> Result<u32, String> {
+    use std::os::fd::AsRawFd;
+    let path = format!("/dev/{}", ssd_device);
+    let file = std::fs::File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let ret = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            NVME_IOCTL_ADMIN_CMD as _,
+            command as *mut _,
+        )
+    };
+    if ret < 0 {
+        return Err(format!(
+            "Failed to read the {} of {}: {}",
+            what,
+            path,
+            std::io::Error::last_os_error()
+        ));
+    }
+    if ret > 0 {
+        return Err(format!(
+            "Failed to read the {} of {}: NVMe status {:#x}",
+            what, path, ret
+        ));
+    }
+    Ok(command.result)
+}
+
+/// Reads the identify data structure `cns` (of namespace `nsid`, 0 for the controller)
+#[cfg(target_os = "linux")]
+fn identify(ssd_device: &str, nsid: u32, cns: u32) -> Result<Vec<u8>, String> {
+    let mut identify = vec![0u8; 4096];
+    let mut command = AdminCommand {
+        opcode: NVME_ADMIN_IDENTIFY,
+        nsid,
+        addr: identify.as_mut_ptr() as u64,
+        data_len: identify.len() as u32,
+        cdw10: cns,
+        ..Default::default()
+    };
+    let what = if cns == NVME_IDENTIFY_CONTROLLER {
+        "identify controller data"
+    } else {
+        "identify namespace data"
+    };
+    admin(ssd_device, &mut command, what)?;
+    Ok(identify)
+}
+
+/// The namespace id of the block device `/dev/<ssd_device>`
+#[cfg(target_os = "linux")]
+fn namespace_id(ssd_device: &str) -> Result<u32, String> {
+    use std::os::fd::AsRawFd;
+    let path = format!("/dev/{}", ssd_device);
+    let file = std::fs::File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let nsid = unsafe { libc::ioctl(file.as_raw_fd(), NVME_IOCTL_ID as _) };
+    if nsid <= 0 {
+        return Err(format!(
+            "Failed to read the namespace id of {}: {}",
+            path,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(nsid as u32)
+}
+
+/// Reads the controller-wide SMART log of `/dev/<ssd_device>`
+#[cfg(target_os = "linux")]
+pub fn smart_log(ssd_device: &str) -> Result<SmartLog, String> {
+    let mut page = [0u8; SMART_LOG_BYTES];
+    let dwords = (SMART_LOG_BYTES / 4) as u32;
+    let mut command = AdminCommand {
+        opcode: NVME_ADMIN_GET_LOG_PAGE,
+        nsid: NVME_NSID_ALL,
+        addr: page.as_mut_ptr() as u64,
+        data_len: SMART_LOG_BYTES as u32,
+        cdw10: NVME_LOG_SMART | ((dwords - 1) << 16),
+        ..Default::default()
+    };
+    admin(ssd_device, &mut command, "SMART log")?;
+    Ok(SmartLog::parse(&page))
+}
+
+/// Power state the controller is in and the most it may draw in it
+#[derive(Debug, Clone, Copy)]
+pub struct PowerState {
+    pub state: u8,
+    pub max_watts: f64,
+}
+
+/// Reads the current power state (feature 0x02) and its descriptor of the identify controller
+/// data structure; NVMe does not report the power actually drawn
+#[cfg(target_os = "linux")]
+pub fn power_state(ssd_device: &str) -> Result<PowerState, String> {
+    let mut command = AdminCommand {
+        opcode: NVME_ADMIN_GET_FEATURES,
+        cdw10: NVME_FEATURE_POWER_MANAGEMENT,
+        ..Default::default()
+    };
+    let state = (admin(ssd_device, &mut command, "power state")? & 0x1f) as u8;
+    let identify = identify(ssd_device, 0, NVME_IDENTIFY_CONTROLLER)?;
+    // power state descriptors are 32 bytes each from byte 2048; MP is in 0.01 W, or 0.0001 W
+    // with the MXPS bit
+    let descriptor = &identify[2048 + 32 * state as usize..][..32];
+    let max_power = u16::from_le_bytes([descriptor[0], descriptor[1]]) as f64;
+    let scale = if descriptor[3] & 1 == 1 { 1e-4 } else { 1e-2 };
+    Ok(PowerState {
+        state,
+        max_watts: max_power * scale,
+    })
+}
+
+/// Command arbitration of the controller (feature 0x01); the weights only apply with weighted
+/// round robin arbitration and submission queues of different priorities
+#[derive(Debug, Clone, Copy)]
+pub struct Arbitration {
+    pub burst: u32, // commands taken from a queue at a time, 0 for no limit
+    pub low_weight: u32,
+    pub medium_weight: u32,
+    pub high_weight: u32,
+}
+
+/// Reads the arbitration feature of the controller of `/dev/<ssd_device>`
+#[cfg(target_os = "linux")]
+pub fn arbitration(ssd_device: &str) -> Result<Arbitration, String> {
+    let mut command = AdminCommand {
+        opcode: NVME_ADMIN_GET_FEATURES,
+        cdw10: NVME_FEATURE_ARBITRATION,
+        ..Default::default()
+    };
+    let value = admin(ssd_device, &mut command, "arbitration feature")?;
+    // AB is a power of two, 7 for no limit; the weights are 0's based
+    let burst = value & 0x07;
+    Ok(Arbitration {
+        burst: if burst == 7 { 0 } else { 1 << burst },
+        low_weight: (value >> 8 & 0xff) + 1,
+        medium_weight: (value >> 16 & 0xff) + 1,
+        high_weight: (value >> 24 & 0xff) + 1,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn arbitration(ssd_device: &str) -> Result<Arbitration, String> {
+    Err(format!(
+        "Failed to read the arbitration feature of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+/// Raw log pages the vendors ask for when a drive misbehaves
+#[derive(Debug, Default)]
+pub struct LogPages {
+    pub smart: Vec<u8>,
+    pub error: Vec<u8>,     // all error information entries the controller keeps
+    pub telemetry: Vec<u8>, // host-initiated telemetry through data area 3, empty if unsupported
+    pub telemetry_truncated: bool, // at MAX_TELEMETRY_BYTES
+}
+
+/// Telemetry logs can be hundreds of MiB; more than this is not captured
+pub const MAX_TELEMETRY_BYTES: usize = 64 << 20;
+
+/// Bytes per get log page command, well within the smallest transfer size controllers support
+#[cfg(target_os = "linux")]
+const LOG_CHUNK_BYTES: usize = 64 << 10;
+
+/// Reads `len` bytes of log page `log_id` with the log specific field `lsp`, in chunks
+#[cfg(target_os = "linux")]
+fn log_page(
+    ssd_device: &str,
+    log_id: u32,
+    lsp: u32,
+    len: usize,
+    what: &str,
+) -> Result<Vec<u8>, String> {
+    let mut page = vec![0u8; len];
+    for (i, chunk) in page.chunks_mut(LOG_CHUNK_BYTES).enumerate() {
+        let offset = (i * LOG_CHUNK_BYTES) as u64;
+        let dwords = (chunk.len() / 4) as u32 - 1;
+        let mut command = AdminCommand {
+            opcode: NVME_ADMIN_GET_LOG_PAGE,
+            nsid: NVME_NSID_ALL,
+            addr: chunk.as_mut_ptr() as u64,
+            data_len: chunk.len() as u32,
+            cdw10: log_id | (lsp << 8) | ((dwords & 0xffff) << 16),
+            cdw11: dwords >> 16,
+            cdw12: offset as u32,
+            cdw13: (offset >> 32) as u32,
+            ..Default::default()
+        };
+        admin(ssd_device, &mut command, what)?;
+    }
+    Ok(page)
+}
+
+/// Reads the SMART and error information logs and, if the controller supports it, creates and
+/// reads a host-initiated telemetry log
+#[cfg(target_os = "linux")]
+pub fn log_pages(ssd_device: &str) -> Result<LogPages, String> {
+    let identify = identify(ssd_device, 0, NVME_IDENTIFY_CONTROLLER)?;
+    // ELPE is the number of error log entries minus one, LPA bit 3 the telemetry support
+    let error_entries = identify[262] as usize + 1;
+    let telemetry_supported = identify[261] & 0x08 != 0;
+
+    let mut pages = LogPages {
+        smart: log_page(ssd_device, NVME_LOG_SMART, 0, SMART_LOG_BYTES, "SMART log")?,
+        error: log_page(
+            ssd_device,
+            NVME_LOG_ERROR,
+            0,
+            error_entries * 64,
+            "error log",
+        )?,
+        ..Default::default()
+    };
+    if telemetry_supported {
+        // LSP 1 makes the controller capture a new telemetry log; its header says how long it is
+        let header = log_page(ssd_device, NVME_LOG_TELEMETRY_HOST, 1, 512, "telemetry log")?;
+        let last_block = u16::from_le_bytes([header[12], header[13]]) as usize;
+        let len = (last_block + 1) * 512;
+        pages.telemetry_truncated = len > MAX_TELEMETRY_BYTES;
+        pages.telemetry = log_page(
+            ssd_device,
+            NVME_LOG_TELEMETRY_HOST,
+            0,
+            len.min(MAX_TELEMETRY_BYTES),
+            "telemetry log",
+        )?;
+    }
+    Ok(pages)
+}
+
+/// LBA format of a namespace, from its identify namespace data structure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NamespaceFormat {
+    pub nsid: u32,
+    pub blocks: u64,
+    pub lba_bytes: usize,
+    pub metadata_bytes: usize,
+    pub extended: bool, // the metadata of every block follows its data in the same buffer
+    pub pi_type: u8,    // 0 without protection information, otherwise type 1, 2, or 3
+    pub pi_first: bool, // in the first 8 bytes of the metadata, otherwise in the last 8
+}
+
+impl NamespaceFormat {
+    /// Bytes of data, without the metadata
+    pub fn capacity(&self) -> u64 {
+        self.blocks * self.lba_bytes as u64
+    }
+}
+
+/// Reads the LBA format of the namespace `/dev/<ssd_device>`
+#[cfg(target_os = "linux")]
+pub fn namespace_format(ssd_device: &str) -> Result<NamespaceFormat, String> {
+    let nsid = namespace_id(ssd_device)?;
+    let identify = identify(ssd_device, nsid, NVME_IDENTIFY_NAMESPACE)?;
+    // FLBAS: the format index in bits 3:0 (and 6:5 above 16 formats), bit 4 the extended LBA
+    let flbas = identify[26];
+    let index = (flbas & 0x0f) as usize | (((flbas >> 5) & 0x03) as usize) << 4;
+    let lba_format = &identify[128 + 4 * index..][..4];
+    let dps = identify[29];
+    Ok(NamespaceFormat {
+        nsid,
+        blocks: u64::from_le_bytes(identify[0..8].try_into().unwrap()),
+        lba_bytes: 1 << lba_format[2],
+        metadata_bytes: u16::from_le_bytes([lba_format[0], lba_format[1]]) as usize,
+        extended: flbas & 0x10 != 0,
+        pi_type: dps & 0x07,
+        pi_first: dps & 0x08 != 0,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn namespace_format(ssd_device: &str) -> Result<NamespaceFormat, String> {
+    Err(format!(
+        "Failed to read the LBA format of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+/// Bytes the namespace writes atomically on power failure: NAWUPF of the namespace if it has
+/// one, otherwise AWUPF of the controller
+#[cfg(target_os = "linux")]
+pub fn atomic_write_unit(ssd_device: &str) -> Result<u64, String> {
+    let format = namespace_format(ssd_device)?;
+    let namespace = identify(ssd_device, format.nsid, NVME_IDENTIFY_NAMESPACE)?;
+    let controller = identify(ssd_device, 0, NVME_IDENTIFY_CONTROLLER)?;
+    // both are 0's based numbers of logical blocks; NSFEAT bit 1 says whether NAWUPF is valid
+    let blocks = if namespace[24] & 0x02 != 0 {
+        u16::from_le_bytes([namespace[40], namespace[41]])
+    } else {
+        u16::from_le_bytes([controller[528], controller[529]])
+    };
+    Ok((blocks as u64 + 1) * format.lba_bytes as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn atomic_write_unit(ssd_device: &str) -> Result<u64, String> {
+    Err(format!(
+        "Failed to read the atomic write unit of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+/// An NVM command set IO command; the buffers are passed as addresses because the kernel writes
+/// into them on reads
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+pub struct IoCommand {
+    pub opcode: u8, // 0x00 flush, 0x01 write, 0x02 read
+    pub nsid: u32,
+    pub slba: u64,
+    pub blocks: u32,
+    pub prinfo: u32,  // PRACT and PRCHK, bits 29:26 of command dword 12
+    pub ref_tag: u32, // expected initial logical block reference tag
+    pub data: u64,
+    pub data_len: u32,
+    pub metadata: u64, // of a namespace with separate metadata
+    pub metadata_len: u32,
+}
+
+/// Issues `command` on the namespace `file` is opened on
+#[cfg(target_os = "linux")]
+pub fn io(file: &std::fs::File, command: &IoCommand) -> std::io::Result<()> {
+    passthru(
+        file,
+        AdminCommand {
+            opcode: command.opcode,
+            nsid: command.nsid,
+            metadata: command.metadata,
+            addr: command.data,
+            metadata_len: command.metadata_len,
+            data_len: command.data_len,
+            cdw10: command.slba as u32,
+            cdw11: (command.slba >> 32) as u32,
+            cdw12: command.blocks.saturating_sub(1) | command.prinfo << 26,
+            cdw14: command.ref_tag,
+            ..Default::default()
+        },
+    )
+}
+
+/// Zeroes `blocks` logical blocks from `slba` (Write Zeroes, opcode 0x08) without transferring
+/// data; the controller may deallocate them instead of writing zeroes
+#[cfg(target_os = "linux")]
+pub fn write_zeroes(
+    file: &std::fs::File,
+    nsid: u32,
+    slba: u64,
+    blocks: u32,
+) -> std::io::Result<()> {
+    passthru(
+        file,
+        AdminCommand {
+            opcode: NVME_CMD_WRITE_ZEROES,
+            nsid,
+            cdw10: slba as u32,
+            cdw11: (slba >> 32) as u32,
+            cdw12: blocks - 1,
+            ..Default::default()
+        },
+    )
+}
+
+/// Deallocates `blocks` logical blocks from `slba` with one Dataset Management range (opcode
+/// 0x09, attribute AD)
+#[cfg(target_os = "linux")]
+pub fn deallocate(file: &std::fs::File, nsid: u32, slba: u64, blocks: u32) -> std::io::Result<()> {
+    // context attributes, length in logical blocks, starting LBA
+    let mut range = [0u8; 16];
+    range[4..8].copy_from_slice(&blocks.to_le_bytes());
+    range[8..16].copy_from_slice(&slba.to_le_bytes());
+    passthru(
+        file,
+        AdminCommand {
+            opcode: NVME_CMD_DATASET_MANAGEMENT,
+            nsid,
+            addr: range.as_ptr() as u64,
+            data_len: range.len() as u32,
+            cdw10: 0, // one range, 0's based
+            cdw11: NVME_DSM_DEALLOCATE,
+            ..Default::default()
+        },
+    )
+}
+
+/// What a namespace supports of the Simple Copy command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyLimits {
+    pub max_ranges: u32,       // source ranges of one command
+    pub max_range_blocks: u32, // logical blocks of one source range
+    pub max_blocks: u32,       // logical blocks of all source ranges of one command
+}
+
+/// Reads the Simple Copy limits of the namespace `/dev/<ssd_device>`; fails if the controller
+/// does not support the Copy command
+#[cfg(target_os = "linux")]
+pub fn copy_limits(ssd_device: &str) -> Result<CopyLimits, String> {
+    let nsid = namespace_id(ssd_device)?;
+    let controller = identify(ssd_device, 0, NVME_IDENTIFY_CONTROLLER)?;
+    // ONCS bit 8: the Copy command
+    if u16::from_le_bytes([controller[520], controller[521]]) & 0x100 == 0 {
+        return Err(format!(
+            "/dev/{} does not support the Simple Copy command",
+            ssd_device
+        ));
+    }
+    let namespace = identify(ssd_device, nsid, NVME_IDENTIFY_NAMESPACE)?;
+    // MSSRL, MCL, and MSRC (0's based)
+    Ok(CopyLimits {
+        max_range_blocks: u16::from_le_bytes([namespace[72], namespace[73]]) as u32,
+        max_blocks: u32::from_le_bytes(namespace[74..78].try_into().unwrap()),
+        max_ranges: namespace[78] as u32 + 1,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn copy_limits(ssd_device: &str) -> Result<CopyLimits, String> {
+    Err(format!(
+        "Failed to read the copy limits of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+/// Copies the source ranges `sources` (starting LBA and logical blocks) to `sdlba` with one
+/// Simple Copy command (opcode 0x19) within the namespace
+#[cfg(target_os = "linux")]
+pub fn copy(
+    file: &std::fs::File,
+    nsid: u32,
+    sources: &[(u64, u32)],
+    sdlba: u64,
+) -> std::io::Result<()> {
+    // source range entries of descriptor format 0: 32 bytes with the starting LBA and the 0's
+    // based number of blocks
+    let mut ranges = vec![0u8; 32 * sources.len()];
+    for ((slba, blocks), entry) in sources.iter().zip(ranges.chunks_mut(32)) {
+        entry[8..16].copy_from_slice(&slba.to_le_bytes());
+        entry[16..18].copy_from_slice(&((blocks - 1) as u16).to_le_bytes());
+    }
+    passthru(
+        file,
+        AdminCommand {
+            opcode: NVME_CMD_COPY,
+            nsid,
+            addr: ranges.as_ptr() as u64,
+            data_len: ranges.len() as u32,
+            cdw10: sdlba as u32,
+            cdw11: (sdlba >> 32) as u32,
+            cdw12: sources.len() as u32 - 1, // number of ranges, 0's based, descriptor format 0
+            ..Default::default()
+        },
+    )
+}
+
+/// Issues an NVM command set command on the namespace `file` is opened on
+#[cfg(target_os = "linux")]
+fn passthru(file: &std::fs::File, mut passthru: AdminCommand) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let ret = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            NVME_IOCTL_IO_CMD as _,
+            &mut passthru as *mut _,
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if ret > 0 {
+        // e.g., 0x282 guard check error, 0x284 reference tag check error
+        return Err(std::io::Error::other(format!("NVMe status {:#x}", ret)));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn log_pages(ssd_device: &str) -> Result<LogPages, String> {
+    Err(format!(
+        "Failed to read the log pages of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn smart_log(ssd_device: &str) -> Result<SmartLog, String> {
+    Err(format!(
+        "Failed to read the SMART log of {}: only supported on Linux",
+        ssd_device
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn power_state(ssd_device: &str) -> Result<PowerState, String> {
+    Err(format!(
+        "Failed to read the power state of {}: only supported on Linux",
+        ssd_device
+    ))
+}
@@ -0,0 +1,269 @@
+//! NVMe Write Zeroes and deallocate as op types of the writer threads.
+//!
+//! File systems zero and discard ranges with Write Zeroes and Dataset Management (deallocate)
+//! commands, and hypervisors pass their guests' discards down as such, so a device's write
+//! latency is only half the story. With `--write-zeroes-fraction` and `--deallocate-fraction`
+//! that fraction of every writer thread's operations is issued as the command (through NVMe
+//! passthrough, on the blocks the write would have gone to) instead of the write. The commands
+//! take the write's slot in the rate, so the target rate stays the same; their latencies are
+//! kept apart from the writes' and reported in summary columns of their own.
+//!
+//! `--copy-fraction` does the same with NVMe Simple Copy commands, the offload for compaction and
+//! garbage collection: every copy gathers `--copy-ranges` source ranges from random places of the
+//! thread's region into the blocks of the write. Every other copy is done by the host instead, as
+//! passthrough reads of the source ranges and a passthrough write, so the summary compares the
+//! offloaded copy with its equivalent read and write from the same run.
+
+use crate::histogram::Histogram;
+use serde::Serialize;
+use std::{fs::File, ops::Range};
+
+#[cfg(target_os = "linux")]
+const NVME_CMD_WRITE: u8 = 0x01;
+#[cfg(target_os = "linux")]
+const NVME_CMD_READ: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Write,
+    WriteZeroes,
+    Deallocate,
+    Copy,
+    /// A copy as reads of the source ranges and a write
+    HostCopy,
+}
+
+/// Fractions of the writer threads' operations
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpMix {
+    pub write_zeroes: f64,
+    pub deallocate: f64,
+    pub copy: f64,
+    pub copy_ranges: u64,
+}
+
+impl OpMix {
+    pub fn any(&self) -> bool {
+        self.write_zeroes > 0.0 || self.deallocate > 0.0 || self.copy > 0.0
+    }
+}
+
+/// Issues the commands of one writer thread
+pub struct Commands {
+    file: File,
+    nsid: u32,
+    lba_bytes: u64,
+    mix: OpMix,
+    rng: fastrand::Rng,
+    region: Range<u64>, // bytes the copies read from
+    base: u64,          // where the offsets start on the namespace, see the lba_window module
+    copies: u64,
+    buffer: Vec<u8>, // of the host copies
+}
+
+impl Commands {
+    /// `region` is the byte range of the thread, where copies take their source ranges from;
+    /// it and the offsets of the commands start at `base` on the namespace
+    pub fn open(
+        ssd_device: &str,
+        mix: OpMix,
+        seed: u64,
+        region: Range<u64>,
+        base: u64,
+    ) -> Result<Commands, String> {
+        let format = crate::nvme::namespace_format(ssd_device)?;
+        Ok(Commands {
+            file: std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(format!("/dev/{}", ssd_device))
+                .map_err(|e| format!("Failed to open /dev/{}: {}", ssd_device, e))?,
+            nsid: format.nsid,
+            lba_bytes: format.lba_bytes as u64,
+            mix,
+            rng: fastrand::Rng::with_seed(seed),
+            region,
+            base,
+            copies: 0,
+            buffer: vec![],
+        })
+    }
+
+    /// Draws the type of the next operation
+    pub fn choose(&mut self) -> Op {
+        let dice = self.rng.f64();
+        if dice < self.mix.write_zeroes {
+            Op::WriteZeroes
+        } else if dice < self.mix.write_zeroes + self.mix.deallocate {
+            Op::Deallocate
+        } else if dice < self.mix.write_zeroes + self.mix.deallocate + self.mix.copy {
+            self.copies += 1;
+            if self.copies % 2 == 1 {
+                Op::Copy
+            } else {
+                Op::HostCopy
+            }
+        } else {
+            Op::Write
+        }
+    }
+
+    /// Source ranges (starting LBA and blocks) of a copy to `len` bytes at `offset`: random
+    /// places of the region, aligned to their length, that do not overlap the destination
+    fn sources(&mut self, offset: u64, len: u64) -> Vec<(u64, u32)> {
+        let range_len = len / self.mix.copy_ranges;
+        let slots = (self.region.end - self.region.start) / range_len;
+        (0..self.mix.copy_ranges)
+            .map(|_| {
+                let mut source = 0;
+                for _ in 0..16 {
+                    source = self.region.start + self.rng.u64(0..slots) * range_len;
+                    if source + range_len <= offset || source >= offset + len {
+                        break;
+                    }
+                }
+                (
+                    (self.base + source) / self.lba_bytes,
+                    (range_len / self.lba_bytes) as u32,
+                )
+            })
+            .collect()
+    }
+
+    /// Issues `op` on `len` bytes at `offset`; both are multiples of the LBA size
+    pub fn issue(&mut self, op: Op, offset: u64, len: u64) -> std::io::Result<()> {
+        let slba = (self.base + offset) / self.lba_bytes;
+        let blocks = (len / self.lba_bytes) as u32;
+        let sources = match op {
+            Op::Copy | Op::HostCopy => self.sources(offset, len),
+            _ => vec![],
+        };
+        #[cfg(target_os = "linux")]
+        match op {
+            Op::WriteZeroes => crate::nvme::write_zeroes(&self.file, self.nsid, slba, blocks),
+            Op::Deallocate => crate::nvme::deallocate(&self.file, self.nsid, slba, blocks),
+            Op::Copy => crate::nvme::copy(&self.file, self.nsid, &sources, slba),
+            Op::HostCopy => {
+                use crate::nvme::{io, IoCommand};
+                self.buffer.resize(len as usize, 0);
+                let range_len = len as usize / sources.len();
+                for (&(source, blocks), chunk) in
+                    sources.iter().zip(self.buffer.chunks_mut(range_len))
+                {
+                    let command = IoCommand {
+                        opcode: NVME_CMD_READ,
+                        nsid: self.nsid,
+                        slba: source,
+                        blocks,
+                        data: chunk.as_mut_ptr() as u64,
+                        data_len: chunk.len() as u32,
+                        ..Default::default()
+                    };
+                    io(&self.file, &command)?;
+                }
+                let command = IoCommand {
+                    opcode: NVME_CMD_WRITE,
+                    nsid: self.nsid,
+                    slba,
+                    blocks,
+                    data: self.buffer.as_ptr() as u64,
+                    data_len: self.buffer.len() as u32,
+                    ..Default::default()
+                };
+                io(&self.file, &command)
+            }
+            Op::Write => unreachable!("writes go through the engine"),
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (op, &self.file, self.nsid, slba, blocks, sources);
+            Err(std::io::ErrorKind::Unsupported.into())
+        }
+    }
+}
+
+/// Latencies of the commands of one writer thread, in nanoseconds
+#[derive(Debug, Clone, Default)]
+pub struct OpLatencies {
+    pub write_zeroes: Histogram,
+    pub deallocate: Histogram,
+    pub copy: Histogram,
+    pub host_copy: Histogram,
+    pub errors: u64,
+}
+
+impl OpLatencies {
+    pub fn record(&mut self, op: Op, latency: u64) {
+        match op {
+            Op::WriteZeroes => self.write_zeroes.record(latency),
+            Op::Deallocate => self.deallocate.record(latency),
+            Op::Copy => self.copy.record(latency),
+            Op::HostCopy => self.host_copy.record(latency),
+            Op::Write => {}
+        }
+    }
+}
+
+/// Summary columns of the commands; all zero without them
+#[derive(Serialize, Debug, Default)]
+pub struct OpStatistics {
+    write_zeroes_operations: u64,
+    write_zeroes_p50th: u64,
+    write_zeroes_p99th: u64,
+    write_zeroes_p999th: u64,
+    write_zeroes_max: u64,
+    deallocate_operations: u64,
+    deallocate_p50th: u64,
+    deallocate_p99th: u64,
+    deallocate_p999th: u64,
+    deallocate_max: u64,
+    copy_operations: u64,
+    copy_p50th: u64,
+    copy_p99th: u64,
+    copy_p999th: u64,
+    copy_max: u64,
+    host_copy_operations: u64,
+    host_copy_p50th: u64,
+    host_copy_p99th: u64,
+    host_copy_p999th: u64,
+    host_copy_max: u64,
+    command_errors: u64,
+}
+
+impl OpStatistics {
+    pub fn create_from_latencies<'a>(
+        latencies: impl Iterator<Item = &'a OpLatencies>,
+    ) -> OpStatistics {
+        let mut all = OpLatencies::default();
+        for thread in latencies {
+            all.write_zeroes.merge(&thread.write_zeroes);
+            all.deallocate.merge(&thread.deallocate);
+            all.copy.merge(&thread.copy);
+            all.host_copy.merge(&thread.host_copy);
+            all.errors += thread.errors;
+        }
+        OpStatistics {
+            write_zeroes_operations: all.write_zeroes.count(),
+            write_zeroes_p50th: all.write_zeroes.percentile(50.0),
+            write_zeroes_p99th: all.write_zeroes.percentile(99.0),
+            write_zeroes_p999th: all.write_zeroes.percentile(99.9),
+            write_zeroes_max: all.write_zeroes.max(),
+            deallocate_operations: all.deallocate.count(),
+            deallocate_p50th: all.deallocate.percentile(50.0),
+            deallocate_p99th: all.deallocate.percentile(99.0),
+            deallocate_p999th: all.deallocate.percentile(99.9),
+            deallocate_max: all.deallocate.max(),
+            copy_operations: all.copy.count(),
+            copy_p50th: all.copy.percentile(50.0),
+            copy_p99th: all.copy.percentile(99.0),
+            copy_p999th: all.copy.percentile(99.9),
+            copy_max: all.copy.max(),
+            host_copy_operations: all.host_copy.count(),
+            host_copy_p50th: all.host_copy.percentile(50.0),
+            host_copy_p99th: all.host_copy.percentile(99.0),
+            host_copy_p999th: all.host_copy.percentile(99.9),
+            host_copy_max: all.host_copy.max(),
+            command_errors: all.errors,
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Outcome of a benchmark run for orchestration (exit code, status line, `--result-json`).
+//!
+//! Benchmark farms that run ssd-benchy as, e.g., Kubernetes Jobs should not have to parse the log
+//! to learn whether a run succeeded. Every run ends through [`exit`]: the exit code tells the
+//! outcome, the last line on stdout is a single-line JSON status with the outcome, the reason,
+//! and how many utilization points completed, and with `--result-json` a small JSON document
+//! with the outcome and the key numbers of every completed point is written. It is written to a
+//! temporary file first, synced, and renamed, so a collector that waits for the path never reads
+//! half of it, not even after a power loss. With `--progress-format json` a `run_end` event with the outcome precedes the status line.
+//!
+//! Failed I/Os end the run as device errors where they happen, so a panic of the benchmark is a
+//! bug of the benchmark and not of the device; it ends the run as an internal error with an exit
+//! code of its own, so orchestration does not blame the drive for it.
+
+use crate::progress;
+use serde_json::{json, Value};
+use std::{
+    fs::{self, File},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+/// The outcome of a run; `exit_code` is the process's exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every utilization point ran
+    Success,
+    /// Invalid flags or a combination the device or build does not support; nothing ran
+    ConfigError,
+    /// The device could not be found, opened, or sized, or an I/O failed before any point completed
+    DeviceError,
+    /// --verify found blocks that do not hold what was written
+    AssertionFailed,
+    /// A configuration or device error after some utilization points completed, e.g., a capacity
+    /// fraction of the sweep that is too small; the results of the completed points were written
+    PartialRun,
+    /// The benchmark panicked
+    InternalError,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::ConfigError => 1,
+            // 2 is taken by clap's usage errors
+            Outcome::DeviceError => 3,
+            Outcome::AssertionFailed => 4,
+            Outcome::PartialRun => 5,
+            Outcome::InternalError => 6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::ConfigError => "config_error",
+            Outcome::DeviceError => "device_error",
+            Outcome::AssertionFailed => "assertion_failed",
+            Outcome::PartialRun => "partial_run",
+            Outcome::InternalError => "internal_error",
+        }
+    }
+}
+
+struct Run {
+    result_json: Option<String>,
+    started: SystemTime,
+    planned_points: usize,
+    points: Vec<Value>, // one object per completed utilization point
+}
+
+static RUN: Mutex<Option<Run>> = Mutex::new(None);
+static EXITING: AtomicBool = AtomicBool::new(false);
+/// Undo changes to the host before the process ends, e.g., remove a cgroup
+static CLEANUPS: Mutex<Vec<fn()>> = Mutex::new(vec![]);
+
+/// Starts recording the run and turns panics into internal errors
+pub fn start(result_json: Option<String>, planned_points: usize) {
+    *RUN.lock().unwrap() = Some(Run {
+        result_json,
+        started: SystemTime::now(),
+        planned_points,
+        points: vec![],
+    });
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info); // prints the message and location to stderr
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        finish(Outcome::InternalError, &message);
+    }));
+}
+
+/// Runs `cleanup` when the run ends, however it ends
+pub fn on_exit(cleanup: fn()) {
+    CLEANUPS.lock().unwrap().push(cleanup);
+}
+
+pub fn point_completed(point: Value) {
+    if let Some(run) = RUN.lock().unwrap().as_mut() {
+        run.points.push(point);
+    }
+}
+
+/// Prints `message` to stderr and ends the process with `outcome`
+pub fn exit(outcome: Outcome, message: &str) -> ! {
+    if !message.is_empty() {
+        eprintln!("{}", message);
+    }
+    finish(outcome, message)
+}
+
+fn finish(outcome: Outcome, message: &str) -> ! {
+    // several threads may end the run at once, e.g., on a failing device; the first one reports
+    if EXITING.swap(true, Ordering::SeqCst) {
+        loop {
+            std::thread::park();
+        }
+    }
+    let cleanups = std::mem::take(&mut *CLEANUPS.lock().unwrap_or_else(|e| e.into_inner()));
+    for cleanup in cleanups {
+        cleanup();
+    }
+    let run = RUN.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let completed = run.as_ref().map_or(0, |r| r.points.len());
+    let outcome = match outcome {
+        Outcome::ConfigError | Outcome::DeviceError if completed > 0 => Outcome::PartialRun,
+        outcome => outcome,
+    };
+    if let Some(Run {
+        result_json: Some(path),
+        started,
+        points,
+        ..
+    }) = &run
+    {
+        if let Err(e) = write_result(path, outcome, *started, points) {
+            eprintln!("{}", e);
+        }
+    }
+    progress::emit(
+        "run_end",
+        json!({
+            "status": outcome.name(),
+            "exit_code": outcome.exit_code(),
+            "points_completed": completed
+        }),
+    );
+    let status = json!({
+        "status": outcome.name(),
+        "exit_code": outcome.exit_code(),
+        "message": (!message.is_empty()).then_some(message),
+        "points_completed": completed,
+        "points_planned": run.as_ref().map(|r| r.planned_points)
+    });
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", status);
+    let _ = stdout.flush();
+    std::process::exit(outcome.exit_code())
+}
+
+/// Writes the result document
+fn write_result(
+    path: &str,
+    outcome: Outcome,
+    started: SystemTime,
+    points: &[Value],
+) -> Result<(), String> {
+    let unix_seconds = |time: SystemTime| {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    };
+    let result = json!({
+        "status": outcome.name(),
+        "exit_code": outcome.exit_code(),
+        "start_time": unix_seconds(started),
+        "end_time": unix_seconds(SystemTime::now()),
+        "hostname": gethostname::gethostname().to_string_lossy().to_string(),
+        "points": points.to_vec()
+    });
+    replace_file(path, &format!("{}\n", result))
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Replaces the file at `path` with `contents` through a synced temporary file, so that `path`
+/// holds either the old or the new contents also after a crash of the host
+pub fn replace_file(path: &str, contents: &str) -> std::io::Result<()> {
+    let temporary = format!("{}.tmp", path);
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+    // the rename itself is only durable once the directory is
+    #[cfg(unix)]
+    {
+        let parent = std::path::Path::new(path)
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(std::path::Path::new("."));
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_outcome_has_its_own_exit_code() {
+        let outcomes = [
+            Outcome::Success,
+            Outcome::ConfigError,
+            Outcome::DeviceError,
+            Outcome::AssertionFailed,
+            Outcome::PartialRun,
+            Outcome::InternalError,
+        ];
+        for (i, a) in outcomes.iter().enumerate() {
+            // 2 is clap's
+            assert_ne!(a.exit_code(), 2);
+            for b in &outcomes[i + 1..] {
+                assert_ne!(a.exit_code(), b.exit_code());
+                assert_ne!(a.name(), b.name());
+            }
+        }
+    }
+
+    #[test]
+    fn replaced_files_hold_the_new_contents() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-replace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("result.json").to_string_lossy().into_owned();
+        replace_file(&path, "old").unwrap();
+        replace_file(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            1,
+            "no temporary file is left"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+//! Capture of latency outliers with the operations that led up to them.
+//!
+//! The samples file keeps about one operation in 500, so a spike is usually not in it, let alone
+//! what happened before it. With `--outlier-threshold-us` every write slower than the threshold
+//! is recorded, regardless of sampling, together with the latencies of the thread's
+//! `--outlier-context` preceding writes and the number of writes that were in flight (of all
+//! threads, including itself) when it was issued.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Outliers a thread keeps at most per utilization point; a threshold that catches more than
+/// this is too low to be useful
+pub const MAX_OUTLIERS_PER_THREAD: usize = 10000;
+
+/// One row of the outliers file
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct Outlier {
+    uuid: u128,
+    utilization_iop: f64,
+    thread_id: u64,
+    id: u64,         // per-thread operation counter, as in the samples file
+    elapsed_ns: u64, // completion since the thread started its operations
+    latency: u64,
+    in_flight: u64,
+    preceding: String, // latencies of the preceding operations, oldest first, separated by ';'
+}
+
+pub struct OutlierCapture {
+    uuid: u128,
+    utilization_iop: f64,
+    thread_id: u64,
+    threshold_ns: u64,
+    context: VecDeque<u64>,
+    context_len: usize,
+    pub outliers: Vec<Outlier>,
+    pub dropped: u64, // beyond MAX_OUTLIERS_PER_THREAD
+}
+
+impl OutlierCapture {
+    pub fn new(
+        uuid: u128,
+        utilization_iop: f64,
+        thread_id: u64,
+        threshold_ns: u64,
+        context_len: usize,
+    ) -> Self {
+        OutlierCapture {
+            uuid,
+            utilization_iop,
+            thread_id,
+            threshold_ns,
+            context: VecDeque::with_capacity(context_len + 1),
+            context_len,
+            outliers: vec![],
+            dropped: 0,
+        }
+    }
+
+    pub fn record(&mut self, id: u64, elapsed_ns: u64, latency: u64, in_flight: u64) {
+        if latency > self.threshold_ns {
+            if self.outliers.len() < MAX_OUTLIERS_PER_THREAD {
+                let preceding: Vec<String> = self.context.iter().map(|l| l.to_string()).collect();
+                self.outliers.push(Outlier {
+                    uuid: self.uuid,
+                    utilization_iop: self.utilization_iop,
+                    thread_id: self.thread_id,
+                    id,
+                    elapsed_ns,
+                    latency,
+                    in_flight,
+                    preceding: preceding.join(";"),
+                });
+            } else {
+                self.dropped += 1;
+            }
+        }
+        if self.context_len > 0 {
+            if self.context.len() == self.context_len {
+                self.context.pop_front();
+            }
+            self.context.push_back(latency);
+        }
+    }
+}
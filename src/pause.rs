@@ -0,0 +1,66 @@
+//! Pausing and resuming the workload with SIGUSR1 (or the control socket).
+//!
+//! Operators sometimes have to yield the device for a moment, e.g., to take a SMART snapshot or
+//! let another job run a quick check, without throwing away hours of a run. `kill -USR1 <pid>`
+//! pauses the writer and bulk threads after their current write, the next one resumes them. A
+//! paused interval does not count: the rate limiters skip it instead of catching up with a
+//! burst, the utilization point runs that much longer, and the achieved rate, stability windows,
+//! and outlier timestamps only see the time the workload ran. The summary reports the time each
+//! point was paused.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn toggle(_signal: libc::c_int) {
+    // only an atomic operation is async-signal-safe here
+    PAUSED.fetch_xor(true, Ordering::SeqCst);
+}
+
+/// Lets SIGUSR1 toggle the pause instead of terminating the process
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            toggle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+        println!(
+            "pause and resume the workload with kill -USR1 {}",
+            std::process::id()
+        );
+    }
+}
+
+/// Pauses or resumes, e.g., from the control socket
+pub fn set(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Blocks while the workload is paused and returns for how long; `report` prints when it pauses
+/// and resumes, which one of the threads does
+pub fn wait_while_paused(report: bool) -> Duration {
+    if !PAUSED.load(Ordering::Relaxed) {
+        return Duration::ZERO;
+    }
+    let start = Instant::now();
+    if report {
+        println!("paused");
+    }
+    while PAUSED.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let paused = start.elapsed();
+    if report {
+        println!("resumed after {:.1}s", paused.as_secs_f64());
+    }
+    paused
+}
@@ -0,0 +1,364 @@
+//! End-to-end data protection (T10 protection information) with the nvme-pi engine.
+//!
+//! A namespace formatted with protection information stores 8 bytes per block next to the data:
+//! a CRC16 guard of the data, an application tag, and a reference tag (the lower 32 bits of the
+//! LBA for types 1 and 2). Through the block layer the kernel generates and checks them behind
+//! the benchmark's back, so the psync and io-uring engines measure a PI format only as a whole.
+//! The nvme-pi engine issues every read and write as an NVMe IO passthrough command instead and
+//! lets `--pi-mode` decide who does the work: the host (`host`, checked by the controller), the
+//! controller alone (`controller`, PRACT), or nobody (`unchecked`, the metadata is written but
+//! not checked). Running the modes back to back, and a format without PI with `unchecked`,
+//! isolates the latency cost of PI. Namespaces with extended LBAs, whose metadata is interleaved
+//! with the data, are written from a staging buffer of data and metadata; the block layer cannot
+//! use them at all, so they only work without --preinitialize, capacity sweeps, and --verify.
+//! Only the 16-bit guard is supported.
+
+use serde::Serialize;
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PiMode {
+    /// The host computes the guard and reference tags of every block; the controller checks them
+    /// on writes and the host on reads
+    #[default]
+    Host,
+    /// The controller generates the protection information on writes and checks and strips it on
+    /// reads (PRACT); requires 8 bytes of metadata per block
+    Controller,
+    /// The host writes the metadata, but nobody checks it
+    Unchecked,
+}
+
+impl std::fmt::Display for PiMode {
+    /// The name used on the command line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// CRC16 of T10-DIF, polynomial 0x8BB7
+const CRC_TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8bb7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continues `crc` over `bytes`
+fn crc16(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::PiEngine;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{crc16, PiMode};
+    use crate::{
+        engine::Engine,
+        nvme::{self, IoCommand, NamespaceFormat},
+    };
+    use std::{cell::RefCell, fs::File, io};
+
+    const NVME_CMD_FLUSH: u8 = 0x00;
+    const NVME_CMD_WRITE: u8 = 0x01;
+    const NVME_CMD_READ: u8 = 0x02;
+    const PRACT: u32 = 0b1000;
+    const PRCHK_GUARD: u32 = 0b0100;
+    const PRCHK_REF: u32 = 0b0001;
+
+    pub struct PiEngine {
+        file: File,
+        format: NamespaceFormat,
+        mode: PiMode,
+        staging: RefCell<Vec<u8>>, // data and metadata of extended LBAs, or the metadata alone
+    }
+
+    impl PiEngine {
+        pub fn new(file: File, format: NamespaceFormat, mode: PiMode) -> Self {
+            PiEngine {
+                file,
+                format,
+                mode,
+                staging: RefCell::new(vec![]),
+            }
+        }
+
+        /// Whether the host transfers metadata; with PRACT and 8 bytes the controller adds it
+        fn transfers_metadata(&self) -> bool {
+            !(self.mode == PiMode::Controller && self.format.metadata_bytes == 8)
+        }
+
+        fn prinfo(&self) -> u32 {
+            let checks = match self.format.pi_type {
+                0 => return 0,
+                3 => PRCHK_GUARD, // the reference tag of type 3 is opaque
+                _ => PRCHK_GUARD | PRCHK_REF,
+            };
+            match self.mode {
+                PiMode::Host => checks,
+                PiMode::Controller => PRACT | checks,
+                PiMode::Unchecked => 0,
+            }
+        }
+
+        /// Expected reference tag of `lba`
+        fn ref_tag(&self, lba: u64) -> u32 {
+            if self.format.pi_type == 3 {
+                0
+            } else {
+                lba as u32
+            }
+        }
+
+        /// Offset of the 8 bytes of protection information within the metadata of a block
+        fn pi_offset(&self) -> usize {
+            if self.format.pi_first {
+                0
+            } else {
+                self.format.metadata_bytes - 8
+            }
+        }
+
+        /// Fills the metadata of the block with `data` at `lba`
+        fn protect(&self, data: &[u8], lba: u64, metadata: &mut [u8]) {
+            metadata.fill(0);
+            if self.format.pi_type == 0 {
+                return;
+            }
+            let offset = self.pi_offset();
+            // the guard covers the metadata bytes in front of the protection information
+            let guard = crc16(crc16(0, data), &metadata[..offset]);
+            let tuple = &mut metadata[offset..offset + 8];
+            tuple[0..2].copy_from_slice(&guard.to_be_bytes());
+            tuple[4..8].copy_from_slice(&self.ref_tag(lba).to_be_bytes());
+        }
+
+        /// Checks the protection information the host wrote
+        fn check(&self, data: &[u8], lba: u64, metadata: &[u8]) -> io::Result<()> {
+            if self.format.pi_type == 0 {
+                return Ok(());
+            }
+            let offset = self.pi_offset();
+            let tuple = &metadata[offset..offset + 8];
+            // an application tag of 0xffff disables the checks of a block, e.g., unwritten
+            if tuple[2..4] == [0xff, 0xff] {
+                return Ok(());
+            }
+            let guard = crc16(crc16(0, data), &metadata[..offset]);
+            if tuple[0..2] != guard.to_be_bytes() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("guard tag mismatch at LBA {}", lba),
+                ));
+            }
+            if self.format.pi_type != 3 && tuple[4..8] != self.ref_tag(lba).to_be_bytes() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("reference tag mismatch at LBA {}", lba),
+                ));
+            }
+            Ok(())
+        }
+
+        /// The first LBA and the number of blocks of `len` bytes at `offset`
+        fn blocks(&self, offset: u64, len: usize) -> io::Result<(u64, usize)> {
+            let lba_bytes = self.format.lba_bytes;
+            if !offset.is_multiple_of(lba_bytes as u64) || !len.is_multiple_of(lba_bytes) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("IO not aligned to the LBA size {}", lba_bytes),
+                ));
+            }
+            Ok((offset / lba_bytes as u64, len / lba_bytes))
+        }
+    }
+
+    impl Engine for PiEngine {
+        fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+            let (slba, blocks) = self.blocks(offset, buf.len())?;
+            let (lba_bytes, metadata_bytes) = (self.format.lba_bytes, self.format.metadata_bytes);
+            let mut staging = self.staging.borrow_mut();
+            let mut command = IoCommand {
+                opcode: NVME_CMD_WRITE,
+                nsid: self.format.nsid,
+                slba,
+                blocks: blocks as u32,
+                prinfo: self.prinfo(),
+                ref_tag: self.ref_tag(slba),
+                data: buf.as_ptr() as u64,
+                data_len: buf.len() as u32,
+                ..Default::default()
+            };
+            if self.transfers_metadata() && metadata_bytes > 0 {
+                if self.format.extended {
+                    let block_bytes = lba_bytes + metadata_bytes;
+                    staging.resize(blocks * block_bytes, 0);
+                    for (i, (data, block)) in buf
+                        .chunks(lba_bytes)
+                        .zip(staging.chunks_mut(block_bytes))
+                        .enumerate()
+                    {
+                        let (block_data, metadata) = block.split_at_mut(lba_bytes);
+                        block_data.copy_from_slice(data);
+                        self.protect(data, slba + i as u64, metadata);
+                    }
+                    command.data = staging.as_ptr() as u64;
+                    command.data_len = staging.len() as u32;
+                } else {
+                    staging.resize(blocks * metadata_bytes, 0);
+                    for (i, (data, metadata)) in buf
+                        .chunks(lba_bytes)
+                        .zip(staging.chunks_mut(metadata_bytes))
+                        .enumerate()
+                    {
+                        self.protect(data, slba + i as u64, metadata);
+                    }
+                    command.metadata = staging.as_ptr() as u64;
+                    command.metadata_len = staging.len() as u32;
+                }
+            }
+            nvme::io(&self.file, &command)?;
+            Ok(buf.len())
+        }
+
+        fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+            let (slba, blocks) = self.blocks(offset, buf.len())?;
+            let (lba_bytes, metadata_bytes) = (self.format.lba_bytes, self.format.metadata_bytes);
+            let mut staging = self.staging.borrow_mut();
+            let mut command = IoCommand {
+                opcode: NVME_CMD_READ,
+                nsid: self.format.nsid,
+                slba,
+                blocks: blocks as u32,
+                prinfo: self.prinfo(),
+                ref_tag: self.ref_tag(slba),
+                data: buf.as_mut_ptr() as u64,
+                data_len: buf.len() as u32,
+                ..Default::default()
+            };
+            if !self.transfers_metadata() || metadata_bytes == 0 {
+                nvme::io(&self.file, &command)?;
+                return Ok(buf.len());
+            }
+            let check = self.mode == PiMode::Host;
+            if self.format.extended {
+                let block_bytes = lba_bytes + metadata_bytes;
+                staging.resize(blocks * block_bytes, 0);
+                command.data = staging.as_mut_ptr() as u64;
+                command.data_len = staging.len() as u32;
+                nvme::io(&self.file, &command)?;
+                for (i, (data, block)) in buf
+                    .chunks_mut(lba_bytes)
+                    .zip(staging.chunks(block_bytes))
+                    .enumerate()
+                {
+                    let (block_data, metadata) = block.split_at(lba_bytes);
+                    data.copy_from_slice(block_data);
+                    if check {
+                        self.check(data, slba + i as u64, metadata)?;
+                    }
+                }
+            } else {
+                staging.resize(blocks * metadata_bytes, 0);
+                command.metadata = staging.as_mut_ptr() as u64;
+                command.metadata_len = staging.len() as u32;
+                nvme::io(&self.file, &command)?;
+                if check {
+                    for (i, (data, metadata)) in buf
+                        .chunks(lba_bytes)
+                        .zip(staging.chunks(metadata_bytes))
+                        .enumerate()
+                    {
+                        self.check(data, slba + i as u64, metadata)?;
+                    }
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn sync(&self) -> io::Result<()> {
+            nvme::io(
+                &self.file,
+                &IoCommand {
+                    opcode: NVME_CMD_FLUSH,
+                    nsid: self.format.nsid,
+                    ..Default::default()
+                },
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn engine(pi_type: u8) -> PiEngine {
+            let format = NamespaceFormat {
+                nsid: 1,
+                blocks: 8,
+                lba_bytes: 512,
+                metadata_bytes: 16,
+                extended: false,
+                pi_type,
+                pi_first: false,
+            };
+            PiEngine::new(File::open("/dev/null").unwrap(), format, PiMode::Host)
+        }
+
+        #[test]
+        fn protected_blocks_pass_their_check() {
+            let type_1 = engine(1);
+            let data = [0x5a; 512];
+            let mut metadata = [0xee; 16];
+            type_1.protect(&data, 7, &mut metadata);
+            type_1.check(&data, 7, &metadata).unwrap();
+            let error = type_1.check(&data, 8, &metadata).unwrap_err();
+            assert!(error.to_string().contains("reference tag"), "{}", error);
+            let error = type_1.check(&[0x5b; 512], 7, &metadata).unwrap_err();
+            assert!(error.to_string().contains("guard tag"), "{}", error);
+            // the reference tag of type 3 is not checked
+            let type_3 = engine(3);
+            type_3.protect(&data, 7, &mut metadata);
+            type_3.check(&data, 8, &metadata).unwrap();
+        }
+
+        #[test]
+        fn an_application_tag_of_ffff_disables_the_checks() {
+            let engine = engine(1);
+            let mut metadata = [0; 16];
+            metadata[10..12].copy_from_slice(&[0xff, 0xff]);
+            engine.check(&[1; 512], 3, &metadata).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_matches_the_t10_dif_check_value() {
+        assert_eq!(crc16(0, b"123456789"), 0xd0db);
+        // continuing a CRC is the CRC of the concatenation
+        assert_eq!(crc16(crc16(0, b"1234"), b"56789"), 0xd0db);
+        assert_eq!(crc16(0, b""), 0);
+    }
+}
@@ -0,0 +1,511 @@
+//! `ssd-benchy plot`: quick-look charts of result files, without a Python environment.
+//!
+//! Three views cover most quick looks: `line` plots one or more columns against another, e.g.,
+//! p99th over utilization_iop of a summary file; `cdf` plots the distribution of a column, e.g.,
+//! the latencies of a samples file; and `time-series` plots every row of a column over another as
+//! points, e.g., latency over seq. Rows are split into one series per value of `--group-by` (runs
+//! by uuid for the samples views). The samples views honor the `weight` column of
+//! `--sampling-method tail`: the CDF accumulates weights instead of rows, and the time series draws
+//! the samples of every weight as a series of their own, so that the complete tail stands apart
+//! from the thinned body. Charts are drawn with plotters, as SVG or, for a `--output` with a bitmap
+//! extension, e.g., .png, as a bitmap; bitmaps render their text with a system font.
+
+use plotters::{
+    coord::{ranged1d::ValueFormatter, types::RangedCoordf64, Shift},
+    prelude::*,
+};
+use std::{collections::BTreeMap, error::Error, ops::Range, path::Path};
+
+/// Points a series is reduced to; a samples file easily has millions of rows
+const MAX_POINTS: usize = 4000;
+
+const SIZE: (u32, u32) = (800, 500);
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Kind {
+    /// The --y columns over the --x column, connected in the order of x
+    #[default]
+    Line,
+    /// Cumulative distribution of the --y column (latency by default), by weight if present
+    Cdf,
+    /// Every row of the --y column (latency by default) over the --x column (seq by default), a
+    /// series per weight if they differ
+    TimeSeries,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct PlotArgs {
+    /// Result file, e.g., summary.csv or samples.csv
+    #[clap(long, env = "SSD_BENCHY_INPUT")]
+    input: String,
+
+    /// The chart that is drawn
+    #[clap(long, env = "SSD_BENCHY_KIND", value_enum, default_value_t = Kind::Line)]
+    kind: Kind,
+
+    /// Column of the x axis
+    #[clap(long, env = "SSD_BENCHY_X")]
+    x: Option<String>,
+
+    /// Columns of the y axis; line charts draw one series per column
+    #[clap(long, env = "SSD_BENCHY_Y", num_args = 1.., value_delimiter = ' ')]
+    y: Vec<String>,
+
+    /// Column whose values split the rows into series (uuid for cdf and time-series if present)
+    #[clap(long, env = "SSD_BENCHY_GROUP_BY")]
+    group_by: Option<String>,
+
+    /// Logarithmic x axis
+    #[clap(long, env = "SSD_BENCHY_LOG_X")]
+    log_x: bool,
+
+    /// Logarithmic y axis
+    #[clap(long, env = "SSD_BENCHY_LOG_Y")]
+    log_y: bool,
+
+    /// Chart file; .svg for a vector image, .png (or .jpg, .bmp) for a bitmap
+    #[clap(long, env = "SSD_BENCHY_OUTPUT", short, default_value_t = String::from("plot.svg"))]
+    output: String,
+}
+
+/// x, y, and the weight of a row, 1 without a weight column
+type WeightedPoint = (f64, f64, f64);
+
+struct Series {
+    name: String,
+    points: Vec<(f64, f64)>,
+}
+
+/// One axis of the chart; `log` axes only show positive values
+struct Axis {
+    label: String,
+    range: Range<f64>,
+    log: bool,
+}
+
+impl Axis {
+    fn new(label: &str, values: impl Iterator<Item = f64>, log: bool) -> Result<Axis, String> {
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for v in values.filter(|v| !log || *v > 0.0) {
+            min = min.min(v);
+            max = max.max(v);
+        }
+        if !min.is_finite() {
+            return Err(format!(
+                "Failed to plot: no {}values of {}",
+                if log { "positive " } else { "" },
+                label
+            ));
+        }
+        if min == max {
+            (min, max) = if log {
+                (min / 10.0, max * 10.0)
+            } else {
+                (min - 1.0, max + 1.0)
+            };
+        }
+        Ok(Axis {
+            label: label.to_string(),
+            range: min..max,
+            log,
+        })
+    }
+
+    fn shows(&self, v: f64) -> bool {
+        !self.log || v > 0.0
+    }
+}
+
+/// Tick label with an SI suffix, e.g., 2.5M
+fn format_tick(v: f64) -> String {
+    let (scaled, suffix) = match v.abs() {
+        a if a >= 1e9 => (v / 1e9, "G"),
+        a if a >= 1e6 => (v / 1e6, "M"),
+        a if a >= 1e3 => (v / 1e3, "k"),
+        _ => (v, ""),
+    };
+    let mut s = format!("{:.2}", scaled);
+    while s.contains('.') && (s.ends_with('0') || s.ends_with('.')) {
+        s.pop();
+    }
+    format!("{}{}", s, suffix)
+}
+
+/// Keeps the minimum and maximum y of every x bucket, so that spikes survive the reduction
+fn reduce(points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    if points.len() <= MAX_POINTS {
+        return points;
+    }
+    let bucket = points.len().div_ceil(MAX_POINTS / 2);
+    points
+        .chunks(bucket)
+        .flat_map(|chunk| {
+            let by_y = |a: &&(f64, f64), b: &&(f64, f64)| a.1.total_cmp(&b.1);
+            let min = *chunk.iter().min_by(by_y).unwrap();
+            let max = *chunk.iter().max_by(by_y).unwrap();
+            if min.0 <= max.0 {
+                [min, max]
+            } else {
+                [max, min]
+            }
+        })
+        .collect()
+}
+
+/// Draws the series into `root` on the coordinates `x_coord` and `y_coord` of the axes
+fn draw<DB, X, Y>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    series: &[Series],
+    (x, x_coord): (&Axis, X),
+    (y, y_coord): (&Axis, Y),
+    lines: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    X: Ranged<ValueType = f64> + ValueFormatter<f64>,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 16))
+        .margin(12)
+        .x_label_area_size(45)
+        .y_label_area_size(70)
+        .build_cartesian_2d(x_coord, y_coord)?;
+    chart
+        .configure_mesh()
+        .x_desc(x.label.as_str())
+        .y_desc(y.label.as_str())
+        .x_label_formatter(&|v| format_tick(*v))
+        .y_label_formatter(&|v| format_tick(*v))
+        .light_line_style(RGBColor(240, 240, 240))
+        .draw()?;
+    for (i, s) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        let points = s
+            .points
+            .iter()
+            .copied()
+            .filter(|&(vx, vy)| x.shows(vx) && y.shows(vy));
+        let drawn = if lines {
+            chart.draw_series(LineSeries::new(points, color.stroke_width(2)))?
+        } else {
+            chart.draw_series(points.map(|p| Circle::new(p, 2, color.filled())))?
+        };
+        if series.len() > 1 || !s.name.is_empty() {
+            drawn.label(s.name.as_str()).legend(move |(lx, ly)| {
+                Rectangle::new([(lx, ly - 5), (lx + 10, ly + 5)], color.filled())
+            });
+        }
+    }
+    if series.len() > 1 || series.iter().any(|s| !s.name.is_empty()) {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+/// Draws into `root` with a linear or logarithmic coordinate per axis
+fn render<DB>(
+    root: DrawingArea<DB, Shift>,
+    title: &str,
+    series: &[Series],
+    x: &Axis,
+    y: &Axis,
+    lines: bool,
+) -> Result<(), Box<dyn Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let linear = |axis: &Axis| RangedCoordf64::from(axis.range.clone());
+    let log = |axis: &Axis| axis.range.clone().log_scale().into();
+    match (x.log, y.log) {
+        (false, false) => draw(&root, title, series, (x, linear(x)), (y, linear(y)), lines),
+        (false, true) => {
+            draw::<_, _, LogCoord<f64>>(&root, title, series, (x, linear(x)), (y, log(y)), lines)
+        }
+        (true, false) => {
+            draw::<_, LogCoord<f64>, _>(&root, title, series, (x, log(x)), (y, linear(y)), lines)
+        }
+        (true, true) => draw::<_, LogCoord<f64>, LogCoord<f64>>(
+            &root,
+            title,
+            series,
+            (x, log(x)),
+            (y, log(y)),
+            lines,
+        ),
+    }
+}
+
+fn plot(args: &PlotArgs) -> Result<(), String> {
+    let extension = Path::new(&args.output)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let svg = match extension.as_deref() {
+        Some("svg") => true,
+        Some("png" | "jpg" | "jpeg" | "bmp") => false,
+        _ => {
+            return Err(format!(
+                "Failed to plot: {} is neither an .svg nor a .png, .jpg, or .bmp file",
+                args.output
+            ))
+        }
+    };
+    let mut rdr = csv::Reader::from_path(&args.input)
+        .map_err(|e| format!("Failed to open {}: {}", args.input, e))?;
+    let header: Vec<String> = rdr
+        .headers()
+        .map_err(|e| format!("Failed to read {}: {}", args.input, e))?
+        .iter()
+        .map(String::from)
+        .collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("Failed to plot: {} has no column {}", args.input, name))
+    };
+
+    let (x, ys) = match args.kind {
+        Kind::Line => {
+            let x = args
+                .x
+                .clone()
+                .ok_or("Failed to plot: line charts need --x")?;
+            if args.y.is_empty() {
+                return Err("Failed to plot: line charts need --y".to_string());
+            }
+            (Some(x), args.y.clone())
+        }
+        Kind::Cdf => {
+            if args.y.len() > 1 {
+                return Err("Failed to plot: cdf charts take a single --y".to_string());
+            }
+            (
+                None,
+                vec![args.y.first().cloned().unwrap_or("latency".into())],
+            )
+        }
+        Kind::TimeSeries => {
+            if args.y.len() > 1 {
+                return Err("Failed to plot: time-series charts take a single --y".to_string());
+            }
+            (
+                Some(args.x.clone().unwrap_or("seq".into())),
+                vec![args.y.first().cloned().unwrap_or("latency".into())],
+            )
+        }
+    };
+    let x_index = x.as_deref().map(column).transpose()?;
+    let y_indices = ys
+        .iter()
+        .map(|y| column(y))
+        .collect::<Result<Vec<_>, _>>()?;
+    let group_by = match (&args.group_by, args.kind) {
+        (Some(group_by), _) => Some(column(group_by)?),
+        (None, Kind::Line) => None,
+        (None, _) => column("uuid").ok(),
+    };
+    let weight_index = match args.kind {
+        Kind::Line => None,
+        Kind::Cdf | Kind::TimeSeries => column("weight").ok(),
+    };
+
+    // series by group and y column; values that are not numbers, e.g., empty optional columns, are skipped
+    let mut groups: BTreeMap<(String, usize), Vec<WeightedPoint>> = BTreeMap::new();
+    let mut skipped = 0;
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", args.input, e))?;
+        let value = |i: usize| record.get(i).and_then(|s| s.parse::<f64>().ok());
+        let group = group_by
+            .and_then(|i| record.get(i))
+            .unwrap_or_default()
+            .to_string();
+        let weight = weight_index.and_then(value).unwrap_or(1.0);
+        for (n, &y_index) in y_indices.iter().enumerate() {
+            let vx = match x_index {
+                Some(i) => value(i),
+                None => Some(0.0),
+            };
+            match (vx, value(y_index)) {
+                (Some(vx), Some(vy)) => groups
+                    .entry((group.clone(), n))
+                    .or_default()
+                    .push((vx, vy, weight)),
+                _ => skipped += 1,
+            }
+        }
+    }
+    if skipped > 0 {
+        println!("warning: skipped {} values that are not numbers", skipped);
+    }
+
+    let series: Vec<Series> = groups
+        .into_iter()
+        .flat_map(|((group, n), mut points)| {
+            let name = match (group.is_empty(), ys.len() > 1) {
+                (true, _) => ys[n].clone(),
+                (false, false) => group,
+                (false, true) => format!("{} {}", group, ys[n]),
+            };
+            let series = |name: String, points| Series {
+                name,
+                points: reduce(points),
+            };
+            match args.kind {
+                Kind::Cdf => {
+                    points.sort_by(|a, b| a.1.total_cmp(&b.1));
+                    let total: f64 = points.iter().map(|p| p.2).sum();
+                    let mut cumulative = 0.0;
+                    let points = points
+                        .into_iter()
+                        .map(|(_, v, weight)| {
+                            cumulative += weight;
+                            (v, cumulative / total)
+                        })
+                        .collect();
+                    vec![series(name, points)]
+                }
+                Kind::Line | Kind::TimeSeries => {
+                    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    let mut weights: Vec<f64> = points.iter().map(|p| p.2).collect();
+                    weights.sort_by(f64::total_cmp);
+                    weights.dedup();
+                    if weights.len() == 1 {
+                        let points = points.into_iter().map(|(vx, vy, _)| (vx, vy)).collect();
+                        return vec![series(name, points)];
+                    }
+                    weights
+                        .into_iter()
+                        .map(|weight| {
+                            let points = points
+                                .iter()
+                                .filter(|p| p.2 == weight)
+                                .map(|&(vx, vy, _)| (vx, vy))
+                                .collect();
+                            series(format!("{} weight {}", name, weight), points)
+                        })
+                        .collect()
+                }
+            }
+        })
+        .collect();
+    if series.is_empty() {
+        return Err(format!(
+            "Failed to plot: {} has no rows to plot",
+            args.input
+        ));
+    }
+
+    let all = || series.iter().flat_map(|s| s.points.iter());
+    let (x_axis, y_axis, title) = match args.kind {
+        Kind::Cdf => (
+            Axis::new(&ys[0], all().map(|p| p.0), args.log_x)?,
+            Axis::new("fraction", all().map(|p| p.1), args.log_y)?,
+            format!("CDF of {}", ys[0]),
+        ),
+        Kind::Line | Kind::TimeSeries => {
+            let x = x.unwrap();
+            let y_label = ys.join(", ");
+            (
+                Axis::new(&x, all().map(|p| p.0), args.log_x)?,
+                Axis::new(&y_label, all().map(|p| p.1), args.log_y)?,
+                format!("{} over {}", y_label, x),
+            )
+        }
+    };
+    let title = format!("{} ({})", title, args.input);
+    let lines = args.kind != Kind::TimeSeries;
+    if svg {
+        let root = SVGBackend::new(&args.output, SIZE).into_drawing_area();
+        render(root, &title, &series, &x_axis, &y_axis, lines)
+    } else {
+        let root = BitMapBackend::new(&args.output, SIZE).into_drawing_area();
+        render(root, &title, &series, &x_axis, &y_axis, lines)
+    }
+    .map_err(|e| format!("Failed to write {}: {}", args.output, e))?;
+    println!("wrote {} series to {}", series.len(), args.output);
+    Ok(())
+}
+
+pub fn run(args: &PlotArgs) {
+    plot(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_output_extension_picks_the_backend() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-plot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("summary.csv");
+        std::fs::write(
+            &input,
+            "utilization_iop,p99th\n0.1,200\n0.5,400\n0.9,2000\n",
+        )
+        .unwrap();
+        let args = |output: &str| PlotArgs {
+            input: input.to_string_lossy().into_owned(),
+            kind: Kind::Line,
+            x: Some("utilization_iop".into()),
+            y: vec!["p99th".into()],
+            group_by: None,
+            log_x: false,
+            log_y: true,
+            output: dir.join(output).to_string_lossy().into_owned(),
+        };
+        plot(&args("p99.svg")).unwrap();
+        let svg = std::fs::read_to_string(dir.join("p99.svg")).unwrap();
+        assert!(svg.contains("<svg") && svg.contains("p99th"));
+        plot(&args("p99.png")).unwrap();
+        let png = std::fs::read(dir.join("p99.png")).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert!(plot(&args("p99.pdf")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tail_samples_are_plotted_by_their_weight() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-plot-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("samples.csv");
+        // the body stands for 3 writes per sample, the tail sample for itself
+        std::fs::write(
+            &input,
+            "uuid,seq,latency,weight\n1,0,10,3\n1,1,20,3\n1,2,900,1\n",
+        )
+        .unwrap();
+        let args = |kind| PlotArgs {
+            input: input.to_string_lossy().into_owned(),
+            kind,
+            x: None,
+            y: vec![],
+            group_by: None,
+            log_x: false,
+            log_y: false,
+            output: dir.join("samples.svg").to_string_lossy().into_owned(),
+        };
+        plot(&args(Kind::Cdf)).unwrap();
+        let svg = std::fs::read_to_string(dir.join("samples.svg")).unwrap();
+        assert!(svg.contains("CDF of latency"));
+        plot(&args(Kind::TimeSeries)).unwrap();
+        let svg = std::fs::read_to_string(dir.join("samples.svg")).unwrap();
+        assert!(svg.contains("1 weight 1") && svg.contains("1 weight 3"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
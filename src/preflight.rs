@@ -0,0 +1,432 @@
+//! `ssd-benchy preflight`: a checklist of the device and host before a long run.
+//!
+//! A misconfiguration that only surfaces after two hours of preinitialization, or never and
+//! skews the results, is expensive. The preflight only reads from the device and checks that it
+//! is a writable block device that supports O_DIRECT with the benchmark's block size, that its
+//! capacity can be determined, and that neither it nor one of its partitions is mounted, used for
+//! swap, or held by device mapper or md. The I/O scheduler and the CPU frequency governor do not
+//! prevent a run but add latency and variance, so they are only warnings. The exit code is 1 if
+//! a check failed.
+
+use crate::{buffer::AlignedBuffer, device, BLOCK_SIZE};
+use std::{
+    fmt, fs,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct PreflightArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        })
+    }
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Check {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// The sysfs directory with `queue/`, the parent's for a partition
+pub(crate) fn queue_dir(sysfs_dir: &Path) -> PathBuf {
+    if sysfs_dir.join("partition").exists() {
+        sysfs_dir.parent().unwrap_or(sysfs_dir).to_path_buf()
+    } else {
+        sysfs_dir.to_path_buf()
+    }
+}
+
+fn permissions(node: &str, sysfs_dir: &Path) -> Check {
+    if read_trimmed(&sysfs_dir.join("ro")).as_deref() == Some("1") {
+        return Check::new(
+            "permissions",
+            Status::Fail,
+            format!("{} is read-only", node),
+        );
+    }
+    match fs::OpenOptions::new().read(true).write(true).open(node) {
+        Ok(_) => Check::new("permissions", Status::Pass, "readable and writable"),
+        Err(e) => Check::new(
+            "permissions",
+            Status::Fail,
+            format!(
+                "Failed to open {} for writing: {}{}",
+                node,
+                e,
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    "; run as root or as a member of the disk group"
+                } else {
+                    ""
+                }
+            ),
+        ),
+    }
+}
+
+/// Reads the first block with O_DIRECT, which also requires an aligned buffer and offset
+fn o_direct(node: &str) -> Check {
+    let file = match fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(node)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            return Check::new(
+                "o_direct",
+                Status::Fail,
+                format!("Failed to open {} with O_DIRECT: {}", node, e),
+            )
+        }
+    };
+    let mut buffer = AlignedBuffer::new(BLOCK_SIZE, 0);
+    match file.read_at(&mut buffer, 0) {
+        Ok(n) if n == BLOCK_SIZE => Check::new(
+            "o_direct",
+            Status::Pass,
+            format!("read {} bytes at offset 0", BLOCK_SIZE),
+        ),
+        Ok(n) => Check::new(
+            "o_direct",
+            Status::Fail,
+            format!("short read of {} instead of {} bytes", n, BLOCK_SIZE),
+        ),
+        Err(e) => Check::new(
+            "o_direct",
+            Status::Fail,
+            format!("Failed to read {} with O_DIRECT: {}", node, e),
+        ),
+    }
+}
+
+fn alignment(sysfs_dir: &Path) -> Check {
+    let queue = queue_dir(sysfs_dir).join("queue");
+    let size = |name: &str| read_trimmed(&queue.join(name)).and_then(|s| s.parse::<usize>().ok());
+    let (Some(logical), Some(physical)) = (size("logical_block_size"), size("physical_block_size"))
+    else {
+        return Check::new(
+            "alignment",
+            Status::Fail,
+            format!("Failed to read the block sizes in {}", queue.display()),
+        );
+    };
+    let sizes = format!("logical {} and physical {} bytes", logical, physical);
+    if !BLOCK_SIZE.is_multiple_of(logical) {
+        return Check::new(
+            "alignment",
+            Status::Fail,
+            format!(
+                "{}: writes of {} bytes are not supported",
+                sizes, BLOCK_SIZE
+            ),
+        );
+    }
+    // the start of a partition is in 512-byte sectors
+    if let Some(start) = read_trimmed(&sysfs_dir.join("start")).and_then(|s| s.parse::<u64>().ok())
+    {
+        if !(start * 512).is_multiple_of(physical.max(BLOCK_SIZE) as u64) {
+            return Check::new(
+                "alignment",
+                Status::Fail,
+                format!(
+                    "{}: the partition starts at byte {}, which is not aligned to {} bytes",
+                    sizes,
+                    start * 512,
+                    physical.max(BLOCK_SIZE)
+                ),
+            );
+        }
+    }
+    if !BLOCK_SIZE.is_multiple_of(physical) {
+        return Check::new(
+            "alignment",
+            Status::Warn,
+            format!(
+                "{}: writes of {} bytes are read-modify-writes",
+                sizes, BLOCK_SIZE
+            ),
+        );
+    }
+    Check::new("alignment", Status::Pass, sizes)
+}
+
+fn capacity(ssd_device: &str) -> Check {
+    match crate::get_device_capacity(ssd_device) {
+        Ok(0) => Check::new("capacity", Status::Fail, "the device is empty (0 bytes)"),
+        Ok(bytes) => Check::new(
+            "capacity",
+            Status::Pass,
+            format!(
+                "{:.1} GiB, {} blocks of {} bytes",
+                bytes as f64 / (1u64 << 30) as f64,
+                bytes / BLOCK_SIZE as u64,
+                BLOCK_SIZE
+            ),
+        ),
+        Err(e) => Check::new("capacity", Status::Fail, e),
+    }
+}
+
+/// The sysfs directories of the device and its partitions
+fn device_and_partitions(sysfs_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![sysfs_dir.to_path_buf()];
+    if let Ok(children) = fs::read_dir(sysfs_dir) {
+        dirs.extend(
+            children
+                .flatten()
+                .map(|child| child.path())
+                .filter(|path| path.join("partition").exists()),
+        );
+    }
+    dirs
+}
+
+fn mount_state(sysfs_dir: &Path) -> Check {
+    let devices: Vec<(String, String, PathBuf)> = device_and_partitions(sysfs_dir)
+        .into_iter()
+        .filter_map(|dir| {
+            let name = dir.file_name()?.to_string_lossy().to_string();
+            Some((name, read_trimmed(&dir.join("dev"))?, dir))
+        })
+        .collect();
+    let mut uses = vec![];
+    // the third field of mountinfo is the major:minor of the mounted device
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let (Some(number), Some(mount_point)) = (fields.get(2), fields.get(4)) {
+            if let Some((name, ..)) = devices.iter().find(|(_, n, _)| n == number) {
+                uses.push(format!("{} is mounted on {}", name, mount_point));
+            }
+        }
+    }
+    let swaps = fs::read_to_string("/proc/swaps").unwrap_or_default();
+    for path in swaps
+        .lines()
+        .skip(1)
+        .filter_map(|l| l.split_whitespace().next())
+    {
+        if let Some((name, ..)) = devices
+            .iter()
+            .find(|(name, ..)| path == format!("/dev/{}", name))
+        {
+            uses.push(format!("{} is used for swap", name));
+        }
+    }
+    for (name, _, dir) in &devices {
+        let holders: Vec<String> = fs::read_dir(dir.join("holders"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|h| h.file_name().to_string_lossy().to_string())
+            .collect();
+        if !holders.is_empty() {
+            uses.push(format!(
+                "{} is held by {} (LVM, RAID, or dm-crypt)",
+                name,
+                holders.join(", ")
+            ));
+        }
+    }
+    if uses.is_empty() {
+        Check::new(
+            "mount_state",
+            Status::Pass,
+            format!(
+                "{} not mounted, swap, or held",
+                if devices.len() > 1 {
+                    "the device and its partitions are"
+                } else {
+                    "the device is"
+                }
+            ),
+        )
+    } else {
+        Check::new(
+            "mount_state",
+            Status::Fail,
+            format!("{}; the benchmark overwrites it", uses.join("; ")),
+        )
+    }
+}
+
+fn namespaces(ssd_device: &str) -> Check {
+    let siblings = crate::namespaces::siblings(ssd_device);
+    if siblings.is_empty() {
+        Check::new(
+            "namespaces",
+            Status::Pass,
+            "no other namespace on the controller",
+        )
+    } else {
+        Check::new(
+            "namespaces",
+            Status::Warn,
+            format!(
+                "shares the controller with {}; IO on them interferes",
+                siblings.join(", ")
+            ),
+        )
+    }
+}
+
+/// The active I/O scheduler of the device (or the partition) at `sysfs_dir`
+pub(crate) fn active_scheduler(sysfs_dir: &Path) -> Option<String> {
+    let schedulers = read_trimmed(&queue_dir(sysfs_dir).join("queue/scheduler"))?;
+    // the active one is in brackets, e.g., "[none] mq-deadline kyber"
+    Some(
+        schedulers
+            .split_whitespace()
+            .find(|s| s.starts_with('['))
+            .map(|s| s.trim_matches(|c| c == '[' || c == ']'))
+            .unwrap_or(&schedulers)
+            .to_string(),
+    )
+}
+
+fn scheduler(sysfs_dir: &Path) -> Check {
+    let path = queue_dir(sysfs_dir).join("queue/scheduler");
+    let Some(active) = active_scheduler(sysfs_dir) else {
+        return Check::new(
+            "scheduler",
+            Status::Warn,
+            format!("Failed to read {}", path.display()),
+        );
+    };
+    if active == "none" {
+        Check::new("scheduler", Status::Pass, "none")
+    } else {
+        Check::new(
+            "scheduler",
+            Status::Warn,
+            format!("{} adds latency; echo none > {}", active, path.display()),
+        )
+    }
+}
+
+fn governor() -> Check {
+    let mut governors: Vec<String> = fs::read_dir("/sys/devices/system/cpu")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|cpu| {
+            let name = cpu.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("cpu") && name[3..].chars().all(|c| c.is_ascii_digit())
+        })
+        .filter_map(|cpu| read_trimmed(&cpu.path().join("cpufreq/scaling_governor")))
+        .collect();
+    if governors.is_empty() {
+        return Check::new(
+            "governor",
+            Status::Pass,
+            "no frequency scaling (fixed frequency or a VM)",
+        );
+    }
+    let cpus = governors.len();
+    governors.retain(|g| g != "performance");
+    if governors.is_empty() {
+        Check::new(
+            "governor",
+            Status::Pass,
+            format!("performance on all {} CPUs", cpus),
+        )
+    } else {
+        governors.sort();
+        let first = governors[0].clone();
+        Check::new(
+            "governor",
+            Status::Warn,
+            format!(
+                "{} on {} of {} CPUs adds variance; cpupower frequency-set -g performance",
+                first,
+                governors.len(),
+                cpus
+            ),
+        )
+    }
+}
+
+fn checks(ssd_device: &str) -> Vec<Check> {
+    let node = format!("/dev/{}", ssd_device);
+    let sysfs_dir = match device::sysfs_dir(ssd_device) {
+        Ok(dir) => dir,
+        Err(e) => {
+            // nothing else can be checked without the device, but the host can
+            return vec![Check::new("device", Status::Fail, e), governor()];
+        }
+    };
+    vec![
+        Check::new(
+            "device",
+            Status::Pass,
+            format!(
+                "block device {} at {}",
+                read_trimmed(&sysfs_dir.join("dev")).unwrap_or_default(),
+                sysfs_dir.display()
+            ),
+        ),
+        permissions(&node, &sysfs_dir),
+        o_direct(&node),
+        alignment(&sysfs_dir),
+        capacity(ssd_device),
+        mount_state(&sysfs_dir),
+        namespaces(ssd_device),
+        scheduler(&sysfs_dir),
+        governor(),
+    ]
+}
+
+pub fn run(args: &PreflightArgs) {
+    println!("preflight of /dev/{}", args.ssd_device);
+    let checks = checks(&args.ssd_device);
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for check in &checks {
+        println!(
+            "  [{}] {:width$}  {}",
+            check.status,
+            check.name,
+            check.detail,
+            width = width
+        );
+    }
+    let count = |status| checks.iter().filter(|c| c.status == status).count();
+    let (failed, warnings) = (count(Status::Fail), count(Status::Warn));
+    if failed > 0 {
+        println!("{} failed, {} warnings", failed, warnings);
+        std::process::exit(1);
+    }
+    println!("ready to run, {} warnings", warnings);
+}
@@ -0,0 +1,302 @@
+//! The privileges of the requested features, checked before the run.
+//!
+//! Most of the benchmark needs more than a normal user has: the device has to be opened for
+//! writing with O_DIRECT, NVMe passthrough commands need CAP_SYS_ADMIN, and so on. Without them
+//! a run used to fail wherever the first of them was used, e.g., when the telemetry capture of
+//! the first slow write hit EPERM an hour into the run, with an error that did not say what was
+//! missing. Before the run, every requested feature is checked against the effective
+//! capabilities of the process (CapEff of /proc/self/status) or probed, e.g., by opening the
+//! device, and the run refuses to start with one line per feature that tells the flag and exactly
+//! what it needs, so that the capabilities can be granted (setcap, the securityContext of a
+//! container) instead of running everything as root. With `--degrade-without-privileges` the
+//! features that the run can do without (telemetry captures, temperature control, the op mix,
+//! IO priorities, energy, SQ polling) are turned off with a warning instead; the summary records
+//! the configuration that actually ran. Access to the device, the nvme-pi and spdk engines, and
+//! the cgroup cannot be done without.
+
+use crate::{engine::EngineKind, CliConfig};
+use std::{io, path::Path};
+
+const CAP_SYS_ADMIN: u32 = 21;
+const CAP_SYS_NICE: u32 = 23;
+
+/// A feature the process lacks the privileges for
+pub struct Requirement {
+    pub feature: String,   // the flag that asks for it
+    pub privilege: String, // what it needs, and what for
+    degrade: Option<fn(&mut CliConfig)>,
+}
+
+impl Requirement {
+    fn new(feature: impl Into<String>, privilege: impl Into<String>) -> Requirement {
+        Requirement {
+            feature: feature.into(),
+            privilege: privilege.into(),
+            degrade: None,
+        }
+    }
+
+    fn or_without(self, degrade: fn(&mut CliConfig)) -> Requirement {
+        Requirement {
+            degrade: Some(degrade),
+            ..self
+        }
+    }
+
+    pub fn can_degrade(&self) -> bool {
+        self.degrade.is_some()
+    }
+}
+
+/// The effective capabilities in the contents of /proc/self/status, one bit per capability
+pub fn effective_capabilities(status: &str) -> u64 {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|bits| u64::from_str_radix(bits.trim(), 16).ok())
+        .unwrap_or(0)
+}
+
+/// Major and minor of a kernel release, e.g., 5.10 of `5.10.0-28-amd64`
+pub fn kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.trim().split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Whether the device node can be opened like the benchmark opens it; other errors than missing
+/// permissions are left to the benchmark
+fn device_access(ssd_device: &str) -> Result<(), io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(format!("/dev/{}", ssd_device))
+    {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the process may write `path`
+fn writable(path: &Path) -> bool {
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether the energy counters of RAPL can be read; without RAPL, there is nothing to lack
+fn rapl_readable() -> bool {
+    let Ok(entries) = std::fs::read_dir("/sys/class/powercap") else {
+        return true;
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("intel-rapl:")
+        })
+        .all(|entry| {
+            !matches!(std::fs::read_to_string(entry.path().join("energy_uj")),
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied)
+        })
+}
+
+/// The requested features of `config` the process cannot use with `capabilities`
+pub fn missing(config: &CliConfig, capabilities: u64) -> Vec<Requirement> {
+    let capable = |capability: u32| capabilities & (1 << capability) != 0;
+    let root = unsafe { libc::geteuid() } == 0;
+    let mut missing = vec![];
+    let on_ssd = config.engines.iter().any(|kind| {
+        matches!(
+            kind,
+            EngineKind::Psync
+                | EngineKind::IoUring
+                | EngineKind::IoUringLinked
+                | EngineKind::Pvsync2
+                | EngineKind::NvmePi
+        )
+    });
+    let devices = config
+        .ssd_device
+        .iter()
+        .filter(|_| on_ssd)
+        .chain(&config.concurrent_namespaces);
+    for device in devices {
+        if let Err(e) = device_access(device) {
+            missing.push(Requirement::new(
+                format!("--ssd-device {}", device),
+                format!(
+                    "read and write access to /dev/{} with O_DIRECT: root or the group of the device node ({})",
+                    device, e
+                ),
+            ));
+        }
+    }
+    if config.engines.contains(&EngineKind::NvmePi) && !capable(CAP_SYS_ADMIN) {
+        missing.push(Requirement::new(
+            "--engines nvme-pi",
+            "CAP_SYS_ADMIN for NVMe IO passthrough",
+        ));
+    }
+    if config.engines.contains(&EngineKind::Spdk) && !root {
+        missing.push(Requirement::new(
+            "--engines spdk",
+            "root for the hugepages and the VFIO device of SPDK",
+        ));
+    }
+    if (config.cgroup_io_max.is_some() || config.cgroup_io_latency_us.is_some())
+        && !writable(&Path::new(&config.cgroup_parent).join("cgroup.subtree_control"))
+    {
+        missing.push(Requirement::new(
+            "--cgroup-io-max and --cgroup-io-latency-us",
+            format!(
+                "write access to the cgroup {}: root or a delegated cgroup as --cgroup-parent",
+                config.cgroup_parent
+            ),
+        ));
+    }
+    if !capable(CAP_SYS_ADMIN) {
+        let passthrough = "CAP_SYS_ADMIN for NVMe IO passthrough";
+        if config.write_zeroes_fraction > 0.0 {
+            missing.push(
+                Requirement::new("--write-zeroes-fraction", passthrough)
+                    .or_without(|c| c.write_zeroes_fraction = 0.0),
+            );
+        }
+        if config.deallocate_fraction > 0.0 {
+            missing.push(
+                Requirement::new("--deallocate-fraction", passthrough)
+                    .or_without(|c| c.deallocate_fraction = 0.0),
+            );
+        }
+        if config.copy_fraction > 0.0 {
+            missing.push(
+                Requirement::new("--copy-fraction", passthrough)
+                    .or_without(|c| c.copy_fraction = 0.0),
+            );
+        }
+        let smart_log = "CAP_SYS_ADMIN for the NVMe admin passthrough of the SMART log";
+        if config.telemetry_threshold_us > 0 {
+            missing.push(
+                Requirement::new(
+                    "--telemetry-threshold-us",
+                    "CAP_SYS_ADMIN for the NVMe admin passthrough of the SMART, error, and telemetry logs",
+                )
+                .or_without(|c| c.telemetry_threshold_us = 0),
+            );
+        }
+        if config.max_temperature_celsius.is_some() {
+            missing.push(
+                Requirement::new("--max-temperature-celsius", smart_log)
+                    .or_without(|c| c.max_temperature_celsius = None),
+            );
+        }
+        if config.soak_temperature_celsius.is_some() {
+            missing.push(
+                Requirement::new("--soak-temperature-celsius", smart_log)
+                    .or_without(|c| c.soak_temperature_celsius = None),
+            );
+        }
+        let before_5_11 = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .ok()
+            .and_then(|release| kernel_version(&release))
+            .is_some_and(|version| version < (5, 11));
+        if config.sqpoll && before_5_11 {
+            missing.push(
+                Requirement::new(
+                    "--sqpoll",
+                    "CAP_SYS_ADMIN for the SQ poll thread of io_uring on kernels before 5.11",
+                )
+                .or_without(|c| c.sqpoll = false),
+            );
+        }
+    }
+    let real_time = config
+        .io_priorities
+        .iter()
+        .any(|priority| priority.class == crate::ioprio::IoClass::RealTime);
+    if real_time && !capable(CAP_SYS_ADMIN) && !capable(CAP_SYS_NICE) {
+        missing.push(
+            Requirement::new(
+                "--io-priorities rt",
+                "CAP_SYS_ADMIN or CAP_SYS_NICE for the real-time IO class",
+            )
+            .or_without(|c| c.io_priorities.clear()),
+        );
+    }
+    if config.measure_energy && !rapl_readable() {
+        missing.push(
+            Requirement::new(
+                "--measure-energy",
+                "read access to the energy counters in /sys/class/powercap: root",
+            )
+            .or_without(|c| c.measure_energy = false),
+        );
+    }
+    missing
+}
+
+/// Refuses to run with a list of the missing privileges, or turns off the features that can be
+/// done without if `config.degrade_without_privileges`
+pub fn enforce(config: &mut CliConfig) {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let missing = missing(config, effective_capabilities(&status));
+    let fatal = !config.degrade_without_privileges || missing.iter().any(|r| !r.can_degrade());
+    if fatal && !missing.is_empty() {
+        let lines: Vec<String> = missing
+            .iter()
+            .map(|r| {
+                format!(
+                    "  {}: {}{}",
+                    r.feature,
+                    r.privilege,
+                    if r.can_degrade() {
+                        " (--degrade-without-privileges turns it off)"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect();
+        crate::outcome::exit(
+            crate::outcome::Outcome::ConfigError,
+            &format!(
+                "the process lacks the privileges of these features; run as root or grant them, e.g., with setcap or the securityContext of a container:\n{}",
+                lines.join("\n")
+            ),
+        );
+    }
+    for requirement in missing {
+        println!(
+            "warning: {} is turned off, it needs {}",
+            requirement.feature, requirement.privilege
+        );
+        if let Some(degrade) = requirement.degrade {
+            degrade(config);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_and_kernel_versions_are_parsed() {
+        let status = "Name:\tssd-benchy\nCapPrm:\t0000000000000000\nCapEff:\t0000000000200000\n";
+        assert_eq!(effective_capabilities(status), 1 << CAP_SYS_ADMIN);
+        assert_eq!(effective_capabilities(""), 0);
+        assert_eq!(effective_capabilities("CapEff:\tnot hex\n"), 0);
+        assert_eq!(kernel_version("5.10.0-28-amd64"), Some((5, 10)));
+        assert_eq!(kernel_version("6.8.12\n"), Some((6, 8)));
+        assert_eq!(kernel_version("6"), None);
+        assert_eq!(kernel_version("6.rc1"), None);
+        assert_eq!(kernel_version(""), None);
+    }
+}
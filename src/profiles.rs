@@ -0,0 +1,238 @@
+//! User-defined presets of benchmark flags (`--profile <name>`).
+//!
+//! Instead of shell scripts that wrap the tool, a team keeps the flags of a benchmark definition
+//! in a TOML file, e.g., `mydb-commit-path.toml` in `--profile-dir` (by default
+//! `$XDG_CONFIG_HOME/ssd-benchy/profiles`, i.e., `~/.config/ssd-benchy/profiles`):
+//!
+//! ```toml
+//! description = "commit path of mydb: 4K writes with fsync"
+//! engines = ["io-uring"]
+//! writer-threads = 4
+//! use-fsync = true
+//! utilization-iops = [0.3, 0.5, 0.7]
+//! summary-file = "/results/mydb-commit-path.csv"
+//! ```
+//!
+//! Every top-level key is the long name of a benchmark flag (with `-` or `_`) and its value what
+//! would follow the flag: an array for flags that take several values, `true` for switches. The
+//! optional `description` is for the reader. `--profile mydb-commit-path` adds the flags of the
+//! file to the command line that it does not have already, so flags on the command line override
+//! the preset, and the preset overrides the environment variables. A name with a `/` or ending in `.toml` is the
+//! path of the file. The summary records the name of the preset in `profile`; `snia-iops` and
+//! `snia-latency` are built in (see pts.rs).
+
+use crate::pts;
+use serde::Deserialize;
+use std::{
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+};
+
+/// The directory presets are looked up in without --profile-dir
+fn default_dir() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config"),
+    }
+    .join("ssd-benchy/profiles")
+}
+
+/// The file of the preset `name`
+pub fn path(name: &str, dir: Option<&OsStr>) -> PathBuf {
+    if name.contains('/') || name.ends_with(".toml") {
+        return PathBuf::from(name);
+    }
+    dir.map_or_else(default_dir, PathBuf::from)
+        .join(format!("{}.toml", name))
+}
+
+/// The built-in profile `name`, if it is one
+pub fn builtin(name: &str) -> Option<pts::Profile> {
+    <pts::Profile as clap::ValueEnum>::from_str(name, false).ok()
+}
+
+/// A preset file: the flags in the order of the file, and a description for the reader
+#[derive(Deserialize)]
+pub struct Preset {
+    #[allow(dead_code)] // only for the reader of the file, but it must be a string
+    description: Option<String>,
+    #[serde(flatten)]
+    flags: toml::Table,
+}
+
+/// Parses the text of a preset file
+pub fn parse(text: &str) -> Result<Preset, String> {
+    toml::from_str(text).map_err(|e| e.to_string())
+}
+
+/// The flags `preset` sets, except those in `given`; `flags` maps the long names and aliases of
+/// the benchmark flags to their long names
+pub fn arguments(
+    preset: &Preset,
+    flags: &[(String, String)],
+    given: &[String],
+) -> Result<Vec<String>, String> {
+    let mut arguments = vec![];
+    for (key, value) in &preset.flags {
+        let flag = key.replace('_', "-");
+        if flag == "profile" || flag == "profile-dir" {
+            return Err(format!("{} cannot be set by a profile", key));
+        }
+        let Some((_, long)) = flags.iter().find(|(name, _)| *name == flag) else {
+            return Err(format!("{} is not a flag of the benchmark", key));
+        };
+        if given.contains(long) {
+            continue;
+        }
+        let text = |value: &toml::Value| match value {
+            toml::Value::Boolean(b) => Ok(b.to_string()),
+            toml::Value::Integer(i) => Ok(i.to_string()),
+            toml::Value::Float(x) => Ok(x.to_string()),
+            toml::Value::String(s) => Ok(s.clone()),
+            other => Err(format!("{} must not hold a {}", key, other.type_str())),
+        };
+        match value {
+            toml::Value::Boolean(false) => {}
+            toml::Value::Boolean(true) => arguments.push(format!("--{}", flag)),
+            toml::Value::Array(values) => {
+                if values.is_empty() {
+                    return Err(format!("{} must not be empty", key));
+                }
+                arguments.push(format!("--{}", flag));
+                for value in values {
+                    arguments.push(text(value)?);
+                }
+            }
+            value => {
+                arguments.push(format!("--{}", flag));
+                arguments.push(text(value)?);
+            }
+        }
+    }
+    Ok(arguments)
+}
+
+/// The value of the flag `--name` on the command line `args`, else of its environment variable
+pub fn flag_value(args: &[OsString], name: &str, variable: &str) -> Option<OsString> {
+    let long = format!("--{}", name);
+    let prefix = format!("--{}=", name);
+    for (index, arg) in args.iter().enumerate() {
+        let arg = arg.to_string_lossy();
+        if arg == long {
+            return args.get(index + 1).cloned();
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Some(value.into());
+        }
+    }
+    std::env::var_os(variable).filter(|value| !value.is_empty())
+}
+
+/// The --profile of the command line `args` or the environment
+pub fn name(args: &[OsString]) -> Option<String> {
+    flag_value(args, "profile", "SSD_BENCHY_PROFILE")
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// The command line `args` with the flags of the preset `name` it does not set; `flags` maps the
+/// long names and aliases of the benchmark flags to their long names
+pub fn expand(
+    args: &[OsString],
+    name: &str,
+    flags: &[(String, String)],
+) -> Result<Vec<OsString>, String> {
+    let dir = flag_value(args, "profile-dir", "SSD_BENCHY_PROFILE_DIR");
+    let path = path(name, dir.as_deref());
+    let preset = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        .and_then(|text| parse(&text).map_err(|e| format!("{}: {}", path.display(), e)))
+        .map_err(|e| format!("Failed to load the profile {}: {}", name, e))?;
+    let given: Vec<String> = args
+        .iter()
+        .filter_map(|arg| {
+            let arg = arg.to_str()?.strip_prefix("--")?;
+            let name = arg.split('=').next()?;
+            flags
+                .iter()
+                .find(|(flag, _)| flag == name)
+                .map(|(_, long)| long.clone())
+        })
+        .collect();
+    let preset =
+        arguments(&preset, flags, &given).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut expanded: Vec<OsString> = args.iter().take(1).cloned().collect();
+    expanded.extend(preset.into_iter().map(OsString::from));
+    expanded.extend(args.iter().skip(1).cloned());
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags() -> Vec<(String, String)> {
+        [
+            ("max-iops", "max-iops"),
+            ("engine", "engines"),
+            ("engines", "engines"),
+            ("use-fsync", "use-fsync"),
+        ]
+        .iter()
+        .map(|(name, long)| (name.to_string(), long.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn presets_become_the_flags_the_command_line_does_not_set() {
+        let preset = parse(
+            "description = \"x\"\nmax_iops = 1000\nengine = [\"null\", \"psync\"]\nuse-fsync = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            arguments(&preset, &flags(), &[]).unwrap(),
+            ["--max-iops", "1000", "--engine", "null", "psync"]
+        );
+        assert_eq!(
+            arguments(&preset, &flags(), &[String::from("engines")]).unwrap(),
+            ["--max-iops", "1000"]
+        );
+        let args: Vec<OsString> = ["ssd-benchy", "--profile=commit", "--profile-dir", "/p"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(name(&args).as_deref(), Some("commit"));
+        assert_eq!(
+            flag_value(&args, "profile-dir", "SSD_BENCHY_UNSET_FOR_TEST"),
+            Some(OsString::from("/p"))
+        );
+        assert_eq!(
+            path("commit", Some(OsStr::new("/p"))),
+            PathBuf::from("/p/commit.toml")
+        );
+        assert_eq!(
+            path("./mine.toml", Some(OsStr::new("/p"))),
+            PathBuf::from("./mine.toml")
+        );
+    }
+
+    #[test]
+    fn presets_outside_the_flags_are_refused() {
+        for (invalid, error) in [
+            ("profile = \"other\"\n", "cannot be set by a profile"),
+            ("max-iop = 1000\n", "is not a flag of the benchmark"),
+            ("engine = []\n", "must not be empty"),
+            ("engine = [[\"null\"]]\n", "must not hold a"),
+            ("engine = { name = \"null\" }\n", "must not hold a"),
+        ] {
+            let message = arguments(&parse(invalid).unwrap(), &flags(), &[]).unwrap_err();
+            assert!(message.contains(error), "{}: {}", invalid, message);
+        }
+        for invalid in [
+            "max-iops = 1000\nmax-iops = 2000\n",
+            "description = 7\n",
+            "[engine]\nname = \"null\"\n[engine]\nname = \"psync\"\n",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Structured progress on stdout for orchestration (`--progress-format json`).
+//!
+//! The prints of a run are meant for a person at a terminal, and wrappers that drive the
+//! benchmark, e.g., to show a progress bar or to time out a stuck point, had to match their
+//! wording. With `--progress-format json` the run additionally prints one line of JSON per event
+//! on stdout, always with `event` and `time` (Unix seconds): `run_start`, `preinit_start` and
+//! `preinit_end` around the preinitialization of a capacity fraction, `point_start`,
+//! `point_progress` every `--progress-interval-seconds` with the writes and IO errors so far,
+//! `point_end` with the key numbers of the point, and `run_end` with the outcome just before the
+//! status line of the outcome module. Other lines of stdout are not JSON objects with an `event`
+//! field, so a wrapper keeps those lines and ignores the rest.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressFormat {
+    /// Only the prints for people
+    #[default]
+    Text,
+    /// One line of JSON per event in addition
+    Json,
+}
+
+static FORMAT: OnceLock<ProgressFormat> = OnceLock::new();
+
+pub fn set_format(format: ProgressFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn enabled() -> bool {
+    FORMAT.get() == Some(&ProgressFormat::Json)
+}
+
+/// The line of an event with the fields of the object `fields`
+pub fn event_line(event: &str, fields: Value) -> Value {
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64());
+    let mut line = json!({
+        "event": event,
+        "time": time
+    });
+    if let (Value::Object(line), Value::Object(fields)) = (&mut line, fields) {
+        line.extend(fields);
+    }
+    line
+}
+
+/// Prints the event with `fields` if --progress-format json
+pub fn emit(event: &str, fields: Value) {
+    if !enabled() {
+        return;
+    }
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", event_line(event, fields));
+    let _ = stdout.flush();
+}
+
+/// What a writer thread has done so far in the running point
+#[derive(Clone, Copy, Default)]
+struct Progress {
+    operations: u64,
+    io_errors: u64,
+    elapsed: Duration, // without pauses
+}
+
+struct Running {
+    info: Value, // the fields that tell the point, e.g., its uuid
+    target_iops: f64,
+    runtime: Duration,
+    threads: Vec<Progress>,
+}
+
+/// Emits `point_progress` every interval from the progress the writer threads publish
+pub struct Reporter {
+    running: Mutex<Option<Running>>,
+    stop: Arc<AtomicBool>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Reporter {
+    pub fn spawn(interval: Duration) -> &'static Reporter {
+        let reporter: &'static Reporter = Box::leak(Box::new(Reporter {
+            running: Mutex::new(None),
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: Mutex::new(None),
+        }));
+        let stop = reporter.stop.clone();
+        let handle = std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::park_timeout(interval);
+                reporter.report();
+            }
+        });
+        *reporter.handle.lock().unwrap() = Some(handle);
+        reporter
+    }
+
+    pub fn start_point(&self, info: Value, target_iops: f64, runtime: Duration, threads: u64) {
+        *self.running.lock().unwrap() = Some(Running {
+            info,
+            target_iops,
+            runtime,
+            threads: vec![Progress::default(); threads as usize],
+        });
+    }
+
+    /// Hands over the progress of writer thread `thread`
+    pub fn publish(&self, thread: u64, operations: u64, io_errors: u64, elapsed: Duration) {
+        if let Some(running) = self.running.lock().unwrap().as_mut() {
+            running.threads[thread as usize] = Progress {
+                operations,
+                io_errors,
+                elapsed,
+            };
+        }
+    }
+
+    pub fn finish_point(&self) {
+        self.running.lock().unwrap().take();
+    }
+
+    fn report(&self) {
+        let running = self.running.lock().unwrap();
+        let Some(running) = running.as_ref() else {
+            return;
+        };
+        let operations: u64 = running.threads.iter().map(|t| t.operations).sum();
+        let elapsed = running
+            .threads
+            .iter()
+            .map(|t| t.elapsed)
+            .max()
+            .unwrap_or_default();
+        if elapsed.is_zero() {
+            return;
+        }
+        let mut fields = running.info.clone();
+        fields["elapsed_seconds"] = json!(elapsed.as_secs_f64());
+        fields["runtime_seconds"] = json!(running.runtime.as_secs_f64());
+        fields["operations"] = json!(operations);
+        fields["io_errors"] = json!(running.threads.iter().map(|t| t.io_errors).sum::<u64>());
+        fields["target_iops"] = json!(running.target_iops);
+        fields["achieved_iops"] = json!(operations as f64 / elapsed.as_secs_f64());
+        emit("point_progress", fields);
+    }
+
+    /// Stops the background thread
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.thread().unpark();
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_events_lead_with_their_name() {
+        let line = event_line(
+            "point_progress",
+            json!({
+                "uuid": "7",
+                "operations": 42u64
+            }),
+        );
+        let text = line.to_string();
+        assert!(
+            text.starts_with(r#"{"event":"point_progress","time":"#),
+            "{}",
+            text
+        );
+        assert!(text.ends_with(r#""uuid":"7","operations":42}"#), "{}", text);
+        assert!(!text.contains('\n'));
+    }
+
+    #[test]
+    fn events_without_fields_have_only_their_name_and_time() {
+        for fields in [json!({}), Value::Null, Value::from(7u64)] {
+            let line = event_line("run_end", fields);
+            let Value::Object(line) = line else {
+                panic!("not an object");
+            };
+            let names: Vec<&str> = line.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["event", "time"]);
+        }
+        let text = event_line("a\"b", json!({})).to_string();
+        assert!(text.starts_with(r#"{"event":"a\"b","#), "{}", text);
+    }
+}
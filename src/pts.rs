@@ -0,0 +1,443 @@
+//! `--profile snia-iops` and `--profile snia-latency`: the IOPS and the latency test of the SNIA
+//! Solid State Storage Performance Test Specification (PTS) Enterprise, so numbers that can be
+//! compared with vendor datasheets come from one flag instead of the latency sweep.
+//!
+//! Both tests run the same procedure on the whole device (the PTS's ActiveRange of 100%):
+//!
+//! 1. Purge: the device is discarded, the closest to a secure erase that works on every drive.
+//! 2. Workload independent preconditioning: twice the capacity is written sequentially in 128KiB
+//!    blocks.
+//! 3. Rounds of the test matrix: every read/write mix (outer loop) with every block size (inner
+//!    loop) runs random IO closed-loop for `--pts-seconds`. The IOPS test issues the IO with
+//!    `IOPS_THREADS` threads, the latency test with one (T1Q1).
+//! 4. Steady state: the tracking variable (the IOPS of 4KiB random writes, or their mean latency
+//!    for the latency test) is in steady state once, over the last `WINDOW` rounds, its range is
+//!    at most 20% of its average and the line fitted through it rises or falls by at most 10% of
+//!    the average. Then, or after `--pts-max-rounds`, the rounds end.
+//!
+//! Every combination of every round is a row of `--pts-file`. The report at the end averages the
+//! rounds of the measurement window (the last `WINDOW` rounds) per combination, as the PTS
+//! reports them. Block sizes below the logical block size of the device cannot be issued with
+//! O_DIRECT and are left out.
+//!
+//! The run hooks run around the whole test and the preinit hooks around the purge and the
+//! preconditioning; there are no utilization points for the point hooks.
+
+use crate::{
+    buffer::AlignedBuffer,
+    engine::Engine,
+    histogram::Histogram,
+    hooks::{Hook, Hooks},
+    partition, schema,
+};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+/// Rounds the steady state is determined over
+const WINDOW: usize = 5;
+/// Threads of the IOPS test, each with one IO in flight
+const IOPS_THREADS: u64 = 32;
+/// Block size of the workload independent preconditioning
+const PRECONDITION_BLOCK_SIZE: usize = 128 * 1024;
+/// Block size and read percentage of the tracking variable
+const TRACKING: (usize, u32) = (4096, 0);
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// PTS-E IOPS test: 7 read/write mixes and 8 block sizes from 512B to 1MiB with 32 threads
+    SniaIops,
+    /// PTS-E latency test: 3 read/write mixes and block sizes of 512B, 4KiB, and 8KiB at T1Q1
+    SniaLatency,
+}
+
+impl Profile {
+    /// The read percentages of the mixes, from 100/0 to 0/100
+    fn read_percents(self) -> &'static [u32] {
+        match self {
+            Profile::SniaIops => &[100, 95, 65, 50, 35, 5, 0],
+            Profile::SniaLatency => &[100, 65, 0],
+        }
+    }
+
+    fn block_sizes(self) -> &'static [usize] {
+        match self {
+            Profile::SniaIops => &[1048576, 131072, 65536, 32768, 16384, 8192, 4096, 512],
+            Profile::SniaLatency => &[8192, 4096, 512],
+        }
+    }
+
+    fn threads(self) -> u64 {
+        match self {
+            Profile::SniaIops => IOPS_THREADS,
+            Profile::SniaLatency => 1,
+        }
+    }
+
+    /// The tracking variable of a combination's row
+    fn tracking_value(self, row: &PtsRow) -> f64 {
+        match self {
+            Profile::SniaIops => row.iops,
+            Profile::SniaLatency => row.mean / 1e3,
+        }
+    }
+
+    fn tracking_name(self) -> &'static str {
+        match self {
+            Profile::SniaIops => "4KiB random write IOPS",
+            Profile::SniaLatency => "4KiB random write mean latency [us]",
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Profile::SniaIops => "snia-iops",
+            Profile::SniaLatency => "snia-latency",
+        })
+    }
+}
+
+pub struct Options<'a> {
+    pub profile: Profile,
+    pub ssd_device: &'a str,
+    pub combination: Duration, // runtime of every combination of a round
+    pub max_rounds: usize,
+    pub output_file: &'a str,
+    pub hooks: &'a Hooks,
+}
+
+/// One combination of one round, a row of `--pts-file`; latencies in nanoseconds
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct PtsRow {
+    uuid: u128, // of the run, equal for all its rows
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    profile: String,
+    round: usize,
+    read_percent: u32,
+    block_size: usize,
+    threads: u64,
+    elapsed_seconds: f64,
+    operations: u64,
+    iops: f64,
+    bandwidth_mib_s: f64,
+    mean: f64,
+    p99th: u64,
+    p9999th: u64,
+    max: u64,
+}
+
+/// Closed-loop random IO of `threads` threads across the device, each operation a read with a
+/// probability of `read_percent`
+fn run_combination(
+    ssd_device: &str,
+    threads: u64,
+    read_percent: u32,
+    block_size: usize,
+    capacity: u64,
+    runtime: Duration,
+) -> (u64, Duration, Histogram) {
+    let blocks = capacity / block_size as u64;
+    let begin = Instant::now();
+    let end_time = begin + runtime;
+    let results: Vec<(u64, Histogram)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(move || {
+                    let ssd_fd = crate::open_ssd(ssd_device);
+                    let mut rng = fastrand::Rng::new();
+                    // the PTS asks for data that does not compress
+                    let mut buffer = AlignedBuffer::new(block_size, 0);
+                    rng.fill(&mut buffer);
+                    let mut histogram = Histogram::new();
+                    let mut operations = 0;
+                    while Instant::now() < end_time {
+                        let offset = rng.u64(0..blocks) * block_size as u64;
+                        let io_begin = Instant::now();
+                        let res = if rng.u32(0..100) < read_percent {
+                            ssd_fd.read_at(&mut buffer, offset)
+                        } else {
+                            ssd_fd.write_at(&buffer, offset)
+                        }
+                        .expect("could not issue io");
+                        histogram.record(crate::stats::nanos(io_begin.elapsed()));
+                        assert_eq!(res, block_size);
+                        operations += 1;
+                    }
+                    (operations, histogram)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut histogram = Histogram::new();
+    let mut operations = 0;
+    for (ops, h) in results {
+        operations += ops;
+        histogram.merge(&h);
+    }
+    (operations, begin.elapsed(), histogram)
+}
+
+/// Writes the device `passes` times sequentially, every thread its part of the blocks
+fn precondition(ssd_device: &str, capacity: u64, passes: u64) {
+    let blocks = capacity / PRECONDITION_BLOCK_SIZE as u64;
+    std::thread::scope(|scope| {
+        for thread_id in 0..IOPS_THREADS {
+            scope.spawn(move || {
+                let ssd_fd = crate::open_ssd(ssd_device);
+                let mut buffer = AlignedBuffer::new(PRECONDITION_BLOCK_SIZE, 0);
+                fastrand::Rng::new().fill(&mut buffer);
+                for _ in 0..passes {
+                    for block in partition(thread_id, IOPS_THREADS, blocks) {
+                        let res = ssd_fd
+                            .write_at(&buffer, block * PRECONDITION_BLOCK_SIZE as u64)
+                            .expect("could not precondition");
+                        assert_eq!(res, PRECONDITION_BLOCK_SIZE);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Whether the last `WINDOW` rounds are in steady state; `values` holds the tracking variable of
+/// every round
+pub fn steady_state(values: &[f64]) -> bool {
+    if values.len() < WINDOW {
+        return false;
+    }
+    let window = &values[values.len() - WINDOW..];
+    let n = WINDOW as f64;
+    let average = window.iter().sum::<f64>() / n;
+    let range = window.iter().cloned().fold(f64::MIN, f64::max)
+        - window.iter().cloned().fold(f64::MAX, f64::min);
+    // least squares fit over x = 0..WINDOW
+    let x_mean = (n - 1.0) / 2.0;
+    let slope = window
+        .iter()
+        .enumerate()
+        .map(|(x, y)| (x as f64 - x_mean) * (y - average))
+        .sum::<f64>()
+        / (0..WINDOW)
+            .map(|x| (x as f64 - x_mean).powi(2))
+            .sum::<f64>();
+    let excursion = (slope * (n - 1.0)).abs();
+    range <= 0.2 * average && excursion <= 0.1 * average
+}
+
+/// A number of a row the report averages
+type Metric = fn(&PtsRow) -> f64;
+
+/// The report over the rows of the measurement window, one row per read/write mix and a column
+/// per block size
+fn report(profile: Profile, rows: &[PtsRow], block_sizes: &[usize]) -> String {
+    let mut out = String::new();
+    let tables: &[(&str, Metric)] = match profile {
+        Profile::SniaIops => &[("IOPS", |row| row.iops)],
+        Profile::SniaLatency => &[
+            ("mean latency [us]", |row| row.mean / 1e3),
+            ("max latency [us]", |row| row.max as f64 / 1e3),
+        ],
+    };
+    for (title, value) in tables {
+        let _ = writeln!(out, "\n{}", title);
+        let header = ["read/write"]
+            .into_iter()
+            .map(String::from)
+            .chain(block_sizes.iter().map(|size| size.to_string()))
+            .collect();
+        let table_rows = profile
+            .read_percents()
+            .iter()
+            .map(|&read_percent| {
+                let mut row = vec![format!("{}/{}", read_percent, 100 - read_percent)];
+                for &block_size in block_sizes {
+                    let window: Vec<f64> = rows
+                        .iter()
+                        .filter(|r| r.read_percent == read_percent && r.block_size == block_size)
+                        .map(value)
+                        .collect();
+                    row.push(format!(
+                        "{:.1}",
+                        window.iter().sum::<f64>() / window.len() as f64
+                    ));
+                }
+                row
+            })
+            .collect();
+        crate::report::Table {
+            header,
+            rows: table_rows,
+        }
+        .write(&mut out, crate::report::Format::Text);
+    }
+    out
+}
+
+/// One row per combination and round of a --profile run, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "ssd-benchy --profile snia-iops|snia-latency",
+        option: "--pts-file",
+        columns: schema::columns_of(&PtsRow::default()),
+    }]
+}
+
+pub fn run(options: &Options) {
+    let profile = options.profile;
+    let output = Path::new(options.output_file);
+    if let Err(e) = schema::ensure_compatible(
+        output,
+        &schema::header_of(&PtsRow::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    ) {
+        crate::outcome::exit(crate::outcome::Outcome::ConfigError, &e);
+    }
+    let capacity = crate::device_capacity(options.ssd_device);
+    #[cfg(unix)]
+    let logical_block_size = crate::device::logical_block_size(options.ssd_device)
+        .unwrap_or_else(|e| crate::outcome::exit(crate::outcome::Outcome::DeviceError, &e));
+    #[cfg(not(unix))]
+    let logical_block_size = 512;
+    let block_sizes: Vec<usize> = profile
+        .block_sizes()
+        .iter()
+        .copied()
+        .filter(|size| (*size as u64).is_multiple_of(logical_block_size))
+        .collect();
+    if block_sizes.len() < profile.block_sizes().len() {
+        println!(
+            "warning: /dev/{} has {}-byte logical blocks, smaller block sizes of {} are left out",
+            options.ssd_device, logical_block_size, profile
+        );
+    }
+
+    let run_label = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("")
+        .as_secs()
+        .to_string();
+    let context = [("DEVICE", options.ssd_device.to_string())];
+    options.hooks.run(Hook::PreRun, &run_label, &context);
+    options.hooks.run(Hook::PrePreinit, &run_label, &context);
+    println!("purging /dev/{}", options.ssd_device);
+    if let Err(e) = crate::open_ssd(options.ssd_device).discard(0, capacity) {
+        println!(
+            "warning: could not discard {} ({}); the test does not start from a purged device",
+            options.ssd_device, e
+        );
+    }
+    println!("preconditioning: writing the capacity twice sequentially in 128KiB blocks");
+    precondition(options.ssd_device, capacity, 2);
+    options.hooks.run(Hook::PostPreinit, &run_label, &context);
+
+    let uuid = Uuid::new_v4().as_u128();
+    let hostname = gethostname().into_string().unwrap();
+    let mut rows: Vec<PtsRow> = vec![];
+    let mut tracking = vec![];
+    let mut steady = false;
+    let mut wtr = schema::csv_appender(output).unwrap();
+    for round in 1..=options.max_rounds {
+        for &read_percent in profile.read_percents() {
+            for &block_size in &block_sizes {
+                let start_time = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("");
+                let (operations, elapsed, h) = run_combination(
+                    options.ssd_device,
+                    profile.threads(),
+                    read_percent,
+                    block_size,
+                    capacity,
+                    options.combination,
+                );
+                let iops = operations as f64 / elapsed.as_secs_f64();
+                let row = PtsRow {
+                    uuid,
+                    start_time: start_time.as_secs(),
+                    hostname: hostname.clone(),
+                    ssd_device: options.ssd_device.to_string(),
+                    profile: profile.to_string(),
+                    round,
+                    read_percent,
+                    block_size,
+                    threads: profile.threads(),
+                    elapsed_seconds: elapsed.as_secs_f64(),
+                    operations,
+                    iops,
+                    bandwidth_mib_s: iops * block_size as f64 / (1024.0 * 1024.0),
+                    mean: h.mean(),
+                    p99th: h.percentile(99.0),
+                    p9999th: h.percentile(99.99),
+                    max: h.max(),
+                };
+                wtr.serialize(&row).unwrap();
+                if (block_size, read_percent) == TRACKING {
+                    tracking.push(profile.tracking_value(&row));
+                }
+                rows.push(row);
+            }
+        }
+        wtr.flush().unwrap();
+        println!(
+            "round {}: {} {:.1}",
+            round,
+            profile.tracking_name(),
+            tracking.last().copied().unwrap_or(0.0)
+        );
+        steady = steady_state(&tracking);
+        if steady {
+            break;
+        }
+    }
+
+    let rounds = tracking.len();
+    match steady {
+        true => println!(
+            "steady state reached in round {}, measurement window rounds {} to {}",
+            rounds,
+            rounds + 1 - WINDOW,
+            rounds
+        ),
+        false => println!(
+            "warning: no steady state within {} rounds, the report covers the last {} rounds",
+            rounds,
+            WINDOW.min(rounds)
+        ),
+    }
+    let first = rounds + 1 - WINDOW.min(rounds);
+    let window: Vec<PtsRow> = rows.into_iter().filter(|r| r.round >= first).collect();
+    print!("{}", report(profile, &window, &block_sizes));
+    options.hooks.run(Hook::PostRun, &run_label, &context);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pts_steady_state_needs_a_flat_window() {
+        // fewer rounds than the window
+        assert!(!steady_state(&[100.0; 4]));
+        assert!(steady_state(&[
+            500.0, 300.0, 100.0, 101.0, 99.0, 100.0, 100.0
+        ]));
+        // within the range, but falling by more than 10%
+        assert!(!steady_state(&[110.0, 107.0, 104.0, 101.0, 98.0]));
+        // flat, but one round 30% off
+        assert!(!steady_state(&[100.0, 100.0, 130.0, 100.0, 100.0]));
+        assert!(!steady_state(&[]));
+        // a round without a measurement is never steady
+        assert!(!steady_state(&[100.0, 100.0, f64::NAN, 100.0, 100.0]));
+    }
+}
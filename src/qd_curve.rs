@@ -0,0 +1,267 @@
+//! `ssd-benchy qd-curve`: latency and IOPS over queue depth at a fixed block size.
+//!
+//! Each queue depth runs closed-loop, i.e., every in-flight IO is immediately replaced by the next
+//! one. The synchronous write path can only keep one IO in flight per thread, so a queue depth of
+//! N is generated by N threads. Every operation is recorded in a histogram, which gives the
+//! familiar datasheet curve of average/tail latency versus IOPS.
+
+use crate::{buffer::AlignedBuffer, engine::Engine, histogram::Histogram, partition, schema};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Read,
+    Write,
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessPattern {
+    #[default]
+    Random,
+    Sequential,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct QdCurveArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// The queue depths that are measured, e.g., 1 2 4 8
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTHS", value_parser, num_args = 1.., value_delimiter = ' ', default_values_t = vec![1, 2, 4, 8, 16, 32, 64])]
+    queue_depths: Vec<u64>,
+
+    /// Size of every IO in bytes; must be a multiple of the logical block size
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 4096)]
+    block_size: usize,
+
+    /// Whether reads or writes are issued
+    #[clap(long, env = "SSD_BENCHY_DIRECTION", value_enum, default_value_t = Direction::Read)]
+    direction: Direction,
+
+    /// Random offsets across the used capacity or a sequential stream per thread
+    #[clap(long, env = "SSD_BENCHY_PATTERN", value_enum, default_value_t = AccessPattern::Random)]
+    pattern: AccessPattern,
+
+    /// Fraction of the SSD the offsets are drawn from
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 0.8)]
+    capacity_fraction: f64,
+
+    /// The runtime in seconds for each queue depth
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 10)]
+    runtime_seconds: u64,
+
+    /// Result file, one row per queue depth
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("qd_curve.csv"))]
+    output_file: String,
+}
+
+/// One closed-loop measurement at a fixed queue depth
+pub struct PointSpec<'a> {
+    pub ssd_device: &'a str,
+    pub queue_depth: u64,
+    pub block_size: usize,
+    pub direction: Direction,
+    pub pattern: AccessPattern,
+    pub blocks: u64, // number of `block_size` blocks offsets are drawn from
+    pub runtime: Duration,
+}
+
+pub struct PointResult {
+    pub operations: u64,
+    pub elapsed: Duration,
+    pub histogram: Histogram,
+}
+
+pub fn run_point(spec: &PointSpec) -> PointResult {
+    let begin = Instant::now();
+    let end_time = begin + spec.runtime;
+    let results: Vec<(u64, Histogram)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..spec.queue_depth)
+            .map(|thread_id| {
+                scope.spawn(move || {
+                    let ssd_fd = crate::open_ssd(spec.ssd_device);
+                    let mut buffer = AlignedBuffer::new(spec.block_size, 0xa5);
+                    let mut rng = fastrand::Rng::new();
+                    let range = partition(thread_id, spec.queue_depth, spec.blocks);
+                    let mut block_current = range.start;
+                    let mut histogram = Histogram::new();
+                    let mut operations = 0;
+                    while Instant::now() < end_time {
+                        let block = match spec.pattern {
+                            AccessPattern::Random => rng.u64(0..spec.blocks),
+                            AccessPattern::Sequential => {
+                                if block_current >= range.end {
+                                    block_current = range.start;
+                                }
+                                block_current += 1;
+                                block_current - 1
+                            }
+                        };
+                        let offset = block * spec.block_size as u64;
+                        let io_begin = Instant::now();
+                        let res = match spec.direction {
+                            Direction::Read => ssd_fd.read_at(&mut buffer, offset),
+                            Direction::Write => ssd_fd.write_at(&buffer, offset),
+                        }
+                        .expect("could not issue io");
+                        histogram.record(crate::stats::nanos(io_begin.elapsed()));
+                        assert_eq!(res, spec.block_size);
+                        operations += 1;
+                    }
+                    (operations, histogram)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut histogram = Histogram::new();
+    let mut operations = 0;
+    for (ops, h) in results {
+        operations += ops;
+        histogram.merge(&h);
+    }
+    PointResult {
+        operations,
+        elapsed: begin.elapsed(),
+        histogram,
+    }
+}
+
+/// One row of the qd-curve result file
+#[derive(Serialize, Debug, Default)]
+pub struct QdCurvePoint {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    direction: Direction,
+    pattern: AccessPattern,
+    block_size: usize,
+    queue_depth: u64,
+    runtime_seconds: u64,
+    elapsed_seconds: f64,
+    operations: u64,
+    pub iops: f64,
+    bandwidth_mib_s: f64,
+    mean: f64,
+    min: u64,
+    pub p50th: u64,
+    pub p99th: u64,
+    pub p999th: u64,
+    max: u64,
+}
+
+impl QdCurvePoint {
+    /// Runs the point described by `spec` and summarizes it
+    pub fn measure(spec: &PointSpec) -> QdCurvePoint {
+        let start_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("");
+        let result = run_point(spec);
+        let elapsed_seconds = result.elapsed.as_secs_f64();
+        let iops = result.operations as f64 / elapsed_seconds;
+        let h = &result.histogram;
+        QdCurvePoint {
+            uuid: Uuid::new_v4().as_u128(),
+            start_time: start_time.as_secs(),
+            hostname: gethostname().into_string().unwrap(),
+            ssd_device: spec.ssd_device.to_string(),
+            direction: spec.direction,
+            pattern: spec.pattern,
+            block_size: spec.block_size,
+            queue_depth: spec.queue_depth,
+            runtime_seconds: spec.runtime.as_secs(),
+            elapsed_seconds,
+            operations: result.operations,
+            iops,
+            bandwidth_mib_s: iops * spec.block_size as f64 / (1024.0 * 1024.0),
+            mean: h.mean(),
+            min: h.min(),
+            p50th: h.percentile(50.0),
+            p99th: h.percentile(99.0),
+            p999th: h.percentile(99.9),
+            max: h.max(),
+        }
+    }
+
+    pub fn print_header() {
+        println!(
+            "{:>5} {:>6} {:>12} {:>10} {:>10} {:>10} {:>10}",
+            "qd", "dir", "iops", "mean[us]", "p50[us]", "p99[us]", "p999[us]"
+        );
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{:>5} {:>6} {:>12.0} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+            self.queue_depth,
+            format!("{:?}", self.direction).to_lowercase(),
+            self.iops,
+            self.mean / 1e3,
+            self.p50th as f64 / 1e3,
+            self.p99th as f64 / 1e3,
+            self.p999th as f64 / 1e3
+        );
+    }
+
+    /// Exits if `path` already holds rows of a different schema
+    pub fn check_output(path: &Path) {
+        schema::ensure_compatible(
+            path,
+            &schema::header_of(&QdCurvePoint::default()),
+            schema::SchemaMismatchPolicy::Refuse,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+    }
+
+    pub fn append_to(&self, path: &Path) {
+        let mut wtr = schema::csv_appender(path).unwrap();
+        wtr.serialize(self).unwrap();
+        wtr.flush().unwrap();
+    }
+}
+
+/// One row per queue depth, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "qd-curve",
+        option: "--output-file",
+        columns: schema::columns_of(&QdCurvePoint::default()),
+    }]
+}
+
+pub fn run(args: &QdCurveArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let blocks = (capacity as f64 * args.capacity_fraction) as u64 / args.block_size as u64;
+    let output = Path::new(&args.output_file);
+    QdCurvePoint::check_output(output);
+
+    QdCurvePoint::print_header();
+    for &queue_depth in &args.queue_depths {
+        let point = QdCurvePoint::measure(&PointSpec {
+            ssd_device: &args.ssd_device,
+            queue_depth,
+            block_size: args.block_size,
+            direction: args.direction,
+            pattern: args.pattern,
+            blocks,
+            runtime: Duration::from_secs(args.runtime_seconds),
+        });
+        point.print();
+        point.append_to(output);
+    }
+}
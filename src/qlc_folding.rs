@@ -0,0 +1,298 @@
+//! `ssd-benchy qlc-folding`: write bursts that fill the SLC cache, then sustained mid-rate writes.
+//!
+//! QLC drives fold the SLC cache into QLC cells in the background. A burst that fills the cache
+//! followed by a moderate write rate, well below the drive's steady-state bandwidth, is what
+//! triggers their pathologies: the folding competes with the new writes, and the cache may not be
+//! free again by the next burst. Every cycle runs one burst of `--burst-bytes` at full speed and
+//! then `--sustained-seconds` of writes paced to `--sustained-mb-per-second`; both phases of every
+//! cycle get a row of their own. Each thread writes its part of the used capacity sequentially and
+//! continues where it stopped in the previous phase, so no phase overwrites data that is still in
+//! the cache.
+
+use crate::{
+    buffer::AlignedBuffer, engine::Engine, histogram::Histogram, partition, schema, RateLimiter,
+    RatePattern, RateSchedule,
+};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct QlcFoldingArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Size of every write in bytes
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 131072)]
+    block_size: usize,
+
+    /// Number of threads, each writing its part of the used capacity sequentially
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTH", default_value_t = 4)]
+    queue_depth: u64,
+
+    /// Fraction of the SSD that is written
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 1.0)]
+    capacity_fraction: f64,
+
+    /// Bytes written at full speed at the start of every cycle; at least the SLC cache size (see
+    /// the slc-cache subcommand)
+    #[clap(long, env = "SSD_BENCHY_BURST_BYTES", default_value_t = 17179869184)]
+    burst_bytes: u64,
+
+    /// Write rate of the sustained phase of all threads together
+    #[clap(
+        long,
+        env = "SSD_BENCHY_SUSTAINED_MB_PER_SECOND",
+        default_value_t = 200.0
+    )]
+    sustained_mb_per_second: f64,
+
+    /// Length of the sustained phase of every cycle
+    #[clap(long, env = "SSD_BENCHY_SUSTAINED_SECONDS", default_value_t = 300)]
+    sustained_seconds: u64,
+
+    /// Number of burst/sustained cycles
+    #[clap(long, env = "SSD_BENCHY_CYCLES", default_value_t = 3)]
+    cycles: u64,
+
+    /// Result file, one row per phase
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("qlc_folding.csv"))]
+    output_file: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Phase {
+    #[default]
+    Burst,
+    Sustained,
+}
+
+/// One row of the qlc-folding result file
+#[derive(Serialize, Debug, Default)]
+struct PhaseStatistics {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    block_size: usize,
+    queue_depth: u64,
+    cycle: u64,
+    phase: Phase,
+    target_mb_per_second: f64, // 0 for the unthrottled bursts
+    elapsed_seconds: f64,
+    written_bytes: u64,
+    mb_per_second: f64,
+    mean: f64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+/// Bytes written, elapsed time, and write latencies of one phase
+struct PhaseResult {
+    written_bytes: u64,
+    elapsed: Duration,
+    histogram: Histogram,
+}
+
+/// Writes until every thread wrote `bytes_per_thread` or, if paced to `mb_per_second`, until
+/// `runtime` is over; `cursors` holds the next block of every thread
+fn run_phase(
+    args: &QlcFoldingArgs,
+    blocks: u64,
+    cursors: &mut [u64],
+    bytes_per_thread: Option<u64>,
+    mb_per_second: f64,
+    runtime: Duration,
+) -> PhaseResult {
+    let begin = Instant::now();
+    let end_time = begin + runtime;
+    let results: Vec<(u64, Histogram)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = cursors
+            .iter_mut()
+            .enumerate()
+            .map(|(thread_id, cursor)| {
+                let thread_id = thread_id as u64;
+                scope.spawn(move || {
+                    let ssd_fd = crate::open_ssd(&args.ssd_device);
+                    let buffer = AlignedBuffer::new(args.block_size, 0x6b);
+                    let range = partition(thread_id, args.queue_depth, blocks);
+                    let iops = mb_per_second * 1e6 / args.block_size as f64;
+                    let mut ratelimiter = (mb_per_second > 0.0).then(|| {
+                        let schedule = RateSchedule {
+                            pattern: RatePattern::Constant,
+                            min_rate: iops,
+                            max_rate: iops,
+                            runtime,
+                            period: runtime,
+                        };
+                        RateLimiter::new(
+                            Instant::now(),
+                            schedule,
+                            args.queue_depth,
+                            thread_id,
+                            1,
+                            1.0,
+                            0.0,
+                        )
+                    });
+                    let mut written = 0;
+                    let mut histogram = Histogram::new();
+                    while Instant::now() < end_time
+                        && bytes_per_thread.is_none_or(|limit| written < limit)
+                    {
+                        if *cursor >= range.end {
+                            *cursor = range.start;
+                        }
+                        let offset = *cursor * args.block_size as u64;
+                        let write = || {
+                            let res = ssd_fd
+                                .write_at(&buffer, offset)
+                                .expect("could not issue io");
+                            assert_eq!(res, args.block_size);
+                            true
+                        };
+                        match ratelimiter.as_mut() {
+                            Some(ratelimiter) => {
+                                ratelimiter.run(write, |nanos, _| histogram.record(nanos))
+                            }
+                            None => {
+                                let io_begin = Instant::now();
+                                write();
+                                histogram.record(crate::stats::nanos(io_begin.elapsed()));
+                            }
+                        }
+                        written += args.block_size as u64;
+                        *cursor += 1;
+                    }
+                    (written, histogram)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut histogram = Histogram::new();
+    let mut written_bytes = 0;
+    for (written, h) in results {
+        written_bytes += written;
+        histogram.merge(&h);
+    }
+    PhaseResult {
+        written_bytes,
+        elapsed: begin.elapsed(),
+        histogram,
+    }
+}
+
+/// One row per phase, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "qlc-folding",
+        option: "--output-file",
+        columns: schema::columns_of(&PhaseStatistics::default()),
+    }]
+}
+
+pub fn run(args: &QlcFoldingArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let blocks = (capacity as f64 * args.capacity_fraction) as u64 / args.block_size as u64;
+    if args.sustained_mb_per_second <= 0.0 {
+        eprintln!("--sustained-mb-per-second must be positive");
+        std::process::exit(1);
+    }
+    let output = Path::new(&args.output_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&PhaseStatistics::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let uuid = Uuid::new_v4().as_u128();
+    let mut cursors: Vec<u64> = (0..args.queue_depth)
+        .map(|thread_id| partition(thread_id, args.queue_depth, blocks).start)
+        .collect();
+
+    println!(
+        "{:>6} {:>10} {:>10} {:>12} {:>10} {:>10} {:>10}",
+        "cycle", "phase", "time[s]", "MB/s", "p50[us]", "p99[us]", "p999[us]"
+    );
+    for cycle in 0..args.cycles {
+        for phase in [Phase::Burst, Phase::Sustained] {
+            let start_time = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("");
+            let (target_mb_per_second, result) = match phase {
+                Phase::Burst => {
+                    let bytes_per_thread = args.burst_bytes / args.queue_depth;
+                    (
+                        0.0,
+                        run_phase(
+                            args,
+                            blocks,
+                            &mut cursors,
+                            Some(bytes_per_thread),
+                            0.0,
+                            // bounded by the bytes; the runtime only guards against a stuck device
+                            Duration::from_secs(u32::MAX as u64),
+                        ),
+                    )
+                }
+                Phase::Sustained => (
+                    args.sustained_mb_per_second,
+                    run_phase(
+                        args,
+                        blocks,
+                        &mut cursors,
+                        None,
+                        args.sustained_mb_per_second,
+                        Duration::from_secs(args.sustained_seconds),
+                    ),
+                ),
+            };
+            let h = &result.histogram;
+            let statistics = PhaseStatistics {
+                uuid,
+                start_time: start_time.as_secs(),
+                hostname: gethostname().into_string().unwrap(),
+                ssd_device: args.ssd_device.clone(),
+                block_size: args.block_size,
+                queue_depth: args.queue_depth,
+                cycle,
+                phase,
+                target_mb_per_second,
+                elapsed_seconds: result.elapsed.as_secs_f64(),
+                written_bytes: result.written_bytes,
+                mb_per_second: result.written_bytes as f64 / 1e6 / result.elapsed.as_secs_f64(),
+                mean: h.mean(),
+                p50th: h.percentile(50.0),
+                p99th: h.percentile(99.0),
+                p999th: h.percentile(99.9),
+                max: h.max(),
+            };
+            println!(
+                "{:>6} {:>10} {:>10.1} {:>12.0} {:>10.1} {:>10.1} {:>10.1}",
+                cycle,
+                format!("{:?}", phase).to_lowercase(),
+                statistics.elapsed_seconds,
+                statistics.mb_per_second,
+                statistics.p50th as f64 / 1e3,
+                statistics.p99th as f64 / 1e3,
+                statistics.p999th as f64 / 1e3
+            );
+            let mut wtr = schema::csv_appender(output).unwrap();
+            wtr.serialize(&statistics).unwrap();
+            wtr.flush().unwrap();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! `ssd-benchy quick`: the canonical 4K random-read triage pair.
+//!
+//! A short sequential preconditioning pass makes sure reads hit mapped LBAs, then random 4K reads
+//! are measured once at a high queue depth (IOPS ceiling) and once at queue depth 1 (latency
+//! floor). Rows are written in the qd-curve format.
+
+use crate::qd_curve::{AccessPattern, Direction, PointSpec, QdCurvePoint};
+use std::{path::Path, time::Duration};
+
+/// Block size of the sequential preconditioning writes
+const PRECONDITION_BLOCK_SIZE: usize = 1048576;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct QuickArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Queue depth of the IOPS measurement
+    #[clap(long, env = "SSD_BENCHY_HIGH_QUEUE_DEPTH", default_value_t = 32)]
+    high_queue_depth: u64,
+
+    /// Block size of the random reads
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 4096)]
+    block_size: usize,
+
+    /// Runtime in seconds of each of the two measurements
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 60)]
+    runtime_seconds: u64,
+
+    /// Seconds of sequential writes before measuring; reads are confined to the written range.
+    /// 0 skips preconditioning and reads from the whole used capacity
+    #[clap(long, env = "SSD_BENCHY_PRECONDITION_SECONDS", default_value_t = 30)]
+    precondition_seconds: u64,
+
+    /// Fraction of the SSD that is used
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 0.8)]
+    capacity_fraction: f64,
+
+    /// Result file (qd-curve format)
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("quick.csv"))]
+    output_file: String,
+}
+
+pub fn run(args: &QuickArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let used_bytes = (capacity as f64 * args.capacity_fraction) as u64;
+    let output = Path::new(&args.output_file);
+    QdCurvePoint::check_output(output);
+
+    let mut read_bytes = used_bytes;
+    if args.precondition_seconds > 0 {
+        println!(
+            "preconditioning: {}s of sequential writes ...",
+            args.precondition_seconds
+        );
+        let result = crate::qd_curve::run_point(&PointSpec {
+            ssd_device: &args.ssd_device,
+            queue_depth: 1,
+            block_size: PRECONDITION_BLOCK_SIZE,
+            direction: Direction::Write,
+            pattern: AccessPattern::Sequential,
+            blocks: used_bytes / PRECONDITION_BLOCK_SIZE as u64,
+            runtime: Duration::from_secs(args.precondition_seconds),
+        });
+        read_bytes = read_bytes.min(result.operations * PRECONDITION_BLOCK_SIZE as u64);
+        println!(
+            " [Done] {:.1} GiB written",
+            read_bytes as f64 / (1u64 << 30) as f64
+        );
+    }
+    let blocks = read_bytes / args.block_size as u64;
+
+    QdCurvePoint::print_header();
+    let mut points = vec![];
+    for queue_depth in [args.high_queue_depth, 1] {
+        let point = QdCurvePoint::measure(&PointSpec {
+            ssd_device: &args.ssd_device,
+            queue_depth,
+            block_size: args.block_size,
+            direction: Direction::Read,
+            pattern: AccessPattern::Random,
+            blocks,
+            runtime: Duration::from_secs(args.runtime_seconds),
+        });
+        point.print();
+        point.append_to(output);
+        points.push(point);
+    }
+
+    println!(
+        "random read: {:.0} IOPS at QD{}, QD1 latency p50 {:.1}us / p99 {:.1}us / p99.9 {:.1}us",
+        points[0].iops,
+        args.high_queue_depth,
+        points[1].p50th as f64 / 1e3,
+        points[1].p99th as f64 / 1e3,
+        points[1].p999th as f64 / 1e3
+    );
+}
@@ -0,0 +1,195 @@
+//! `ssd-benchy read-disturb`: hammer reads on a narrow LBA range for a long time.
+//!
+//! Reading a NAND page slightly disturbs the cells of its neighbors; high-density TLC/QLC drives
+//! have to refresh (relocate) blocks after enough reads before the bit errors become
+//! uncorrectable. The first `--range-bytes` of the device are written once and then read
+//! closed-loop for `--runtime-seconds`. Every `--interval-seconds` a row records the read latency
+//! of the interval next to the SMART media-error counters, so refresh activity shows up as latency
+//! spikes and failed handling as growing media errors.
+
+use crate::{
+    nvme,
+    qd_curve::{AccessPattern, Direction, PointSpec},
+    schema,
+};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReadDisturbArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Size of the hammered range at the start of the device
+    #[clap(long, env = "SSD_BENCHY_RANGE_BYTES", default_value_t = 16777216)]
+    range_bytes: u64,
+
+    /// Size of every read in bytes; must be a multiple of the logical block size
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 4096)]
+    block_size: usize,
+
+    /// Queue depth (threads) of the random reads
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTH", default_value_t = 16)]
+    queue_depth: u64,
+
+    /// Total duration of the reads in seconds
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 3600)]
+    runtime_seconds: u64,
+
+    /// Length of the intervals latency and the SMART counters are sampled at
+    #[clap(long, env = "SSD_BENCHY_INTERVAL_SECONDS", default_value_t = 60)]
+    interval_seconds: u64,
+
+    /// Result file, one row per interval
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("read_disturb.csv"))]
+    output_file: String,
+}
+
+/// One row of the read-disturb result file
+#[derive(Serialize, Debug, Default)]
+struct ReadDisturbSample {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    range_bytes: u64,
+    block_size: usize,
+    queue_depth: u64,
+    interval: u64,
+    elapsed_seconds: f64, // at the end of the interval
+    reads: u64,           // since the start of the run
+    reads_per_block: f64, // since the start of the run
+    iops: f64,
+    mean: f64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+    smart_available: bool,
+    media_errors: u64, // increase since the start of the run
+    error_log_entries: u64,
+    critical_warning: u8,
+    temperature_celsius: i64,
+}
+
+/// The periodic samples of the read loop, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "read-disturb",
+        option: "--output-file",
+        columns: schema::columns_of(&ReadDisturbSample::default()),
+    }]
+}
+
+pub fn run(args: &ReadDisturbArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    if args.range_bytes > capacity || args.range_bytes < args.block_size as u64 {
+        eprintln!(
+            "--range-bytes must lie between the block size and the device capacity ({} bytes)",
+            capacity
+        );
+        std::process::exit(1);
+    }
+    let output = Path::new(&args.output_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&ReadDisturbSample::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let blocks = args.range_bytes / args.block_size as u64;
+    let uuid = Uuid::new_v4().as_u128();
+    let start_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("");
+    let initial_smart = nvme::smart_log(&args.ssd_device)
+        .inspect_err(|e| println!("warning: {}; the SMART columns stay empty", e))
+        .ok();
+
+    println!(
+        "writing the first {:.1} MiB once ...",
+        args.range_bytes as f64 / (1u64 << 20) as f64
+    );
+    crate::fill_range(&args.ssd_device, 0..blocks * args.block_size as u64);
+
+    println!(
+        "{:>8} {:>14} {:>12} {:>10} {:>10} {:>10} {:>12}",
+        "time[s]", "reads/block", "iops", "p50[us]", "p99[us]", "max[us]", "media_errors"
+    );
+    let begin = Instant::now();
+    let end_time = begin + Duration::from_secs(args.runtime_seconds);
+    let mut reads = 0;
+    let mut interval = 0;
+    while Instant::now() < end_time {
+        let runtime = Duration::from_secs(args.interval_seconds)
+            .min(end_time.saturating_duration_since(Instant::now()));
+        let result = crate::qd_curve::run_point(&PointSpec {
+            ssd_device: &args.ssd_device,
+            queue_depth: args.queue_depth,
+            block_size: args.block_size,
+            direction: Direction::Read,
+            pattern: AccessPattern::Random,
+            blocks,
+            runtime,
+        });
+        reads += result.operations;
+        let smart = initial_smart.and_then(|_| nvme::smart_log(&args.ssd_device).ok());
+        let h = &result.histogram;
+        let mut sample = ReadDisturbSample {
+            uuid,
+            start_time: start_time.as_secs(),
+            hostname: gethostname().into_string().unwrap(),
+            ssd_device: args.ssd_device.clone(),
+            range_bytes: blocks * args.block_size as u64,
+            block_size: args.block_size,
+            queue_depth: args.queue_depth,
+            interval,
+            elapsed_seconds: begin.elapsed().as_secs_f64(),
+            reads,
+            reads_per_block: reads as f64 / blocks as f64,
+            iops: result.operations as f64 / result.elapsed.as_secs_f64(),
+            mean: h.mean(),
+            p50th: h.percentile(50.0),
+            p99th: h.percentile(99.0),
+            p999th: h.percentile(99.9),
+            max: h.max(),
+            ..Default::default()
+        };
+        if let (Some(initial), Some(smart)) = (initial_smart, smart) {
+            sample.smart_available = true;
+            sample.media_errors = smart.media_errors.saturating_sub(initial.media_errors);
+            sample.error_log_entries = smart
+                .error_log_entries
+                .saturating_sub(initial.error_log_entries);
+            sample.critical_warning = smart.critical_warning;
+            sample.temperature_celsius = smart.temperature_celsius;
+        }
+        println!(
+            "{:>8.0} {:>14.0} {:>12.0} {:>10.1} {:>10.1} {:>10.1} {:>12}",
+            sample.elapsed_seconds,
+            sample.reads_per_block,
+            sample.iops,
+            sample.p50th as f64 / 1e3,
+            sample.p99th as f64 / 1e3,
+            sample.max as f64 / 1e3,
+            if sample.smart_available {
+                sample.media_errors.to_string()
+            } else {
+                "-".to_string()
+            }
+        );
+        let mut wtr = schema::csv_appender(output).unwrap();
+        wtr.serialize(&sample).unwrap();
+        wtr.flush().unwrap();
+        interval += 1;
+    }
+}
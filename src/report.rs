@@ -0,0 +1,491 @@
+//! `ssd-benchy report`: a results table and the key findings of a summary file.
+//!
+//! Utilization points are grouped by configuration (machine, device, engine, workload, and
+//! threads) and listed in order of utilization. For every configuration three findings are
+//! derived: the knee, i.e., the first utilization whose p99 exceeds `--knee-factor` times the p99
+//! at the lowest utilization; the maximum stable utilization, the highest utilization up to which
+//! every point achieved its target rate and, where the summary has the column, kept its
+//! stability score below `--max-stability-score`; and, with `--outliers-file`, the number of
+//! latency spikes captured per point. With `--fanout-file`, a second table lists the projected p99
+//! of requests that fan out to K writes (fanout module) per point. `--format markdown` prints
+//! tables that can be pasted into PRs and wiki pages.
+
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+};
+
+/// A point counts as achieving its target rate above this fraction of it
+const ACHIEVED_FRACTION: f64 = 0.95;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Aligned plain text for the terminal
+    #[default]
+    Text,
+    /// GitHub-flavored markdown
+    Markdown,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportArgs {
+    /// Summary file of the benchmark
+    #[clap(long, env = "SSD_BENCHY_SUMMARY_FILE", default_value_t = String::from("summary.csv"))]
+    summary_file: String,
+
+    /// Outliers file of the same runs (--outlier-threshold-us) to count spikes per point
+    #[clap(long, env = "SSD_BENCHY_OUTLIERS_FILE")]
+    outliers_file: Option<String>,
+
+    /// Fan-out file of the same runs (--fanouts) to list the projected p99 of fanned-out requests
+    #[clap(long, env = "SSD_BENCHY_FANOUT_FILE")]
+    fanout_file: Option<String>,
+
+    #[clap(long, env = "SSD_BENCHY_FORMAT", value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// The knee is where the p99 exceeds this multiple of the p99 at the lowest utilization
+    #[clap(long, env = "SSD_BENCHY_KNEE_FACTOR", default_value_t = 2.0)]
+    knee_factor: f64,
+
+    /// Points with a higher stability score are not stable
+    #[clap(long, env = "SSD_BENCHY_MAX_STABILITY_SCORE", default_value_t = 0.25)]
+    max_stability_score: f64,
+
+    /// Write the report to this file instead of stdout
+    #[clap(long, env = "SSD_BENCHY_OUTPUT", short)]
+    output: Option<String>,
+}
+
+/// The columns of a summary row the report uses; latencies in nanoseconds
+struct Point {
+    uuid: String,
+    utilization: f64,
+    target_iops: f64,
+    achieved_iops: f64,
+    p50: f64,
+    p99: f64,
+    p999: f64,
+    max: f64,
+    stability_score: Option<f64>, // none without complete windows
+}
+
+impl Point {
+    fn achieved_target(&self) -> bool {
+        self.achieved_iops >= ACHIEVED_FRACTION * self.target_iops
+    }
+}
+
+/// A table of text cells, also used by the analyze subcommand
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn write(&self, out: &mut String, format: Format) {
+        match format {
+            Format::Markdown => {
+                let _ = writeln!(out, "| {} |", self.header.join(" | "));
+                let _ = writeln!(out, "|{}", "---:|".repeat(self.header.len()));
+                for row in &self.rows {
+                    let _ = writeln!(out, "| {} |", row.join(" | "));
+                }
+            }
+            Format::Text => {
+                let widths: Vec<usize> = (0..self.header.len())
+                    .map(|i| {
+                        self.rows
+                            .iter()
+                            .map(|r| r[i].len())
+                            .chain([self.header[i].len()])
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                for row in [&self.header].into_iter().chain(&self.rows) {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, width)| format!("{:>width$}", cell, width = width))
+                        .collect();
+                    let _ = writeln!(out, "{}", cells.join("  "));
+                }
+            }
+        }
+    }
+}
+
+fn us(ns: f64) -> String {
+    format!("{:.1}", ns / 1e3)
+}
+
+fn read_summary(path: &str) -> Result<BTreeMap<String, Vec<Point>>, String> {
+    let mut rdr =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let header: Vec<String> = rdr
+        .headers()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .iter()
+        .map(String::from)
+        .collect();
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let required = |name: &str| {
+        column(name).ok_or_else(|| format!("Failed to report: {} has no column {}", path, name))
+    };
+    let utilization = required("utilization_iop")?;
+    let iops = required("iops")?;
+    let achieved = required("achieved_iops")?;
+    let p50 = required("p50th")?;
+    let p99 = required("p99th")?;
+    let p999 = required("p999th")?;
+    let max = required("max")?;
+    let uuid = required("uuid")?;
+    let stability_score = column("stability_score");
+    let stability_windows = column("stability_windows");
+    let configuration: Vec<usize> = [
+        "hostname",
+        "instance_type",
+        "ssd_device",
+        "engine",
+        "workload",
+        "writer_threads",
+    ]
+    .iter()
+    .filter_map(|name| column(name))
+    .collect();
+
+    let mut configurations: BTreeMap<String, Vec<Point>> = BTreeMap::new();
+    for (line, record) in rdr.records().enumerate() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let number = |i: usize| {
+            record
+                .get(i)
+                .and_then(|s| s.parse::<f64>().ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Failed to parse {} of row {} of {}",
+                        header[i],
+                        line + 2,
+                        path
+                    )
+                })
+        };
+        let key: Vec<&str> = configuration
+            .iter()
+            .map(|&i| record.get(i).unwrap_or(""))
+            .collect();
+        configurations
+            .entry(key.join(" "))
+            .or_default()
+            .push(Point {
+                uuid: record.get(uuid).unwrap_or("").to_string(),
+                utilization: number(utilization)?,
+                target_iops: number(iops)?,
+                achieved_iops: number(achieved)?,
+                p50: number(p50)?,
+                p99: number(p99)?,
+                p999: number(p999)?,
+                max: number(max)?,
+                // a point shorter than one window has no score, not a perfect one
+                stability_score: stability_score
+                    .filter(|_| stability_windows.is_some_and(|i| number(i).is_ok_and(|w| w > 0.0)))
+                    .and_then(|i| number(i).ok()),
+            });
+    }
+    for points in configurations.values_mut() {
+        points.sort_by(|a, b| a.utilization.total_cmp(&b.utilization));
+    }
+    Ok(configurations)
+}
+
+/// Outliers per run uuid
+fn count_outliers(path: &str) -> Result<HashMap<String, u64>, String> {
+    let mut rdr =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let uuid = rdr
+        .headers()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .iter()
+        .position(|c| c == "uuid")
+        .ok_or_else(|| format!("Failed to report: {} has no column uuid", path))?;
+    let mut counts = HashMap::new();
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        *counts
+            .entry(record.get(uuid).unwrap_or("").to_string())
+            .or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// The columns of a fan-out row the report uses; latencies in nanoseconds
+#[derive(Deserialize)]
+struct Fanout {
+    uuid: String,
+    fanout: u64,
+    single_io_percentile: f64,
+    projected_p99th: f64,
+    empirical_p99th: Option<f64>,
+}
+
+/// Fan-out rows per run uuid, in the order of the file
+fn read_fanouts(path: &str) -> Result<HashMap<String, Vec<Fanout>>, String> {
+    let mut rdr =
+        csv::Reader::from_path(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut fanouts: HashMap<String, Vec<Fanout>> = HashMap::new();
+    for row in rdr.deserialize::<Fanout>() {
+        let row = row.map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+        fanouts.entry(row.uuid.clone()).or_default().push(row);
+    }
+    Ok(fanouts)
+}
+
+fn report(args: &ReportArgs) -> Result<String, String> {
+    let configurations = read_summary(&args.summary_file)?;
+    if configurations.is_empty() {
+        return Err(format!(
+            "Failed to report: {} has no utilization points",
+            args.summary_file
+        ));
+    }
+    let outliers = args
+        .outliers_file
+        .as_deref()
+        .map(count_outliers)
+        .transpose()?;
+    let fanouts = args.fanout_file.as_deref().map(read_fanouts).transpose()?;
+
+    let mut out = String::new();
+    let markdown = args.format == Format::Markdown;
+    let _ = writeln!(
+        out,
+        "{}Latency report of {}\n",
+        if markdown { "# " } else { "" },
+        args.summary_file
+    );
+    for (configuration, points) in &configurations {
+        let _ = writeln!(
+            out,
+            "{}{}\n",
+            if markdown { "## " } else { "== " },
+            configuration
+        );
+
+        let mut header = vec![
+            "utilization",
+            "target IOPS",
+            "achieved IOPS",
+            "p50 [us]",
+            "p99 [us]",
+            "p99.9 [us]",
+            "max [us]",
+            "stability",
+        ];
+        if outliers.is_some() {
+            header.push("spikes");
+        }
+        let rows = points
+            .iter()
+            .map(|p| {
+                let mut row = vec![
+                    format!("{:.2}", p.utilization),
+                    format!("{:.0}", p.target_iops),
+                    format!("{:.0}", p.achieved_iops),
+                    us(p.p50),
+                    us(p.p99),
+                    us(p.p999),
+                    us(p.max),
+                    p.stability_score
+                        .map(|s| format!("{:.3}", s))
+                        .unwrap_or_default(),
+                ];
+                if let Some(outliers) = &outliers {
+                    row.push(outliers.get(&p.uuid).copied().unwrap_or(0).to_string());
+                }
+                row
+            })
+            .collect();
+        Table {
+            header: header.into_iter().map(String::from).collect(),
+            rows,
+        }
+        .write(&mut out, args.format);
+
+        let baseline = &points[0];
+        let knee = points
+            .iter()
+            .find(|p| p.p99 > args.knee_factor * baseline.p99);
+        let stable = points
+            .iter()
+            .take_while(|p| {
+                p.achieved_target()
+                    && p.stability_score
+                        .is_none_or(|s| s <= args.max_stability_score)
+            })
+            .last();
+        let mut findings = vec![
+            match knee {
+                Some(knee) => format!(
+                    "Knee at utilization {:.2}: p99 {}us, {:.1}x the {}us at {:.2}",
+                    knee.utilization,
+                    us(knee.p99),
+                    knee.p99 / baseline.p99,
+                    us(baseline.p99),
+                    baseline.utilization
+                ),
+                None => format!(
+                    "No knee up to utilization {:.2}: p99 stays within {}x of {}us",
+                    points.last().unwrap().utilization,
+                    args.knee_factor,
+                    us(baseline.p99)
+                ),
+            },
+            match stable {
+                Some(stable) => format!(
+                    "Max stable utilization {:.2} ({:.0} IOPS achieved)",
+                    stable.utilization, stable.achieved_iops
+                ),
+                None => format!(
+                    "No stable utilization: already {:.2} misses its target rate or stability",
+                    baseline.utilization
+                ),
+            },
+        ];
+        if let Some(outliers) = &outliers {
+            let spikes: Vec<u64> = points
+                .iter()
+                .map(|p| outliers.get(&p.uuid).copied().unwrap_or(0))
+                .collect();
+            let (worst, most) = spikes
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, count)| **count)
+                .unwrap();
+            findings.push(format!(
+                "{} latency spikes in total, most ({}) at utilization {:.2}",
+                spikes.iter().sum::<u64>(),
+                most,
+                points[worst].utilization
+            ));
+        }
+        let _ = writeln!(out);
+        for finding in findings {
+            let _ = writeln!(out, "{}{}", if markdown { "- " } else { "  " }, finding);
+        }
+        let _ = writeln!(out);
+
+        let rows: Vec<Vec<String>> = points
+            .iter()
+            .flat_map(|p| {
+                let projections = fanouts.as_ref().and_then(|f| f.get(&p.uuid));
+                projections.into_iter().flatten().map(|f| {
+                    vec![
+                        format!("{:.2}", p.utilization),
+                        f.fanout.to_string(),
+                        format!("p{:.3}", f.single_io_percentile),
+                        us(f.projected_p99th),
+                        f.empirical_p99th.map(us).unwrap_or_default(),
+                    ]
+                })
+            })
+            .collect();
+        if !rows.is_empty() {
+            let _ = writeln!(
+                out,
+                "{}Fan-out projection\n",
+                if markdown { "### " } else { "-- " }
+            );
+            Table {
+                header: [
+                    "utilization",
+                    "fan-out",
+                    "single-IO percentile",
+                    "p99 [us]",
+                    "empirical p99 [us]",
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+                rows,
+            }
+            .write(&mut out, args.format);
+            let _ = writeln!(out);
+        }
+    }
+    Ok(out)
+}
+
+pub fn run(args: &ReportArgs) {
+    let out = report(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    match &args.output {
+        Some(path) => fs::write(path, out).unwrap_or_else(|e| {
+            eprintln!("Failed to write {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => print!("{}", out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fanout_projections_are_listed_per_point() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-report-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let summary = dir.join("summary.csv");
+        fs::write(
+            &summary,
+            "hostname,ssd_device,uuid,utilization_iop,iops,achieved_iops,p50th,p99th,p999th,max\n\
+             a,nvme0n1,1,0.5,500,500,10000,20000,30000,40000\n\
+             a,nvme0n1,2,0.9,900,900,10000,25000,35000,45000\n",
+        )
+        .unwrap();
+        let fanout = dir.join("fanout.csv");
+        fs::write(
+            &fanout,
+            "uuid,utilization_iop,fanout,samples,single_io_percentile,projected_p99th,empirical_p99th\n\
+             1,0.5,1,100,99.0,20000,\n\
+             1,0.5,10,100,99.9,30000,29000\n\
+             3,0.5,10,100,99.9,1000000,\n",
+        )
+        .unwrap();
+        let args = ReportArgs {
+            summary_file: summary.to_string_lossy().into_owned(),
+            outliers_file: None,
+            fanout_file: Some(fanout.to_string_lossy().into_owned()),
+            format: Format::Text,
+            knee_factor: 2.0,
+            max_stability_score: 0.25,
+            output: None,
+        };
+        let out = report(&args).unwrap();
+        let section = &out[out.find("Fan-out projection").unwrap()..];
+        let rows: Vec<Vec<&str>> = section
+            .lines()
+            .skip(3)
+            .take_while(|l| !l.is_empty())
+            .map(|l| l.split_whitespace().collect())
+            .collect();
+        // the uuid of no summary row is left out, a point without projections too
+        assert_eq!(
+            rows,
+            [
+                vec!["0.50", "1", "p99.000", "20.0"],
+                vec!["0.50", "10", "p99.900", "30.0", "29.0"]
+            ]
+        );
+
+        let mut args = args;
+        args.fanout_file = None;
+        assert!(!report(&args).unwrap().contains("Fan-out"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! Background serialization of samples.
+//!
+//! Writer threads hand their samples in batches to a bounded channel that is drained by a
+//! dedicated writer thread. The IO path only ever uses `try_send`: when the channel is full the
+//! batch is kept and retried with the next one, and the event is counted so that a run whose
+//! serialization could not keep up is visible in the results. A thread holds back at most
+//! `MAX_PENDING` samples, so a slow disk cannot grow its memory without bound; the samples beyond
+//! are dropped, and the summary counts them in `samples_dropped`.
+//!
+//! With `--samples-max-rows` a point whose projected number of samples exceeds the limit keeps a
+//! uniform random subset of its samples instead (reservoir sampling, an equal share of the limit
+//! per thread) and writes it when the measurement is over, so a long run at a high rate cannot
+//! fill the disk; the summary records the fraction of the writes that made it into the file.
+
+use crate::{seekable::SeekableWriter, Sample};
+use serde::Serialize;
+use std::{
+    fs::File,
+    path::Path,
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Number of samples a thread accumulates before handing them to the writer thread
+const BATCH_SIZE: usize = 256;
+/// Number of batches that may be queued before threads start holding samples back
+const CHANNEL_CAPACITY: usize = 1024;
+/// Number of samples a thread holds back at most while the channel is full
+const MAX_PENDING: usize = 64 * BATCH_SIZE;
+
+/// Bytes the channel holds at most
+pub fn max_queued_bytes() -> usize {
+    CHANNEL_CAPACITY * BATCH_SIZE * std::mem::size_of::<Sample>()
+}
+
+/// `<samples file without extension>.<uuid>.<suffix>`, used for per-thread sample files and their manifest
+pub fn samples_path_for_run(samples_file: &str, uuid: u128, suffix: &str) -> String {
+    let stem = Path::new(samples_file).with_extension("");
+    format!("{}.{}.{}", stem.display(), uuid, suffix)
+}
+
+pub fn thread_samples_path(samples_file: &str, uuid: u128, thread_id: u64) -> String {
+    samples_path_for_run(samples_file, uuid, &format!("t{}.csv", thread_id))
+}
+
+#[derive(clap::ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SamplesFormat {
+    /// Rows of text, appended to the samples file
+    #[default]
+    Csv,
+    /// A compressed file per utilization point with a time index, see the seekable module
+    ZstdSeekable,
+}
+
+pub enum Destination {
+    /// Append to the shared samples file
+    Shared { samples_file: String },
+    /// One file per writer thread, see [`thread_samples_path`]
+    PerThread {
+        samples_file: String,
+        uuid: u128,
+        threads: u64,
+    },
+    /// A new seekable file of frames that span `frame` each
+    Seekable { path: String, frame: Duration },
+}
+
+pub struct SampleWriter {
+    handle: JoinHandle<()>,
+}
+
+impl SampleWriter {
+    /// Starts the writer thread; every benchmark thread gets a clone of the returned sender.
+    pub fn spawn(destination: Destination) -> (SampleWriter, SyncSender<Vec<Sample>>) {
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        let handle = std::thread::spawn(move || SampleWriter::drain(destination, receiver));
+        (SampleWriter { handle }, sender)
+    }
+
+    /// Waits until all senders are dropped and every queued sample is on disk.
+    pub fn join(self) {
+        self.handle.join().expect("sample writer thread panicked");
+    }
+
+    fn drain(destination: Destination, receiver: Receiver<Vec<Sample>>) {
+        match destination {
+            Destination::Shared { samples_file } => {
+                let mut wtr = crate::schema::csv_appender(Path::new(&samples_file)).unwrap();
+                for batch in receiver {
+                    for sample in batch {
+                        wtr.serialize(&sample).unwrap();
+                    }
+                }
+                wtr.flush().unwrap();
+            }
+            Destination::PerThread {
+                samples_file,
+                uuid,
+                threads,
+            } => {
+                // files are created upfront so that threads without samples still get a file with a header
+                let mut writers: Vec<csv::Writer<File>> = (0..threads)
+                    .map(|thread_id| {
+                        let path = thread_samples_path(&samples_file, uuid, thread_id);
+                        let mut wtr = csv::WriterBuilder::new()
+                            .has_headers(false)
+                            .from_path(path)
+                            .expect("could not create samples file");
+                        wtr.write_record(crate::schema::header_of(&Sample::default()))
+                            .unwrap();
+                        wtr
+                    })
+                    .collect();
+                for batch in receiver {
+                    for sample in batch {
+                        writers[sample.thread_id as usize]
+                            .serialize(&sample)
+                            .unwrap();
+                    }
+                }
+                for wtr in writers.iter_mut() {
+                    wtr.flush().unwrap();
+                }
+            }
+            Destination::Seekable { path, frame } => {
+                let header = crate::schema::header_of(&Sample::default());
+                let mut writer = SeekableWriter::create(&path, &header, frame)
+                    .unwrap_or_else(|e| panic!("{}", e));
+                for batch in receiver {
+                    for sample in batch {
+                        writer
+                            .push(&sample, sample.realtime_ns)
+                            .unwrap_or_else(|e| panic!("{}", e));
+                    }
+                }
+                writer.finish().unwrap_or_else(|e| panic!("{}", e));
+            }
+        }
+    }
+}
+
+/// A uniform random subset of at most `capacity` of the samples offered to it
+struct Reservoir {
+    samples: Vec<Sample>,
+    capacity: usize,
+    rng: fastrand::Rng,
+}
+
+/// The per-thread end of the channel
+pub struct SampleStream {
+    sender: SyncSender<Vec<Sample>>,
+    batch: Vec<Sample>,
+    reservoir: Option<Reservoir>,
+    pub offered: u64,             // samples pushed, also those the reservoir dropped
+    pub backpressure_events: u64, // number of times the channel was full
+    pub max_pending: usize,       // largest number of samples a thread had to hold back
+    pub dropped: u64,             // as the channel stayed full with MAX_PENDING held back
+}
+
+impl SampleStream {
+    pub fn new(sender: SyncSender<Vec<Sample>>) -> Self {
+        SampleStream {
+            sender,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            reservoir: None,
+            offered: 0,
+            backpressure_events: 0,
+            max_pending: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Keeps at most `capacity` samples, written by [`SampleStream::finish`]
+    pub fn downsampled(sender: SyncSender<Vec<Sample>>, capacity: usize, seed: u64) -> Self {
+        SampleStream {
+            reservoir: Some(Reservoir {
+                samples: vec![],
+                capacity,
+                rng: fastrand::Rng::with_seed(seed),
+            }),
+            ..SampleStream::new(sender)
+        }
+    }
+
+    /// Never blocks: if the writer thread is behind, the samples stay in the local batch, up to
+    /// `MAX_PENDING`; later ones are dropped until there is room in the channel again.
+    pub fn push(&mut self, sample: Sample) {
+        self.offered += 1;
+        if let Some(reservoir) = self.reservoir.as_mut() {
+            if reservoir.samples.len() < reservoir.capacity {
+                reservoir.samples.push(sample);
+            } else {
+                let slot = reservoir.rng.u64(0..self.offered) as usize;
+                if slot < reservoir.capacity {
+                    reservoir.samples[slot] = sample;
+                }
+            }
+            return;
+        }
+        self.batch.push(sample);
+        if self.batch.len() < BATCH_SIZE {
+            return;
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        match self.sender.try_send(batch) {
+            Ok(()) => {}
+            Err(TrySendError::Full(mut batch)) => {
+                self.backpressure_events += 1;
+                if batch.len() > MAX_PENDING {
+                    batch.pop();
+                    self.dropped += 1;
+                }
+                self.max_pending = self.max_pending.max(batch.len());
+                self.batch = batch;
+            }
+            Err(TrySendError::Disconnected(_)) => panic!("sample writer thread terminated"),
+        }
+    }
+
+    /// Bytes of the samples the thread holds: its batch, also what it had to hold back, and the
+    /// reservoir
+    pub fn pending_bytes(&self) -> usize {
+        let reservoir = self.reservoir.as_ref().map_or(0, |r| r.samples.capacity());
+        (self.batch.capacity() + reservoir) * std::mem::size_of::<Sample>()
+    }
+
+    /// Hands over the held-back samples, waiting for room in the channel if it is full
+    pub fn flush(&mut self) {
+        if self.reservoir.is_some() || self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        self.sender
+            .send(batch)
+            .expect("sample writer thread terminated");
+    }
+
+    /// Hands over the remaining samples, or those of the reservoir in the order they were taken;
+    /// may block and must only be called after the measurement is over. Returns the number of
+    /// samples written of this thread.
+    pub fn finish(mut self) -> u64 {
+        let written = match self.reservoir.take() {
+            Some(mut reservoir) => {
+                reservoir.samples.sort_unstable_by_key(|s| s.seq);
+                self.batch = reservoir.samples;
+                self.batch.len() as u64
+            }
+            None => self.offered - self.dropped,
+        };
+        if !self.batch.is_empty() {
+            self.sender
+                .send(self.batch)
+                .expect("sample writer thread terminated");
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seq: u64) -> Sample {
+        Sample {
+            seq,
+            ..Sample::default()
+        }
+    }
+
+    #[test]
+    fn a_full_channel_holds_back_a_bounded_number_of_samples() {
+        let (sender, receiver) = sync_channel(1);
+        let mut stream = SampleStream::new(sender);
+        let offered = BATCH_SIZE + MAX_PENDING + 100;
+        for seq in 0..offered as u64 {
+            stream.push(sample(seq));
+        }
+        // the first batch fills the channel, the thread holds back the next ones
+        assert!(stream.backpressure_events > 0);
+        assert_eq!(stream.max_pending, MAX_PENDING);
+        assert_eq!(stream.dropped, 100);
+        assert!(stream.pending_bytes() <= 2 * MAX_PENDING * std::mem::size_of::<Sample>());
+
+        let reader = std::thread::spawn(move || {
+            receiver.iter().flatten().map(|s| s.seq).collect::<Vec<_>>()
+        });
+        assert_eq!(stream.finish(), (BATCH_SIZE + MAX_PENDING) as u64);
+        let written = reader.join().unwrap();
+        assert_eq!(written.len(), BATCH_SIZE + MAX_PENDING);
+        // the oldest samples are kept, the newest dropped
+        assert_eq!(
+            *written.last().unwrap(),
+            (BATCH_SIZE + MAX_PENDING - 1) as u64
+        );
+    }
+
+    #[test]
+    fn a_writer_that_keeps_up_loses_nothing() {
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        let mut stream = SampleStream::new(sender);
+        for seq in 0..3 * BATCH_SIZE as u64 + 5 {
+            stream.push(sample(seq));
+        }
+        assert_eq!((stream.backpressure_events, stream.dropped), (0, 0));
+        assert_eq!(stream.finish(), 3 * BATCH_SIZE as u64 + 5);
+        assert_eq!(receiver.iter().flatten().count(), 3 * BATCH_SIZE + 5);
+    }
+
+    #[test]
+    fn run_files_are_named_after_the_samples_file() {
+        assert_eq!(
+            thread_samples_path("results/samples.csv", 7, 2),
+            "results/samples.7.t2.csv"
+        );
+        assert_eq!(
+            samples_path_for_run("samples", 7, "manifest.json"),
+            "samples.7.manifest.json"
+        );
+    }
+
+    #[test]
+    fn samples_are_downsampled_to_the_limit() {
+        let (sender, receiver) = sync_channel(4);
+        let mut stream = SampleStream::downsampled(sender, 10, 7);
+        for seq in 0..1000 {
+            stream.push(sample(seq));
+        }
+        assert_eq!(stream.offered, 1000);
+        assert_eq!(stream.finish(), 10);
+        let kept: Vec<u64> = receiver.recv().unwrap().iter().map(|s| s.seq).collect();
+        assert_eq!(kept.len(), 10);
+        assert!(kept.windows(2).all(|w| w[0] < w[1]));
+        // not just the first ones
+        assert!(kept[9] >= 10);
+    }
+
+    #[test]
+    fn a_reservoir_keeps_all_of_fewer_samples() {
+        let (sender, receiver) = sync_channel(4);
+        let mut stream = SampleStream::downsampled(sender, 10, 7);
+        for seq in 0..5 {
+            stream.push(sample(seq));
+        }
+        // the reservoir is handed over as a whole at the end
+        stream.flush();
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(stream.finish(), 5);
+        let kept: Vec<u64> = receiver.recv().unwrap().iter().map(|s| s.seq).collect();
+        assert_eq!(kept, [0, 1, 2, 3, 4]);
+
+        let (sender, receiver) = sync_channel(4);
+        let mut stream = SampleStream::downsampled(sender, 0, 7);
+        stream.push(sample(0));
+        assert_eq!(stream.finish(), 0);
+        assert!(receiver.try_recv().is_err());
+    }
+}
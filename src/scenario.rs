@@ -0,0 +1,566 @@
+//! Multi-phase scenarios (`--scenario`).
+//!
+//! A utilization point runs one load for `--runtime-seconds`. What a database node sees over a
+//! day is a sequence of different loads: a bulk load, bursts of ingest, idle periods in which the
+//! drive collects garbage, mixed reads and writes, and scans. Instead of scripting one invocation
+//! per load, `--scenario day.toml` runs such a sequence of `[[phase]]` tables in order:
+//!
+//! ```toml
+//! [[phase]]
+//! kind = "precondition"  # write the used capacity sequentially
+//! passes = 2
+//!
+//! [[phase]]
+//! name = "ingest"
+//! kind = "burst"         # writes, as fast as the device allows without a utilization
+//! duration_seconds = 30
+//!
+//! [[phase]]
+//! kind = "idle"
+//! duration_seconds = 60
+//!
+//! [[phase]]
+//! kind = "mixed"         # random reads and writes
+//! duration_seconds = 300
+//! utilization = 0.5      # of --max-iops
+//! read_fraction = 0.3
+//!
+//! [[phase]]
+//! kind = "read-scan"     # sequential reads of the used capacity
+//! duration_seconds = 120
+//! ```
+//!
+//! The `--writer-threads` threads each own a region of the first `--capacity-fraction` of the
+//! device and issue IOs of `--iovcnt` blocks with the first of `--engines`. Bursts write their
+//! region sequentially and continue where the previous write phase stopped; a precondition
+//! writes it `passes` times (1 by default), bounded by `duration_seconds` if given. Every phase
+//! gets a row in `--scenario-stats-file` with the read and write latencies, tied together by the
+//! uuid of the run. The point hooks run around every phase, with its name in
+//! `SSD_BENCHY_HOOK_PHASE`.
+
+use crate::{
+    buffer::AlignedBuffer,
+    engine,
+    histogram::Histogram,
+    hooks::{Hook, Hooks},
+    partition, schema, CliConfig, RateLimiter, RatePattern, RateSchedule, StartBarrier, BLOCK_SIZE,
+};
+use gethostname::gethostname;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PhaseKind {
+    /// Sequential writes of the used capacity, `passes` times
+    Precondition,
+    /// Sequential writes for the duration
+    #[default]
+    Burst,
+    /// No IO for the duration
+    Idle,
+    /// Random reads and writes, a read with a probability of `read_fraction`
+    Mixed,
+    /// Sequential reads of the used capacity for the duration
+    ReadScan,
+}
+
+impl std::fmt::Display for PhaseKind {
+    /// The name used in the scenario file
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        f.write_str(self.to_possible_value().unwrap().get_name())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Phase {
+    pub name: String,
+    pub kind: PhaseKind,
+    pub duration: Duration, // zero for a precondition bounded by its passes only
+    pub utilization: f64,   // of --max-iops, 0 as fast as the device allows
+    pub read_fraction: f64, // of mixed phases
+    pub passes: u64,        // of preconditions
+}
+
+/// A scenario file: the `[[phase]]` tables
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(default)]
+    phase: Vec<PhaseEntry>,
+}
+
+/// One `[[phase]]` table as written; missing keys take their defaults in `parse`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhaseEntry {
+    name: Option<String>,
+    kind: PhaseKind,
+    duration_seconds: Option<f64>,
+    utilization: Option<f64>,
+    read_fraction: Option<f64>,
+    passes: Option<u64>,
+}
+
+/// Reads the phases of the file at `path`
+pub fn load(path: &str) -> Result<Vec<Phase>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse(text: &str) -> Result<Vec<Phase>, String> {
+    let file: ScenarioFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    if file.phase.is_empty() {
+        return Err(String::from("no [[phase]] defined"));
+    }
+    let mut parsed = vec![];
+    for (index, phase) in file.phase.into_iter().enumerate() {
+        let kind = phase.kind;
+        let name = phase.name.unwrap_or_else(|| format!("phase{}", index + 1));
+        // the keys a kind ignores are refused instead, a typo of the kind must not go unnoticed
+        let ignored = [
+            (
+                "utilization",
+                phase.utilization.is_some()
+                    && matches!(kind, PhaseKind::Precondition | PhaseKind::Idle),
+            ),
+            (
+                "read_fraction",
+                phase.read_fraction.is_some() && kind != PhaseKind::Mixed,
+            ),
+            (
+                "passes",
+                phase.passes.is_some() && kind != PhaseKind::Precondition,
+            ),
+        ];
+        if let Some((key, _)) = ignored.iter().find(|(_, ignored)| *ignored) {
+            return Err(format!("{} does not apply to {} phase {}", key, kind, name));
+        }
+        let duration = phase.duration_seconds;
+        if duration.is_some_and(|seconds| !seconds.is_finite() || seconds <= 0.0) {
+            return Err(format!(
+                "duration_seconds of phase {} must be positive",
+                name
+            ));
+        }
+        if duration.is_none() && kind != PhaseKind::Precondition {
+            return Err(format!("phase {} needs a duration_seconds", name));
+        }
+        let utilization = phase.utilization.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&utilization) {
+            return Err(format!(
+                "utilization of phase {} must be within [0, 1]",
+                name
+            ));
+        }
+        let read_fraction = phase.read_fraction.unwrap_or(0.5);
+        if !(0.0..=1.0).contains(&read_fraction) {
+            return Err(format!(
+                "read_fraction of phase {} must be within [0, 1]",
+                name
+            ));
+        }
+        let passes = phase.passes.unwrap_or(1);
+        if passes == 0 {
+            return Err(format!(
+                "passes of phase {} must be a positive integer",
+                name
+            ));
+        }
+        if parsed.iter().any(|p: &Phase| p.name == name) {
+            return Err(format!("phase {} is defined twice", name));
+        }
+        parsed.push(Phase {
+            name,
+            kind,
+            duration: duration.map_or(Duration::ZERO, Duration::from_secs_f64),
+            utilization,
+            read_fraction: match kind {
+                PhaseKind::Mixed => read_fraction,
+                PhaseKind::ReadScan => 1.0,
+                _ => 0.0,
+            },
+            passes,
+        });
+    }
+    Ok(parsed)
+}
+
+/// One row of the scenario stats file: one phase of a scenario; latencies in nanoseconds
+#[derive(Serialize, Debug, Default)]
+pub struct PhaseStatistics {
+    uuid: u128, // of the run, equal for all its phases
+    start_time: u64,
+    hostname: String,
+    instance_type: String,
+    ssd_device: String,
+    engine: engine::EngineKind,
+    phase: usize, // position in the scenario, from 0
+    name: String,
+    kind: PhaseKind,
+    target_iops: u64, // 0 without a utilization
+    elapsed_seconds: f64,
+    reads: u64,
+    writes: u64,
+    io_errors: u64,
+    achieved_iops: f64,
+    read_p50th: u64,
+    read_p99th: u64,
+    read_p999th: u64,
+    read_max: u64,
+    write_p50th: u64,
+    write_p99th: u64,
+    write_p999th: u64,
+    write_max: u64,
+}
+
+/// One row per phase, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "ssd-benchy",
+        option: "--scenario-stats-file",
+        columns: schema::columns_of(&PhaseStatistics::default()),
+    }]
+}
+
+/// What the threads of one phase did
+#[derive(Default)]
+struct PhaseResult {
+    reads: Histogram,
+    writes: Histogram,
+    io_errors: u64,
+}
+
+/// The position of every thread within its region, carried over from phase to phase
+struct Cursors {
+    write: Vec<u64>,
+    read: Vec<u64>,
+}
+
+/// Runs `phase` with one thread per region; `blocks` is the used capacity in IOs
+fn run_phase(
+    config: &CliConfig,
+    device: &engine::Device,
+    kind: engine::EngineKind,
+    phase: &Phase,
+    blocks: u64,
+    cursors: &mut Cursors,
+) -> PhaseResult {
+    let threads = config.writer_threads;
+    let io_bytes = config.iovcnt as usize * BLOCK_SIZE;
+    let start_barrier = StartBarrier::new(threads as usize);
+    let results: Vec<PhaseResult> = std::thread::scope(|scope| {
+        let handles: Vec<_> = cursors
+            .write
+            .iter_mut()
+            .zip(cursors.read.iter_mut())
+            .enumerate()
+            .map(|(thread_id, (write_cursor, read_cursor))| {
+                let thread_id = thread_id as u64;
+                let start_barrier = &start_barrier;
+                scope.spawn(move || {
+                    let engine = device.open(kind);
+                    let mut buffer = AlignedBuffer::new(io_bytes, 0x5c);
+                    let range = partition(thread_id, threads, blocks);
+                    let mut rng = fastrand::Rng::new();
+                    let rate = config.max_iops as f64 * phase.utilization;
+                    let begin = start_barrier.wait();
+                    let end_time = (!phase.duration.is_zero()).then_some(begin + phase.duration);
+                    let mut ratelimiter = (rate > 0.0).then(|| {
+                        let schedule = RateSchedule {
+                            pattern: RatePattern::Constant,
+                            min_rate: rate,
+                            max_rate: rate,
+                            runtime: phase.duration,
+                            period: phase.duration,
+                        };
+                        RateLimiter::new(begin, schedule, threads, thread_id, 1, 1.0, 0.0)
+                    });
+                    let mut result = PhaseResult::default();
+                    let precondition_ios = phase.passes * (range.end - range.start);
+                    let mut ios = 0;
+                    while end_time.is_none_or(|end| Instant::now() < end) {
+                        if phase.kind == PhaseKind::Precondition && ios >= precondition_ios {
+                            break;
+                        }
+                        let read = phase.read_fraction > 0.0 && rng.f64() < phase.read_fraction;
+                        let cursor = match phase.kind {
+                            PhaseKind::Mixed => None,
+                            _ if read => Some(&mut *read_cursor),
+                            _ => Some(&mut *write_cursor),
+                        };
+                        let block = match cursor {
+                            Some(cursor) => {
+                                if *cursor < range.start || *cursor >= range.end {
+                                    *cursor = range.start;
+                                }
+                                *cursor += 1;
+                                *cursor - 1
+                            }
+                            None => rng.u64(range.clone()),
+                        };
+                        let offset = block * io_bytes as u64;
+                        let mut io_errors = 0;
+                        let mut io = || {
+                            let res = if read {
+                                engine.read_at(&mut buffer, offset)
+                            } else {
+                                engine.write_at(&buffer, offset)
+                            };
+                            let complete = res.is_ok_and(|len| len == io_bytes);
+                            io_errors += u64::from(!complete);
+                            complete
+                        };
+                        let latencies = if read {
+                            &mut result.reads
+                        } else {
+                            &mut result.writes
+                        };
+                        match ratelimiter.as_mut() {
+                            Some(ratelimiter) => {
+                                ratelimiter.run(io, |latency, _| latencies.record(latency))
+                            }
+                            None => {
+                                let io_begin = Instant::now();
+                                if io() {
+                                    latencies.record(crate::stats::nanos(io_begin.elapsed()));
+                                }
+                            }
+                        }
+                        result.io_errors += io_errors;
+                        ios += 1;
+                    }
+                    result
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut phase_result = PhaseResult::default();
+    for result in results {
+        phase_result.reads.merge(&result.reads);
+        phase_result.writes.merge(&result.writes);
+        phase_result.io_errors += result.io_errors;
+    }
+    phase_result
+}
+
+/// Runs the phases of `--scenario` in order on the first engine and appends a row per phase to
+/// `--scenario-stats-file`
+pub fn run(config: &CliConfig, phases: &[Phase], hooks: &Hooks) {
+    use crate::outcome::{exit, Outcome};
+    if config.engines.len() > 1 {
+        exit(Outcome::ConfigError, "--scenario runs with one engine");
+    }
+    let kind = config.engines[0];
+    let device = engine::Device::new(
+        kind,
+        config.ssd_device.as_deref(),
+        config.simulated_device_bytes,
+        Duration::from_secs_f64(config.simulated_latency_us / 1e6),
+        engine::IoUringOptions {
+            sqpoll: config.sqpoll,
+            sqpoll_cpu: config.sqpoll_cpu,
+            hipri: config.hipri,
+        },
+        config.pi_mode,
+        config.window,
+    );
+    crate::ensure_serial(config, &device.name(), device.identity().serial);
+    if phases.iter().any(|p| p.utilization > 0.0) && config.max_iops == 0 {
+        exit(
+            Outcome::ConfigError,
+            "the utilization of a phase requires --max-iops",
+        );
+    }
+    let io_bytes = config.iovcnt * BLOCK_SIZE as u64;
+    let blocks = (device.capacity() as f64 * config.capacity_fraction[0]) as u64 / io_bytes;
+    if config.writer_threads == 0 || blocks < config.writer_threads {
+        exit(
+            Outcome::ConfigError,
+            &format!(
+                "the used capacity holds {} IOs of {} bytes, fewer than --writer-threads {}",
+                blocks, io_bytes, config.writer_threads
+            ),
+        );
+    }
+    crate::refuse_results_on_written_disks(
+        config,
+        &[&config.scenario_stats_file],
+        &[device.name()],
+    );
+    let output = Path::new(&config.scenario_stats_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&PhaseStatistics::default()),
+        config.schema_mismatch,
+    )
+    .unwrap_or_else(|e| exit(Outcome::ConfigError, &e));
+    let uuid = Uuid::new_v4().as_u128();
+    let mut cursors = Cursors {
+        write: (0..config.writer_threads)
+            .map(|thread_id| partition(thread_id, config.writer_threads, blocks).start)
+            .collect(),
+        read: (0..config.writer_threads)
+            .map(|thread_id| partition(thread_id, config.writer_threads, blocks).start)
+            .collect(),
+    };
+
+    let run_label = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("")
+        .as_secs()
+        .to_string();
+    hooks.run(Hook::PreRun, &run_label, &[("DEVICE", device.name())]);
+    println!(
+        "{:>16} {:>12} {:>10} {:>12} {:>12} {:>12} {:>12}",
+        "phase", "kind", "time[s]", "IOPS", "read p99[us]", "write p99[us]", "write max[us]"
+    );
+    for (index, phase) in phases.iter().enumerate() {
+        // the phases of a run share its uuid, so the name tells the logs apart
+        let phase_label = format!("{}-{}", uuid, phase.name);
+        let phase_context = [
+            ("DEVICE", device.name()),
+            ("CAPACITY_FRACTION", config.capacity_fraction[0].to_string()),
+            ("UUID", uuid.to_string()),
+            ("ENGINE", kind.to_string()),
+            ("UTILIZATION", phase.utilization.to_string()),
+            ("PHASE", phase.name.clone()),
+        ];
+        hooks.run(Hook::PrePoint, &phase_label, &phase_context);
+        let start_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("");
+        let begin = Instant::now();
+        let result = if phase.kind == PhaseKind::Idle {
+            std::thread::sleep(phase.duration);
+            PhaseResult::default()
+        } else {
+            run_phase(config, &device, kind, phase, blocks, &mut cursors)
+        };
+        let elapsed = begin.elapsed().as_secs_f64();
+        let (reads, writes) = (&result.reads, &result.writes);
+        let operations = reads.count() + writes.count();
+        let statistics = PhaseStatistics {
+            uuid,
+            start_time: start_time.as_secs(),
+            hostname: gethostname().into_string().unwrap(),
+            instance_type: config.instance_type.clone(),
+            ssd_device: device.name(),
+            engine: kind,
+            phase: index,
+            name: phase.name.clone(),
+            kind: phase.kind,
+            target_iops: (config.max_iops as f64 * phase.utilization) as u64,
+            elapsed_seconds: elapsed,
+            reads: reads.count(),
+            writes: writes.count(),
+            io_errors: result.io_errors,
+            achieved_iops: if elapsed > 0.0 {
+                operations as f64 / elapsed
+            } else {
+                0.0
+            },
+            read_p50th: reads.percentile(50.0),
+            read_p99th: reads.percentile(99.0),
+            read_p999th: reads.percentile(99.9),
+            read_max: reads.max(),
+            write_p50th: writes.percentile(50.0),
+            write_p99th: writes.percentile(99.0),
+            write_p999th: writes.percentile(99.9),
+            write_max: writes.max(),
+        };
+        println!(
+            "{:>16} {:>12} {:>10.1} {:>12.0} {:>12.1} {:>12.1} {:>12.1}",
+            statistics.name,
+            phase.kind.to_string(),
+            statistics.elapsed_seconds,
+            statistics.achieved_iops,
+            statistics.read_p99th as f64 / 1e3,
+            statistics.write_p99th as f64 / 1e3,
+            statistics.write_max as f64 / 1e3
+        );
+        let mut wtr = schema::csv_appender(output).unwrap();
+        wtr.serialize(&statistics).unwrap();
+        wtr.flush().unwrap();
+        crate::outcome::point_completed(json!({
+            "uuid": uuid.to_string(),
+            "phase": phase.name.clone(),
+            "kind": phase.kind.to_string(),
+            "achieved_iops": statistics.achieved_iops,
+            "io_errors": statistics.io_errors,
+            "read_p99th": statistics.read_p99th,
+            "write_p99th": statistics.write_p99th
+        }));
+        hooks.run(Hook::PostPoint, &phase_label, &phase_context);
+    }
+    hooks.run(Hook::PostRun, &run_label, &[("DEVICE", device.name())]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenario_phases_are_read_from_toml() {
+        let phases = parse(
+            r#"
+            [[phase]]
+            kind = "precondition"
+            passes = 2
+
+            [[phase]]
+            name = "ingest"
+            kind = "burst"
+            duration_seconds = 30
+
+            [[phase]]
+            kind = "mixed"
+            duration_seconds = 0.5
+            utilization = 0.5
+            read_fraction = 0.3
+
+            [[phase]]
+            kind = "read-scan"
+            duration_seconds = 60
+            "#,
+        )
+        .unwrap();
+        assert_eq!(phases.len(), 4);
+        assert_eq!(phases[0].name, "phase1");
+        assert_eq!((phases[0].passes, phases[0].duration), (2, Duration::ZERO));
+        assert_eq!(phases[1].kind, PhaseKind::Burst);
+        assert_eq!(phases[1].utilization, 0.0);
+        assert_eq!(phases[2].duration, Duration::from_millis(500));
+        assert_eq!(phases[2].read_fraction, 0.3);
+        assert_eq!(phases[3].read_fraction, 1.0);
+
+        for invalid in [
+            "[[phase]]\nkind = \"burst\"\n",
+            "[[phase]]\nkind = \"sleep\"\nduration_seconds = 1\n",
+            "[[phase]]\nkind = \"idle\"\nduration_seconds = 1\nutilization = 0.5\n",
+            "[[phase]]\nkind = \"mixed\"\nduration_seconds = 1\nread_fraction = 2\n",
+            "[[phase]]\nkind = \"precondition\"\npasses = 0\n",
+            "[[phase]]\nduration_seconds = 1\n",
+            "[phase]\n",
+            "",
+            "[[phase]]\nkind = \"burst\"\nduration_seconds = -1\n",
+            "[[phase]]\nkind = \"burst\"\nduration_seconds = 1\nrepeat = 2\n",
+            "[[phase]]\nname = \"a\"\nkind = \"idle\"\nduration_seconds = 1\n\
+             [[phase]]\nname = \"a\"\nkind = \"idle\"\nduration_seconds = 1\n",
+            "[[phase]]\nkind = \"burst\"\nduration_seconds = 1\npasses = 2\n",
+            "[[phase]]\nkind = \"precondition\"\npasses = 1.5\n",
+            "[[phase]]\nkind = \"idle\"\nduration_seconds = 1\nduration_seconds = 2\n",
+            "[defaults]\nutilization = 0.5\n[[phase]]\nkind = \"idle\"\nduration_seconds = 1\n",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
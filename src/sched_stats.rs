@@ -0,0 +1,239 @@
+//! Context switches of the writer threads and softirqs of the host during a point.
+//!
+//! A tail latency of a few hundred microseconds is as likely the host as the drive: a writer
+//! thread preempted by another task, or a core busy with network or timer softirqs, completes
+//! its write late without the SSD being slow. Every writer thread counts its voluntary and
+//! involuntary context switches while it runs (getrusage with RUSAGE_THREAD), and the host's
+//! context switches (`ctxt` of /proc/stat) and the per-core counters of /proc/softirqs are read
+//! before and after the point. The summary gets the totals; the manifest of
+//! `--samples-per-thread` gets the switches of every thread and the softirqs of every kind and
+//! core, to match a slow thread or a window of slow samples with the core that was interrupted.
+//! An involuntary switch is a preemption of the thread by the scheduler, so a count near zero
+//! rules the scheduler out. Hosts without these interfaces report zeros.
+
+use serde::Serialize;
+use serde_json::json;
+
+/// Context switches of a thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadSwitches {
+    pub voluntary: u64,
+    pub involuntary: u64, // preemptions
+}
+
+impl ThreadSwitches {
+    /// The context switches of the calling thread so far
+    #[cfg(target_os = "linux")]
+    pub fn current() -> ThreadSwitches {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) } != 0 {
+            return ThreadSwitches::default();
+        }
+        ThreadSwitches {
+            voluntary: usage.ru_nvcsw as u64,
+            involuntary: usage.ru_nivcsw as u64,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn current() -> ThreadSwitches {
+        ThreadSwitches::default()
+    }
+
+    /// The switches since `start`, a value of [`ThreadSwitches::current`] of the same thread
+    pub fn since(self, start: ThreadSwitches) -> ThreadSwitches {
+        ThreadSwitches {
+            voluntary: self.voluntary.saturating_sub(start.voluntary),
+            involuntary: self.involuntary.saturating_sub(start.involuntary),
+        }
+    }
+}
+
+/// The host counters of /proc/stat and /proc/softirqs
+#[derive(Debug, Clone, Default)]
+pub struct HostCounters {
+    context_switches: u64,
+    softirqs: Vec<(String, Vec<u64>)>, // per kind, e.g., BLOCK, the count of every core
+}
+
+impl HostCounters {
+    pub fn read() -> HostCounters {
+        let stat = std::fs::read_to_string("/proc/stat").unwrap_or_default();
+        let softirqs = std::fs::read_to_string("/proc/softirqs").unwrap_or_default();
+        HostCounters::parse(&stat, &softirqs)
+    }
+
+    /// The counters in the contents of /proc/stat and /proc/softirqs
+    pub fn parse(stat: &str, softirqs: &str) -> HostCounters {
+        let context_switches = stat
+            .lines()
+            .find_map(|line| line.strip_prefix("ctxt "))
+            .and_then(|count| count.trim().parse().ok())
+            .unwrap_or(0);
+        let softirqs = softirqs
+            .lines()
+            .skip(1) // the CPU names
+            .filter_map(|line| {
+                let (kind, counts) = line.split_once(':')?;
+                let counts = counts
+                    .split_whitespace()
+                    .map(|count| count.parse().unwrap_or(0))
+                    .collect();
+                Some((kind.trim().to_string(), counts))
+            })
+            .collect();
+        HostCounters {
+            context_switches,
+            softirqs,
+        }
+    }
+
+    /// The counts since `start`
+    pub fn since(&self, start: &HostCounters) -> HostCounters {
+        let softirqs = self
+            .softirqs
+            .iter()
+            .map(|(kind, counts)| {
+                let before = start
+                    .softirqs
+                    .iter()
+                    .find(|(k, _)| k == kind)
+                    .map_or(&[][..], |(_, counts)| counts.as_slice());
+                let counts = counts
+                    .iter()
+                    .enumerate()
+                    .map(|(core, count)| {
+                        count.saturating_sub(before.get(core).copied().unwrap_or(0))
+                    })
+                    .collect();
+                (kind.clone(), counts)
+            })
+            .collect();
+        HostCounters {
+            context_switches: self.context_switches.saturating_sub(start.context_switches),
+            softirqs,
+        }
+    }
+
+    /// The context switches and the softirqs of every kind, by core
+    pub fn to_json(&self) -> serde_json::Value {
+        let softirqs: serde_json::Map<String, serde_json::Value> = self
+            .softirqs
+            .iter()
+            .map(|(kind, counts)| (kind.clone(), json!(counts)))
+            .collect();
+        json!({
+            "context_switches": self.context_switches,
+            "softirqs": softirqs
+        })
+    }
+}
+
+/// Summary columns of the context switches and softirqs of a point
+#[derive(Serialize, Debug, Default)]
+pub struct SchedStatistics {
+    voluntary_switches: u64, // of all writer threads
+    involuntary_switches: u64,
+    max_thread_involuntary_switches: u64,
+    host_context_switches: u64,
+    host_softirqs: u64, // of all kinds and cores
+}
+
+impl SchedStatistics {
+    pub fn create(threads: &[ThreadSwitches], host: &HostCounters) -> SchedStatistics {
+        SchedStatistics {
+            voluntary_switches: threads.iter().map(|t| t.voluntary).sum(),
+            involuntary_switches: threads.iter().map(|t| t.involuntary).sum(),
+            max_thread_involuntary_switches: threads
+                .iter()
+                .map(|t| t.involuntary)
+                .max()
+                .unwrap_or(0),
+            host_context_switches: host.context_switches,
+            host_softirqs: host.softirqs.iter().flat_map(|(_, counts)| counts).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn host_counters_are_taken_per_core_and_kind() {
+        let softirqs = "                    CPU0       CPU1
+          HI:          1          0
+       BLOCK:        100         50
+     NET_RX:          7          3
+";
+        let before = HostCounters::parse("cpu  1 2 3\nctxt 1000\n", softirqs);
+        let after = HostCounters::parse(
+            "ctxt 1500\n",
+            &softirqs.replace("100         50", "180         51"),
+        );
+        let host = after.since(&before);
+        assert_eq!(
+            host.to_json().to_string(),
+            r#"{"context_switches":500,"softirqs":{"HI":[0,0],"BLOCK":[80,1],"NET_RX":[0,0]}}"#
+        );
+        let threads = [
+            ThreadSwitches {
+                voluntary: 10,
+                involuntary: 2,
+            },
+            ThreadSwitches {
+                voluntary: 5,
+                involuntary: 7,
+            },
+        ];
+        let statistics = serde_json::to_value(SchedStatistics::create(&threads, &host)).unwrap();
+        assert_eq!(
+            statistics.get("involuntary_switches").unwrap().as_u64(),
+            Some(9)
+        );
+        assert_eq!(
+            statistics
+                .get("max_thread_involuntary_switches")
+                .unwrap()
+                .as_u64(),
+            Some(7)
+        );
+        assert_eq!(statistics.get("host_softirqs").unwrap().as_u64(), Some(81));
+        let thread = ThreadSwitches::current();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(ThreadSwitches::current().since(thread).voluntary >= 1);
+    }
+
+    #[test]
+    fn unreadable_or_reset_counters_count_nothing() {
+        let missing = HostCounters::parse("", "");
+        assert_eq!(
+            missing.to_json().to_string(),
+            r#"{"context_switches":0,"softirqs":{}}"#
+        );
+        let garbled = HostCounters::parse("ctxt many\n", "CPU0\nBLOCK: x 5\nno colon\n");
+        assert_eq!(garbled.context_switches, 0);
+        assert_eq!(garbled.softirqs, [(String::from("BLOCK"), vec![0, 5])]);
+        // e.g., a core that went offline in between
+        let before = HostCounters::parse("ctxt 10\n", "CPU0\nBLOCK: 7 9\n");
+        let after = HostCounters::parse("ctxt 4\n", "CPU0\nBLOCK: 8\nTIMER: 3\n");
+        let host = after.since(&before);
+        assert_eq!(host.context_switches, 0);
+        assert_eq!(
+            host.softirqs,
+            [
+                (String::from("BLOCK"), vec![1]),
+                (String::from("TIMER"), vec![3])
+            ]
+        );
+        let statistics = SchedStatistics::create(&[], &host);
+        assert_eq!(
+            (
+                statistics.max_thread_involuntary_switches,
+                statistics.host_softirqs
+            ),
+            (0, 4)
+        );
+    }
+}
@@ -0,0 +1,586 @@
+//! Header handling for the append-only CSV result files.
+//!
+//! Result files are appended to across many invocations. If the column set of a row type changes
+//! between versions, appending blindly produces rows that no longer line up with the header. The
+//! helpers in here compare the header of an existing file with the header of the rows we are about
+//! to write and either refuse or migrate the existing file. Rows appended to a summary file are
+//! only comparable if the drive kept its firmware, so a firmware update since earlier rows of the
+//! same serial number is pointed out, too.
+//!
+//! `ssd-benchy schema` prints the columns and types of every result file of this version, read off
+//! the serde structs the rows are written from, so downstream ETL can check its expectations
+//! against the tool instead of against a sample file.
+
+use serde::{ser, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt::{self, Write},
+    fs::{self, File, OpenOptions},
+    path::Path,
+};
+
+/// Version of the summary row layout; bump whenever columns are added, removed, or reordered.
+/// Files written before the `schema_version` column existed are considered version 1.
+pub const SUMMARY_SCHEMA_VERSION: u32 = 47;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum SchemaMismatchPolicy {
+    /// Abort before the benchmark starts
+    Refuse,
+    /// Rewrite the existing file to the new header (a backup of the original is kept)
+    Migrate,
+}
+
+/// Returns the header columns `record` produces when serialized with the csv writer.
+pub fn header_of<T: Serialize>(record: &T) -> Vec<String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(vec![]);
+    wtr.serialize(record).expect("could not serialize header");
+    let data = wtr.into_inner().expect("could not flush header");
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(data.as_slice());
+    let mut header = csv::StringRecord::new();
+    rdr.read_record(&mut header).expect("could not read header");
+    header.iter().map(String::from).collect()
+}
+
+/// Returns the header of an existing csv file or `None` if the file does not exist or is empty.
+pub fn existing_header(path: &Path) -> Result<Option<Vec<String>>, String> {
+    if !path.exists() || fs::metadata(path).map(|m| m.len()).unwrap_or(0) == 0 {
+        return Ok(None);
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut header = csv::StringRecord::new();
+    rdr.read_record(&mut header)
+        .map_err(|e| format!("Failed to read header of {}: {}", path.display(), e))?;
+    Ok(Some(header.iter().map(String::from).collect()))
+}
+
+/// Opens `path` for appending rows; the header is only written if the file is new or empty.
+pub fn csv_appender(path: &Path) -> Result<csv::Writer<File>, String> {
+    let write_header = existing_header(path)?.is_none();
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    Ok(csv::WriterBuilder::new()
+        .has_headers(write_header)
+        .from_writer(file))
+}
+
+/// Checks that rows with header `expected` can be appended to the file at `path`.
+///
+/// Returns whether the header still has to be written, i.e., whether the file is new or empty.
+pub fn ensure_compatible(
+    path: &Path,
+    expected: &[String],
+    policy: SchemaMismatchPolicy,
+) -> Result<bool, String> {
+    let existing = match existing_header(path)? {
+        None => return Ok(true),
+        Some(existing) => existing,
+    };
+    if existing == expected {
+        return Ok(false);
+    }
+
+    let missing: Vec<_> = expected.iter().filter(|c| !existing.contains(c)).collect();
+    let dropped: Vec<_> = existing.iter().filter(|c| !expected.contains(c)).collect();
+    let description = format!(
+        "header of {} does not match the current schema (missing columns: {:?}, unknown columns: {:?}{})",
+        path.display(),
+        missing,
+        dropped,
+        if missing.is_empty() && dropped.is_empty() { ", different order" } else { "" }
+    );
+
+    match policy {
+        SchemaMismatchPolicy::Refuse => Err(format!(
+            "{}; use another file or pass --schema-mismatch migrate",
+            description
+        )),
+        SchemaMismatchPolicy::Migrate => {
+            let backup = migrate(path, &existing, expected)?;
+            println!("{}; migrated (original kept at {})", description, backup);
+            Ok(false)
+        }
+    }
+}
+
+/// Rewrites the file at `path` so that its columns match `expected`. Columns that do not exist in
+/// the old file are left empty, columns that no longer exist are dropped. The original file is
+/// renamed to a backup whose path is returned; the backup of an earlier migration is never
+/// replaced, as it may be the only copy of the original rows.
+fn migrate(path: &Path, existing: &[String], expected: &[String]) -> Result<String, String> {
+    let migrated = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let backup = format!("{}.{}.bak", path.display(), migrated);
+    if Path::new(&backup).exists() {
+        return Err(format!(
+            "Failed to back up {}: {} exists already",
+            path.display(),
+            backup
+        ));
+    }
+    fs::rename(path, &backup)
+        .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(&backup)
+        .map_err(|e| format!("Failed to open {}: {}", backup, e))?;
+    let mut wtr = csv::Writer::from_path(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+    let mapping: Vec<Option<usize>> = expected
+        .iter()
+        .map(|column| existing.iter().position(|c| c == column))
+        .collect();
+
+    wtr.write_record(expected).map_err(|e| e.to_string())?;
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", backup, e))?;
+        let row: Vec<&str> = mapping
+            .iter()
+            .map(|index| index.and_then(|i| record.get(i)).unwrap_or(""))
+            .collect();
+        wtr.write_record(&row).map_err(|e| e.to_string())?;
+    }
+    wtr.flush().map_err(|e| e.to_string())?;
+    Ok(backup)
+}
+
+/// The firmware revisions that earlier rows of the summary file at `path` recorded for the device
+/// with serial number `serial`, in the order they first appear; empty for a new file or one
+/// written before these columns existed
+pub fn firmware_revisions(path: &Path, serial: &str) -> Result<Vec<String>, String> {
+    let Some(header) = existing_header(path)? else {
+        return Ok(vec![]);
+    };
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let (Some(serial_column), Some(firmware_column)) =
+        (column("device_serial"), column("device_firmware_rev"))
+    else {
+        return Ok(vec![]);
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_path(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut revisions: Vec<String> = vec![];
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if record.get(serial_column) != Some(serial) {
+            continue;
+        }
+        match record.get(firmware_column) {
+            Some(firmware) if !firmware.is_empty() && !revisions.iter().any(|r| r == firmware) => {
+                revisions.push(firmware.to_string())
+            }
+            _ => {}
+        }
+    }
+    Ok(revisions)
+}
+
+/// A column of a result file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub kind: String, // the Rust type of the values, e.g., u64, f64, bool, or string
+    pub nullable: bool, // empty cells are valid
+}
+
+/// Returns the columns `record` produces when serialized with the csv writer, with their types.
+pub fn columns_of<T: Serialize>(record: &T) -> Vec<Column> {
+    let mut columns = Columns::default();
+    record
+        .serialize(&mut columns)
+        .expect("could not serialize header");
+    columns.columns
+}
+
+/// Collects the columns of a row; `field` and `type_name` describe the struct field that is
+/// serialized at the moment
+#[derive(Default)]
+struct Columns {
+    columns: Vec<Column>,
+    field: &'static str,
+    type_name: &'static str,
+}
+
+impl Columns {
+    fn push(&mut self, kind: &str) -> Result<(), Unsupported> {
+        if self.field.is_empty() {
+            return Err(Unsupported(format!("{} outside of a struct", kind)));
+        }
+        self.columns.push(Column {
+            name: self.field.to_string(),
+            kind: kind.to_string(),
+            nullable: self.type_name.starts_with("core::option::Option<"),
+        });
+        self.field = "";
+        Ok(())
+    }
+
+    /// The kind of a field that is `None`, from the name of its type
+    fn push_none(&mut self) -> Result<(), Unsupported> {
+        let inner = self
+            .type_name
+            .trim_start_matches("core::option::Option<")
+            .trim_end_matches('>');
+        let kind = match inner.rsplit("::").next().unwrap_or(inner) {
+            primitive @ ("bool" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8"
+            | "i16" | "i32" | "i64" | "i128" | "isize" | "f32" | "f64") => primitive,
+            // strings and unit-only enums, which are written as the name of the variant
+            _ => "string",
+        };
+        self.push(kind)
+    }
+}
+
+#[derive(Debug)]
+struct Unsupported(String);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not a csv column", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+impl ser::Error for Unsupported {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Unsupported(msg.to_string())
+    }
+}
+
+macro_rules! primitive {
+    ($($method:ident($t:ty) => $kind:expr),* $(,)?) => {
+        $(fn $method(self, _: $t) -> Result<(), Unsupported> {
+            self.push($kind)
+        })*
+    };
+}
+
+impl ser::Serializer for &mut Columns {
+    type Ok = ();
+    type Error = Unsupported;
+    type SerializeSeq = ser::Impossible<(), Unsupported>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = ser::Impossible<(), Unsupported>;
+    type SerializeTupleVariant = ser::Impossible<(), Unsupported>;
+    type SerializeMap = ser::Impossible<(), Unsupported>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = ser::Impossible<(), Unsupported>;
+
+    primitive!(
+        serialize_bool(bool) => "bool",
+        serialize_i8(i8) => "i8",
+        serialize_i16(i16) => "i16",
+        serialize_i32(i32) => "i32",
+        serialize_i64(i64) => "i64",
+        serialize_i128(i128) => "i128",
+        serialize_u8(u8) => "u8",
+        serialize_u16(u16) => "u16",
+        serialize_u32(u32) => "u32",
+        serialize_u64(u64) => "u64",
+        serialize_u128(u128) => "u128",
+        serialize_f32(f32) => "f32",
+        serialize_f64(f64) => "f64",
+        serialize_char(char) => "string",
+        serialize_str(&str) => "string",
+    );
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Unsupported> {
+        Err(Unsupported(String::from("bytes")))
+    }
+    fn serialize_none(self) -> Result<(), Unsupported> {
+        self.push_none()
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Unsupported> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Unsupported> {
+        self.push("string")
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Unsupported> {
+        self.push("string")
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Unsupported> {
+        self.push("string")
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Unsupported> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), Unsupported> {
+        Err(Unsupported(name.to_string()))
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Unsupported> {
+        Err(Unsupported(String::from("a sequence")))
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self, Unsupported> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Unsupported> {
+        Err(Unsupported(name.to_string()))
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Unsupported> {
+        Err(Unsupported(name.to_string()))
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Unsupported> {
+        Err(Unsupported(String::from("a map")))
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Unsupported> {
+        Ok(self)
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Unsupported> {
+        Err(Unsupported(name.to_string()))
+    }
+}
+
+impl ser::SerializeTuple for &mut Columns {
+    type Ok = ();
+    type Error = Unsupported;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Unsupported> {
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), Unsupported> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Columns {
+    type Ok = ();
+    type Error = Unsupported;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Unsupported> {
+        self.field = key;
+        self.type_name = std::any::type_name::<T>();
+        value.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), Unsupported> {
+        Ok(())
+    }
+}
+
+/// A result file of the benchmark or a subcommand, for `ssd-benchy schema`
+pub struct ResultFile {
+    pub command: &'static str, // the subcommand that writes it, ssd-benchy for the benchmark
+    pub option: &'static str,  // that names the file
+    pub columns: Vec<Column>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// One block of columns per file
+    #[default]
+    Text,
+    /// A JSON document to validate against
+    Json,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SchemaArgs {
+    #[clap(long, env = "SSD_BENCHY_FORMAT", value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+fn describe(files: &[ResultFile], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            let _ = writeln!(
+                out,
+                "ssd-benchy {}, summary schema version {}",
+                env!("CARGO_PKG_VERSION"),
+                SUMMARY_SCHEMA_VERSION
+            );
+            for file in files {
+                let _ = writeln!(out, "\n{} {}", file.command, file.option);
+                let width = file.columns.iter().map(|c| c.name.len()).max().unwrap_or(0);
+                for column in &file.columns {
+                    let nullable = if column.nullable { " (nullable)" } else { "" };
+                    let _ = writeln!(
+                        out,
+                        "  {:width$}  {}{}",
+                        column.name,
+                        column.kind,
+                        nullable,
+                        width = width
+                    );
+                }
+            }
+        }
+        Format::Json => {
+            let files: Vec<Value> = files
+                .iter()
+                .map(|file| {
+                    let columns: Vec<Value> = file
+                        .columns
+                        .iter()
+                        .map(|column| {
+                            json!({
+                                "name": column.name.as_str(),
+                                "type": column.kind.as_str(),
+                                "nullable": column.nullable
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "command": file.command,
+                        "option": file.option,
+                        "columns": columns
+                    })
+                })
+                .collect();
+            let document = json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "summary_schema_version": SUMMARY_SCHEMA_VERSION,
+                "files": files
+            });
+            let _ = writeln!(out, "{}", document);
+        }
+    }
+    out
+}
+
+pub fn run(args: &SchemaArgs, files: &[ResultFile]) {
+    print!("{}", describe(files, args.format));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn mismatched_headers_are_refused_or_migrated_with_a_backup() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-schema-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("summary.csv");
+        let expected = columns(&["a", "b", "c"]);
+        assert!(ensure_compatible(&path, &expected, SchemaMismatchPolicy::Refuse).unwrap());
+
+        fs::write(&path, "c,a,old\n3,1,x\n6,4,y\n").unwrap();
+        let error = ensure_compatible(&path, &expected, SchemaMismatchPolicy::Refuse).unwrap_err();
+        assert!(error.contains(r#"missing columns: ["b"]"#), "{}", error);
+        assert!(error.contains(r#"unknown columns: ["old"]"#), "{}", error);
+        assert_eq!(
+            existing_header(&path).unwrap(),
+            Some(columns(&["c", "a", "old"]))
+        );
+
+        assert!(!ensure_compatible(&path, &expected, SchemaMismatchPolicy::Migrate).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,b,c\n1,,3\n4,,6\n");
+        let backups = || {
+            fs::read_dir(&dir)
+                .unwrap()
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().ends_with(".bak"))
+                .map(|e| e.path())
+                .collect::<Vec<_>>()
+        };
+        let backup = backups().pop().unwrap();
+        assert_eq!(
+            fs::read_to_string(&backup).unwrap(),
+            "c,a,old\n3,1,x\n6,4,y\n"
+        );
+        assert!(!ensure_compatible(&path, &expected, SchemaMismatchPolicy::Migrate).unwrap());
+
+        // a second migration must not replace the backup of the first
+        fs::write(&path, "a,b\n7,8\n").unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for second in [now, now + 1] {
+            let taken = format!("{}.{}.bak", path.display(), second);
+            if !Path::new(&taken).exists() {
+                fs::write(&taken, "taken\n").unwrap();
+            }
+        }
+        let error = ensure_compatible(&path, &expected, SchemaMismatchPolicy::Migrate).unwrap_err();
+        assert!(error.contains("exists already"), "{}", error);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,b\n7,8\n");
+        assert_eq!(
+            fs::read_to_string(&backup).unwrap(),
+            "c,a,old\n3,1,x\n6,4,y\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn firmware_revisions_of_earlier_rows() {
+        let path =
+            std::env::temp_dir().join(format!("ssd-benchy-firmware-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "ssd_device,device_serial,device_firmware_rev\n\
+             nvme0n1,S1,1.0\n\
+             nvme1n1,S2,2.0\n\
+             nvme0n1,S1,1.1\n\
+             nvme0n1,S1,1.0\n",
+        )
+        .unwrap();
+        assert_eq!(firmware_revisions(&path, "S1").unwrap(), vec!["1.0", "1.1"]);
+        assert!(firmware_revisions(&path, "S3").unwrap().is_empty());
+        // files of older versions have no such columns
+        fs::write(&path, "ssd_device\nnvme0n1\n").unwrap();
+        assert!(firmware_revisions(&path, "S1").unwrap().is_empty());
+        // rows without a revision do not count
+        fs::write(&path, "device_serial,device_firmware_rev\nS1,\nS1,1.0\n").unwrap();
+        assert_eq!(firmware_revisions(&path, "S1").unwrap(), vec!["1.0"]);
+        fs::write(&path, "device_serial,device_firmware_rev\nS1,1.0,extra\n").unwrap();
+        assert!(firmware_revisions(&path, "S1")
+            .unwrap_err()
+            .starts_with("Failed to read"));
+        fs::remove_file(&path).unwrap();
+        assert!(firmware_revisions(&path, "S1").unwrap().is_empty());
+    }
+}
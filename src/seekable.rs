@@ -0,0 +1,350 @@
+//! Seekable compressed samples (`--samples-format zstd-seekable`).
+//!
+//! A capture of every write of a long run is tens of gigabytes of CSV, and computing the latency
+//! of one minute of it means scanning all of it. The seekable format cuts the rows into
+//! independent zstd frames of `--samples-frame-ms` each (by the `realtime_ns` of the samples) and
+//! ends the file with two skippable frames: a time index with the first and last timestamp and
+//! the number of rows of every frame, and the seek table of the zstd seekable format
+//! (`contrib/seekable_format` of zstd) with the compressed and decompressed size of every frame.
+//! `ssd-benchy analyze --samples-file` reads both from the end of the file and decompresses only
+//! the frames that overlap the requested window. The first frame holds the CSV header, so
+//! `zstd -d` of the whole file is the plain samples file.
+//!
+//! Compression needs a build with `--features zstd`, which compiles the bundled libzstd of the
+//! zstd crate.
+
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+/// Magic of the skippable frame with the time index (any of 0x184D2A50..=0x184D2A5F is skipped)
+const INDEX_MAGIC: u32 = 0x184D2A5D;
+/// Magic of the skippable frame with the seek table and of its footer
+const SEEK_TABLE_MAGIC: u32 = 0x184D2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+/// Bytes of a seek table entry (without checksums) and of the footer
+const SEEK_ENTRY_LEN: u64 = 8;
+const FOOTER_LEN: u64 = 9;
+/// Bytes of a time index entry: first and last timestamp and rows
+const INDEX_ENTRY_LEN: u64 = 24;
+const SKIPPABLE_HEADER_LEN: u64 = 8;
+/// A frame is cut early once its rows reach this size
+const MAX_FRAME_BYTES: usize = 16 << 20;
+#[cfg(feature = "zstd")]
+const COMPRESSION_LEVEL: i32 = 3;
+
+pub const SUPPORTED: bool = cfg!(feature = "zstd");
+
+#[cfg(feature = "zstd")]
+fn compress(data: &[u8]) -> Result<Vec<u8>, String> {
+    zstd::bulk::compress(data, COMPRESSION_LEVEL)
+        .map_err(|e| format!("Failed to compress samples: {}", e))
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(frame: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    // `len` is the decompressed size of the seek table, a larger frame is corrupt
+    zstd::bulk::decompress(frame, len).map_err(|e| format!("Failed to decompress samples: {}", e))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_: &[u8]) -> Result<Vec<u8>, String> {
+    Err(String::from(
+        "zstd-seekable samples require a build with --features zstd",
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_: &[u8], _: usize) -> Result<Vec<u8>, String> {
+    Err(String::from(
+        "zstd-seekable samples require a build with --features zstd",
+    ))
+}
+
+/// A frame of the file, as the time index and the seek table describe it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Frame {
+    pub offset: u64, // of the compressed frame in the file
+    compressed: u32,
+    decompressed: u32,
+    pub first_ns: u64, // smallest timestamp of its rows
+    pub last_ns: u64,
+    pub rows: u64, // 0 for the header frame
+}
+
+fn row_writer() -> csv::Writer<Vec<u8>> {
+    csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![])
+}
+
+/// Writes rows into frames of `interval` of their timestamps
+pub struct SeekableWriter {
+    file: BufWriter<File>,
+    path: String,
+    frames: Vec<Frame>,
+    rows: csv::Writer<Vec<u8>>,
+    current: Frame, // timestamps and rows of the frame being filled
+    interval_ns: u64,
+}
+
+impl SeekableWriter {
+    /// Creates the file at `path`; its first frame is the CSV `header`
+    pub fn create(
+        path: &str,
+        header: &[String],
+        interval: Duration,
+    ) -> Result<SeekableWriter, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut writer = SeekableWriter {
+            file: BufWriter::new(file),
+            path: path.to_string(),
+            frames: vec![],
+            rows: row_writer(),
+            current: Frame::default(),
+            interval_ns: crate::stats::nanos(interval).max(1),
+        };
+        writer
+            .rows
+            .write_record(header)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        writer.cut()?;
+        Ok(writer)
+    }
+
+    /// Appends a row with timestamp `time_ns`; the rows must come in roughly increasing time
+    pub fn push<T: Serialize>(&mut self, row: &T, time_ns: u64) -> Result<(), String> {
+        if self.current.rows > 0
+            && (time_ns >= self.current.first_ns + self.interval_ns
+                || self.rows.get_ref().len() >= MAX_FRAME_BYTES)
+        {
+            self.cut()?;
+        }
+        self.rows
+            .serialize(row)
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))?;
+        let current = &mut self.current;
+        current.first_ns = if current.rows == 0 {
+            time_ns
+        } else {
+            current.first_ns.min(time_ns)
+        };
+        current.last_ns = current.last_ns.max(time_ns);
+        current.rows += 1;
+        Ok(())
+    }
+
+    /// Compresses the buffered rows into a frame of their own
+    fn cut(&mut self) -> Result<(), String> {
+        let data = std::mem::replace(&mut self.rows, row_writer())
+            .into_inner()
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))?;
+        let frame = compress(&data)?;
+        let offset = self
+            .frames
+            .last()
+            .map_or(0, |f| f.offset + f.compressed as u64);
+        self.frames.push(Frame {
+            offset,
+            compressed: frame.len() as u32,
+            decompressed: data.len() as u32,
+            ..self.current
+        });
+        self.current = Frame::default();
+        self.file
+            .write_all(&frame)
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))
+    }
+
+    /// Writes the last frame, the time index, and the seek table
+    pub fn finish(mut self) -> Result<(), String> {
+        if self.current.rows > 0 {
+            self.cut()?;
+        }
+        let mut index = vec![];
+        let mut seek_table = vec![];
+        for frame in &self.frames {
+            index.extend(frame.first_ns.to_le_bytes());
+            index.extend(frame.last_ns.to_le_bytes());
+            index.extend(frame.rows.to_le_bytes());
+            seek_table.extend(frame.compressed.to_le_bytes());
+            seek_table.extend(frame.decompressed.to_le_bytes());
+        }
+        seek_table.extend((self.frames.len() as u32).to_le_bytes());
+        seek_table.push(0); // descriptor: no checksums
+        seek_table.extend(SEEKABLE_MAGIC.to_le_bytes());
+        let mut tail = vec![];
+        for (magic, content) in [(INDEX_MAGIC, index), (SEEK_TABLE_MAGIC, seek_table)] {
+            tail.extend(magic.to_le_bytes());
+            tail.extend((content.len() as u32).to_le_bytes());
+            tail.extend(content);
+        }
+        self.file
+            .write_all(&tail)
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))
+    }
+}
+
+/// Reads the frames of a seekable samples file
+pub struct SeekableReader {
+    file: File,
+    path: String,
+    pub frames: Vec<Frame>,
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+impl SeekableReader {
+    /// Opens the file at `path` and reads its time index and seek table
+    pub fn open(path: &str) -> Result<SeekableReader, String> {
+        let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let invalid = |what: &str| format!("Failed to read {}: {}", path, what);
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?
+            .len();
+        let mut read_at = |offset: u64, n: u64| -> Result<Vec<u8>, String> {
+            let mut bytes = vec![0u8; n as usize];
+            file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.read_exact(&mut bytes))
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            Ok(bytes)
+        };
+        if len < FOOTER_LEN {
+            return Err(invalid("not a seekable samples file"));
+        }
+        let footer = read_at(len - FOOTER_LEN, FOOTER_LEN)?;
+        if u32_at(&footer, 5) != SEEKABLE_MAGIC || footer[4] != 0 {
+            return Err(invalid("not a seekable samples file"));
+        }
+        let frames = u32_at(&footer, 0) as u64;
+        let seek_table_len = SKIPPABLE_HEADER_LEN + frames * SEEK_ENTRY_LEN + FOOTER_LEN;
+        let index_len = SKIPPABLE_HEADER_LEN + frames * INDEX_ENTRY_LEN;
+        if len < seek_table_len + index_len {
+            return Err(invalid("truncated seek table"));
+        }
+        let seek_table = read_at(len - seek_table_len, seek_table_len)?;
+        let index = read_at(len - seek_table_len - index_len, index_len)?;
+        if u32_at(&seek_table, 0) != SEEK_TABLE_MAGIC || u32_at(&index, 0) != INDEX_MAGIC {
+            return Err(invalid("no time index; written by another tool?"));
+        }
+
+        let mut parsed = vec![];
+        let mut offset = 0;
+        for i in 0..frames as usize {
+            let seek = SKIPPABLE_HEADER_LEN as usize + i * SEEK_ENTRY_LEN as usize;
+            let entry = SKIPPABLE_HEADER_LEN as usize + i * INDEX_ENTRY_LEN as usize;
+            let frame = Frame {
+                offset,
+                compressed: u32_at(&seek_table, seek),
+                decompressed: u32_at(&seek_table, seek + 4),
+                first_ns: u64_at(&index, entry),
+                last_ns: u64_at(&index, entry + 8),
+                rows: u64_at(&index, entry + 16),
+            };
+            offset += frame.compressed as u64;
+            parsed.push(frame);
+        }
+        if offset > len - seek_table_len - index_len {
+            return Err(invalid("frames beyond the end of the file"));
+        }
+        Ok(SeekableReader {
+            file,
+            path: path.to_string(),
+            frames: parsed,
+        })
+    }
+
+    /// The decompressed CSV of frame `index`; frame 0 is the header
+    pub fn read_frame(&mut self, index: usize) -> Result<Vec<u8>, String> {
+        let frame = self.frames[index];
+        let mut compressed = vec![0u8; frame.compressed as usize];
+        self.file
+            .seek(SeekFrom::Start(frame.offset))
+            .and_then(|_| self.file.read_exact(&mut compressed))
+            .map_err(|e| format!("Failed to read {}: {}", self.path, e))?;
+        decompress(&compressed, frame.decompressed as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn seekable_samples_are_indexed_by_time() {
+        use crate::{schema, Sample};
+        let path = std::env::temp_dir().join(format!("ssd-benchy-{}.zst", std::process::id()));
+        let path = path.to_str().unwrap();
+        let header = schema::header_of(&Sample::default());
+        let mut writer = SeekableWriter::create(path, &header, Duration::from_nanos(100)).unwrap();
+        for realtime_ns in (0..1000).step_by(10) {
+            let sample = Sample {
+                latency: realtime_ns,
+                realtime_ns,
+                ..Sample::default()
+            };
+            writer.push(&sample, realtime_ns).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = SeekableReader::open(path).unwrap();
+        // the header and one frame per 100ns
+        assert_eq!(reader.frames.len(), 11);
+        assert_eq!(reader.frames[0].rows, 0);
+        let frame = reader.frames[3];
+        assert_eq!((frame.first_ns, frame.last_ns, frame.rows), (200, 290, 10));
+        let mut rows = reader.read_frame(0).unwrap();
+        rows.extend(reader.read_frame(3).unwrap());
+        let latencies: Vec<u64> = csv::Reader::from_reader(rows.as_slice())
+            .records()
+            .map(|r| r.unwrap()[0].parse().unwrap())
+            .collect();
+        assert_eq!(latencies, (200..300).step_by(10).collect::<Vec<_>>());
+
+        // e.g., a copy that was cut off
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(SeekableReader::open(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        let path =
+            std::env::temp_dir().join(format!("ssd-benchy-plain-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let error = |path| SeekableReader::open(path).err().unwrap();
+        assert!(error(path).starts_with("Failed to open"));
+        for contents in ["", "latency,op\n1000,write\n2000,write\n"] {
+            std::fs::write(path, contents).unwrap();
+            assert!(error(path).ends_with("not a seekable samples file"));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn writing_needs_the_zstd_feature() {
+        let path =
+            std::env::temp_dir().join(format!("ssd-benchy-nozstd-{}.zst", std::process::id()));
+        let path = path.to_str().unwrap();
+        let error =
+            SeekableWriter::create(path, &[String::from("latency")], Duration::from_secs(1))
+                .err()
+                .unwrap();
+        assert!(error.contains("--features zstd"), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+}
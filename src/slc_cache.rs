@@ -0,0 +1,326 @@
+//! `ssd-benchy slc-cache`: size of the pseudo-SLC write cache and performance behind it.
+//!
+//! Consumer and QLC drives absorb writes in a part of the flash run in SLC mode and fold them into
+//! the dense cells later. A long sequential write at full bandwidth runs fast until this cache is
+//! exhausted and then falls off a cliff. The run is cut into windows; the cliff is the first
+//! window from which on the throughput stays below `--cliff-fraction` of the initial throughput
+//! (the median of the first windows) for several windows, and everything written before it is
+//! the cache size estimate. The device should be idle (or trimmed) beforehand so that the cache starts empty.
+
+use crate::{
+    buffer::AlignedBuffer,
+    engine::Engine,
+    partition, schema,
+    stats::{self, PercentileMethod},
+};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+/// Number of windows the initial throughput is the median of
+const BASELINE_WINDOWS: usize = 5;
+/// The throughput must stay low for this many windows to count as the cliff, not a hiccup
+const CLIFF_CONFIRM_WINDOWS: usize = 3;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SlcCacheArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Size of every write in bytes
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 1048576)]
+    block_size: usize,
+
+    /// Number of threads, each writing its part of the used capacity sequentially
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTH", default_value_t = 4)]
+    queue_depth: u64,
+
+    /// Fraction of the SSD that is written at most
+    #[clap(long, env = "SSD_BENCHY_CAPACITY_FRACTION", default_value_t = 1.0)]
+    capacity_fraction: f64,
+
+    /// Upper bound of the runtime in seconds; the run also ends once the used capacity is written
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 1800)]
+    runtime_seconds: u64,
+
+    /// Length of the windows throughput and latency are computed over
+    #[clap(long, env = "SSD_BENCHY_WINDOW_MS", default_value_t = 1000)]
+    window_ms: u64,
+
+    /// The cliff is where the throughput drops below this fraction of the initial throughput
+    #[clap(long, env = "SSD_BENCHY_CLIFF_FRACTION", default_value_t = 0.5)]
+    cliff_fraction: f64,
+
+    /// How the reported percentiles are computed, as for the summary of a run
+    #[clap(long, env = "SSD_BENCHY_PERCENTILE_METHOD", value_enum, default_value_t = PercentileMethod::Nearest)]
+    percentile_method: PercentileMethod,
+
+    /// Result file, one row per run
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("slc_cache.csv"))]
+    output_file: String,
+
+    /// Throughput and latency of every window
+    #[clap(long, env = "SSD_BENCHY_TIMELINE_FILE", default_value_t = String::from("slc_cache_timeline.csv"))]
+    timeline_file: String,
+}
+
+/// Completion time since the start of the run and latency of one write, in nanoseconds
+type Completion = (u64, u64);
+
+/// One row of the timeline file
+#[derive(Serialize, Debug, Default, Clone)]
+struct Window {
+    uuid: u128,
+    window: usize,
+    start_seconds: f64,
+    written_bytes: u64, // before the end of the window
+    mb_per_second: f64,
+    p50th: u64,
+    p99th: u64,
+    max: u64,
+}
+
+/// One row of the result file
+#[derive(Serialize, Debug, Default)]
+struct SlcCacheResult {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    block_size: usize,
+    queue_depth: u64,
+    elapsed_seconds: f64,
+    written_bytes: u64,
+    cliff_found: bool,
+    cliff_seconds: f64,
+    cache_bytes: u64, // written before the cliff; all bytes if no cliff was found
+    cached_mb_per_second: f64,
+    cached_p99th: u64,
+    steady_mb_per_second: f64, // after the cliff
+    steady_p50th: u64,
+    steady_p99th: u64,
+    steady_p999th: u64,
+    steady_max: u64,
+    percentile_method: PercentileMethod,
+}
+
+/// Writes the used capacity sequentially until it is full or the runtime is over
+fn write_sequentially(args: &SlcCacheArgs, blocks: u64) -> (Vec<Completion>, Duration) {
+    let begin = Instant::now();
+    let end_time = begin + Duration::from_secs(args.runtime_seconds);
+    let mut completions: Vec<Completion> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..args.queue_depth)
+            .map(|thread_id| {
+                scope.spawn(move || {
+                    let ssd_fd = crate::open_ssd(&args.ssd_device);
+                    let buffer = AlignedBuffer::new(args.block_size, 0x5a);
+                    let mut completions = vec![];
+                    for block in partition(thread_id, args.queue_depth, blocks) {
+                        if Instant::now() >= end_time {
+                            break;
+                        }
+                        let io_begin = Instant::now();
+                        let res = ssd_fd
+                            .write_at(&buffer, block * args.block_size as u64)
+                            .expect("could not issue io");
+                        assert_eq!(res, args.block_size);
+                        completions.push((
+                            crate::stats::nanos(begin.elapsed()),
+                            crate::stats::nanos(io_begin.elapsed()),
+                        ));
+                    }
+                    completions
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    completions.sort_unstable();
+    (completions, begin.elapsed())
+}
+
+fn windows(
+    uuid: u128,
+    completions: &[Completion],
+    window: Duration,
+    block_size: usize,
+    method: PercentileMethod,
+) -> Vec<Window> {
+    let window_ns = window.as_nanos() as u64;
+    let count = completions.last().map_or(0, |c| c.0 / window_ns + 1) as usize;
+    let mut latencies = vec![vec![]; count];
+    for (at, latency) in completions {
+        latencies[(at / window_ns) as usize].push(*latency);
+    }
+    let mut written_bytes = 0;
+    latencies
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut latencies)| {
+            latencies.sort_unstable();
+            let bytes = (latencies.len() * block_size) as u64;
+            written_bytes += bytes;
+            Window {
+                uuid,
+                window: index,
+                start_seconds: (index as u64 * window_ns) as f64 / 1e9,
+                written_bytes,
+                mb_per_second: bytes as f64 / 1e6 / window.as_secs_f64(),
+                p50th: stats::percentile(&latencies, 50.0, method),
+                p99th: stats::percentile(&latencies, 99.0, method),
+                max: latencies.last().copied().unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+/// First window that starts `CLIFF_CONFIRM_WINDOWS` windows below `fraction` of the initial
+/// throughput
+fn find_cliff(windows: &[Window], fraction: f64) -> Option<usize> {
+    let mut initial: Vec<f64> = windows
+        .iter()
+        .take(BASELINE_WINDOWS)
+        .map(|w| w.mb_per_second)
+        .collect();
+    initial.sort_by(|a, b| a.total_cmp(b));
+    let baseline = *initial.get(initial.len() / 2)?;
+    // the last window is usually cut short by the end of the run
+    let full = &windows[..windows.len().saturating_sub(1)];
+    (BASELINE_WINDOWS.min(full.len())..full.len()).find(|&start| {
+        let confirm = &full[start..(start + CLIFF_CONFIRM_WINDOWS).min(full.len())];
+        confirm.len() == CLIFF_CONFIRM_WINDOWS
+            && confirm
+                .iter()
+                .all(|w| w.mb_per_second < baseline * fraction)
+    })
+}
+
+/// Throughput and sorted latencies of the completions within `from..to` nanoseconds
+fn phase(completions: &[Completion], from: u64, to: u64, block_size: usize) -> (f64, Vec<u64>) {
+    let mut latencies: Vec<u64> = completions
+        .iter()
+        .filter(|(at, _)| (from..to).contains(at))
+        .map(|(_, latency)| *latency)
+        .collect();
+    latencies.sort_unstable();
+    let seconds = (to - from) as f64 / 1e9;
+    let mb_per_second = if seconds > 0.0 {
+        (latencies.len() * block_size) as f64 / 1e6 / seconds
+    } else {
+        0.0
+    };
+    (mb_per_second, latencies)
+}
+
+/// The result and the bandwidth timeline, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![
+        schema::ResultFile {
+            command: "slc-cache",
+            option: "--output-file",
+            columns: schema::columns_of(&SlcCacheResult::default()),
+        },
+        schema::ResultFile {
+            command: "slc-cache",
+            option: "--timeline-file",
+            columns: schema::columns_of(&Window::default()),
+        },
+    ]
+}
+
+pub fn run(args: &SlcCacheArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    let blocks = (capacity as f64 * args.capacity_fraction) as u64 / args.block_size as u64;
+    let output = Path::new(&args.output_file);
+    let timeline = Path::new(&args.timeline_file);
+    for (path, header) in [
+        (output, schema::header_of(&SlcCacheResult::default())),
+        (timeline, schema::header_of(&Window::default())),
+    ] {
+        schema::ensure_compatible(path, &header, schema::SchemaMismatchPolicy::Refuse)
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+    }
+    let uuid = Uuid::new_v4().as_u128();
+    let start_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("");
+
+    println!(
+        "writing up to {:.1} GiB sequentially for at most {}s ...",
+        (blocks * args.block_size as u64) as f64 / (1u64 << 30) as f64,
+        args.runtime_seconds
+    );
+    let (completions, elapsed) = write_sequentially(args, blocks);
+    let window = Duration::from_millis(args.window_ms);
+    let windows = windows(
+        uuid,
+        &completions,
+        window,
+        args.block_size,
+        args.percentile_method,
+    );
+    let cliff = find_cliff(&windows, args.cliff_fraction);
+
+    let end_ns = elapsed.as_nanos() as u64;
+    let cliff_ns = cliff.map_or(end_ns, |w| w as u64 * window.as_nanos() as u64);
+    let (cached_mb_per_second, cached) = phase(&completions, 0, cliff_ns, args.block_size);
+    let (steady_mb_per_second, steady) = phase(&completions, cliff_ns, end_ns, args.block_size);
+    let percentile = |sorted: &[u64], p| stats::percentile(sorted, p, args.percentile_method);
+    let result = SlcCacheResult {
+        uuid,
+        start_time: start_time.as_secs(),
+        hostname: gethostname().into_string().unwrap(),
+        ssd_device: args.ssd_device.clone(),
+        block_size: args.block_size,
+        queue_depth: args.queue_depth,
+        elapsed_seconds: elapsed.as_secs_f64(),
+        written_bytes: (completions.len() * args.block_size) as u64,
+        cliff_found: cliff.is_some(),
+        cliff_seconds: cliff_ns as f64 / 1e9,
+        cache_bytes: (cached.len() * args.block_size) as u64,
+        cached_mb_per_second,
+        cached_p99th: percentile(&cached, 99.0),
+        steady_mb_per_second,
+        steady_p50th: percentile(&steady, 50.0),
+        steady_p99th: percentile(&steady, 99.0),
+        steady_p999th: percentile(&steady, 99.9),
+        steady_max: steady.last().copied().unwrap_or(0),
+        percentile_method: args.percentile_method,
+    };
+
+    match cliff {
+        Some(_) => println!(
+            "cliff after {:.1}s: cache ~{:.1} GiB at {:.0} MB/s, then {:.0} MB/s (p99 {:.1}us)",
+            result.cliff_seconds,
+            result.cache_bytes as f64 / (1u64 << 30) as f64,
+            result.cached_mb_per_second,
+            result.steady_mb_per_second,
+            result.steady_p99th as f64 / 1e3
+        ),
+        None => println!(
+            "no cliff within {:.1} GiB written at {:.0} MB/s; the cache is larger or there is none",
+            result.written_bytes as f64 / (1u64 << 30) as f64,
+            result.cached_mb_per_second
+        ),
+    }
+
+    let mut wtr = schema::csv_appender(timeline).unwrap();
+    for window in &windows {
+        wtr.serialize(window).unwrap();
+    }
+    wtr.flush().unwrap();
+    let mut wtr = schema::csv_appender(output).unwrap();
+    wtr.serialize(&result).unwrap();
+    wtr.flush().unwrap();
+}
@@ -0,0 +1,111 @@
+//! Multi-day runs with rotating result files (`--soak-hours`).
+//!
+//! Endurance and drift studies run a drive for days at the same utilization. As a single point,
+//! such a run writes one samples file of hundreds of gigabytes and one summary row at the very
+//! end, which says nothing about how the latencies changed and is lost entirely if the run is
+//! aborted on the second day. With `--soak-hours 72` the configured utilization points are
+//! repeated in segments of `--soak-rotate-minutes` (by default an hour) until the soak time is
+//! covered; every segment replaces `--runtime-seconds`, so every point of every segment writes a
+//! summary row of its own, numbered by its `soak_segment`. The samples and the host statistics
+//! of a segment go to files of their own, `samples_file.segment0003.csv` for the fourth segment,
+//! which the per-thread and seekable samples files are named after as well. When a segment
+//! starts, the files of the segment `--soak-keep-segments` before it are deleted, so that a run
+//! keeps the most recent segments only; the summary rows are never pruned.
+
+use std::{fs, path::Path};
+
+/// The number of segments of `rotate_minutes` that cover `hours`, at least one
+pub fn segments(hours: f64, rotate_minutes: u64) -> u64 {
+    ((hours * 60.0 / rotate_minutes.max(1) as f64).ceil() as u64).max(1)
+}
+
+/// `<path without extension>.segment<segment>.<extension>`
+pub fn segment_path(path: &str, segment: u64) -> String {
+    let file = Path::new(path);
+    let stem = file.with_extension("");
+    match file.extension() {
+        Some(extension) => format!(
+            "{}.segment{:04}.{}",
+            stem.display(),
+            segment,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}.segment{:04}", stem.display(), segment),
+    }
+}
+
+/// Deletes the files of `segment` derived from the result file at `path`; returns the number of
+/// deleted files
+pub fn prune(path: &str, segment: u64) -> Result<usize, String> {
+    let rotated = Path::new(path).with_extension("");
+    let prefix = format!(
+        "{}.segment{:04}",
+        rotated
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+        segment
+    );
+    let dir = match rotated.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    let mut pruned = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let derived = name
+            .strip_prefix(prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'));
+        if derived {
+            fs::remove_file(entry.path())
+                .map_err(|e| format!("Failed to prune {}: {}", entry.path().display(), e))?;
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soak_segments_rotate_and_prune_their_files() {
+        assert_eq!(segments(72.0, 60), 72);
+        assert_eq!(segments(0.5, 60), 1);
+        assert_eq!(segments(1.1, 30), 3);
+        assert_eq!(segments(0.0, 60), 1);
+        assert_eq!(segments(1.0, 0), 60);
+        assert_eq!(segment_path("samples", 2), "samples.segment0002");
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-soak-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let samples_file = dir.join("samples.csv").to_string_lossy().into_owned();
+        let rotated = segment_path(&samples_file, 3);
+        assert!(rotated.ends_with("samples.segment0003.csv"), "{}", rotated);
+        let per_thread = crate::sample_writer::thread_samples_path(&rotated, 7, 0);
+        let kept = [
+            samples_file.clone(),
+            segment_path(&samples_file, 4),
+            segment_path(&samples_file, 30),
+        ];
+        for path in [&rotated, &per_thread].into_iter().chain(&kept) {
+            fs::write(path, "").unwrap();
+        }
+        assert_eq!(prune(&samples_file, 3).unwrap(), 2);
+        assert!(kept.iter().all(|path| Path::new(path).exists()));
+        assert_eq!(prune(&samples_file, 3).unwrap(), 0);
+        let missing_dir = dir.join("missing/samples.csv");
+        assert_eq!(prune(&missing_dir.to_string_lossy(), 3).unwrap(), 0);
+        // the directory of the result file is a file
+        let below_a_file = Path::new(&samples_file).join("samples.csv");
+        assert!(prune(&below_a_file.to_string_lossy(), 3)
+            .unwrap_err()
+            .starts_with("Failed to read"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+//! SPDK engine: NVMe from user space, without the kernel block layer.
+//!
+//! Built with `--features spdk` against an installed SPDK (found with pkg-config, see build.rs).
+//! The controller must be bound to a user-space driver first (`spdk/scripts/setup.sh`), and
+//! `--ssd-device` names it by PCI address (`0000:01:00.0`) or by a full transport id
+//! (`trtype:PCIe traddr:0000:01:00.0`). IO goes to namespace 1.
+//!
+//! Every writer thread owns an IO queue pair and keeps one IO in flight, polling the queue pair
+//! for its completion; there are no interrupts and no syscalls. SPDK only transfers from DMA-able
+//! memory, so every IO is copied through a buffer from `spdk_zmalloc`, a memcpy of one block.
+
+use crate::engine::Engine;
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_char, c_int, c_void, CString},
+    io, ptr,
+    sync::Once,
+};
+
+const SPDK_ENV_SOCKET_ID_ANY: c_int = -1;
+const SPDK_MALLOC_DMA: u32 = 0x01;
+const NAMESPACE_ID: u32 = 1;
+const SPDK_NVME_DSM_ATTR_DEALLOCATE: u32 = 0x4;
+/// Generously sized storage for SPDK structs whose layout differs between releases; both are
+/// initialized by SPDK itself and only read back by SPDK
+const OPAQUE_LEN: usize = 4096;
+
+type Opaque = [u64; OPAQUE_LEN / 8];
+
+#[repr(C)]
+struct Completion {
+    cdw0: u32,
+    cdw1: u32,
+    sqhd: u16,
+    sqid: u16,
+    cid: u16,
+    status: u16, // phase:1 sc:8 sct:3 crd:2 more:1 dnr:1
+}
+
+#[repr(C)]
+struct DsmRange {
+    attributes: u32,
+    length: u32, // in LBAs
+    starting_lba: u64,
+}
+
+type CompletionCallback = extern "C" fn(ctx: *mut c_void, cpl: *const Completion);
+
+// linked by build.rs
+extern "C" {
+    fn spdk_env_opts_init(opts: *mut Opaque);
+    fn spdk_env_init(opts: *const Opaque) -> c_int;
+    fn spdk_nvme_transport_id_parse(trid: *mut Opaque, s: *const c_char) -> c_int;
+    fn spdk_nvme_connect(trid: *const Opaque, opts: *const c_void, opts_size: usize)
+        -> *mut c_void;
+    fn spdk_nvme_detach(ctrlr: *mut c_void) -> c_int;
+    fn spdk_nvme_ctrlr_get_ns(ctrlr: *mut c_void, nsid: u32) -> *mut c_void;
+    fn spdk_nvme_ns_get_sector_size(ns: *mut c_void) -> u32;
+    fn spdk_nvme_ns_get_size(ns: *mut c_void) -> u64;
+    fn spdk_nvme_ctrlr_alloc_io_qpair(
+        ctrlr: *mut c_void,
+        opts: *const c_void,
+        opts_size: usize,
+    ) -> *mut c_void;
+    fn spdk_nvme_ctrlr_free_io_qpair(qpair: *mut c_void) -> c_int;
+    fn spdk_nvme_ns_cmd_write(
+        ns: *mut c_void,
+        qpair: *mut c_void,
+        payload: *mut c_void,
+        lba: u64,
+        lba_count: u32,
+        cb: CompletionCallback,
+        cb_arg: *mut c_void,
+        io_flags: u32,
+    ) -> c_int;
+    fn spdk_nvme_ns_cmd_read(
+        ns: *mut c_void,
+        qpair: *mut c_void,
+        payload: *mut c_void,
+        lba: u64,
+        lba_count: u32,
+        cb: CompletionCallback,
+        cb_arg: *mut c_void,
+        io_flags: u32,
+    ) -> c_int;
+    fn spdk_nvme_ns_cmd_flush(
+        ns: *mut c_void,
+        qpair: *mut c_void,
+        cb: CompletionCallback,
+        cb_arg: *mut c_void,
+    ) -> c_int;
+    fn spdk_nvme_ns_cmd_dataset_management(
+        ns: *mut c_void,
+        qpair: *mut c_void,
+        type_: u32,
+        ranges: *const DsmRange,
+        num_ranges: u16,
+        cb: CompletionCallback,
+        cb_arg: *mut c_void,
+    ) -> c_int;
+    fn spdk_nvme_qpair_process_completions(qpair: *mut c_void, max_completions: u32) -> i32;
+    fn spdk_zmalloc(
+        size: usize,
+        align: usize,
+        phys_addr: *mut u64,
+        socket_id: c_int,
+        flags: u32,
+    ) -> *mut c_void;
+    fn spdk_free(buf: *mut c_void);
+}
+
+/// `trtype:PCIe traddr:0000:01:00.0` for `0000:01:00.0`; transport ids are taken as they are
+fn transport_id(ssd_device: &str) -> String {
+    if ssd_device.contains("trtype") {
+        ssd_device.to_string()
+    } else {
+        format!("trtype:PCIe traddr:{}", ssd_device)
+    }
+}
+
+/// The SPDK environment (hugepages, DPDK) is set up once per process
+fn init_env() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let name = c"ssd-benchy";
+        let mut opts: Opaque = [0; OPAQUE_LEN / 8];
+        unsafe {
+            spdk_env_opts_init(&mut opts);
+            // `name` is the first member of struct spdk_env_opts in every release
+            *(opts.as_mut_ptr() as *mut *const c_char) = name.as_ptr();
+            if spdk_env_init(&opts) < 0 {
+                crate::outcome::exit(
+                    crate::outcome::Outcome::DeviceError,
+                    "could not initialize the SPDK environment (hugepages set up?)",
+                );
+            }
+        }
+    });
+}
+
+/// An attached NVMe controller and the namespace the benchmark writes to
+pub struct Controller {
+    name: String,
+    ctrlr: *mut c_void,
+    ns: *mut c_void,
+    sector_size: u64,
+}
+
+// the controller is thread safe; queue pairs are not and stay with their `SpdkEngine`
+unsafe impl Send for Controller {}
+unsafe impl Sync for Controller {}
+
+impl Controller {
+    pub fn attach(ssd_device: &str) -> Controller {
+        init_env();
+        let trid_string = CString::new(transport_id(ssd_device)).unwrap();
+        let mut trid: Opaque = [0; OPAQUE_LEN / 8];
+        if unsafe { spdk_nvme_transport_id_parse(&mut trid, trid_string.as_ptr()) } != 0 {
+            crate::outcome::exit(
+                crate::outcome::Outcome::ConfigError,
+                &format!("invalid SPDK transport id: {}", ssd_device),
+            );
+        }
+        let ctrlr = unsafe { spdk_nvme_connect(&trid, ptr::null(), 0) };
+        if ctrlr.is_null() {
+            crate::outcome::exit(
+                crate::outcome::Outcome::DeviceError,
+                &format!(
+                    "could not attach to {}; is it bound to vfio-pci or uio (spdk/scripts/setup.sh)?",
+                    ssd_device
+                ),
+            );
+        }
+        let ns = unsafe { spdk_nvme_ctrlr_get_ns(ctrlr, NAMESPACE_ID) };
+        if ns.is_null() {
+            crate::outcome::exit(
+                crate::outcome::Outcome::DeviceError,
+                &format!("{} has no namespace {}", ssd_device, NAMESPACE_ID),
+            );
+        }
+        Controller {
+            name: ssd_device.to_string(),
+            ctrlr,
+            ns,
+            sector_size: unsafe { spdk_nvme_ns_get_sector_size(ns) } as u64,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn capacity(&self) -> u64 {
+        unsafe { spdk_nvme_ns_get_size(self.ns) }
+    }
+
+    /// An engine with its own queue pair, for one thread
+    pub fn engine(&self) -> SpdkEngine {
+        let qpair = unsafe { spdk_nvme_ctrlr_alloc_io_qpair(self.ctrlr, ptr::null(), 0) };
+        assert!(!qpair.is_null(), "could not allocate an SPDK queue pair");
+        SpdkEngine {
+            ns: self.ns,
+            qpair,
+            sector_size: self.sector_size,
+            dma: RefCell::new(DmaBuffer::new(crate::BLOCK_SIZE)),
+        }
+    }
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        unsafe { spdk_nvme_detach(self.ctrlr) };
+    }
+}
+
+struct DmaBuffer {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl DmaBuffer {
+    fn new(len: usize) -> DmaBuffer {
+        let ptr = unsafe {
+            spdk_zmalloc(
+                len,
+                crate::BLOCK_SIZE,
+                ptr::null_mut(),
+                SPDK_ENV_SOCKET_ID_ANY,
+                SPDK_MALLOC_DMA,
+            )
+        };
+        assert!(!ptr.is_null(), "could not allocate SPDK DMA memory");
+        DmaBuffer { ptr, len }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        unsafe { spdk_free(self.ptr) };
+    }
+}
+
+/// State shared with the completion callback
+struct Pending {
+    done: Cell<bool>,
+    status: Cell<u16>,
+}
+
+extern "C" fn complete(ctx: *mut c_void, cpl: *const Completion) {
+    let pending = unsafe { &*(ctx as *const Pending) };
+    pending.status.set(unsafe { (*cpl).status });
+    pending.done.set(true);
+}
+
+pub struct SpdkEngine {
+    ns: *mut c_void,
+    qpair: *mut c_void,
+    sector_size: u64,
+    dma: RefCell<DmaBuffer>, // grown to the largest transfer seen
+}
+
+// the queue pair is only used by the thread that owns the engine
+unsafe impl Send for SpdkEngine {}
+
+impl SpdkEngine {
+    /// The DMA buffer, grown to at least `len`
+    fn dma(&self, len: usize) -> std::cell::RefMut<'_, DmaBuffer> {
+        let mut dma = self.dma.borrow_mut();
+        if dma.len < len {
+            *dma = DmaBuffer::new(len);
+        }
+        dma
+    }
+
+    /// Submits with `submit` and polls the queue pair until the command completes
+    fn submit_and_wait(
+        &self,
+        submit: impl FnOnce(CompletionCallback, *mut c_void) -> c_int,
+    ) -> io::Result<()> {
+        let pending = Pending {
+            done: Cell::new(false),
+            status: Cell::new(0),
+        };
+        let rc = submit(complete, &pending as *const Pending as *mut c_void);
+        if rc < 0 {
+            return Err(io::Error::from_raw_os_error(-rc));
+        }
+        while !pending.done.get() {
+            let rc = unsafe { spdk_nvme_qpair_process_completions(self.qpair, 0) };
+            if rc < 0 {
+                return Err(io::Error::from_raw_os_error(-rc));
+            }
+        }
+        let status = pending.status.get();
+        let (code, code_type) = ((status >> 1) & 0xff, (status >> 9) & 0x7);
+        if code != 0 || code_type != 0 {
+            return Err(io::Error::other(format!(
+                "NVMe command failed: status code type {}, status code {:#x}",
+                code_type, code
+            )));
+        }
+        Ok(())
+    }
+
+    fn lbas(&self, len: usize, offset: u64) -> (u64, u32) {
+        (
+            offset / self.sector_size,
+            (len as u64 / self.sector_size) as u32,
+        )
+    }
+}
+
+impl Engine for SpdkEngine {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let dma = self.dma(buf.len());
+        unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), dma.ptr as *mut u8, buf.len()) };
+        let (lba, count) = self.lbas(buf.len(), offset);
+        self.submit_and_wait(|cb, arg| unsafe {
+            spdk_nvme_ns_cmd_write(self.ns, self.qpair, dma.ptr, lba, count, cb, arg, 0)
+        })?;
+        Ok(buf.len())
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let dma = self.dma(buf.len());
+        let (lba, count) = self.lbas(buf.len(), offset);
+        self.submit_and_wait(|cb, arg| unsafe {
+            spdk_nvme_ns_cmd_read(self.ns, self.qpair, dma.ptr, lba, count, cb, arg, 0)
+        })?;
+        unsafe { ptr::copy_nonoverlapping(dma.ptr as *const u8, buf.as_mut_ptr(), buf.len()) };
+        Ok(buf.len())
+    }
+
+    fn discard(&self, offset: u64, len: u64) -> io::Result<()> {
+        let (lba, count) = self.lbas(len as usize, offset);
+        let range = DsmRange {
+            attributes: 0,
+            length: count,
+            starting_lba: lba,
+        };
+        self.submit_and_wait(|cb, arg| unsafe {
+            spdk_nvme_ns_cmd_dataset_management(
+                self.ns,
+                self.qpair,
+                SPDK_NVME_DSM_ATTR_DEALLOCATE,
+                &range,
+                1,
+                cb,
+                arg,
+            )
+        })
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.submit_and_wait(|cb, arg| unsafe {
+            spdk_nvme_ns_cmd_flush(self.ns, self.qpair, cb, arg)
+        })
+    }
+}
+
+impl Drop for SpdkEngine {
+    fn drop(&mut self) {
+        unsafe { spdk_nvme_ctrlr_free_io_qpair(self.qpair) };
+    }
+}
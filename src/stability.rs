@@ -0,0 +1,109 @@
+//! Stability of the tail latency over the course of a utilization point.
+//!
+//! A p99 over the whole point does not tell a drive that is consistently mediocre from one that is
+//! mostly fast and occasionally terrible. With `--stability-window-seconds` every operation is also
+//! recorded in the histogram of its window, the p99 of every complete window is computed, and the
+//! summary reports how much it moves: the minimum, maximum, mean, and standard deviation across
+//! windows, and their coefficient of variation as a single stability score (0 is perfectly
+//! stable).
+
+use crate::histogram::Histogram;
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Histograms of all threads, merged per window
+pub type Windows = Arc<Mutex<Vec<Histogram>>>;
+
+/// Records the operations of one thread into the window they completed in
+pub struct WindowRecorder {
+    window: Duration,
+    windows: Windows,
+    current: usize,
+    histogram: Histogram,
+}
+
+impl WindowRecorder {
+    pub fn new(window: Duration, windows: Windows) -> Self {
+        WindowRecorder {
+            window,
+            windows,
+            current: 0,
+            histogram: Histogram::new(),
+        }
+    }
+
+    /// `elapsed` is the time since the thread started its operations
+    pub fn record(&mut self, elapsed: Duration, latency: u64) {
+        let window = (elapsed.as_nanos() / self.window.as_nanos()) as usize;
+        if window != self.current {
+            self.flush();
+            self.current = window;
+        }
+        self.histogram.record(latency);
+    }
+
+    fn flush(&mut self) {
+        if self.histogram.count() == 0 {
+            return;
+        }
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() <= self.current {
+            windows.resize_with(self.current + 1, Histogram::new);
+        }
+        windows[self.current].merge(&self.histogram);
+        self.histogram = Histogram::new();
+    }
+
+    pub fn finish(mut self) {
+        self.flush();
+    }
+}
+
+/// Summary columns of --stability-window-seconds; all zero without it
+#[derive(Serialize, Debug, Default)]
+pub struct StabilityStatistics {
+    stability_window_seconds: u64,
+    stability_windows: u64, // complete windows with operations
+    p99_window_min: u64,
+    p99_window_max: u64,
+    p99_window_mean: f64,
+    p99_window_stddev: f64,
+    stability_score: f64, // coefficient of variation of the window p99s
+}
+
+impl StabilityStatistics {
+    /// Only windows that lie completely within `runtime` count; the last one is usually cut short
+    pub fn create_from_windows(windows: &Windows, window: Duration, runtime: Duration) -> Self {
+        let complete = (runtime.as_nanos() / window.as_nanos()) as usize;
+        let p99s: Vec<u64> = windows
+            .lock()
+            .unwrap()
+            .iter()
+            .take(complete)
+            .filter(|h| h.count() > 0)
+            .map(|h| h.percentile(99.0))
+            .collect();
+        if p99s.is_empty() {
+            return StabilityStatistics {
+                stability_window_seconds: window.as_secs(),
+                ..Default::default()
+            };
+        }
+        let n = p99s.len() as f64;
+        let mean = p99s.iter().sum::<u64>() as f64 / n;
+        let variance = p99s.iter().map(|&p| (p as f64 - mean).powi(2)).sum::<f64>() / n;
+        let stddev = variance.sqrt();
+        StabilityStatistics {
+            stability_window_seconds: window.as_secs(),
+            stability_windows: p99s.len() as u64,
+            p99_window_min: *p99s.iter().min().unwrap(),
+            p99_window_max: *p99s.iter().max().unwrap(),
+            p99_window_mean: mean,
+            p99_window_stddev: stddev,
+            stability_score: if mean > 0.0 { stddev / mean } else { 0.0 },
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Staging of the result files in a tmpfs during the run (`--staging-dir`).
+//!
+//! On a host with a single disk besides the one under test, or with the results on a network
+//! file system, writing the samples and the summary while the writer threads run competes with
+//! the measurement for CPU, page cache writeback, and, on shared disks, the device itself. With
+//! `--staging-dir /dev/shm/ssd-benchy` every result file is written below a directory of the run
+//! in that directory instead and copied to where its flag says when the run ends, however it
+//! ends, after the last utilization point. A result file that exists already is copied into the
+//! staging directory first, so appending to it and checking its header work as without staging.
+//! Only the result files and the files named after them (e.g., `samples.<uuid>.t0.csv` of
+//! `samples.csv`) are copied back, so what an earlier run left in the staging directory never
+//! replaces a result file.
+//!
+//! The crash acknowledgments and checkpoints are never staged, as they are only of use if they
+//! survive a crash of the host, and neither is the `--result-json`, which is written after the
+//! result files are in place.
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// A staging directory of the run
+struct Staged {
+    dir: PathBuf,
+    final_dir: PathBuf,   // its files are copied to
+    names: Vec<OsString>, // of the result files staged in it
+}
+
+impl Staged {
+    /// Whether the file `name` is a result file or named after one
+    fn owns(&self, name: &OsString) -> bool {
+        self.names.iter().any(|staged| {
+            let stem = Path::new(staged).with_extension("");
+            staged == name
+                || name
+                    .to_string_lossy()
+                    .starts_with(&format!("{}.", stem.to_string_lossy()))
+        })
+    }
+}
+
+static STAGED: Mutex<Vec<Staged>> = Mutex::new(Vec::new());
+
+/// Creates the directory of this run below `dir`; it must not exist yet
+fn create_run_dir(dir: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let run_dir = Path::new(dir).join(format!("run-{}-{}", std::process::id(), started));
+    fs::create_dir(&run_dir)
+        .map_err(|e| format!("Failed to create {}: {}", run_dir.display(), e))?;
+    Ok(run_dir)
+}
+
+/// Moves the result files at `paths` below a new directory of the run in `dir`, rewriting the
+/// paths. Files of the same directory share a subdirectory, so the files derived from them (e.g.,
+/// the per-thread samples files) end up next to them again.
+pub fn stage(dir: &str, paths: Vec<&mut String>) -> Result<(), String> {
+    let mut staged = STAGED.lock().unwrap();
+    let run_dir = create_run_dir(dir)?;
+    for path in paths {
+        let file = Path::new(path.as_str());
+        let name = file
+            .file_name()
+            .ok_or_else(|| format!("Failed to stage {}: not a file name", path))?
+            .to_owned();
+        let parent = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let final_dir = parent
+            .canonicalize()
+            .map_err(|e| format!("Failed to stage {}: {}", path, e))?;
+        let index = match staged.iter().position(|s| s.final_dir == final_dir) {
+            Some(index) => index,
+            None => {
+                let staging_dir = run_dir.join(staged.len().to_string());
+                fs::create_dir(&staging_dir)
+                    .map_err(|e| format!("Failed to create {}: {}", staging_dir.display(), e))?;
+                staged.push(Staged {
+                    dir: staging_dir,
+                    final_dir: final_dir.clone(),
+                    names: vec![],
+                });
+                staged.len() - 1
+            }
+        };
+        staged[index].names.push(name.clone());
+        let staged_file = staged[index].dir.join(&name);
+        let final_file = final_dir.join(&name);
+        if final_file.exists() {
+            fs::copy(&final_file, &staged_file).map_err(|e| {
+                format!(
+                    "Failed to copy {} to {}: {}",
+                    final_file.display(),
+                    staged_file.display(),
+                    e
+                )
+            })?;
+        }
+        *path = staged_file.to_string_lossy().into_owned();
+    }
+    if !staged.is_empty() {
+        crate::outcome::on_exit(copy_back);
+    }
+    Ok(())
+}
+
+/// Where a file written below the staging directory ends up after the run
+pub fn final_path(path: &str) -> String {
+    let staged = STAGED.lock().unwrap();
+    let file = Path::new(path);
+    staged
+        .iter()
+        .find(|s| file.parent() == Some(s.dir.as_path()))
+        .map_or_else(
+            || path.to_owned(),
+            |s| {
+                s.final_dir
+                    .join(file.file_name().unwrap())
+                    .to_string_lossy()
+                    .into_owned()
+            },
+        )
+}
+
+/// Copies the staged files and those named after them to their final directories and removes
+/// them from the staging directory; a file that cannot be copied is left where it is
+pub fn copy_back() {
+    let staged = std::mem::take(&mut *STAGED.lock().unwrap_or_else(|e| e.into_inner()));
+    let mut copied = 0;
+    for staging in &staged {
+        let entries = match fs::read_dir(&staging.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", staging.dir.display(), e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            if !staging.owns(&entry.file_name()) {
+                continue;
+            }
+            let staged_file = entry.path();
+            let final_file = staging.final_dir.join(entry.file_name());
+            match fs::copy(&staged_file, &final_file) {
+                Ok(_) => {
+                    copied += 1;
+                    let _ = fs::remove_file(&staged_file);
+                }
+                Err(e) => eprintln!(
+                    "Failed to copy {} to {}, it is left in the staging directory: {}",
+                    staged_file.display(),
+                    final_file.display(),
+                    e
+                ),
+            }
+        }
+        let _ = fs::remove_dir(&staging.dir);
+    }
+    if let Some(run_dir) = staged.first().and_then(|s| s.dir.parent()) {
+        let _ = fs::remove_dir(run_dir);
+    }
+    println!("copied {} staged result files", copied);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_writer;
+
+    #[test]
+    fn staged_results_are_copied_to_their_paths() {
+        let root = std::env::temp_dir().join(format!("ssd-benchy-staging-{}", std::process::id()));
+        let results = root.join("results");
+        fs::create_dir_all(&results).unwrap();
+        fs::write(results.join("summary.csv"), "a\n1\n").unwrap();
+        let mut summary_file = results.join("summary.csv").to_string_lossy().into_owned();
+        let mut samples_file = results.join("samples.csv").to_string_lossy().into_owned();
+        let staging_dir = root.join("staging").to_string_lossy().into_owned();
+        // a file without a name cannot be staged
+        assert!(stage(&staging_dir, vec![&mut String::from("/")])
+            .unwrap_err()
+            .contains("not a file name"));
+        stage(&staging_dir, vec![&mut summary_file, &mut samples_file]).unwrap();
+        assert!(summary_file.starts_with(&staging_dir));
+        // appends go to the copy of the existing file
+        assert_eq!(fs::read_to_string(&summary_file).unwrap(), "a\n1\n");
+        let mut summary = fs::OpenOptions::new()
+            .append(true)
+            .open(&summary_file)
+            .unwrap();
+        std::io::Write::write_all(&mut summary, b"2\n").unwrap();
+        let per_thread = sample_writer::thread_samples_path(&samples_file, 1, 0);
+        fs::write(&per_thread, "latency\n").unwrap();
+        let final_per_thread = final_path(&per_thread);
+        assert!(final_per_thread.starts_with(results.canonicalize().unwrap().to_str().unwrap()));
+        // left behind by someone else, it must not replace anything
+        let stray = Path::new(&summary_file).with_file_name("other.csv");
+        fs::write(&stray, "stray\n").unwrap();
+        fs::write(results.join("other.csv"), "mine\n").unwrap();
+
+        copy_back();
+        assert_eq!(
+            fs::read_to_string(results.join("summary.csv")).unwrap(),
+            "a\n1\n2\n"
+        );
+        assert!(Path::new(&final_per_thread).exists());
+        assert!(!Path::new(&summary_file).exists());
+        assert_eq!(
+            fs::read_to_string(results.join("other.csv")).unwrap(),
+            "mine\n"
+        );
+        assert!(stray.exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
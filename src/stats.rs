@@ -0,0 +1,321 @@
+//! Partitioning of the device between threads and the percentiles of the summary.
+//!
+//! Percentiles are computed from the sorted latency samples with one of three methods. Nearest rank
+//! (the default) reports the smallest sample that at least p% of the samples do not exceed, the
+//! sample at rank ceil(p/100 * N); it is always an observed latency, but with few samples the high
+//! percentiles all collapse onto the maximum, e.g., the p99 and the p99.9 of 100 samples. Linear
+//! interpolation places p between the samples at ranks floor and ceil of 1 + p/100 * (N - 1), as
+//! NumPy's default does, so it moves smoothly with p, but it reports latencies nobody observed.
+//! Histogram estimates (`hdr`) take the nearest rank from the log-linear histogram of the samples
+//! and report the highest value of its bucket, less than 1% above the sample, which is what
+//! the histogram files and checkpoints of a run contain. The three agree for large N. The samples
+//! of `--sampling-method tail` stand for different numbers of writes, their `weight`;
+//! [`weighted_percentile`] takes the nearest rank of their weighted ECDF.
+//!
+//! Latencies are nanoseconds in a u64 everywhere, which covers 584 years; [`nanos`] saturates
+//! instead of truncating the u128 of [`Duration::as_nanos`].
+
+use crate::histogram::Histogram;
+use serde::Serialize;
+use std::{ops::Range, time::Duration};
+
+/// `duration` in nanoseconds, `u64::MAX` if it does not fit
+pub fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// The `id`th of `participants` equal parts of `0..n`; the last one also gets the remainder,
+/// and all but the last are empty if `n` is smaller than `participants`
+pub fn partition(id: u64, participants: u64, n: u64) -> Range<u64> {
+    let block_size = n / participants;
+    let begin = id * block_size;
+    let mut end = begin + block_size;
+    if id == participants - 1 {
+        end = n;
+    }
+    begin..end
+}
+
+#[derive(clap::ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PercentileMethod {
+    /// The sample at rank ceil(p/100 * N)
+    #[default]
+    Nearest,
+    /// Interpolated between the samples around rank 1 + p/100 * (N - 1)
+    Linear,
+    /// The nearest rank in the log-linear histogram of the samples, the highest value of its bucket
+    Hdr,
+}
+
+fn histogram_of(latencies: &[u64]) -> Histogram {
+    let mut histogram = Histogram::new();
+    for &latency in latencies {
+        histogram.record(latency);
+    }
+    histogram
+}
+
+/// Ranks are computed in floating point; p/100 * N is an ulp above the integer for, e.g., p = 1.1
+/// and N = 3000, which would move nearest rank one sample up
+pub(crate) const RANK_EPSILON: f64 = 1e-9;
+
+/// The `percentile` (0 to 100) of the sorted `latencies`, 0 without any; `Hdr` builds a histogram
+/// for every call
+pub fn percentile(latencies: &[u64], percentile: f64, method: PercentileMethod) -> u64 {
+    let len = latencies.len();
+    if len == 0 {
+        return 0;
+    }
+    match method {
+        PercentileMethod::Nearest => {
+            let rank = ((len as f64) * percentile / 100.0 - RANK_EPSILON).ceil() as usize;
+            latencies[rank.clamp(1, len) - 1]
+        }
+        PercentileMethod::Linear => {
+            let position = (len - 1) as f64 * percentile / 100.0;
+            let lower = (position + RANK_EPSILON).floor() as usize;
+            let upper = (lower + 1).min(len - 1);
+            let fraction = (position - lower as f64).max(0.0);
+            // only the difference goes through f64, large latencies would lose precision
+            let (low, high) = (latencies[lower], latencies[upper]);
+            low + ((high - low) as f64 * fraction).round() as u64
+        }
+        PercentileMethod::Hdr => histogram_of(latencies).percentile(percentile),
+    }
+}
+
+/// The `percentile` (0 to 100) of the sorted `latencies` whose samples stand for `weights`
+/// operations each, as those of `--sampling-method tail` do: the nearest rank of the weighted
+/// ECDF, the smallest sample that the samples of at least p% of the weight do not exceed; 0
+/// without any
+pub fn weighted_percentile(latencies: &[u64], weights: &[f64], percentile: f64) -> u64 {
+    let Some(&max) = latencies.last() else {
+        return 0;
+    };
+    let total: f64 = weights.iter().sum();
+    // relative to the total, the weights need not be integers
+    let rank = total * (percentile / 100.0 - RANK_EPSILON);
+    let mut cumulative = 0.0;
+    for (&latency, &weight) in latencies.iter().zip(weights) {
+        cumulative += weight;
+        if cumulative >= rank {
+            return latency;
+        }
+    }
+    max
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SummaryStatistics {
+    pub min: u64,
+    pub max: u64,
+    pub p50th: u64,
+    pub p75th: u64,
+    pub p90th: u64,
+    pub p99th: u64,
+    pub p999th: u64,
+}
+
+impl SummaryStatistics {
+    pub fn create_from_latencies(latencies: &mut [u64]) -> SummaryStatistics {
+        SummaryStatistics::create_with_method(latencies, PercentileMethod::Nearest)
+    }
+
+    /// Sorts `latencies`; panics without samples
+    pub fn create_with_method(
+        latencies: &mut [u64],
+        method: PercentileMethod,
+    ) -> SummaryStatistics {
+        latencies.sort_unstable();
+        let min = *latencies.first().expect("no samples collected");
+        let max = *latencies.last().expect("no samples collected");
+
+        if method == PercentileMethod::Hdr {
+            let histogram = histogram_of(latencies);
+            let percentile = |p| histogram.percentile(p);
+            return SummaryStatistics {
+                min,
+                max,
+                p50th: percentile(50.0),
+                p75th: percentile(75.0),
+                p90th: percentile(90.0),
+                p99th: percentile(99.0),
+                p999th: percentile(99.9),
+            };
+        }
+        SummaryStatistics {
+            min,
+            max,
+            p50th: percentile(latencies, 50.0, method),
+            p75th: percentile(latencies, 75.0, method),
+            p90th: percentile(latencies, 90.0, method),
+            p99th: percentile(latencies, 99.0, method),
+            p999th: percentile(latencies, 99.9, method),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const METHODS: [PercentileMethod; 3] = [
+        PercentileMethod::Nearest,
+        PercentileMethod::Linear,
+        PercentileMethod::Hdr,
+    ];
+
+    /// Samples in any order, with many duplicates or hardly any
+    fn samples(len: std::ops::RangeInclusive<usize>) -> impl Strategy<Value = Vec<u64>> {
+        prop_oneof![Just(10u64), Just(1_000_000u64)]
+            .prop_flat_map(move |max| prop::collection::vec(0..max, len.clone()))
+    }
+
+    fn sorted_samples(len: std::ops::RangeInclusive<usize>) -> impl Strategy<Value = Vec<u64>> {
+        samples(len).prop_map(|mut samples| {
+            samples.sort_unstable();
+            samples
+        })
+    }
+
+    /// Up to 64 participants of a range that is as often smaller than them as not
+    fn participants_and_range() -> impl Strategy<Value = (u64, u64)> {
+        (1u64..=64).prop_flat_map(|participants| {
+            (
+                Just(participants),
+                prop_oneof![0..participants, 0..100_000u64],
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn partitions_cover_the_range_in_order((participants, n) in participants_and_range()) {
+            let mut next = 0;
+            for id in 0..participants {
+                let range = partition(id, participants, n);
+                prop_assert_eq!(
+                    range.start, next,
+                    "gap or overlap at {}/{} of {}",
+                    id, participants, n
+                );
+                if id < participants - 1 {
+                    prop_assert_eq!(range.end - range.start, n / participants);
+                } else {
+                    prop_assert_eq!(range.end - range.start, n / participants + n % participants);
+                }
+                next = range.end;
+            }
+            prop_assert_eq!(next, n);
+        }
+
+        #[test]
+        fn nearest_rank_is_the_smallest_sample_covering_p_percent(
+            samples in sorted_samples(1..=2000),
+            random_per_mille in 0u64..=1000,
+        ) {
+            let len = samples.len();
+            // per mille, so the expected rank is exact integer math
+            for per_mille in [0, 1, 500, 750, 900, 990, 999, 1000, random_per_mille] {
+                let p = per_mille as f64 / 10.0;
+                let value = percentile(&samples, p, PercentileMethod::Nearest);
+                let rank = ((len as u64 * per_mille).div_ceil(1000) as usize).max(1);
+                prop_assert_eq!(value, samples[rank - 1], "p{} of {} samples", p, len);
+            }
+        }
+
+        #[test]
+        fn percentiles_are_ordered_and_within_the_samples(samples in samples(1..=500)) {
+            for method in METHODS {
+                let s = SummaryStatistics::create_with_method(&mut samples.clone(), method);
+                prop_assert!(s.min <= s.p50th && s.p50th <= s.p75th && s.p75th <= s.p90th);
+                prop_assert!(s.p90th <= s.p99th && s.p99th <= s.p999th && s.p999th <= s.max);
+            }
+        }
+
+        #[test]
+        fn a_single_sample_is_every_percentile(sample in any::<u64>()) {
+            for method in METHODS {
+                let s = SummaryStatistics::create_with_method(&mut [sample], method);
+                prop_assert_eq!([s.min, s.p50th, s.p99th, s.p999th, s.max], [sample; 5]);
+            }
+        }
+
+        #[test]
+        fn linear_interpolation_lies_between_the_neighbouring_samples(
+            samples in sorted_samples(1..=200),
+            p in 0.0..=100.0f64,
+        ) {
+            let len = samples.len();
+            let value = percentile(&samples, p, PercentileMethod::Linear);
+            let position = (len - 1) as f64 * p / 100.0;
+            let (lower, upper) = (
+                position.floor() as usize,
+                (position.ceil() as usize).min(len - 1),
+            );
+            prop_assert!(samples[lower] <= value && value <= samples[upper]);
+        }
+
+        #[test]
+        fn equal_weights_are_the_nearest_rank(
+            samples in sorted_samples(1..=500),
+            weight in prop_oneof![Just(1.0), Just(0.25), Just(500.0)],
+            per_mille in 0u64..=1000,
+        ) {
+            let p = per_mille as f64 / 10.0;
+            prop_assert_eq!(
+                weighted_percentile(&samples, &vec![weight; samples.len()], p),
+                percentile(&samples, p, PercentileMethod::Nearest)
+            );
+        }
+
+        #[test]
+        fn a_weight_counts_as_that_many_samples(
+            weighted in prop::collection::vec((0u64..1000, 1u64..=5), 1..=200),
+            per_mille in 0u64..=1000,
+        ) {
+            let mut weighted = weighted;
+            weighted.sort_unstable();
+            let latencies: Vec<u64> = weighted.iter().map(|&(latency, _)| latency).collect();
+            let weights: Vec<f64> = weighted.iter().map(|&(_, weight)| weight as f64).collect();
+            let repeated: Vec<u64> = weighted
+                .iter()
+                .flat_map(|&(latency, weight)| std::iter::repeat_n(latency, weight as usize))
+                .collect();
+            let p = per_mille as f64 / 10.0;
+            prop_assert_eq!(
+                weighted_percentile(&latencies, &weights, p),
+                percentile(&repeated, p, PercentileMethod::Nearest)
+            );
+        }
+    }
+
+    #[test]
+    fn linear_interpolation_of_two_samples() {
+        assert_eq!(percentile(&[10, 20], 50.0, PercentileMethod::Linear), 15);
+        assert_eq!(percentile(&[10, 20], 100.0, PercentileMethod::Linear), 20);
+    }
+
+    #[test]
+    fn no_samples_have_percentiles_of_0() {
+        assert_eq!(weighted_percentile(&[], &[], 99.0), 0);
+        for method in METHODS {
+            assert_eq!(percentile(&[], 99.0, method), 0);
+        }
+    }
+
+    #[test]
+    fn nanos_saturate() {
+        assert_eq!(nanos(Duration::from_micros(3)), 3000);
+        assert_eq!(nanos(Duration::from_nanos(u64::MAX)), u64::MAX);
+        assert_eq!(nanos(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "no samples collected")]
+    fn no_samples_panic() {
+        SummaryStatistics::create_from_latencies(&mut []);
+    }
+}
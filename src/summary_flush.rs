@@ -0,0 +1,241 @@
+//! Provisional summary rows of the running point (`--summary-flush-seconds`).
+//!
+//! The summary row of a point is written when the point ends, so an external monitor sees
+//! nothing of an hour-long point until it is over, and a run that crashes in it leaves no row at
+//! all. With `--summary-flush-seconds 300` every writer thread hands its latency histogram (all
+//! writes, not only the sampled ones) and counters to a background thread every five minutes,
+//! which writes a summary row of the point so far, with `partial` set: the percentiles of the
+//! writes so far, the achieved rate and the IO errors, and zeros for what is only known at the
+//! end. Every provisional row replaces the one before it, and the final row replaces the last
+//! one, so a completed point has exactly one row. A row is replaced by truncating the file to its
+//! length when the point started, so no other process must append to the summary file during a
+//! run. `ssd-benchy aggregate` skips partial rows, as they are not comparable to complete runs.
+
+use crate::{histogram::Histogram, schema, stats::SummaryStatistics};
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// What the writer threads of the running point have done so far
+#[derive(Clone, Default)]
+pub struct Progress {
+    pub latency: Histogram,
+    pub operations: u64,
+    pub io_errors: u64,
+    pub elapsed: Duration, // without pauses
+}
+
+impl Progress {
+    /// The latency statistics of the writes so far
+    pub fn statistics(&self) -> SummaryStatistics {
+        let percentile = |p| self.latency.percentile(p);
+        SummaryStatistics {
+            min: self.latency.min(),
+            max: self.latency.max(),
+            p50th: percentile(50.0),
+            p75th: percentile(75.0),
+            p90th: percentile(90.0),
+            p99th: percentile(99.0),
+            p999th: percentile(99.9),
+        }
+    }
+}
+
+type WriteRow = Box<dyn Fn(&Progress, &mut csv::Writer<File>) -> csv::Result<()> + Send>;
+
+struct Running {
+    offset: u64, // length of the summary file before the rows of the point
+    threads: Vec<Progress>,
+    write_row: WriteRow,
+}
+
+pub struct Flusher {
+    path: String,
+    running: Mutex<Option<Running>>,
+    stop: Arc<AtomicBool>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Removes the rows appended to the file at `path` after its first `offset` bytes
+fn truncate(path: &str, offset: u64) -> Result<(), String> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(offset))
+        .map_err(|e| format!("Failed to remove the provisional row of {}: {}", path, e))
+}
+
+impl Flusher {
+    pub fn spawn(path: &str, interval: Duration) -> &'static Flusher {
+        let flusher: &'static Flusher = Box::leak(Box::new(Flusher {
+            path: path.to_string(),
+            running: Mutex::new(None),
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: Mutex::new(None),
+        }));
+        let stop = flusher.stop.clone();
+        let handle = std::thread::spawn(move || {
+            let mut failed = false;
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::park_timeout(interval);
+                if let Err(e) = flusher.write() {
+                    if !failed {
+                        println!("warning: {}; later failures are not reported", e);
+                        failed = true;
+                    }
+                }
+            }
+        });
+        *flusher.handle.lock().unwrap() = Some(handle);
+        flusher
+    }
+
+    /// `row` makes the provisional summary row of the point from its progress
+    pub fn start_point<R: Serialize>(
+        &self,
+        threads: u64,
+        row: impl Fn(&Progress) -> R + Send + 'static,
+    ) {
+        let offset = std::fs::metadata(&self.path).map_or(0, |m| m.len());
+        *self.running.lock().unwrap() = Some(Running {
+            offset,
+            threads: vec![Progress::default(); threads as usize],
+            write_row: Box::new(move |progress, wtr| wtr.serialize(row(progress))),
+        });
+    }
+
+    /// Hands over the progress of writer thread `thread`
+    pub fn publish(
+        &self,
+        thread: u64,
+        latency: &Histogram,
+        operations: u64,
+        io_errors: u64,
+        elapsed: Duration,
+    ) {
+        if let Some(running) = self.running.lock().unwrap().as_mut() {
+            running.threads[thread as usize] = Progress {
+                latency: latency.clone(),
+                operations,
+                io_errors,
+                elapsed,
+            };
+        }
+    }
+
+    /// Removes the provisional row of the running point, which its final row replaces
+    pub fn finish_point(&self) {
+        if let Some(running) = self.running.lock().unwrap().take() {
+            if let Err(e) = truncate(&self.path, running.offset) {
+                println!("warning: {}", e);
+            }
+        }
+    }
+
+    /// Replaces the provisional row of the running point, once a thread has written
+    fn write(&self) -> Result<(), String> {
+        let running = self.running.lock().unwrap();
+        let Some(running) = running.as_ref() else {
+            return Ok(());
+        };
+        let mut progress = Progress::default();
+        for thread in &running.threads {
+            progress.latency.merge(&thread.latency);
+            progress.operations += thread.operations;
+            progress.io_errors += thread.io_errors;
+            progress.elapsed = progress.elapsed.max(thread.elapsed);
+        }
+        if progress.latency.count() == 0 {
+            return Ok(());
+        }
+        truncate(&self.path, running.offset)?;
+        let mut wtr = schema::csv_appender(Path::new(&self.path))?;
+        (running.write_row)(&progress, &mut wtr)
+            .and_then(|_| wtr.flush().map_err(csv::Error::from))
+            .map_err(|e| format!("Failed to write {}: {}", self.path, e))
+    }
+
+    /// Stops the background thread
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.thread().unpark();
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn provisional_summary_rows_are_replaced() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-flush-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let summary_file = dir.join("summary.csv").to_string_lossy().into_owned();
+        std::fs::write(&summary_file, "operations,partial\n7,false\n").unwrap();
+        let flusher = Flusher::spawn(&summary_file, Duration::from_millis(10));
+        flusher.start_point(2, |progress| (progress.operations, true));
+        let mut latency = Histogram::new();
+        latency.record(1000);
+        let wait_for = |contents: &str| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while std::fs::read_to_string(&summary_file).unwrap() != contents {
+                assert!(Instant::now() < deadline, "{}", contents);
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+        flusher.publish(0, &latency, 3, 0, Duration::from_secs(1));
+        wait_for("operations,partial\n7,false\n3,true\n");
+        flusher.publish(1, &latency, 4, 0, Duration::from_secs(1));
+        wait_for("operations,partial\n7,false\n7,true\n");
+        flusher.finish_point();
+        flusher.stop();
+        assert_eq!(
+            std::fs::read_to_string(&summary_file).unwrap(),
+            "operations,partial\n7,false\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_written_before_the_first_write() {
+        let dir = std::env::temp_dir().join(format!("ssd-benchy-noflush-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let summary_file = dir.join("summary.csv").to_string_lossy().into_owned();
+        let flusher = Flusher::spawn(&summary_file, Duration::from_secs(3600));
+        flusher.write().unwrap();
+        flusher.start_point(1, |progress| (progress.operations, true));
+        flusher.write().unwrap();
+        flusher.finish_point();
+        assert!(!Path::new(&summary_file).exists());
+        flusher.stop();
+
+        let unwritable = dir
+            .join("missing/summary.csv")
+            .to_string_lossy()
+            .into_owned();
+        let flusher = Flusher::spawn(&unwritable, Duration::from_secs(3600));
+        flusher.start_point(1, |progress| (progress.operations, true));
+        let mut latency = Histogram::new();
+        latency.record(1000);
+        flusher.publish(0, &latency, 1, 0, Duration::from_secs(1));
+        assert!(flusher.write().is_err());
+        flusher.finish_point();
+        flusher.stop();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
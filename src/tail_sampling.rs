@@ -0,0 +1,99 @@
+//! Sampling that keeps every write of the tail (`--sampling-method tail`).
+//!
+//! At the default sample rate of 0.2%, the samples file has two writes of a thousand and a few
+//! dozen of the p99.9 tail of a short point, too few to tell what the slow writes have in common
+//! (a thread, a time, an operation before them). With `--sampling-method tail` every writer
+//! thread keeps a running estimate of the `--tail-percentile` of its write latencies and writes
+//! every write above it to the samples file, while the writes below it are sampled with
+//! `--sample-rate` as with bernoulli. Until a thread has seen enough writes for an estimate (ten
+//! expected writes above the percentile), every write is above it. The `weight` column of a
+//! sample tells how many writes it stands for: 1 for the writes of the tail, 1 / sample rate for
+//! the others, so weighted percentiles of the samples file are unbiased, and
+//! `ssd-benchy analyze --samples-file`, `ssd-benchy compare` and the samples views of
+//! `ssd-benchy plot` count every sample by its weight. The percentiles of the summary and the
+//! fan-out projection are computed from the writes sampled with `--sample-rate` only, as before.
+
+use crate::histogram::Histogram;
+
+/// Writes between two updates of the estimate
+const UPDATE_INTERVAL: u64 = 1024;
+
+/// The running estimate of a thread
+#[derive(Debug, Clone)]
+pub struct TailSampler {
+    percentile: f64,
+    histogram: Histogram, // of all writes of the thread
+    threshold: u64,       // nanoseconds, 0 until there are enough writes
+    warm_up: u64,
+}
+
+impl TailSampler {
+    pub fn new(percentile: f64) -> TailSampler {
+        TailSampler {
+            percentile,
+            histogram: Histogram::new(),
+            threshold: 0,
+            warm_up: (10.0 * 100.0 / (100.0 - percentile)).ceil() as u64,
+        }
+    }
+
+    /// Checks that the percentile leaves a tail
+    pub fn validate(percentile: f64) -> Result<(), String> {
+        if !(percentile > 0.0 && percentile < 100.0) {
+            return Err("--tail-percentile must be within (0, 100)".to_string());
+        }
+        Ok(())
+    }
+
+    /// Records the latency of a write; true if it is above the estimate and is captured
+    pub fn captures(&mut self, latency: u64) -> bool {
+        let captured = latency > self.threshold;
+        self.histogram.record(latency);
+        let count = self.histogram.count();
+        if count >= self.warm_up && count.is_multiple_of(UPDATE_INTERVAL) {
+            self.threshold = self.histogram.percentile(self.percentile);
+        }
+        captured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_sampling_captures_the_writes_above_the_estimate() {
+        let mut sampler = TailSampler::new(99.0);
+        let latency = |write: u64| {
+            if write.is_multiple_of(500) {
+                1_000_000
+            } else {
+                10_000
+            }
+        };
+        // everything is captured until the estimate is there
+        assert!((0..1024).all(|write| sampler.captures(latency(write))));
+        let captured: Vec<u64> = (1024..10_000)
+            .filter(|&write| sampler.captures(latency(write)))
+            .collect();
+        assert!(!captured.is_empty());
+        assert!(captured.iter().all(|&write| latency(write) == 1_000_000));
+        assert_eq!(
+            captured.len(),
+            (1024..10_000u64).filter(|w| w.is_multiple_of(500)).count()
+        );
+        assert!(TailSampler::validate(100.0).is_err());
+        assert!(TailSampler::validate(0.0).is_err());
+        assert!(TailSampler::validate(-1.0).is_err());
+        assert!(TailSampler::validate(f64::NAN).is_err());
+        assert!(TailSampler::validate(99.99).is_ok());
+    }
+
+    #[test]
+    fn writes_at_the_estimate_are_not_captured() {
+        let mut sampler = TailSampler::new(50.0);
+        let captured = (0..10_000).filter(|_| sampler.captures(10_000)).count();
+        // only the writes before the first estimate
+        assert_eq!(captured, UPDATE_INTERVAL as usize);
+    }
+}
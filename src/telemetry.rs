@@ -0,0 +1,157 @@
+//! NVMe log pages captured when a write is slower than `--telemetry-threshold-us`.
+//!
+//! The first thing a vendor asks for after a latency spike is the telemetry log of the drive,
+//! taken right after it happened. With `--telemetry-threshold-us` the writer threads hand every
+//! write above the threshold to a background thread, which reads the SMART and error information
+//! logs and creates and reads a host-initiated telemetry log (log page 0x07) into a directory of
+//! its own under `--telemetry-dir`, next to `info.json` with the point and the latency that
+//! triggered it. Captures are at least `--telemetry-cooldown-seconds` apart and at most
+//! MAX_CAPTURES per run; the admin commands are issued while the workload is running, so the
+//! writes right after a capture may be slowed down by it.
+
+use crate::nvme;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{JoinHandle, Thread},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Captures of one run at most; telemetry logs are large and a flapping drive would fill the disk
+pub const MAX_CAPTURES: usize = 10;
+
+/// Instant of the last capture of the run, shared by the points
+static LAST_CAPTURE: Mutex<Option<Instant>> = Mutex::new(None);
+static CAPTURES: Mutex<usize> = Mutex::new(0);
+
+/// Fails if the log pages of `ssd_device` cannot be read, before the run starts
+pub fn check(ssd_device: &str) -> Result<(), String> {
+    nvme::smart_log(ssd_device).map(|_| ())
+}
+
+/// Where a capturer stores its captures and how often it may capture
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub dir: String,
+    pub threshold_ns: u64,
+    pub cooldown: Duration,
+}
+
+/// Takes the captures of one utilization point in the background
+pub struct Capturer {
+    threshold_ns: u64,
+    trigger: Arc<AtomicU64>, // latency of the slowest write not captured yet, 0 if none
+    stop: Arc<AtomicBool>,
+    thread: Thread,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+}
+
+/// Writes the log pages of `ssd_device` into a new directory in `options.dir`
+fn capture(
+    ssd_device: &str,
+    options: &Options,
+    point: &Value,
+    latency_ns: u64,
+) -> Result<PathBuf, String> {
+    let triggered = unix_nanos();
+    let pages = nvme::log_pages(ssd_device)?;
+    let dir = Path::new(&options.dir).join(format!("{}-{}", ssd_device, triggered));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let mut info = point.clone();
+    info["ssd_device"] = json!(ssd_device);
+    info["trigger_time_ns"] = json!(triggered.to_string());
+    info["latency_ns"] = json!(latency_ns);
+    info["threshold_ns"] = json!(options.threshold_ns);
+    info["capture_seconds"] = json!((unix_nanos() - triggered) as f64 / 1e9);
+    info["telemetry_bytes"] = json!(pages.telemetry.len());
+    info["telemetry_truncated"] = json!(pages.telemetry_truncated);
+    for (name, contents) in [
+        ("smart_log.bin", pages.smart),
+        ("error_log.bin", pages.error),
+        ("telemetry_host.bin", pages.telemetry),
+        ("info.json", format!("{}\n", info).into_bytes()),
+    ] {
+        if name == "telemetry_host.bin" && contents.is_empty() {
+            continue;
+        }
+        let path = dir.join(name);
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(dir)
+}
+
+impl Capturer {
+    /// `point` describes the utilization point, e.g., its uuid, and goes into every info.json
+    pub fn spawn(ssd_device: &str, options: &Options, point: Value) -> Arc<Capturer> {
+        let trigger = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (trigger, stop) = (trigger.clone(), stop.clone());
+            let ssd_device = ssd_device.to_string();
+            let options = options.clone();
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::park_timeout(Duration::from_millis(100));
+                    let latency_ns = trigger.swap(0, Ordering::Relaxed);
+                    if latency_ns == 0 {
+                        continue;
+                    }
+                    let mut last = LAST_CAPTURE.lock().unwrap();
+                    let mut captures = CAPTURES.lock().unwrap();
+                    if *captures >= MAX_CAPTURES
+                        || last.is_some_and(|last| last.elapsed() < options.cooldown)
+                    {
+                        continue;
+                    }
+                    *last = Some(Instant::now());
+                    *captures += 1;
+                    match capture(&ssd_device, &options, &point, latency_ns) {
+                        Ok(dir) => println!(
+                            "captured the NVMe log pages of {} after a write of {:.1}us into {}",
+                            ssd_device,
+                            latency_ns as f64 / 1e3,
+                            dir.display()
+                        ),
+                        Err(e) => println!("warning: {}", e),
+                    }
+                }
+            })
+        };
+        Arc::new(Capturer {
+            threshold_ns: options.threshold_ns,
+            trigger,
+            stop,
+            thread: handle.thread().clone(),
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    /// Called with the latency of every write; cheap unless it is above the threshold
+    pub fn record(&self, latency_ns: u64) {
+        if latency_ns > self.threshold_ns {
+            self.trigger.fetch_max(latency_ns, Ordering::Relaxed);
+            self.thread.unpark();
+        }
+    }
+
+    /// Waits for a capture in progress
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.thread().unpark();
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Temperature-controlled pacing of the utilization points (thermal soak).
+//!
+//! Latencies depend on the drive temperature, e.g., through thermal throttling, so runs in
+//! different ambient conditions are only comparable at the same temperature. With
+//! `--max-temperature-celsius` every utilization point waits until the drive cooled down to that
+//! temperature, and with `--soak-temperature-celsius` sequential writes heat it up to the target
+//! before the point starts. A point that is already running is not paused, which would distort its
+//! rate schedule; instead its highest temperature is reported next to it. Temperatures are the
+//! composite temperature of the NVMe SMART log.
+
+use crate::{engine, nvme};
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How often the temperature is read
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Summary columns of the drive temperature; empty when the SMART log cannot be read
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct TemperatureStatistics {
+    pub temperature_start_celsius: Option<i64>,
+    pub temperature_max_celsius: Option<i64>, // while the point was running
+    pub cooldown_seconds: f64,
+    pub soak_seconds: f64,
+}
+
+pub fn temperature(device: &engine::Device) -> Option<i64> {
+    nvme::smart_log(&device.name())
+        .ok()
+        .map(|smart| smart.temperature_celsius)
+}
+
+/// Idles until the drive is at most `max` degrees warm
+pub fn cool_down(device: &engine::Device, max: i64) -> Duration {
+    let begin = Instant::now();
+    let mut announced = false;
+    while let Some(celsius) = temperature(device).filter(|&celsius| celsius > max) {
+        if !announced {
+            println!("cooling down from {}°C to {}°C ...", celsius, max);
+            announced = true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    begin.elapsed()
+}
+
+/// Writes the used capacity sequentially until the drive is at least `target` degrees warm or
+/// `timeout` is over
+pub fn soak(
+    device: &engine::Device,
+    capacity_fraction: f64,
+    target: i64,
+    timeout: Duration,
+) -> Duration {
+    const BLOCK_SIZE: u64 = 2097152;
+    let begin = Instant::now();
+    if temperature(device).is_some_and(|celsius| celsius >= target) {
+        return begin.elapsed();
+    }
+    println!("soaking until {}°C ...", target);
+    let blocks = (device.capacity() as f64 * capacity_fraction) as u64 / BLOCK_SIZE;
+    let buffer = crate::buffer::AlignedBuffer::new(BLOCK_SIZE as usize, 5);
+    let ssd_fd = device.open(engine::EngineKind::Psync);
+    let mut last_check = Instant::now();
+    let mut block = 0;
+    loop {
+        if last_check.elapsed() >= POLL_INTERVAL {
+            last_check = Instant::now();
+            let celsius = temperature(device);
+            if celsius.is_some_and(|celsius| celsius >= target) {
+                break;
+            }
+            if begin.elapsed() >= timeout {
+                println!(
+                    "warning: the drive did not reach {}°C within {}s (last read {:?}°C); measuring anyway",
+                    target,
+                    timeout.as_secs(),
+                    celsius
+                );
+                break;
+            }
+        }
+        let res = ssd_fd
+            .write_at(&buffer, block * BLOCK_SIZE)
+            .expect("Could not write");
+        assert_eq!(res, BLOCK_SIZE as usize);
+        block = (block + 1) % blocks.max(1);
+    }
+    ssd_fd.sync().unwrap();
+    begin.elapsed()
+}
+
+/// Follows the drive temperature in the background while a point is running
+pub struct Monitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<i64>,
+}
+
+impl Monitor {
+    /// Returns None if the temperature of the device cannot be read
+    pub fn spawn(device: &'static engine::Device) -> Option<(Monitor, i64)> {
+        let start = temperature(device)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut max = start;
+                while !stop.load(Ordering::Relaxed) {
+                    std::thread::park_timeout(POLL_INTERVAL);
+                    if let Some(celsius) = temperature(device) {
+                        max = max.max(celsius);
+                    }
+                }
+                max
+            })
+        };
+        Some((Monitor { stop, handle }, start))
+    }
+
+    /// The highest temperature since the monitor was spawned
+    pub fn stop(self) -> i64 {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        self.handle.join().unwrap()
+    }
+}
@@ -0,0 +1,268 @@
+//! Heterogeneous writer threads (`--thread-groups`).
+//!
+//! A database node does not write at one rate and size: a WAL writer issues small writes at a
+//! high rate, a flusher larger ones in bursts, and a compactor large ones in the background.
+//! `--thread-groups` reads such a mix from a TOML file with one `[[group]]` per kind of writer:
+//!
+//! ```toml
+//! [[group]]
+//! name = "wal"
+//! threads = 1
+//! share = 0.7          # of the target rate of every utilization point
+//! write_bytes = 4096
+//!
+//! [[group]]
+//! name = "compactor"
+//! threads = 2
+//! share = 0.3
+//! write_bytes = 262144
+//! rate_pattern = "sine"
+//! ```
+//!
+//! The groups replace `--writer-threads` (their threads add up to it) and take `--iovcnt` and
+//! `--rate-pattern` as defaults for `write_bytes` and `rate_pattern`. Without any `share` the rate
+//! is split by threads; otherwise every group needs one and they add up to 1. Every group gets a
+//! row per utilization point in `--thread-group-stats-file`.
+
+use crate::{histogram::Histogram, RatePattern, BLOCK_SIZE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct ThreadGroup {
+    pub name: String,
+    pub threads: u64,
+    pub share: f64, // of the target rate of a utilization point
+    pub iovcnt: u64,
+    pub rate_pattern: RatePattern,
+}
+
+impl fmt::Display for ThreadGroup {
+    /// `name:threads:write_bytes:share`, e.g., `wal:1:4096:0.7`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.name,
+            self.threads,
+            self.iovcnt * BLOCK_SIZE as u64,
+            self.share
+        )
+    }
+}
+
+/// A thread group file: the `[[group]]` tables
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupsFile {
+    #[serde(default)]
+    group: Vec<GroupEntry>,
+}
+
+/// One `[[group]]` table as written; missing keys take their defaults in `parse`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupEntry {
+    name: Option<String>,
+    threads: Option<u64>,
+    share: Option<f64>,
+    write_bytes: Option<u64>,
+    rate_pattern: Option<RatePattern>,
+}
+
+/// Reads the groups of the file at `path`; `iovcnt` and `rate_pattern` are the defaults
+pub fn load(
+    path: &str,
+    iovcnt: u64,
+    rate_pattern: RatePattern,
+) -> Result<Vec<ThreadGroup>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    parse(&text, iovcnt, rate_pattern).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse(
+    text: &str,
+    iovcnt: u64,
+    rate_pattern: RatePattern,
+) -> Result<Vec<ThreadGroup>, String> {
+    let file: GroupsFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    if file.group.is_empty() {
+        return Err(String::from("no [[group]] defined"));
+    }
+    let mut parsed = vec![];
+    let mut shares = vec![];
+    for (index, group) in file.group.into_iter().enumerate() {
+        let name = group.name.unwrap_or_else(|| format!("group{}", index + 1));
+        let threads = group.threads.unwrap_or(1);
+        if threads == 0 {
+            return Err(format!(
+                "threads of group {} must be a positive integer",
+                name
+            ));
+        }
+        let write_bytes = group.write_bytes.unwrap_or(iovcnt * BLOCK_SIZE as u64);
+        if write_bytes == 0 || write_bytes % BLOCK_SIZE as u64 != 0 {
+            return Err(format!(
+                "write_bytes of group {} must be a multiple of {}",
+                name, BLOCK_SIZE
+            ));
+        }
+        if group
+            .share
+            .is_some_and(|share| !(share > 0.0 && share <= 1.0))
+        {
+            return Err(format!("share of group {} must be within (0, 1]", name));
+        }
+        shares.push(group.share);
+        if parsed.iter().any(|g: &ThreadGroup| g.name == name) {
+            return Err(format!("group {} is defined twice", name));
+        }
+        parsed.push(ThreadGroup {
+            name,
+            threads,
+            share: group.share.unwrap_or(0.0),
+            iovcnt: write_bytes / BLOCK_SIZE as u64,
+            rate_pattern: group.rate_pattern.unwrap_or(rate_pattern),
+        });
+    }
+    if shares.iter().all(Option::is_none) {
+        let threads: u64 = parsed.iter().map(|g| g.threads).sum();
+        for group in &mut parsed {
+            group.share = group.threads as f64 / threads as f64;
+        }
+    } else if shares.iter().any(Option::is_none) {
+        return Err(String::from(
+            "either every group or none has a share of the rate",
+        ));
+    } else if (parsed.iter().map(|g| g.share).sum::<f64>() - 1.0).abs() > 1e-6 {
+        return Err(String::from("the shares of the groups must add up to 1"));
+    }
+    Ok(parsed)
+}
+
+/// The group of writer thread `worker_id` and the index of the thread within it; the threads of
+/// the groups are numbered in the order of the file
+pub fn of(groups: &[ThreadGroup], worker_id: u64) -> Option<(&ThreadGroup, u64)> {
+    let mut first = 0;
+    for group in groups {
+        if worker_id < first + group.threads {
+            return Some((group, worker_id - first));
+        }
+        first += group.threads;
+    }
+    None
+}
+
+/// One row of the thread group stats file: the writer threads of one group at one utilization point
+#[derive(Serialize, Debug, Default)]
+pub struct GroupStatistics {
+    uuid: u128, // of the utilization point
+    utilization_iop: f64,
+    group: String,
+    writer_threads: u64,
+    write_bytes: u64,
+    share: f64, // of the target rate of the point
+    achieved_iops: f64,
+    operations: u64,
+    io_errors: u64,
+    p50th: u64,
+    p99th: u64,
+    p999th: u64,
+    max: u64,
+}
+
+impl GroupStatistics {
+    pub fn create_from_histogram(
+        uuid: u128,
+        utilization_iop: f64,
+        group: &ThreadGroup,
+        achieved_iops: f64,
+        operations: u64,
+        io_errors: u64,
+        latency: &Histogram,
+    ) -> GroupStatistics {
+        GroupStatistics {
+            uuid,
+            utilization_iop,
+            group: group.name.clone(),
+            writer_threads: group.threads,
+            write_bytes: group.iovcnt * BLOCK_SIZE as u64,
+            share: group.share,
+            achieved_iops,
+            operations,
+            io_errors,
+            p50th: latency.percentile(50.0),
+            p99th: latency.percentile(99.0),
+            p999th: latency.percentile(99.9),
+            max: latency.max(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_groups_are_read_from_toml() {
+        let groups = parse(
+            r#"
+            # a database node
+            [[group]]
+            name = "wal"
+            share = 0.75
+            write_bytes = 4_096
+
+            [[group]]
+            name = 'compactor'
+            threads = 2
+            share = 0.25 # background
+            write_bytes = 262144
+            rate_pattern = "sine"
+            "#,
+            2,
+            RatePattern::Constant,
+        )
+        .unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].to_string(), "wal:1:4096:0.75");
+        assert_eq!(groups[1].iovcnt, 64);
+        assert_eq!(groups[1].rate_pattern, RatePattern::Sine);
+        let index = |worker_id| of(&groups, worker_id).map(|(g, i)| (g.name.as_str(), i));
+        assert_eq!(index(0), Some(("wal", 0)));
+        assert_eq!(index(2), Some(("compactor", 1)));
+        assert_eq!(index(3), None);
+
+        let groups = parse("[[group]]\nthreads = 3\n[[group]]\n", 2, RatePattern::Ramp).unwrap();
+        assert_eq!((groups[0].share, groups[1].share), (0.75, 0.25));
+        assert_eq!(
+            (groups[1].iovcnt, groups[1].rate_pattern),
+            (2, RatePattern::Ramp)
+        );
+
+        for invalid in [
+            "[[group]]\nshare = 0.5\n[[group]]\n",
+            "[[group]]\nshare = 0.5\n",
+            "[[group]]\nwrite_bytes = 1000\n",
+            "[[group]]\nthreads = 0\n",
+            "[[group]]\nrate = 5\n",
+            "[group]\n",
+            "",
+            "[[group]]\nname = 7\n",
+            "[[group]]\nthreads = 1\nnice = 5\n",
+            "[[group]]\nthreads = 1.5\n",
+            "[[group]]\nrate_pattern = \"square\"\n",
+            "[[group]]\nname = \"wal\" extra\n",
+            "[[group]]\nthreads = 1\nthreads = 2\n",
+        ] {
+            assert!(
+                parse(invalid, 1, RatePattern::Constant).is_err(),
+                "{}",
+                invalid
+            );
+        }
+        let missing = load("/nonexistent/groups.toml", 1, RatePattern::Constant).unwrap_err();
+        assert!(missing.contains("/nonexistent/groups.toml"), "{}", missing);
+    }
+}
@@ -0,0 +1,113 @@
+//! Where the time of the writer threads goes.
+//!
+//! A writer thread busy-waits in the rate limiter until its next write is due, issues the write,
+//! and records its latency. At low rates it spends almost all of its time spinning; as the rate
+//! rises the spinning shrinks, and once a thread spends all of its time in the IO calls and in
+//! recording the samples it cannot keep its schedule, however fast the drive is. The rate limiter
+//! measures the three parts of every operation, and the summary reports them as fractions of the
+//! runtime of the threads (without pauses): the mean over the threads of the spinning, the IO,
+//! the sampling, and the rest (the loop around them, e.g., pausing and sample rate updates), and
+//! the spinning of the busiest thread. A busiest thread that spins less than a few percent is at
+//! the limit of the host, and higher rates need more threads rather than a faster drive.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// The time a thread spent in the parts of its operations
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadProfile {
+    pub spin: Duration, // waiting in the rate limiter for the next batch
+    pub io: Duration,
+    pub sampling: Duration,
+}
+
+/// Summary columns of the profiles of the writer threads
+#[derive(Serialize, Debug, Default)]
+pub struct ProfileStatistics {
+    thread_spin_fraction: f64, // mean of the threads
+    thread_io_fraction: f64,
+    thread_sampling_fraction: f64,
+    thread_other_fraction: f64,
+    min_thread_spin_fraction: f64, // of the busiest thread
+}
+
+impl ProfileStatistics {
+    /// `threads` are the profiles of the threads with the time they ran
+    pub fn create(threads: &[(ThreadProfile, Duration)]) -> ProfileStatistics {
+        let fractions: Vec<[f64; 4]> = threads
+            .iter()
+            .filter(|(_, runtime)| !runtime.is_zero())
+            .map(|(profile, runtime)| {
+                let fraction = |part: Duration| part.as_secs_f64() / runtime.as_secs_f64();
+                let (spin, io, sampling) = (
+                    fraction(profile.spin),
+                    fraction(profile.io),
+                    fraction(profile.sampling),
+                );
+                [spin, io, sampling, (1.0 - spin - io - sampling).max(0.0)]
+            })
+            .collect();
+        if fractions.is_empty() {
+            return ProfileStatistics::default();
+        }
+        let mean =
+            |part: usize| fractions.iter().map(|f| f[part]).sum::<f64>() / fractions.len() as f64;
+        ProfileStatistics {
+            thread_spin_fraction: mean(0),
+            thread_io_fraction: mean(1),
+            thread_sampling_fraction: mean(2),
+            thread_other_fraction: mean(3),
+            min_thread_spin_fraction: fractions.iter().map(|f| f[0]).fold(f64::INFINITY, f64::min),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractions_are_the_mean_of_the_threads() {
+        let ms = Duration::from_millis;
+        let spinning = ThreadProfile {
+            spin: ms(600),
+            io: ms(200),
+            sampling: ms(100),
+        };
+        let busy = ThreadProfile {
+            spin: ms(0),
+            io: ms(700),
+            sampling: ms(200),
+        };
+        let statistics = serde_json::to_value(ProfileStatistics::create(&[
+            (spinning, ms(1000)),
+            (busy, ms(1000)),
+        ]))
+        .unwrap();
+        let column = |name| statistics.get(name).unwrap().as_f64().unwrap();
+        assert!((column("thread_spin_fraction") - 0.3).abs() < 1e-9);
+        assert!((column("thread_io_fraction") - 0.45).abs() < 1e-9);
+        assert!((column("thread_other_fraction") - 0.1).abs() < 1e-9);
+        assert_eq!(column("min_thread_spin_fraction"), 0.0);
+    }
+
+    #[test]
+    fn threads_that_did_not_run_are_left_out() {
+        let ms = Duration::from_millis;
+        let idle = ProfileStatistics::create(&[]);
+        assert_eq!(idle.min_thread_spin_fraction, 0.0);
+        // the parts overlap, e.g., with a coarse clock
+        let overlapping = ThreadProfile {
+            spin: ms(600),
+            io: ms(600),
+            sampling: ms(0),
+        };
+        let statistics = ProfileStatistics::create(&[
+            (overlapping, ms(1000)),
+            (ThreadProfile::default(), Duration::ZERO),
+        ]);
+        assert_eq!(statistics.thread_spin_fraction, 0.6);
+        assert_eq!(statistics.thread_other_fraction, 0.0);
+        assert_eq!(statistics.min_thread_spin_fraction, 0.6);
+    }
+}
@@ -0,0 +1,161 @@
+//! Multi-block transactions with a commit record (`--workload transaction`).
+//!
+//! A storage engine commits a transaction by writing its data blocks, waiting until they are
+//! durable, and only then writing the commit record that makes the transaction visible after a
+//! crash; without the flush barrier in between, the device may persist the commit record before
+//! the data it refers to. Every write of the workload is one such transaction:
+//! `--transaction-blocks` data writes of the thread's write size back to back, an fdatasync as the
+//! barrier, the commit record (one more write), and an fdatasync that makes it durable. The
+//! latencies of the summary and the samples file are those of the whole transaction, the
+//! `transaction_*` columns break them down into the data writes, the barrier, and the durable
+//! commit record.
+
+use crate::{engine::Engine, histogram::Histogram, stats};
+use std::time::Instant;
+
+/// Nanoseconds a transaction spent in its parts
+#[derive(Debug, Clone, Copy)]
+pub struct Transaction {
+    pub data: u64,
+    pub barrier: u64,
+    pub commit: u64, // the commit record and its fdatasync
+}
+
+/// Why a transaction did not commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    ShortWrite,
+    IoError,
+}
+
+/// Writes a transaction of `data_writes` writes of `bufs` and its commit record back to back from
+/// `offset`; `bytes` counts what was written, also of a failed transaction
+pub fn execute(
+    engine: &dyn Engine,
+    bufs: &[&[u8]],
+    offset: u64,
+    data_writes: u64,
+    bytes: &mut u64,
+) -> Result<Transaction, Failure> {
+    let write_len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+    let mut write =
+        |index: u64| match engine.write_vectored_at(bufs, offset + index * write_len as u64) {
+            Ok(written) => {
+                *bytes += written as u64;
+                if written == write_len {
+                    Ok(())
+                } else {
+                    Err(Failure::ShortWrite)
+                }
+            }
+            Err(_) => Err(Failure::IoError),
+        };
+    let begin = Instant::now();
+    for index in 0..data_writes {
+        write(index)?;
+    }
+    let data_end = Instant::now();
+    engine.sync().map_err(|_| Failure::IoError)?;
+    let barrier_end = Instant::now();
+    write(data_writes)?;
+    engine.sync().map_err(|_| Failure::IoError)?;
+    Ok(Transaction {
+        data: stats::nanos(data_end - begin),
+        barrier: stats::nanos(barrier_end - data_end),
+        commit: stats::nanos(barrier_end.elapsed()),
+    })
+}
+
+/// The parts of the transactions of a thread
+#[derive(Debug, Clone, Default)]
+pub struct TransactionLatencies {
+    pub data: Histogram,
+    pub barrier: Histogram,
+    pub commit: Histogram,
+}
+
+impl TransactionLatencies {
+    pub fn record(&mut self, transaction: Transaction) {
+        self.data.record(transaction.data);
+        self.barrier.record(transaction.barrier);
+        self.commit.record(transaction.commit);
+    }
+
+    pub fn merge(&mut self, other: &TransactionLatencies) {
+        self.data.merge(&other.data);
+        self.barrier.merge(&other.barrier);
+        self.commit.merge(&other.commit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BLOCK_SIZE;
+    use std::io;
+
+    #[test]
+    fn transaction_writes_its_commit_record_after_the_data() {
+        let path = std::env::temp_dir().join(format!("ssd-benchy-tx-{}", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let block = [3u8; BLOCK_SIZE];
+        let mut bytes = 0;
+        let committed = execute(&file, &[&block], BLOCK_SIZE as u64, 2, &mut bytes).unwrap();
+        assert_eq!(bytes, 3 * BLOCK_SIZE as u64);
+        // the block before the transaction stays a hole
+        assert_eq!(file.metadata().unwrap().len(), 4 * BLOCK_SIZE as u64);
+        assert!(committed.commit > 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A device that is full after `capacity` bytes
+    struct Full {
+        capacity: u64,
+    }
+
+    impl Engine for Full {
+        fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+            Ok((buf.len() as u64).min(self.capacity.saturating_sub(offset)) as usize)
+        }
+
+        fn read_at(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+            Ok(0)
+        }
+
+        fn sync(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_writes_fail_the_transaction() {
+        let block = [3u8; BLOCK_SIZE];
+        let mut bytes = 0;
+        // the commit record does not fit anymore
+        let full = Full {
+            capacity: 5 * BLOCK_SIZE as u64 / 2,
+        };
+        assert_eq!(
+            execute(&full, &[&block], 0, 2, &mut bytes).unwrap_err(),
+            Failure::ShortWrite
+        );
+        assert_eq!(bytes, 5 * BLOCK_SIZE as u64 / 2);
+
+        let path = std::env::temp_dir().join(format!("ssd-benchy-tx-ro-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let read_only = std::fs::File::open(&path).unwrap();
+        let mut bytes = 0;
+        assert_eq!(
+            execute(&read_only, &[&block], 0, 2, &mut bytes).unwrap_err(),
+            Failure::IoError
+        );
+        assert_eq!(bytes, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! `ssd-benchy trim-freshness`: write latency into recently trimmed vs. overwritten LBAs.
+//!
+//! Two regions of `--region-bytes` are written once. The first is then discarded (TRIMmed), and
+//! after `--idle-seconds` both are written again with random writes that alternate between the
+//! regions, so the two classes see the same device state. Every block is written at most once, so
+//! a write into the trimmed region always lands on an LBA that is still trimmed. Both latency
+//! distributions are reported side by side, one row per class.
+
+use crate::{buffer::AlignedBuffer, engine::Engine, histogram::Histogram, partition, schema};
+use gethostname::gethostname;
+use serde::Serialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TrimFreshnessArgs {
+    /// Name of the SSD device, e.g., nvme1n1; an alias in /dev is resolved to its block device
+    #[clap(long, env = "SSD_BENCHY_SSD_DEVICE")]
+    ssd_device: String,
+
+    /// Size of each of the two regions at the start of the device
+    #[clap(long, env = "SSD_BENCHY_REGION_BYTES", default_value_t = 8589934592)]
+    region_bytes: u64,
+
+    /// Size of every write in bytes; must be a multiple of the logical block size
+    #[clap(long, env = "SSD_BENCHY_BLOCK_SIZE", default_value_t = 4096)]
+    block_size: usize,
+
+    /// Queue depth (threads) of the random writes
+    #[clap(long, env = "SSD_BENCHY_QUEUE_DEPTH", default_value_t = 4)]
+    queue_depth: u64,
+
+    /// Seconds between the discard and the measurement, in which the device can process the TRIM
+    #[clap(long, env = "SSD_BENCHY_IDLE_SECONDS", default_value_t = 10)]
+    idle_seconds: u64,
+
+    /// Upper bound of the measurement in seconds; it also ends once both regions are written
+    #[clap(long, env = "SSD_BENCHY_RUNTIME_SECONDS", default_value_t = 300)]
+    runtime_seconds: u64,
+
+    /// Result file, one row per class
+    #[clap(long, env = "SSD_BENCHY_OUTPUT_FILE", default_value_t = String::from("trim_freshness.csv"))]
+    output_file: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Class {
+    #[default]
+    Trimmed,
+    Overwritten,
+}
+
+/// One row of the trim-freshness result file
+#[derive(Serialize, Debug, Default)]
+struct ClassStatistics {
+    uuid: u128,
+    start_time: u64,
+    hostname: String,
+    ssd_device: String,
+    block_size: usize,
+    queue_depth: u64,
+    region_bytes: u64,
+    idle_seconds: u64,
+    class: Class,
+    operations: u64,
+    mean: f64,
+    min: u64,
+    p10th: u64,
+    p50th: u64,
+    p90th: u64,
+    p99th: u64,
+    p999th: u64,
+    p9999th: u64,
+    max: u64,
+}
+
+/// Writes every block of both regions at most once in random order, alternating between the
+/// trimmed and the overwritten region; returns their latency histograms in that order
+fn measure(args: &TrimFreshnessArgs, blocks: u64) -> [Histogram; 2] {
+    let end_time = Instant::now() + Duration::from_secs(args.runtime_seconds);
+    let results: Vec<[Histogram; 2]> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..args.queue_depth)
+            .map(|thread_id| {
+                scope.spawn(move || {
+                    let ssd_fd = crate::open_ssd(&args.ssd_device);
+                    let buffer = AlignedBuffer::new(args.block_size, 0x7e);
+                    let mut rng = fastrand::Rng::new();
+                    let mut order: Vec<u64> =
+                        partition(thread_id, args.queue_depth, blocks).collect();
+                    rng.shuffle(&mut order);
+                    let mut histograms = [Histogram::new(), Histogram::new()];
+                    'writes: for block in order {
+                        // the overwritten region directly follows the trimmed one
+                        for (class, histogram) in histograms.iter_mut().enumerate() {
+                            if Instant::now() >= end_time {
+                                break 'writes;
+                            }
+                            let offset = (class as u64 * blocks + block) * args.block_size as u64;
+                            let io_begin = Instant::now();
+                            let res = ssd_fd
+                                .write_at(&buffer, offset)
+                                .expect("could not issue io");
+                            histogram.record(crate::stats::nanos(io_begin.elapsed()));
+                            assert_eq!(res, args.block_size);
+                        }
+                    }
+                    histograms
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut histograms = [Histogram::new(), Histogram::new()];
+    for result in results {
+        for (histogram, h) in histograms.iter_mut().zip(&result) {
+            histogram.merge(h);
+        }
+    }
+    histograms
+}
+
+/// One row per LBA class, for `ssd-benchy schema`
+pub fn result_files() -> Vec<schema::ResultFile> {
+    vec![schema::ResultFile {
+        command: "trim-freshness",
+        option: "--output-file",
+        columns: schema::columns_of(&ClassStatistics::default()),
+    }]
+}
+
+pub fn run(args: &TrimFreshnessArgs) {
+    let capacity = crate::device_capacity(&args.ssd_device);
+    if 2 * args.region_bytes > capacity || args.region_bytes < args.block_size as u64 {
+        eprintln!(
+            "two regions of --region-bytes must fit the device ({} bytes)",
+            capacity
+        );
+        std::process::exit(1);
+    }
+    let output = Path::new(&args.output_file);
+    schema::ensure_compatible(
+        output,
+        &schema::header_of(&ClassStatistics::default()),
+        schema::SchemaMismatchPolicy::Refuse,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let blocks = args.region_bytes / args.block_size as u64;
+    let region_bytes = blocks * args.block_size as u64;
+    let uuid = Uuid::new_v4().as_u128();
+    let start_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("");
+
+    println!(
+        "writing both regions of {:.1} GiB once ...",
+        region_bytes as f64 / (1u64 << 30) as f64
+    );
+    crate::fill_range(&args.ssd_device, 0..2 * region_bytes);
+    crate::open_ssd(&args.ssd_device)
+        .discard(0, region_bytes)
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to discard {}: {}", args.ssd_device, e);
+            std::process::exit(1);
+        });
+    println!(
+        "discarded the first region, idling for {}s ...",
+        args.idle_seconds
+    );
+    std::thread::sleep(Duration::from_secs(args.idle_seconds));
+
+    let histograms = measure(args, blocks);
+    println!(
+        "{:>12} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "class", "writes", "mean[us]", "p50[us]", "p99[us]", "p999[us]", "max[us]"
+    );
+    let mut wtr = schema::csv_appender(output).unwrap();
+    for (class, h) in [Class::Trimmed, Class::Overwritten]
+        .into_iter()
+        .zip(&histograms)
+    {
+        let statistics = ClassStatistics {
+            uuid,
+            start_time: start_time.as_secs(),
+            hostname: gethostname().into_string().unwrap(),
+            ssd_device: args.ssd_device.clone(),
+            block_size: args.block_size,
+            queue_depth: args.queue_depth,
+            region_bytes,
+            idle_seconds: args.idle_seconds,
+            class,
+            operations: h.count(),
+            mean: h.mean(),
+            min: h.min(),
+            p10th: h.percentile(10.0),
+            p50th: h.percentile(50.0),
+            p90th: h.percentile(90.0),
+            p99th: h.percentile(99.0),
+            p999th: h.percentile(99.9),
+            p9999th: h.percentile(99.99),
+            max: h.max(),
+        };
+        println!(
+            "{:>12} {:>12} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+            format!("{:?}", class).to_lowercase(),
+            statistics.operations,
+            statistics.mean / 1e3,
+            statistics.p50th as f64 / 1e3,
+            statistics.p99th as f64 / 1e3,
+            statistics.p999th as f64 / 1e3,
+            statistics.max as f64 / 1e3
+        );
+        wtr.serialize(&statistics).unwrap();
+    }
+    wtr.flush().unwrap();
+}
@@ -0,0 +1,295 @@
+//! Read-back verification of the write pattern.
+//!
+//! With `--verify` every written block starts with a [`Stamp`] naming the run, the thread, the
+//! block, and how often the thread already wrapped around its region. After the write phase the
+//! regions are read back and each block is compared with the stamp the partitioning and
+//! wrap-around logic says it must carry.
+
+use crate::engine::Engine;
+use std::ops::Range;
+
+const MAGIC: &[u8; 8] = b"SSDBENCH";
+pub const STAMP_LEN: usize = 48;
+/// Read granularity of the verification pass; must be a multiple of the block size
+const READ_SIZE: usize = 2097152;
+/// Number of mismatches printed in detail per region
+const REPORTED_MISMATCHES: u64 = 5;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Stamp {
+    pub uuid: u128,
+    pub thread_id: u64,
+    pub block: u64,
+    pub pass: u64, // how often the thread wrapped around its region before this write
+}
+
+impl Stamp {
+    pub fn write_to(&self, buf: &mut [u8]) {
+        buf[0..8].copy_from_slice(MAGIC);
+        buf[8..24].copy_from_slice(&self.uuid.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.thread_id.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.block.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.pass.to_le_bytes());
+    }
+
+    pub fn read_from(buf: &[u8]) -> Option<Stamp> {
+        if &buf[0..8] != MAGIC {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+        Some(Stamp {
+            uuid: u128::from_le_bytes(buf[8..24].try_into().unwrap()),
+            thread_id: u64_at(24),
+            block: u64_at(32),
+            pass: u64_at(40),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub verified_blocks: u64,
+    pub mismatches: u64,
+    pub uncovered_blocks: u64, // blocks of the initialized area owned by no thread
+    pub overlapping_blocks: u64, // blocks owned by more than one thread
+}
+
+impl VerifyReport {
+    pub fn failed(&self) -> bool {
+        self.mismatches > 0 || self.uncovered_blocks > 0 || self.overlapping_blocks > 0
+    }
+
+    pub fn print(&self) {
+        println!(
+            "verify: {} of {} blocks read back {}, {} blocks owned by no thread, {} blocks owned by more than one",
+            self.mismatches,
+            self.verified_blocks,
+            if self.mismatches == 1 { "differs" } else { "differ" },
+            self.uncovered_blocks,
+            self.overlapping_blocks
+        );
+    }
+}
+
+/// The stamp block `block` of a region must carry after `written_blocks` sequential block writes
+/// that wrapped around the region, or `None` if the block was never written.
+pub fn expected_stamp(
+    uuid: u128,
+    thread_id: u64,
+    range: &Range<u64>,
+    written_blocks: u64,
+    block: u64,
+) -> Option<Stamp> {
+    let len = range.end - range.start;
+    let offset = block - range.start;
+    let writes = written_blocks / len + u64::from(offset < written_blocks % len);
+    (writes > 0).then(|| Stamp {
+        uuid,
+        thread_id,
+        block,
+        pass: writes - 1,
+    })
+}
+
+/// Checks that the regions cover `0..blocks` exactly once.
+fn check_coverage(ranges: &[Range<u64>], blocks: u64, report: &mut VerifyReport) {
+    let mut sorted: Vec<&Range<u64>> = ranges.iter().collect();
+    sorted.sort_by_key(|r| r.start);
+    let mut covered_until = 0;
+    for range in sorted {
+        if range.start > covered_until {
+            report.uncovered_blocks += range.start - covered_until;
+        } else {
+            report.overlapping_blocks += covered_until.min(range.end) - range.start;
+        }
+        covered_until = covered_until.max(range.end);
+    }
+    report.uncovered_blocks += blocks.saturating_sub(covered_until);
+}
+
+/// Reads back one region and compares every block with its expected stamp.
+fn verify_region(
+    engine: &dyn Engine,
+    uuid: u128,
+    thread_id: u64,
+    range: &Range<u64>,
+    written: &Range<u64>,
+    written_blocks: u64,
+    block_size: usize,
+) -> (u64, u64) {
+    let mut buffer = crate::DirectIOBuffer::<READ_SIZE>::new_boxed(0);
+    let blocks_per_read = (READ_SIZE / block_size) as u64;
+    let mut verified = 0;
+    let mut mismatches = 0;
+    let mut block = range.start;
+    while block < range.end {
+        let count = blocks_per_read.min(range.end - block);
+        let bytes = count as usize * block_size;
+        engine
+            .read_exact_at(&mut buffer.0[..bytes], block * block_size as u64)
+            .expect("could not read during verification");
+        for i in 0..count {
+            let at = i as usize * block_size;
+            let found = Stamp::read_from(&buffer.0[at..at + STAMP_LEN]);
+            let expected = if written.contains(&(block + i)) {
+                expected_stamp(uuid, thread_id, written, written_blocks, block + i)
+            } else {
+                None
+            };
+            let ok = match (expected, found) {
+                (Some(expected), found) => found == Some(expected),
+                // never written in this run: must not carry a stamp of this run
+                (None, found) => found.is_none_or(|f| f.uuid != uuid),
+            };
+            if !ok {
+                if mismatches < REPORTED_MISMATCHES {
+                    println!(
+                        "verify: block {} of thread {}: expected {:?}, found {:?}",
+                        block + i,
+                        thread_id,
+                        expected,
+                        found
+                    );
+                }
+                mismatches += 1;
+            }
+            verified += 1;
+        }
+        block += count;
+    }
+    (verified, mismatches)
+}
+
+/// Verifies all regions in parallel. Thread `i` owns `ranges[i]`, wrapped around the part
+/// `written[i]` of it, and issued `written_blocks[i]` block writes.
+pub fn verify_regions(
+    device: &crate::engine::Device,
+    uuid: u128,
+    ranges: &[Range<u64>],
+    written: &[Range<u64>],
+    written_blocks: &[u64],
+    blocks: u64,
+    block_size: usize,
+) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    check_coverage(ranges, blocks, &mut report);
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = ranges
+            .iter()
+            .zip(written)
+            .zip(written_blocks)
+            .enumerate()
+            .map(|(thread_id, ((range, written), written_blocks))| {
+                scope.spawn(move || {
+                    let file = device.open(crate::engine::EngineKind::Psync);
+                    verify_region(
+                        file.as_ref(),
+                        uuid,
+                        thread_id as u64,
+                        range,
+                        written,
+                        *written_blocks,
+                        block_size,
+                    )
+                })
+            })
+            .collect();
+        for handle in handles {
+            let (verified, mismatches) = handle.join().unwrap();
+            report.verified_blocks += verified;
+            report.mismatches += mismatches;
+        }
+    });
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_round_trip_and_need_the_magic() {
+        let stamp = Stamp {
+            uuid: u128::MAX - 7,
+            thread_id: 3,
+            block: 1 << 40,
+            pass: 2,
+        };
+        let mut block = [0u8; 4096];
+        assert_eq!(Stamp::read_from(&block), None);
+        stamp.write_to(&mut block);
+        assert_eq!(Stamp::read_from(&block), Some(stamp));
+        block[0] ^= 1;
+        assert_eq!(Stamp::read_from(&block), None);
+    }
+
+    #[test]
+    fn expected_stamps_follow_the_wrap_around() {
+        let range = 10..14;
+        let pass_of = |written_blocks, block| {
+            expected_stamp(1, 0, &range, written_blocks, block).map(|s| s.pass)
+        };
+        assert_eq!(pass_of(0, 10), None);
+        assert_eq!(pass_of(2, 11), Some(0));
+        assert_eq!(pass_of(2, 12), None);
+        // the second pass overwrote the first two blocks only
+        assert_eq!(pass_of(6, 11), Some(1));
+        assert_eq!(pass_of(6, 13), Some(0));
+    }
+
+    #[test]
+    fn coverage_counts_gaps_and_overlaps() {
+        let mut report = VerifyReport::default();
+        check_coverage(&[0..10, 10..20], 20, &mut report);
+        assert!(!report.failed());
+        let mut report = VerifyReport::default();
+        check_coverage(&[12..20, 0..10], 24, &mut report);
+        assert_eq!((report.uncovered_blocks, report.overlapping_blocks), (6, 0));
+        let mut report = VerifyReport::default();
+        check_coverage(&[0..10, 8..20], 20, &mut report);
+        assert_eq!((report.uncovered_blocks, report.overlapping_blocks), (0, 2));
+        assert!(report.failed());
+    }
+
+    #[test]
+    fn read_back_blocks_are_compared_with_their_stamps() {
+        const BLOCK: usize = 4096;
+        let path = std::env::temp_dir().join(format!("ssd-benchy-verify-{}", std::process::id()));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let (uuid, range) = (42, 0..8);
+        let written_blocks = 10; // blocks 0 and 1 twice
+        let mut block = vec![0u8; BLOCK];
+        for b in range.clone() {
+            expected_stamp(uuid, 0, &range, written_blocks, b)
+                .unwrap()
+                .write_to(&mut block);
+            file.write_at(&block, b * BLOCK as u64).unwrap();
+        }
+        assert_eq!(
+            verify_region(&file, uuid, 0, &range, &range, written_blocks, BLOCK),
+            (8, 0)
+        );
+        // a lost write leaves the stamp of the first pass
+        expected_stamp(uuid, 0, &range, 1, 0)
+            .unwrap()
+            .write_to(&mut block);
+        file.write_at(&block, 0).unwrap();
+        assert_eq!(
+            verify_region(&file, uuid, 0, &range, &range, written_blocks, BLOCK),
+            (8, 1)
+        );
+        // blocks outside the written part must not carry a stamp of the run
+        assert_eq!(
+            verify_region(&file, uuid, 0, &range, &(0..4), 4, BLOCK),
+            (8, 5)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
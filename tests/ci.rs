@@ -0,0 +1,145 @@
+//! End-to-end runs of the benchmark on a loop device or brd ramdisk (`--features ci`).
+//!
+//! Set SSD_BENCHY_CI_DEVICE to the device to write, e.g., loop0 over a file of at least 64 MiB
+//! (`losetup -f --show disk.img`) or ram0 of `modprobe brd rd_size=65536`; everything on it is
+//! overwritten. Without it the tests pass without running.
+
+#![cfg(feature = "ci")]
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::Mutex,
+};
+
+/// The tests share the device and run one at a time
+static DEVICE: Mutex<()> = Mutex::new(());
+
+fn ci_device() -> Option<String> {
+    let device = std::env::var("SSD_BENCHY_CI_DEVICE").ok();
+    if device.is_none() {
+        eprintln!("skipped: SSD_BENCHY_CI_DEVICE is not set");
+    }
+    device
+}
+
+/// A fresh directory for the result files of one test
+fn work_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ssd-benchy-ci-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs ssd-benchy in `dir` with `args`, buffered on the CI device
+fn benchmark(dir: &Path, device: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ssd-benchy"))
+        .current_dir(dir)
+        .args([
+            "--ssd-device",
+            device,
+            "--buffered-io",
+            "--instance-type",
+            "ci",
+            "--max-iops",
+            "2000",
+        ])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// The rows of a CSV file, header first
+fn rows(path: &Path) -> Vec<Vec<String>> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(',').map(String::from).collect())
+        .collect()
+}
+
+#[test]
+fn writer_threads_wrap_around_their_partitions_without_corruption() {
+    let Some(device) = ci_device() else {
+        return;
+    };
+    let _device = DEVICE.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = work_dir("wrap");
+    // 4 threads write 5% of the device at 500 IOPS each, so every one wraps around its partition
+    let output = benchmark(
+        &dir,
+        &device,
+        &[
+            "--writer-threads",
+            "4",
+            "--capacity-fraction",
+            "0.05",
+            "--utilization-iops",
+            "1.0",
+            "--runtime-seconds",
+            "3",
+            "--verify",
+        ],
+    );
+    assert_success(&output);
+    let summary = rows(&dir.join("summary_file.csv"));
+    assert_eq!(summary.len(), 2);
+    let column = |name: &str| summary[0].iter().position(|c| c == name).unwrap();
+    assert_eq!(summary[1][column("direct_io")], "false");
+    assert_eq!(summary[1][column("io_errors")], "0");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn runs_append_to_the_result_files() {
+    let Some(device) = ci_device() else {
+        return;
+    };
+    let _device = DEVICE.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = work_dir("append");
+    for _ in 0..2 {
+        let output = benchmark(
+            &dir,
+            &device,
+            &[
+                "--writer-threads",
+                "2",
+                "--capacity-fraction",
+                "0.1",
+                "--utilization-iops",
+                "0.4 0.8",
+                "--runtime-seconds",
+                "2",
+                "--serialize-samples",
+                "--result-json",
+                "result.json",
+            ],
+        );
+        assert_success(&output);
+    }
+    let summary = rows(&dir.join("summary_file.csv"));
+    assert_eq!(summary.len(), 5, "one header and two points per run");
+    assert!(summary.iter().all(|row| row.len() == summary[0].len()));
+    let samples = rows(&dir.join("samples_file.csv"));
+    assert!(samples.len() > 1);
+    assert!(samples.iter().all(|row| row.len() == samples[0].len()));
+    let result = std::fs::read_to_string(dir.join("result.json")).unwrap();
+    assert!(result.contains("\"status\":\"success\""), "{}", result);
+
+    let report = Command::new(env!("CARGO_BIN_EXE_ssd-benchy"))
+        .current_dir(&dir)
+        .args(["report", "--summary-file", "summary_file.csv"])
+        .output()
+        .unwrap();
+    assert_success(&report);
+    std::fs::remove_dir_all(&dir).unwrap();
+}